@@ -0,0 +1,15 @@
+// generates the gRPC service out of `proto/segment.proto` when the grpc feature
+// is on, protoc comes vendored so building doesn't need it installed
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/segment.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("protoc is vendored");
+        std::env::set_var("PROTOC", protoc);
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/segment.proto"], &["proto"])
+            .expect("proto/segment.proto compiles");
+    }
+}
@@ -0,0 +1,218 @@
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+// what the arc evictor should do with the key at the front of a queue, see
+// `Adaptive::evict`
+#[derive(Debug, PartialEq)]
+pub enum Candidate {
+    // the key is no longer held, it is forgotten
+    Missing,
+    // the key can't be evicted, like a pinned key, it goes to the back of its
+    // queue
+    Kept,
+    // the key was accessed since it was queued, it moves to the back of the
+    // frequent queue
+    Referenced,
+    // like `Referenced` along with when the key was last accessed, so the
+    // exact lru evictor can queue it as of then
+    Accessed(Instant),
+    // the key wasn't accessed since it was queued and is evicted
+    Cold,
+}
+
+// the bookkeeping of the arc evictor, an adaptive replacement cache which
+// balances keys seen once recently against keys seen more than once. it
+// follows CAR, the clock flavour of ARC: instead of moving a key on every
+// access the evictor checks whether the key was accessed since it was queued
+// once the key reaches the front of its queue. keys that were evicted are
+// remembered for a while as ghosts, a ghost being written again shows which
+// queue evicted too eagerly and the target size of the queues adapts to it.
+#[derive(Debug, Default)]
+pub struct Adaptive {
+    // keys seen once since they were written
+    recent: Queue,
+    // keys seen more than once
+    frequent: Queue,
+    // keys evicted from `recent` and `frequent`
+    recent_ghosts: Queue,
+    frequent_ghosts: Queue,
+    // the number of keys `recent` should hold, the rest go to `frequent`
+    target: usize,
+}
+
+// keys in the order they were queued in, oldest first, along with when they
+// were queued. shared with the other evictors which queue keys.
+#[derive(Debug, Default)]
+pub struct Queue {
+    order: BTreeMap<u64, Bytes>,
+    keys: HashMap<Bytes, (u64, Instant)>,
+    next: u64,
+}
+
+impl Adaptive {
+    // a new key was written, keys written again are left where they are
+    pub fn insert(&mut self, key: &Bytes, now: Instant) {
+        if self.recent.contains(key) || self.frequent.contains(key) {
+            return;
+        }
+        let (recent_ghosts, frequent_ghosts) =
+            (self.recent_ghosts.len(), self.frequent_ghosts.len());
+        if self.recent_ghosts.remove(key) {
+            // evicted from `recent` too soon, let it grow
+            let step = (frequent_ghosts / recent_ghosts).max(1);
+            self.target = (self.target + step).min(self.len());
+            self.frequent.push(key.clone(), now);
+        } else if self.frequent_ghosts.remove(key) {
+            // evicted from `frequent` too soon, let it grow instead
+            let step = (recent_ghosts / frequent_ghosts).max(1);
+            self.target = self.target.saturating_sub(step);
+            self.frequent.push(key.clone(), now);
+        } else {
+            self.recent.push(key.clone(), now);
+        }
+        // ghosts are remembered for as many keys as are held
+        while self.recent_ghosts.len() + self.frequent_ghosts.len() > self.len() {
+            if self.recent_ghosts.len() >= self.frequent_ghosts.len() {
+                self.recent_ghosts.pop();
+            } else {
+                self.frequent_ghosts.pop();
+            }
+        }
+    }
+
+    // a key was removed other than by eviction, like a deleted key
+    pub fn remove(&mut self, key: &Bytes) {
+        if !self.recent.remove(key) {
+            self.frequent.remove(key);
+        }
+    }
+
+    // picks the key to evict, `check` tells what to do with the keys at the
+    // front of the queues. returns `None` when every key was kept.
+    pub fn evict(
+        &mut self,
+        mut check: impl FnMut(&Bytes, Instant) -> Candidate,
+        now: Instant,
+    ) -> Option<Bytes> {
+        // every key gets looked at at most twice, once to find out it was
+        // referenced and once more after it moved
+        for _ in 0..=2 * self.len() {
+            let from_recent = !self.recent.is_empty()
+                && (self.recent.len() >= self.target.max(1) || self.frequent.is_empty());
+            let queue = if from_recent {
+                &mut self.recent
+            } else {
+                &mut self.frequent
+            };
+            let (key, queued_at) = queue.pop()?;
+            match check(&key, queued_at) {
+                Candidate::Missing => {}
+                Candidate::Kept => queue.push(key, now),
+                Candidate::Referenced | Candidate::Accessed(_) => self.frequent.push(key, now),
+                Candidate::Cold => {
+                    if from_recent {
+                        self.recent_ghosts.push(key.clone(), now);
+                    } else {
+                        self.frequent_ghosts.push(key.clone(), now);
+                    }
+                    return Some(key);
+                }
+            }
+        }
+        None
+    }
+
+    // the number of keys held, ghosts aside
+    pub fn len(&self) -> usize {
+        self.recent.len() + self.frequent.len()
+    }
+}
+
+impl Queue {
+    // queues the key at the back as of `now`
+    pub fn push(&mut self, key: Bytes, now: Instant) {
+        self.remove(&key);
+        let tick = self.next;
+        self.next += 1;
+        self.order.insert(tick, key.clone());
+        self.keys.insert(key, (tick, now));
+    }
+
+    // takes the oldest key out of the queue along with when it was queued
+    pub fn pop(&mut self) -> Option<(Bytes, Instant)> {
+        let (_, key) = self.order.pop_first()?;
+        let (_, queued_at) = self.keys.remove(&key)?;
+        Some((key, queued_at))
+    }
+
+    pub fn remove(&mut self, key: &Bytes) -> bool {
+        match self.keys.remove(key) {
+            Some((tick, _)) => {
+                self.order.remove(&tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, key: &Bytes) -> bool {
+        self.keys.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adaptive(keys: &[&str]) -> Adaptive {
+        let mut adaptive = Adaptive::default();
+        for key in keys {
+            adaptive.insert(&Bytes::copy_from_slice(key.as_bytes()), Instant::now());
+        }
+        adaptive
+    }
+
+    #[test]
+    fn evict_given_referenced_key_evicts_the_cold_one() {
+        let mut adaptive = adaptive(&["a", "b"]);
+        let evicted = adaptive.evict(
+            |key, _| match &key[..] {
+                b"a" => Candidate::Referenced,
+                _ => Candidate::Cold,
+            },
+            Instant::now(),
+        );
+        assert_eq!(evicted, Some(Bytes::from("b")));
+        assert_eq!(adaptive.len(), 1);
+        assert!(adaptive.frequent.contains(&Bytes::from("a")));
+    }
+
+    #[test]
+    fn evict_given_only_kept_keys_returns_none() {
+        let mut adaptive = adaptive(&["a", "b"]);
+        assert_eq!(adaptive.evict(|_, _| Candidate::Kept, Instant::now()), None);
+        assert_eq!(adaptive.len(), 2);
+    }
+
+    #[test]
+    fn insert_given_ghost_of_recent_key_grows_recent_target() {
+        let mut adaptive = adaptive(&["a", "b", "c"]);
+        assert_eq!(
+            adaptive.evict(|_, _| Candidate::Cold, Instant::now()),
+            Some(Bytes::from("a"))
+        );
+        assert_eq!(adaptive.target, 0);
+        adaptive.insert(&Bytes::from("a"), Instant::now());
+        assert_eq!(adaptive.target, 1);
+        assert!(adaptive.frequent.contains(&Bytes::from("a")));
+    }
+}
@@ -0,0 +1,31 @@
+// jemalloc is the global allocator when segment is built with the `jemalloc`
+// feature. the memory used by the server is then read from the statistics of
+// the allocator instead of being sampled from the operating system, which
+// works on every platform and is up to date whenever it is read.
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+pub fn name() -> &'static str {
+    if cfg!(feature = "jemalloc") {
+        "jemalloc"
+    } else {
+        "system"
+    }
+}
+
+// the bytes allocated by the server, `None` when the allocator doesn't keep
+// statistics
+#[cfg(feature = "jemalloc")]
+pub fn allocated() -> Option<u64> {
+    // the statistics are a snapshot taken when the epoch last advanced
+    tikv_jemalloc_ctl::epoch::advance().ok()?;
+    tikv_jemalloc_ctl::stats::allocated::read()
+        .ok()
+        .map(|allocated| allocated as u64)
+}
+
+#[cfg(not(feature = "jemalloc"))]
+pub fn allocated() -> Option<u64> {
+    None
+}
@@ -0,0 +1,587 @@
+// the server replays the log, the reading side is unused without it
+#![cfg_attr(not(feature = "server"), allow(dead_code))]
+
+use crate::connection::Connection;
+use crate::frame::{self, Frame, ParseFrameError, Partial};
+use bytes::{Bytes, BytesMut};
+use crossbeam::sync::WaitGroup;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{
+    broadcast, mpsc, oneshot, Mutex as OrderLock, OwnedMutexGuard, OwnedRwLockReadGuard,
+    OwnedRwLockWriteGuard, OwnedSemaphorePermit, RwLock, Semaphore,
+};
+use tokio::time::{self, MissedTickBehavior};
+use tracing::{debug, error};
+
+pub const FILE_NAME: &str = "segment.aof";
+
+// the log starts with the magic and the version of its format, a log of
+// another version is refused rather than replayed wrong
+const MAGIC: &[u8] = b"SEGAOF\0";
+pub const VERSION: u32 = 1;
+
+// how many writes can wait to be appended, commands wait for room once the
+// queue is full so the log never falls far behind the keyspaces
+const QUEUE_SIZE: usize = 4096;
+
+// how many bytes are read from the log at once while it is replayed
+const READ_SIZE: usize = 64 * 1024;
+
+// the append only file, see `appendonly` in the config. every write which ran
+// successfully is appended to the log in the frame protocol, as it was sent,
+// by a single task. on startup the log is replayed before the server accepts
+// connections, which brings the keyspaces back to where they were.
+#[derive(Debug)]
+pub struct Aof {
+    tx: mpsc::UnboundedSender<Append>,
+    // the room left in the queue, a command holds a permit until it is
+    // written
+    room: Arc<Semaphore>,
+    // held while a write runs and is queued, so the writes are appended in
+    // the order they ran, see `Aof::order`. writes to given keyspaces share
+    // it and hold the locks of their keyspaces, the other writes hold it
+    // alone. the locks of dropped keyspaces are kept, they are tiny.
+    order: Arc<RwLock<()>>,
+    keyspaces: Mutex<HashMap<Bytes, Arc<OrderLock<()>>>>,
+    fsync: AppendFsync,
+}
+
+// held by a write from before it runs until it is queued, see `Aof::order`
+#[derive(Debug)]
+pub enum OrderGuard {
+    Keyspaces(OwnedRwLockReadGuard<()>, Vec<OwnedMutexGuard<()>>),
+    All(OwnedRwLockWriteGuard<()>),
+}
+
+// a command queued to be appended, with the sender to let go once it is
+// synced with `appendfsync always`. `FSYNC` queues a sender alone.
+#[derive(Debug)]
+struct Append {
+    command: Option<Frame>,
+    synced: Option<oneshot::Sender<()>>,
+    _room: Option<OwnedSemaphorePermit>,
+}
+
+// when the log is synced to disk, see `appendfsync` in the config. the writes
+// since the last sync are lost when the machine goes down, the log is written
+// out to the operating system either way so a crash of the server alone loses
+// nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AppendFsync {
+    // after every write, which waits for the sync before it replies
+    Always,
+    // once a second
+    #[default]
+    EverySec,
+    // whenever the operating system writes it out
+    No,
+}
+
+// appends the keys a keyspace removes by itself, because they expired or
+// were evicted, as `DEL`s so a replay doesn't bring them back. the keyspace
+// holds its store lock while it queues them, so a write to one of the keys
+// that runs later is appended after the `DEL`.
+#[derive(Debug, Clone)]
+pub struct Removals {
+    keyspace: Bytes,
+    // the keyspaces whose writes change the keys, the keyspace itself and
+    // the keyspace it is a canary of
+    writers: Vec<Bytes>,
+    // set once the log was replayed, like the one of the db
+    aof: Arc<OnceLock<Aof>>,
+}
+
+// the task appending the commands to the log
+#[derive(Debug)]
+struct Writer {
+    log: Connection<File>,
+    sync: File,
+    path: String,
+    fsync: AppendFsync,
+    // whether commands were written since the last sync
+    dirty: bool,
+    // the commands waiting for the next sync, with `appendfsync always`
+    synced: Vec<oneshot::Sender<()>>,
+}
+
+// reads the commands of the log back in the order they were appended
+#[derive(Debug)]
+pub struct AofReader {
+    file: fs::File,
+    path: String,
+    buf: BytesMut,
+    partial: Partial,
+    // bytes read from the file so far and where the last complete command
+    // ends
+    read: u64,
+    end: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum AofError {
+    #[error("failed to open append only file '{0}': {1}")]
+    Open(String, io::Error),
+
+    #[error("failed to read append only file '{0}': {1}")]
+    Read(String, io::Error),
+
+    #[error("append only file '{0}' is corrupt at byte {1}: {2}")]
+    Corrupt(String, u64, ParseFrameError),
+
+    #[error("'{0}' is not an append only file")]
+    UnknownFormat(String),
+
+    #[error(
+        "append only file '{0}' is of version {1}, only version {} can be replayed",
+        VERSION
+    )]
+    UnsupportedVersion(String, u32),
+}
+
+impl Aof {
+    // opens the log at `path` for appending, the writer stops on `done` once
+    // the writes queued until then are appended
+    pub async fn open(
+        path: &Path,
+        fsync: AppendFsync,
+        done: broadcast::Receiver<()>,
+        wg: WaitGroup,
+    ) -> Result<Self, AofError> {
+        let name = path.display().to_string();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(path)
+            .await
+            .map_err(|e| AofError::Open(name.clone(), e))?;
+        let len = file
+            .metadata()
+            .await
+            .map_err(|e| AofError::Open(name.clone(), e))?
+            .len();
+        if len == 0 {
+            let mut header = MAGIC.to_vec();
+            header.extend_from_slice(&VERSION.to_le_bytes());
+            file.write_all(&header)
+                .await
+                .map_err(|e| AofError::Open(name.clone(), e))?;
+        }
+        // the connection splits the file, the clone is kept to sync it
+        let sync = file
+            .try_clone()
+            .await
+            .map_err(|e| AofError::Open(name.clone(), e))?;
+        let writer = Writer {
+            log: Connection::new(file, 0),
+            sync,
+            path: name,
+            fsync,
+            dirty: false,
+            synced: Vec::new(),
+        };
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(writer.run(rx, done, wg));
+        Ok(Aof {
+            tx,
+            room: Arc::new(Semaphore::new(QUEUE_SIZE)),
+            order: Arc::new(RwLock::new(())),
+            keyspaces: Mutex::new(HashMap::new()),
+            fsync,
+        })
+    }
+
+    // two writes to the same key could run in one order and be queued in the
+    // other, a replay would then end up with the value of the wrong one. the
+    // writes hold the guard from before they run until they are queued.
+    // writes to other keyspaces don't wait on each other, `keyspaces` is
+    // `None` for the writes which can change any keyspace.
+    pub async fn order(&self, keyspaces: Option<&[Bytes]>) -> OrderGuard {
+        let keyspaces = match keyspaces {
+            Some(keyspaces) => keyspaces,
+            None => return OrderGuard::All(self.order.clone().write_owned().await),
+        };
+        let all = self.order.clone().read_owned().await;
+        // the locks are taken in the same order by every write
+        let mut names = keyspaces.to_vec();
+        names.sort();
+        names.dedup();
+        let locks: Vec<_> = {
+            let mut handle = self.keyspaces.lock();
+            names
+                .into_iter()
+                .map(|name| handle.entry(name).or_default().clone())
+                .collect()
+        };
+        let mut guards = Vec::with_capacity(locks.len());
+        for lock in locks {
+            guards.push(lock.lock_owned().await);
+        }
+        OrderGuard::Keyspaces(all, guards)
+    }
+
+    // queues the command to be appended, waits while the queue is full. with
+    // `appendfsync always` it returns the receiver which is let go once the
+    // command is synced to disk, it is awaited after the order guard is
+    // dropped so the writes queued meanwhile are synced together.
+    pub async fn queue(&self, command: Frame) -> Option<oneshot::Receiver<()>> {
+        // the semaphore is never closed
+        let room = self.room.clone().acquire_owned().await.ok();
+        let (synced, synced_rx) = match self.fsync {
+            AppendFsync::Always => {
+                let (synced_tx, synced_rx) = oneshot::channel();
+                (Some(synced_tx), Some(synced_rx))
+            }
+            _ => (None, None),
+        };
+        // the writer is only gone once the server is shutting down
+        self.tx
+            .send(Append {
+                command: Some(command),
+                synced,
+                _room: room,
+            })
+            .ok()?;
+        synced_rx
+    }
+
+    // queues the command and waits until it is appended as `appendfsync`
+    // asks
+    pub async fn append(&self, command: Frame) {
+        if let Some(synced) = self.queue(command).await {
+            let _ = synced.await;
+        }
+    }
+
+    // queues the command without waiting for room or a sync, for the keys a
+    // keyspace removes by itself while it holds its locks, see `Removals`
+    pub fn queue_now(&self, command: Frame) {
+        let _ = self.tx.send(Append {
+            command: Some(command),
+            synced: None,
+            _room: None,
+        });
+    }
+
+    // waits until the commands queued so far are synced to disk, whatever
+    // `appendfsync` is, see `FSYNC`
+    pub async fn sync(&self) {
+        let (synced_tx, synced_rx) = oneshot::channel();
+        let barrier = Append {
+            command: None,
+            synced: Some(synced_tx),
+            _room: None,
+        };
+        if self.tx.send(barrier).is_ok() {
+            let _ = synced_rx.await;
+        }
+    }
+}
+
+impl Removals {
+    // `source` is the keyspace the keyspace is a canary of, its writes are
+    // mirrored to the keyspace
+    pub fn new(keyspace: Bytes, source: Option<Bytes>, aof: Arc<OnceLock<Aof>>) -> Self {
+        let writers = [Some(keyspace.clone()), source]
+            .into_iter()
+            .flatten()
+            .collect();
+        Removals {
+            keyspace,
+            writers,
+            aof,
+        }
+    }
+
+    pub fn append(&self, key: &Bytes) {
+        if let Some(aof) = self.aof.get() {
+            aof.queue_now(Frame::Array(vec![
+                Frame::String(Bytes::from_static(b"del")),
+                Frame::String(self.keyspace.clone()),
+                Frame::String(key.clone()),
+            ]));
+        }
+    }
+
+    // keys evicted in the background are removed with the guard held, so a
+    // write to one of them can't run before the eviction and be appended
+    // after it
+    pub async fn order(&self) -> Option<OrderGuard> {
+        match self.aof.get() {
+            Some(aof) => Some(aof.order(Some(&self.writers)).await),
+            None => None,
+        }
+    }
+}
+
+impl AppendFsync {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AppendFsync::Always => "always",
+            AppendFsync::EverySec => "everysec",
+            AppendFsync::No => "no",
+        }
+    }
+}
+
+impl Writer {
+    async fn run(
+        mut self,
+        mut rx: mpsc::UnboundedReceiver<Append>,
+        mut done: broadcast::Receiver<()>,
+        wg: WaitGroup,
+    ) {
+        let mut tick = time::interval(Duration::from_secs(1));
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = done.recv() => {
+                    debug!("stopping append only file writer, shutdown signal received");
+                    break;
+                }
+                _ = tick.tick(), if self.fsync == AppendFsync::EverySec && self.dirty => {
+                    self.sync().await;
+                }
+                command = rx.recv() => {
+                    let command = match command {
+                        Some(command) => command,
+                        None => break,
+                    };
+                    self.queue(command);
+                    // the commands queued meanwhile go out in the same write
+                    // and are synced together
+                    while let Ok(command) = rx.try_recv() {
+                        self.queue(command);
+                    }
+                    self.flush().await;
+                    // commands wait for the sync with `appendfsync always`
+                    // and after `FSYNC`
+                    if !self.synced.is_empty() {
+                        self.sync().await;
+                    }
+                }
+            }
+        }
+        // the writes that made it into the queue before the shutdown are kept
+        rx.close();
+        while let Ok(command) = rx.try_recv() {
+            self.queue(command);
+        }
+        self.flush().await;
+        self.sync().await;
+        drop(wg)
+    }
+
+    fn queue(&mut self, append: Append) {
+        if let Some(command) = &append.command {
+            if let Err(e) = self.log.queue_frame(command) {
+                error!("failed to append to '{}', error = {}", self.path, e);
+            }
+        }
+        self.synced.extend(append.synced);
+    }
+
+    async fn flush(&mut self) {
+        if let Err(e) = self.log.flush().await {
+            error!(
+                "failed to write append only file '{}', error = {}",
+                self.path, e
+            );
+        }
+        self.dirty = true;
+    }
+
+    // the commands waiting for the sync are let go even if it failed, the
+    // error is logged
+    async fn sync(&mut self) {
+        if let Err(e) = self.sync.sync_data().await {
+            error!(
+                "failed to sync append only file '{}', error = {}",
+                self.path, e
+            );
+        }
+        self.dirty = false;
+        for synced in self.synced.drain(..) {
+            let _ = synced.send(());
+        }
+    }
+}
+
+impl AofReader {
+    // `None` when there is no log at `path`. an empty log has no header yet,
+    // it is written when the log is opened for appending.
+    pub fn open(path: &Path) -> Result<Option<Self>, AofError> {
+        let name = path.display().to_string();
+        let mut file = match fs::OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(AofError::Open(name, e)),
+        };
+        let len = file
+            .metadata()
+            .map_err(|e| AofError::Open(name.clone(), e))?
+            .len();
+        let mut header = [0; MAGIC.len() + 4];
+        if len > 0 {
+            file.read_exact(&mut header).map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => AofError::UnknownFormat(name.clone()),
+                _ => AofError::Read(name.clone(), e),
+            })?;
+            if &header[..MAGIC.len()] != MAGIC {
+                return Err(AofError::UnknownFormat(name));
+            }
+            let version = u32::from_le_bytes(header[MAGIC.len()..].try_into().unwrap());
+            if version != VERSION {
+                return Err(AofError::UnsupportedVersion(name, version));
+            }
+        }
+        let read = if len > 0 { header.len() as u64 } else { 0 };
+        Ok(Some(AofReader {
+            file,
+            path: name,
+            buf: BytesMut::new(),
+            partial: Partial::default(),
+            read,
+            end: read,
+        }))
+    }
+
+    // the next command, `None` once every complete command was read
+    pub fn next_command(&mut self) -> Result<Option<Frame>, AofError> {
+        loop {
+            match frame::parse_buf(&mut self.buf, &mut self.partial) {
+                Ok(command) => {
+                    self.end = self.read - self.buf.len() as u64;
+                    return Ok(Some(command));
+                }
+                Err(ParseFrameError::Incomplete) => {}
+                Err(e) => return Err(AofError::Corrupt(self.path.clone(), self.end, e)),
+            }
+            let len = self.buf.len();
+            self.buf.resize(len + READ_SIZE, 0);
+            let n = self
+                .file
+                .read(&mut self.buf[len..])
+                .map_err(|e| AofError::Read(self.path.clone(), e))?;
+            self.buf.truncate(len + n);
+            if n == 0 {
+                return Ok(None);
+            }
+            self.read += n as u64;
+        }
+    }
+
+    // cuts off a command left half written by a crash, so the commands
+    // appended from now on follow the last complete one. returns the number
+    // of bytes cut off.
+    pub fn finish(self) -> Result<u64, AofError> {
+        let cut = self.read - self.end;
+        if cut > 0 {
+            self.file
+                .set_len(self.end)
+                .map_err(|e| AofError::Read(self.path.clone(), e))?;
+        }
+        Ok(cut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::CommandBuilder;
+    use std::env;
+
+    #[tokio::test]
+    async fn next_command_given_appended_commands_reads_them_back() {
+        let path = env::temp_dir().join(format!("segment-aof-test-{}", std::process::id()));
+        let (done_tx, done) = broadcast::channel(1);
+        let wg = WaitGroup::new();
+        let aof = Aof::open(&path, AppendFsync::Always, done, wg.clone())
+            .await
+            .unwrap();
+        let set = CommandBuilder::new("set").args(["foo", "bar", "1"]).build();
+        let del = CommandBuilder::new("del").args(["foo", "bar"]).build();
+        aof.append(set.clone()).await;
+        aof.append(del.clone()).await;
+        drop(done_tx);
+        tokio::task::spawn_blocking(move || wg.wait())
+            .await
+            .unwrap();
+
+        // a command cut short by a crash is dropped from the log
+        let mut file = fs::read(&path).unwrap();
+        assert!(file.starts_with(MAGIC));
+        file.extend_from_slice(b"*3\r\n$3\r\nset\r\n");
+        fs::write(&path, &file).unwrap();
+        let mut reader = AofReader::open(&path).unwrap().unwrap();
+        assert_eq!(reader.next_command().unwrap(), Some(set));
+        assert_eq!(reader.next_command().unwrap(), Some(del));
+        assert_eq!(reader.next_command().unwrap(), None);
+        assert_eq!(reader.finish().unwrap(), 13);
+        let len = fs::metadata(&path).unwrap().len();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(len as usize, file.len() - 13);
+    }
+
+    #[tokio::test]
+    async fn sync_given_appendfsync_no_writes_queued_commands() {
+        let path = env::temp_dir().join(format!("segment-aof-sync-{}", std::process::id()));
+        let (_done_tx, done) = broadcast::channel(1);
+        let aof = Aof::open(&path, AppendFsync::No, done, WaitGroup::new())
+            .await
+            .unwrap();
+        let set = CommandBuilder::new("set").args(["foo", "bar", "1"]).build();
+        aof.append(set.clone()).await;
+        aof.sync().await;
+        let mut reader = AofReader::open(&path).unwrap().unwrap();
+        let appended = reader.next_command().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(appended, Some(set));
+    }
+
+    #[tokio::test]
+    async fn order_given_write_running_waits_only_for_its_keyspace() {
+        let path = env::temp_dir().join(format!("segment-aof-order-{}", std::process::id()));
+        let (_done_tx, done) = broadcast::channel(1);
+        let aof = Aof::open(&path, AppendFsync::No, done, WaitGroup::new())
+            .await
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        let foo = [Bytes::from("foo")];
+        let bar = [Bytes::from("bar")];
+        let both = [Bytes::from("bar"), Bytes::from("foo")];
+        let running = aof.order(Some(&foo)).await;
+        let wait = Duration::from_millis(50);
+        assert!(time::timeout(wait, aof.order(Some(&bar))).await.is_ok());
+        assert!(time::timeout(wait, aof.order(Some(&both))).await.is_err());
+        assert!(time::timeout(wait, aof.order(None)).await.is_err());
+        drop(running);
+        assert!(time::timeout(wait, aof.order(Some(&foo))).await.is_ok());
+    }
+
+    #[test]
+    fn open_given_file_of_other_format_returns_error() {
+        let path = env::temp_dir().join(format!("segment-aof-format-{}", std::process::id()));
+        fs::write(&path, b"*1\r\n$4\r\nping\r\n").unwrap();
+        let unknown = AofReader::open(&path);
+        let mut newer = MAGIC.to_vec();
+        newer.extend_from_slice(&(VERSION + 1).to_le_bytes());
+        fs::write(&path, &newer).unwrap();
+        let unsupported = AofReader::open(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(unknown, Err(AofError::UnknownFormat(_))));
+        assert!(matches!(
+            unsupported,
+            Err(AofError::UnsupportedVersion(_, version)) if version == VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::frame::Frame;
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+
+// remembers the results of the last `capacity` batches by their idempotency
+// token, so a retried batch gets the recorded result back instead of being
+// applied again. the oldest token is forgotten once the log is full.
+#[derive(Debug)]
+pub struct BatchLog {
+    capacity: usize,
+    results: HashMap<Bytes, Frame>,
+    tokens: VecDeque<Bytes>,
+}
+
+impl BatchLog {
+    pub fn new(capacity: usize) -> Self {
+        BatchLog {
+            capacity,
+            results: HashMap::new(),
+            tokens: VecDeque::new(),
+        }
+    }
+
+    pub fn get(&self, token: &Bytes) -> Option<&Frame> {
+        self.results.get(token)
+    }
+
+    pub fn insert(&mut self, token: Bytes, result: Frame) {
+        if self.results.insert(token.clone(), result).is_some() {
+            return;
+        }
+        self.tokens.push_back(token);
+        if self.tokens.len() > self.capacity {
+            if let Some(oldest) = self.tokens.pop_front() {
+                self.results.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_given_recorded_token_returns_result() {
+        let mut log = BatchLog::new(2);
+        log.insert(Bytes::from("a"), Frame::Integer(1));
+        assert_eq!(log.get(&Bytes::from("a")), Some(&Frame::Integer(1)));
+        assert_eq!(log.get(&Bytes::from("b")), None);
+    }
+
+    #[test]
+    fn insert_given_full_log_forgets_oldest_token() {
+        let mut log = BatchLog::new(2);
+        log.insert(Bytes::from("a"), Frame::Integer(1));
+        log.insert(Bytes::from("b"), Frame::Integer(2));
+        log.insert(Bytes::from("c"), Frame::Integer(3));
+        assert_eq!(log.get(&Bytes::from("a")), None);
+        assert_eq!(log.get(&Bytes::from("b")), Some(&Frame::Integer(2)));
+        assert_eq!(log.get(&Bytes::from("c")), Some(&Frame::Integer(3)));
+    }
+
+    #[test]
+    fn insert_given_recorded_token_keeps_single_entry() {
+        let mut log = BatchLog::new(2);
+        log.insert(Bytes::from("a"), Frame::Integer(1));
+        log.insert(Bytes::from("a"), Frame::Integer(2));
+        log.insert(Bytes::from("b"), Frame::Integer(3));
+        assert_eq!(log.get(&Bytes::from("a")), Some(&Frame::Integer(2)));
+        assert_eq!(log.get(&Bytes::from("b")), Some(&Frame::Integer(3)));
+    }
+}
@@ -0,0 +1,244 @@
+use segment::frame::Frame;
+
+// how the commands are typed in, the server only documents what a command
+// does. commands the connected server doesn't support are reported as such.
+const USAGE: &[(&str, &str)] = &[
+    (
+        "create",
+        "CREATE <keyspace> [EVICTOR NOP|RANDOM|LRU|ARC|SLRU|CLOCK|EXACTLRU] [IF NOT EXISTS] [ORDERED YES|NO] [RETENTION <ms>] [SAMPLES <n>] [TEMP] [UPSTREAM <address> [CACHE <ms>]] [CANARY <keyspace>] [MAXMEM <bytes>[kb|mb|gb]] [MAXKEYS <n>] [TOMBSTONE <ms>] [WEIGHT <n>]",
+    ),
+    (
+        "alter",
+        "ALTER <keyspace> [EVICTOR|EV NOP|RANDOM|LRU|ARC|SLRU|CLOCK|EXACTLRU] [SAMPLES|SS <n>] [WEIGHT <n>]",
+    ),
+    ("drop", "DROP <keyspace> [IF EXISTS]"),
+    ("keyspaces", "KEYSPACES"),
+    ("flushall", "FLUSHALL [DROP] [ASYNC]"),
+    ("count", "COUNT <keyspace>"),
+    ("range", "RANGE <keyspace> <start> <end>"),
+    ("delrange", "DELRANGE <keyspace> <start> <end>"),
+    ("countbyprefix", "COUNTBYPREFIX <keyspace> <prefix>"),
+    ("sample", "SAMPLE <keyspace> <count>"),
+    ("evictstats", "EVICTSTATS <keyspace>"),
+    ("evictor", "EVICTOR PAUSE|RESUME <keyspace>"),
+    ("sum", "SUM <keyspace> [MATCH <pattern>]"),
+    ("avg", "AVG <keyspace> [MATCH <pattern>]"),
+    (
+        "set",
+        "SET <keyspace> <key> <value> [EXPIRE AT <timestamp>|EXPIRE AFTER <ms>] [IF NOT EXISTS|IF EXISTS|NX|XX]",
+    ),
+    ("mset", "MSET <keyspace> <key> <value> [<key> <value> ...]"),
+    ("cset", "CSET <keyspace> <key> <value> <version>"),
+    ("get", "GET <keyspace> <key> [WITHVERSION]"),
+    ("xget", "XGET [<keyspace> <key>] ..."),
+    ("del", "DEL <keyspace> <key>"),
+    ("ttl", "TTL <keyspace> <key>"),
+    ("expireat", "EXPIREAT <keyspace> <key> <timestamp>"),
+    ("pin", "PIN <keyspace> <key>"),
+    ("unpin", "UNPIN <keyspace> <key>"),
+    ("undelete", "UNDELETE <keyspace> <key>"),
+    ("dump", "DUMP <keyspace> <key>"),
+    ("restore", "RESTORE <keyspace> <key> <payload> [REPLACE] [EXPIRE AT <timestamp>]"),
+    ("type", "TYPE <keyspace> <key>"),
+    ("setbit", "SETBIT <keyspace> <key> <offset> 0|1"),
+    ("getbit", "GETBIT <keyspace> <key> <offset>"),
+    ("bitcount", "BITCOUNT <keyspace> <key> [<start> <end>]"),
+    ("getrange", "GETRANGE <keyspace> <key> <start> <end>"),
+    ("setrange", "SETRANGE <keyspace> <key> <offset> <value>"),
+    ("lpush", "LPUSH <keyspace> <key> <value> [<value> ...]"),
+    ("rpush", "RPUSH <keyspace> <key> <value> [<value> ...]"),
+    ("lpop", "LPOP <keyspace> <key>"),
+    ("rpop", "RPOP <keyspace> <key>"),
+    ("lrange", "LRANGE <keyspace> <key> <start> <stop>"),
+    ("llen", "LLEN <keyspace> <key>"),
+    ("hset", "HSET <keyspace> <key> <field> <value> [<field> <value> ...]"),
+    ("hget", "HGET <keyspace> <key> <field>"),
+    ("hdel", "HDEL <keyspace> <key> <field> [<field> ...]"),
+    ("hgetall", "HGETALL <keyspace> <key>"),
+    ("zadd", "ZADD <keyspace> <key> <score> <member> [<score> <member> ...]"),
+    ("zrange", "ZRANGE <keyspace> <key> <start> <stop> [WITHSCORES]"),
+    ("zscore", "ZSCORE <keyspace> <key> <member>"),
+    ("zrem", "ZREM <keyspace> <key> <member> [<member> ...]"),
+    (
+        "xadd",
+        "XADD <keyspace> <key> *|<id> <field> <value> [<field> <value> ...]",
+    ),
+    (
+        "xrange",
+        "XRANGE <keyspace> <key> -|<start> +|<end> [COUNT <count>]",
+    ),
+    ("xlen", "XLEN <keyspace> <key>"),
+    ("watch", "WATCH <keyspace> <key>"),
+    ("unwatch", "UNWATCH"),
+    ("multi", "MULTI"),
+    ("exec", "EXEC"),
+    ("discard", "DISCARD"),
+    ("batch", "BATCH <token> [<command>] ..."),
+    (
+        "view",
+        "VIEW CREATE <view> <keyspace> | VIEW DROP <view> | VIEW LIST",
+    ),
+    (
+        "schedule",
+        "SCHEDULE ADD <name> <cron> <command> ... | SCHEDULE REMOVE <name> | SCHEDULE LIST",
+    ),
+    ("delay", "DELAY <ms> <command> ... | DELAY AT <timestamp> <command> ..."),
+    ("watchkey", "WATCHKEY <keyspace> <key>"),
+    ("watchlifecycle", "WATCHLIFECYCLE"),
+    ("use", "USE [<keyspace>]"),
+    ("ping", "PING [<message>]"),
+    ("echo", "ECHO <message>"),
+    ("info", "INFO"),
+    ("client", "CLIENT INFO"),
+    ("memory", "MEMORY USAGE <keyspace> <key>"),
+    ("object", "OBJECT IDLETIME|META <keyspace> <key>"),
+    ("command", "COMMAND DOCS [<command> ...]"),
+    ("explain", "EXPLAIN <command> ..."),
+    (
+        "debug",
+        "DEBUG POPULATE <keyspace> <count> [<size>] | DEBUG QUICKCHECK <keyspace> | DEBUG PROTOCOL ON|OFF",
+    ),
+    (
+        "tasks",
+        "TASKS LIST | TASKS STATUS <id> | TASKS CANCEL <id>",
+    ),
+    ("save", "SAVE"),
+    ("bgsave", "BGSAVE"),
+    ("export", "EXPORT <keyspace> <path> [FORMAT JSON|CSV]"),
+    ("fsync", "FSYNC"),
+    ("eval", "EVAL <script> [<arg> ...]"),
+    (
+        "function",
+        "FUNCTION LOAD <library> <module> | FUNCTION DELETE <library> | FUNCTION LIST",
+    ),
+    ("fcall", "FCALL <library> <function> [<arg> ...]"),
+];
+
+// a command as documented by the server
+#[derive(Debug, PartialEq)]
+pub struct Doc {
+    name: String,
+    group: String,
+    summary: String,
+}
+
+// reads the reply of `COMMAND DOCS`
+pub fn parse_docs(reply: Frame) -> Vec<Doc> {
+    let mut docs = Vec::new();
+    let frames = match reply {
+        Frame::Map(frames) => frames,
+        _ => return docs,
+    };
+    for pair in frames.chunks(2) {
+        let (name, fields) = match pair {
+            [Frame::String(name), Frame::Map(fields)] => (name, fields),
+            _ => continue,
+        };
+        let mut doc = Doc {
+            name: String::from_utf8_lossy(name).to_string(),
+            group: String::new(),
+            summary: String::new(),
+        };
+        for field in fields.chunks(2) {
+            match field {
+                [Frame::String(key), Frame::String(value)] if &key[..] == b"group" => {
+                    doc.group = String::from_utf8_lossy(value).to_string()
+                }
+                [Frame::String(key), Frame::String(value)] if &key[..] == b"summary" => {
+                    doc.summary = String::from_utf8_lossy(value).to_string()
+                }
+                _ => {}
+            }
+        }
+        docs.push(doc);
+    }
+    docs
+}
+
+fn usage(name: &str) -> Option<&'static str> {
+    USAGE
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map(|(_, usage)| *usage)
+}
+
+// lists the commands of the server by group, or describes a single command
+pub fn format_help(docs: &[Doc], topic: Option<&str>) -> String {
+    let topic = match topic {
+        Some(topic) => topic.to_lowercase(),
+        None => {
+            let mut help = String::new();
+            let mut group = None;
+            for doc in docs {
+                if group != Some(&doc.group) {
+                    help.push_str(&format!("\n{}\n", doc.group));
+                    group = Some(&doc.group);
+                }
+                help.push_str(&format!("  {:<14} {}\n", doc.name, doc.summary));
+            }
+            help.push_str("\ntype `help <command>` for the usage of a command, `exit` to quit");
+            return help.trim_start().to_string();
+        }
+    };
+
+    match docs.iter().find(|doc| doc.name == topic) {
+        Some(doc) => {
+            // commands without a local usage, like plugin commands, are shown
+            // by name only
+            let usage = usage(&topic)
+                .map(str::to_string)
+                .unwrap_or_else(|| topic.to_uppercase());
+            format!("{}\n  {}\n  group: {}", usage, doc.summary, doc.group)
+        }
+        None if usage(&topic).is_some() => {
+            format!("'{}' is not supported by the connected server", topic)
+        }
+        None => format!("unknown command '{}'", topic),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn docs() -> Vec<Doc> {
+        parse_docs(Frame::Map(vec![
+            Frame::String(Bytes::from("get")),
+            Frame::Map(vec![
+                Frame::String(Bytes::from("group")),
+                Frame::String(Bytes::from("string")),
+                Frame::String(Bytes::from("summary")),
+                Frame::String(Bytes::from("Returns the value of a key")),
+            ]),
+            Frame::String(Bytes::from("mul")),
+            Frame::Map(vec![
+                Frame::String(Bytes::from("group")),
+                Frame::String(Bytes::from("plugin")),
+                Frame::String(Bytes::from("summary")),
+                Frame::String(Bytes::from("Calls 'mul' of the 'math' plugin")),
+            ]),
+        ]))
+    }
+
+    #[test]
+    fn format_help_given_documented_command_merges_usage_and_docs() {
+        assert_eq!(
+            format_help(&docs(), Some("GET")),
+            "GET <keyspace> <key> [WITHVERSION]\n  Returns the value of a key\n  group: string"
+        );
+        assert_eq!(
+            format_help(&docs(), Some("mul")),
+            "MUL\n  Calls 'mul' of the 'math' plugin\n  group: plugin"
+        );
+    }
+
+    #[test]
+    fn format_help_given_command_missing_on_server_returns_unsupported() {
+        assert_eq!(
+            format_help(&docs(), Some("eval")),
+            "'eval' is not supported by the connected server"
+        );
+        assert_eq!(format_help(&docs(), Some("foo")), "unknown command 'foo'");
+    }
+}
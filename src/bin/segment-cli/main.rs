@@ -0,0 +1,315 @@
+use anyhow::Result;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bytes::Bytes;
+use clap::{Parser, ValueEnum};
+use segment::client::Client;
+use segment::frame::{CommandBuilder, Frame};
+use std::io::{self, BufRead, Write};
+
+mod help;
+mod wizard;
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// host of the segment server
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// port of the segment server
+    #[arg(long, default_value_t = 1698)]
+    port: u16,
+
+    /// how strings in replies are displayed
+    #[arg(long, value_enum, default_value_t = Display::Text)]
+    display: Display,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Display {
+    /// quoted text, bytes that aren't printable are escaped as `\xHH`
+    Text,
+    /// `b64:` prefixed base64
+    Base64,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let address = format!("{}:{}", args.host, args.port);
+    let mut client = Client::connect(&address).await?;
+
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("{}> ", address);
+        io::stdout().flush()?;
+
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let tokens = match tokenize_command(&line) {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                println!("(error) {}", e);
+                continue;
+            }
+        };
+        if tokens.is_empty() {
+            continue;
+        }
+        match tokens[0].to_ascii_lowercase().as_slice() {
+            b"quit" | b"exit" => break,
+            b"help" => {
+                let topic = tokens.get(1).map(|topic| String::from_utf8_lossy(topic));
+                let mut command = CommandBuilder::new("command").arg("docs");
+                if let Some(topic) = &topic {
+                    command = command.arg(topic.as_bytes());
+                }
+                let reply = client.send(&command.build()).await?;
+                if let Frame::Error(_) = reply {
+                    println!("{}", format_reply(&reply, args.display, 0));
+                    continue;
+                }
+                let docs = help::parse_docs(reply);
+                println!("{}", help::format_help(&docs, topic.as_deref()));
+                continue;
+            }
+            b":create" => {
+                let options = match wizard::ask_create_options() {
+                    Ok(options) => options,
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(e) => return Err(e.into()),
+                };
+                create_keyspace(&mut client, &options, args.display).await?;
+                continue;
+            }
+            _ => {}
+        }
+
+        let command = CommandBuilder::new(&tokens[0]).args(&tokens[1..]).build();
+        let reply = client.send(&command).await?;
+        println!("{}", format_reply(&reply, args.display, 0));
+    }
+
+    Ok(())
+}
+
+// sends the `CREATE` built by the wizard and echoes the settings the server
+// reports for the new keyspace, defaults included
+async fn create_keyspace(
+    client: &mut Client,
+    options: &wizard::CreateOptions,
+    display: Display,
+) -> Result<()> {
+    let tokens = options.tokens();
+    println!("{}", tokens.join(" "));
+    let reply = client
+        .send(&CommandBuilder::new(&tokens[0]).args(&tokens[1..]).build())
+        .await?;
+    println!("{}", format_reply(&reply, display, 0));
+    if reply != Frame::Boolean(true) {
+        return Ok(());
+    }
+
+    let keyspaces = match client
+        .send(&CommandBuilder::new("keyspaces").build())
+        .await?
+    {
+        Frame::Array(keyspaces) => keyspaces,
+        _ => return Ok(()),
+    };
+    let name = [
+        Frame::String(Bytes::from_static(b"name")),
+        Frame::String(Bytes::copy_from_slice(options.name().as_bytes())),
+    ];
+    for keyspace in keyspaces {
+        if let Frame::Map(fields) = &keyspace {
+            if fields.chunks(2).any(|pair| pair == name) {
+                println!("{}", format_reply(&keyspace, display, 0));
+            }
+        }
+    }
+    Ok(())
+}
+
+// splits a line into tokens on whitespace. a token can be quoted with double
+// quotes, which support `\"`, `\\`, `\n`, `\r`, `\t` and `\xHH` escapes, or
+// with single quotes, which are taken as they are. unquoted tokens prefixed
+// with `b64:` are decoded from base64, so binary values can be typed in.
+fn tokenize_command(line: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let quote = match chars.peek() {
+            None => return Ok(tokens),
+            Some('"') | Some('\'') => chars.next(),
+            Some(_) => None,
+        };
+
+        let mut token = Vec::new();
+        loop {
+            match (chars.next(), quote) {
+                (None, Some(_)) => return Err("unbalanced quotes".to_string()),
+                (None, None) => break,
+                (Some(c), Some(q)) if c == q => {
+                    if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                        return Err("closing quote must be followed by a space".to_string());
+                    }
+                    break;
+                }
+                (Some('\\'), Some('"')) => match chars.next() {
+                    Some('n') => token.push(b'\n'),
+                    Some('r') => token.push(b'\r'),
+                    Some('t') => token.push(b'\t'),
+                    Some('x') => {
+                        let hex: String = chars.by_ref().take(2).collect();
+                        let byte = u8::from_str_radix(&hex, 16)
+                            .ok()
+                            .filter(|_| hex.len() == 2)
+                            .ok_or_else(|| format!("invalid escape '\\x{}'", hex))?;
+                        token.push(byte);
+                    }
+                    Some(c) => push_char(&mut token, c),
+                    None => return Err("unbalanced quotes".to_string()),
+                },
+                (Some(c), None) if c.is_whitespace() => break,
+                (Some(c), _) => push_char(&mut token, c),
+            }
+        }
+
+        if quote.is_none() {
+            if let Some(encoded) = token.strip_prefix(b"b64:") {
+                token = BASE64_STANDARD
+                    .decode(encoded)
+                    .map_err(|e| format!("invalid base64 literal, {}", e))?;
+            }
+        }
+        tokens.push(token);
+    }
+}
+
+fn push_char(token: &mut Vec<u8>, c: char) {
+    token.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+// the text display escapes the bytes the same way `tokenize_command` reads
+// them, so a displayed string can be pasted back as an argument.
+fn format_string(data: &[u8], display: Display) -> String {
+    match display {
+        Display::Text => {
+            let mut text = String::from("\"");
+            for chunk in data.utf8_chunks() {
+                for c in chunk.valid().chars() {
+                    match c {
+                        '"' => text.push_str("\\\""),
+                        '\\' => text.push_str("\\\\"),
+                        '\n' => text.push_str("\\n"),
+                        '\r' => text.push_str("\\r"),
+                        '\t' => text.push_str("\\t"),
+                        c if c.is_control() => {
+                            for byte in c.encode_utf8(&mut [0; 4]).as_bytes() {
+                                text.push_str(&format!("\\x{:02x}", byte));
+                            }
+                        }
+                        c => text.push(c),
+                    }
+                }
+                for byte in chunk.invalid() {
+                    text.push_str(&format!("\\x{:02x}", byte));
+                }
+            }
+            text.push('"');
+            text
+        }
+        Display::Base64 => format!("b64:{}", BASE64_STANDARD.encode(data)),
+    }
+}
+
+fn format_reply(frame: &Frame, display: Display, indent: usize) -> String {
+    match frame {
+        Frame::String(data) => format_string(data, display),
+        Frame::Integer(value) => format!("(integer) {}", value),
+        Frame::Double(value) => format!("(double) {}", value),
+        Frame::Boolean(value) => format!("(boolean) {}", value),
+        Frame::Null => "(nil)".to_string(),
+        Frame::Error(message) => format!("(error) {}", String::from_utf8_lossy(message)),
+        Frame::Array(frames) if frames.is_empty() => "(empty array)".to_string(),
+        Frame::Array(frames) => frames
+            .iter()
+            .enumerate()
+            .map(|(idx, frame)| {
+                format!(
+                    "{:indent$}{}) {}",
+                    "",
+                    idx + 1,
+                    format_reply(frame, display, indent + 3).trim_start(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Frame::Map(frames) if frames.is_empty() => "(empty map)".to_string(),
+        Frame::Map(frames) => frames
+            .chunks(2)
+            .map(|pair| {
+                format!(
+                    "{:indent$}{} => {}",
+                    "",
+                    format_reply(&pair[0], display, indent + 3).trim_start(),
+                    format_reply(&pair[1], display, indent + 3).trim_start(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_command_given_quoted_tokens_returns_tokens() {
+        assert_eq!(
+            tokenize_command("set  foo \"hello \\\"world\\\"\" 'a b'\n").unwrap(),
+            vec![
+                b"set".to_vec(),
+                b"foo".to_vec(),
+                b"hello \"world\"".to_vec(),
+                b"a b".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_command_given_binary_literals_returns_bytes() {
+        assert_eq!(
+            tokenize_command(r#"set "\xff\x00a" b64:/wBh '\xff'"#).unwrap(),
+            vec![
+                b"set".to_vec(),
+                vec![0xff, 0x00, b'a'],
+                vec![0xff, 0x00, b'a'],
+                br"\xff".to_vec()
+            ]
+        );
+        assert!(tokenize_command(r#"set "\xzz""#).is_err());
+        assert!(tokenize_command("set b64:***").is_err());
+    }
+
+    #[test]
+    fn format_string_given_binary_data_returns_tokenizable_text() {
+        let data = [0xff, 0x00, b'"', 0xc3, 0xa9];
+        let text = format_string(&data, Display::Text);
+        assert_eq!(text, r#""\xff\x00\"é""#);
+        assert_eq!(tokenize_command(&text).unwrap(), vec![data.to_vec()]);
+        assert_eq!(format_string(&data[..2], Display::Base64), "b64:/wA=");
+    }
+
+    #[test]
+    fn tokenize_command_given_unbalanced_quotes_returns_error() {
+        assert!(tokenize_command("set foo \"bar").is_err());
+        assert!(tokenize_command("set foo \"bar\"baz").is_err());
+    }
+}
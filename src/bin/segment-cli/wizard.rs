@@ -0,0 +1,180 @@
+use std::io::{self, BufRead, Write};
+
+// the settings of a new keyspace as answered in the `:create` wizard
+#[derive(Debug, PartialEq)]
+pub struct CreateOptions {
+    name: String,
+    evictor: String,
+    sample_size: Option<u8>,
+    ordered: bool,
+    retention: Option<u64>,
+    temp: bool,
+}
+
+impl CreateOptions {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // the tokens of the `CREATE` command, settings left at their default are
+    // left out
+    pub fn tokens(&self) -> Vec<String> {
+        let mut tokens = vec!["CREATE".to_string(), self.name.clone()];
+        if self.evictor != "NOP" {
+            tokens.extend(["EVICTOR".to_string(), self.evictor.clone()]);
+        }
+        if let Some(sample_size) = self.sample_size {
+            tokens.extend(["SAMPLES".to_string(), sample_size.to_string()]);
+        }
+        if self.ordered {
+            tokens.extend(["ORDERED".to_string(), "YES".to_string()]);
+        }
+        if let Some(retention) = self.retention {
+            tokens.extend(["RETENTION".to_string(), retention.to_string()]);
+        }
+        if self.temp {
+            tokens.push("TEMP".to_string());
+        }
+        tokens
+    }
+}
+
+// asks for the settings of a new keyspace one at a time, settings that don't
+// apply to the answers given so far are skipped. invalid answers are asked
+// again, an `UnexpectedEof` error is returned when stdin is closed.
+pub fn ask_create_options() -> io::Result<CreateOptions> {
+    let name = ask("keyspace name", None, parse_name)?;
+    let evictor = ask(
+        "evictor (nop, random, lru, arc, slru, clock, exactlru)",
+        Some("nop"),
+        parse_evictor,
+    )?;
+    let sample_size = if evictor == "NOP" {
+        None
+    } else {
+        ask(
+            "keys sampled per eviction (1-255)",
+            Some("3"),
+            parse_sample_size,
+        )?
+    };
+    let ordered = ask("keep keys ordered (yes, no)", Some("no"), parse_yes_no)?;
+    let retention = if ordered {
+        ask("retention in milliseconds", Some("none"), parse_retention)?
+    } else {
+        None
+    };
+    let temp = ask(
+        "drop the keyspace when this connection closes (yes, no)",
+        Some("no"),
+        parse_yes_no,
+    )?;
+    Ok(CreateOptions {
+        name,
+        evictor,
+        sample_size,
+        ordered,
+        retention,
+        temp,
+    })
+}
+
+fn ask<T>(
+    question: &str,
+    default: Option<&str>,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> io::Result<T> {
+    let mut line = String::new();
+    loop {
+        match default {
+            Some(default) => print!("{} [{}]: ", question, default),
+            None => print!("{}: ", question),
+        }
+        io::stdout().flush()?;
+
+        line.clear();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let answer = match (line.trim(), default) {
+            ("", Some(default)) => default,
+            (answer, _) => answer,
+        };
+        match parse(answer) {
+            Ok(value) => return Ok(value),
+            Err(e) => println!("(error) {}", e),
+        }
+    }
+}
+
+fn parse_name(answer: &str) -> Result<String, String> {
+    if answer.is_empty() {
+        return Err("the keyspace needs a name".to_string());
+    }
+    Ok(answer.to_string())
+}
+
+fn parse_evictor(answer: &str) -> Result<String, String> {
+    match answer.to_lowercase().as_str() {
+        "nop" | "random" | "lru" | "arc" | "slru" | "clock" | "exactlru" => {
+            Ok(answer.to_uppercase())
+        }
+        _ => Err(format!("unknown evictor '{}'", answer)),
+    }
+}
+
+fn parse_sample_size(answer: &str) -> Result<Option<u8>, String> {
+    match answer.parse::<u8>() {
+        Ok(sample_size) if sample_size > 0 => Ok(Some(sample_size)),
+        _ => Err("the number of keys sampled must be between 1 and 255".to_string()),
+    }
+}
+
+fn parse_yes_no(answer: &str) -> Result<bool, String> {
+    match answer.to_lowercase().as_str() {
+        "yes" | "y" => Ok(true),
+        "no" | "n" => Ok(false),
+        _ => Err("answer with yes or no".to_string()),
+    }
+}
+
+fn parse_retention(answer: &str) -> Result<Option<u64>, String> {
+    if answer.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    match answer.parse::<u64>() {
+        Ok(retention) if retention > 0 => Ok(Some(retention)),
+        _ => Err("the retention must be a positive number of milliseconds".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_given_options_returns_create_command() {
+        let options = CreateOptions {
+            name: "foo".to_string(),
+            evictor: parse_evictor("lru").unwrap(),
+            sample_size: parse_sample_size("5").unwrap(),
+            ordered: true,
+            retention: parse_retention("60000").unwrap(),
+            temp: false,
+        };
+        assert_eq!(
+            options.tokens().join(" "),
+            "CREATE foo EVICTOR LRU SAMPLES 5 ORDERED YES RETENTION 60000"
+        );
+    }
+
+    #[test]
+    fn parse_given_invalid_answers_returns_error() {
+        assert!(parse_name("").is_err());
+        assert!(parse_evictor("lfu").is_err());
+        assert!(parse_sample_size("0").is_err());
+        assert!(parse_sample_size("256").is_err());
+        assert!(parse_yes_no("maybe").is_err());
+        assert!(parse_retention("-1").is_err());
+    }
+}
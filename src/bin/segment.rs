@@ -1,32 +1,88 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
 use segment::config::ServerConfig;
-use segment::server;
+use segment::{crash, doctor, server};
 use tokio::net::TcpListener;
-use tracing::Level;
+use tokio::task::JoinSet;
+use tracing::{Instrument, Level};
 
 #[derive(Debug, Parser)]
 struct Args {
-    /// path to segment config file
+    /// path to segment config file, repeat it to run several independent
+    /// servers in one process
     #[arg(long, default_value = "segment.conf")]
-    config: String,
+    config: Vec<String>,
 
     /// start the server in debug mode
     #[arg(long)]
     debug: bool,
+
+    /// check the config and the environment, print a readiness report and exit
+    #[arg(long)]
+    doctor: bool,
+
+    /// directory of the snapshot, overrides `data_dir` of the config
+    #[arg(long)]
+    data_dir: Option<String>,
+
+    /// restore the keyspaces from the snapshot in the data directory at startup
+    #[arg(long)]
+    load_snapshot: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    // servers sharing a data directory would overwrite each other's snapshot
+    if args.data_dir.is_some() && args.config.len() > 1 {
+        anyhow::bail!("--data-dir can't be used with more than one --config");
+    }
+    if args.doctor {
+        let mut ready = true;
+        for path in &args.config {
+            let report = doctor::run(path).await;
+            println!("{}", report);
+            ready &= report.is_ready();
+        }
+        if !ready {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     let mut log_level = Level::INFO;
     if args.debug {
         log_level = Level::DEBUG;
     }
     let subscriber = tracing_subscriber::fmt().with_max_level(log_level).finish();
     tracing::subscriber::set_global_default(subscriber)?;
-    let cfg = ServerConfig::load_from_disk(&args.config)?;
-    let ln = TcpListener::bind(format!("{}:{}", cfg.bind(), cfg.port())).await?;
-    server::start(ln, cfg).await?;
+
+    // every config is loaded and bound before any server starts, so a bad
+    // config doesn't leave the other servers running on their own
+    let mut instances = Vec::new();
+    for path in &args.config {
+        let mut cfg = ServerConfig::load_from_disk(path)
+            .with_context(|| format!("unable to load config '{}'", path))?;
+        if let Some(data_dir) = &args.data_dir {
+            cfg.set_data_dir(data_dir.clone());
+        }
+        cfg.set_load_snapshot(args.load_snapshot);
+        let addr = format!("{}:{}", cfg.bind(), cfg.port());
+        let ln = TcpListener::bind(&addr)
+            .await
+            .with_context(|| format!("unable to listen on {} from config '{}'", addr, path))?;
+        instances.push((ln, cfg));
+    }
+    // crash reports are written where the first config says
+    crash::install(&instances[0].1);
+
+    let mut servers = JoinSet::new();
+    for (ln, cfg) in instances {
+        let span = tracing::info_span!("server", port = cfg.port());
+        servers.spawn(server::start(ln, cfg).instrument(span));
+    }
+    // the servers all stop on ctrl-c, an error stops the whole process
+    while let Some(res) = servers.join_next().await {
+        res??;
+    }
     Ok(())
 }
@@ -0,0 +1,51 @@
+use crate::connection::Connection;
+use crate::frame::Frame;
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+pub use crate::connection::ConnectionError;
+
+// buffer size of client connections, replies that don't fit grow the buffer
+const CLIENT_BUFFER_SIZE: usize = 4096;
+// arguments longer than this are sent as chunked strings
+const CLIENT_CHUNK_SIZE: usize = 64 * 1024;
+
+// a connection to a segment server which sends one command at a time and waits
+// for its reply.
+#[derive(Debug)]
+pub struct Client {
+    connection: Connection<TcpStream>,
+    // commands sent whose reply wasn't read yet
+    unanswered: usize,
+}
+
+impl Client {
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self, ConnectionError> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Client {
+            connection: Connection::new(stream, CLIENT_BUFFER_SIZE)
+                .with_chunk_size(CLIENT_CHUNK_SIZE),
+            unanswered: 0,
+        })
+    }
+
+    // sends the command and returns its reply, errors returned by the server
+    // are replies too and come back as `Frame::Error`. when a previous send
+    // was cancelled before its reply was read, that reply is skipped so the
+    // reply returned always belongs to this command.
+    pub async fn send(&mut self, command: &Frame) -> Result<Frame, ConnectionError> {
+        self.connection.queue_frame(command)?;
+        self.unanswered += 1;
+        self.connection.flush().await?;
+        loop {
+            let reply = self
+                .connection
+                .read_frame()
+                .await?
+                .ok_or(ConnectionError::Reset)?;
+            self.unanswered -= 1;
+            if self.unanswered == 0 {
+                return Ok(reply);
+            }
+        }
+    }
+}
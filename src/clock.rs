@@ -0,0 +1,84 @@
+use parking_lot::Mutex;
+use std::fmt::Debug;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// where keyspaces take the time from, for expiry deadlines, when values were
+// last accessed and when the evictors queued keys. the server runs on
+// `SystemClock`, tests and embedders can pass a `MockClock` to `Db::with_clock`
+// and move time forward by hand instead of sleeping.
+pub trait Clock: Debug + Send + Sync {
+    // the time since the unix epoch, expiry deadlines are kept in it
+    fn now(&self) -> Duration;
+
+    // a monotonic instant, for measuring how long ago something happened
+    fn instant(&self) -> Instant;
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        // a system clock set before 1970 is taken as the epoch itself
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// a clock which only moves when it is advanced, it starts out at the time it
+// was created
+#[derive(Debug)]
+pub struct MockClock {
+    started_at: (Duration, Instant),
+    elapsed: Mutex<Duration>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        let clock = SystemClock;
+        MockClock {
+            started_at: (clock.now(), clock.instant()),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock() += by;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        self.started_at.0 + *self.elapsed.lock()
+    }
+
+    fn instant(&self) -> Instant {
+        self.started_at.1 + *self.elapsed.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_given_mock_clock_moves_both_times() {
+        let clock = MockClock::new();
+        let (now, instant) = (clock.now(), clock.instant());
+        assert_eq!(clock.now(), now);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(clock.now() - now, Duration::from_secs(5));
+        assert_eq!(clock.instant() - instant, Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,198 @@
+#[cfg(feature = "wasm")]
+use super::PLUGIN_COMMANDS;
+use crate::frame::Frame;
+use bytes::Bytes;
+
+// the commands the server understands along with a one line summary, grouped
+// by what they work on. see `COMMAND DOCS`.
+const DOCS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "keyspace",
+        &[
+            ("create", "Creates a new keyspace"),
+            ("alter", "Changes the evictor of a keyspace"),
+            ("drop", "Drops a keyspace and its keys"),
+            ("keyspaces", "Lists the keyspaces"),
+            ("flushall", "Removes the keys of all keyspaces"),
+            ("count", "Returns the number of keys"),
+            ("range", "Returns a range of an ordered keyspace"),
+            ("delrange", "Removes a range of an ordered keyspace"),
+            ("countbyprefix", "Counts the keys with a prefix"),
+            ("sample", "Returns keys picked at random with their values"),
+            ("evictstats", "Returns what the evictor of a keyspace did"),
+            ("evictor", "Pauses or resumes the evictor of a keyspace"),
+            ("sum", "Sums the integer values"),
+            ("avg", "Averages the integer values"),
+        ],
+    ),
+    (
+        "string",
+        &[
+            ("set", "Sets a key to a value"),
+            ("mset", "Sets several keys at once"),
+            ("cset", "Sets a key if its version matches"),
+            ("get", "Returns the value of a key"),
+            ("xget", "Returns keys from several keyspaces"),
+            ("del", "Removes a key"),
+            ("ttl", "Returns the time to live of a key"),
+            ("expireat", "Sets the expiry of a key to a point in time"),
+            ("pin", "Keeps a key from being evicted"),
+            ("unpin", "Lets a pinned key be evicted again"),
+            ("undelete", "Restores a recently deleted key"),
+            ("dump", "Serializes a key to move it to another instance"),
+            ("restore", "Recreates a key from what dump returned"),
+            ("type", "Returns the type of the value of a key"),
+            ("setbit", "Sets or clears a bit of a value"),
+            ("getbit", "Returns a bit of a value"),
+            ("bitcount", "Counts the set bits of a value"),
+            ("getrange", "Returns a range of bytes of a value"),
+            ("setrange", "Overwrites part of a value"),
+        ],
+    ),
+    (
+        "list",
+        &[
+            ("lpush", "Pushes values to the front of a list"),
+            ("rpush", "Pushes values to the back of a list"),
+            ("lpop", "Pops the first value of a list"),
+            ("rpop", "Pops the last value of a list"),
+            ("lrange", "Returns a range of a list"),
+            ("llen", "Returns the length of a list"),
+        ],
+    ),
+    (
+        "hash",
+        &[
+            ("hset", "Sets fields of a hash"),
+            ("hget", "Returns a field of a hash"),
+            ("hdel", "Removes fields from a hash"),
+            ("hgetall", "Returns all the fields of a hash"),
+        ],
+    ),
+    (
+        "sorted set",
+        &[
+            ("zadd", "Adds members to a sorted set"),
+            ("zrange", "Returns a range of a sorted set by rank"),
+            ("zscore", "Returns the score of a member"),
+            ("zrem", "Removes members from a sorted set"),
+        ],
+    ),
+    (
+        "stream",
+        &[
+            ("xadd", "Appends an entry to a stream"),
+            ("xrange", "Returns a range of a stream by id"),
+            ("xlen", "Returns the number of entries of a stream"),
+        ],
+    ),
+    (
+        "transaction",
+        &[
+            ("watch", "Aborts the transaction if a key changes"),
+            ("unwatch", "Forgets the watched keys"),
+            ("multi", "Starts a transaction"),
+            ("exec", "Runs the queued commands"),
+            ("discard", "Drops the queued commands"),
+            ("batch", "Runs a batch of commands at most once"),
+        ],
+    ),
+    ("view", &[("view", "Manages frozen copies of keyspaces")]),
+    (
+        "scheduling",
+        &[
+            ("schedule", "Runs a command on a cron schedule"),
+            ("delay", "Runs a command after a delay"),
+        ],
+    ),
+    (
+        "server",
+        &[
+            ("watchkey", "Streams the events of a key"),
+            (
+                "watchlifecycle",
+                "Streams the lifecycle events of the server",
+            ),
+            ("use", "Picks the keyspace of GET, SET and DEL"),
+            ("ping", "Pings the server"),
+            ("echo", "Echoes the message back"),
+            ("info", "Returns information about the server"),
+            ("client", "Returns information about this connection"),
+            ("memory", "Estimates the memory taken by a key"),
+            ("object", "Returns the metadata of a key"),
+            ("command", "Returns the docs of the commands"),
+            ("explain", "Describes a command without running it"),
+            (
+                "debug",
+                "Fills or checks a keyspace or traces the connection",
+            ),
+            ("tasks", "Lists or cancels long running commands"),
+            ("save", "Writes a snapshot of the keyspaces"),
+            ("bgsave", "Writes a snapshot in the background"),
+            ("export", "Writes the keys of a keyspace as json or csv"),
+            ("fsync", "Waits until the append only file is synced"),
+        ],
+    ),
+    #[cfg(feature = "lua")]
+    ("scripting", &[("eval", "Runs a lua script atomically")]),
+    #[cfg(feature = "wasm")]
+    (
+        "scripting",
+        &[
+            ("function", "Manages wasm libraries"),
+            ("fcall", "Calls a function of a wasm library"),
+        ],
+    ),
+];
+
+// returns a map of command names to their docs, all the commands are returned
+// when no names are given. names the server doesn't know are left out.
+pub fn reply(names: &[String]) -> Frame {
+    let docs = DOCS.iter().flat_map(|(group, commands)| {
+        commands
+            .iter()
+            .map(|(name, summary)| (name.to_string(), *group, summary.to_string()))
+    });
+    #[cfg(feature = "wasm")]
+    let docs = docs.chain(plugin_docs());
+
+    let mut map = Vec::new();
+    for (name, group, summary) in docs {
+        if !names.is_empty() && !names.contains(&name) {
+            continue;
+        }
+        map.push(Frame::String(Bytes::from(name)));
+        map.push(Frame::Map(vec![
+            Frame::String(Bytes::from_static(b"group")),
+            Frame::String(Bytes::copy_from_slice(group.as_bytes())),
+            Frame::String(Bytes::from_static(b"summary")),
+            Frame::String(Bytes::from(summary)),
+        ]));
+    }
+    Frame::Map(map)
+}
+
+// plugin commands shadowed by built in commands can't be called, so they are
+// left out.
+#[cfg(feature = "wasm")]
+fn plugin_docs() -> Vec<(String, &'static str, String)> {
+    let mut docs: Vec<_> = PLUGIN_COMMANDS
+        .read()
+        .iter()
+        .filter(|(name, _)| {
+            !DOCS
+                .iter()
+                .any(|(_, commands)| commands.iter().any(|(known, _)| known == name))
+        })
+        .map(|(name, (library, function))| {
+            let summary = format!(
+                "Calls '{}' of the '{}' plugin",
+                String::from_utf8_lossy(function),
+                String::from_utf8_lossy(library)
+            );
+            (name.clone(), "plugin", summary)
+        })
+        .collect();
+    docs.sort();
+    docs
+}
@@ -1,16 +1,37 @@
-use crate::db::Evictor;
+use crate::db::{Evictor, KeyspaceConfig};
+use crate::export::ExportFormat;
 use crate::frame::Frame;
+use crate::snapshot;
+use crate::stream::StreamId;
+use crate::upstream::UpstreamConfig;
 use bytes::Bytes;
+use cron::Schedule;
+#[cfg(feature = "wasm")]
+use parking_lot::RwLock;
+#[cfg(feature = "wasm")]
+use std::collections::HashMap;
 use std::iter::Peekable;
-use std::ops::Add;
-use std::str::{self, Utf8Error};
-use std::time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH};
+use std::mem;
+use std::str::{self, FromStr, Utf8Error};
+#[cfg(feature = "wasm")]
+use std::sync::LazyLock;
+use std::time::Duration;
 use std::vec::IntoIter;
 use thiserror::Error;
 
+pub mod docs;
+pub mod registry;
 #[cfg(test)]
 mod test;
 
+// the largest value a single command can grow a value to
+const MAX_VALUE_SIZE: u64 = 512 * 1024 * 1024;
+const MAX_BIT_OFFSET: u64 = MAX_VALUE_SIZE * 8;
+// how long values fetched from an upstream are cached, in milliseconds
+const DEFAULT_UPSTREAM_CACHE_TTL: u64 = 60_000;
+// the most keys a single `SAMPLE` can return
+const MAX_SAMPLE_COUNT: usize = 1000;
+
 #[derive(Debug)]
 struct Parser {
     tokens: Peekable<IntoIter<Frame>>,
@@ -21,6 +42,26 @@ pub struct Create {
     keyspace: Bytes,
     evictor: Evictor,
     if_not_exists: bool,
+    temp: bool,
+    ordered: bool,
+    retention: Option<u64>,
+    sample_size: u8,
+    upstream: Option<UpstreamConfig>,
+    canary_of: Option<Bytes>,
+    max_memory: u64,
+    max_keys: u64,
+    tombstone: Option<u64>,
+    spill: bool,
+    weight: u32,
+}
+
+// `ALTER`, settings left out are kept as they are
+#[derive(Debug, PartialEq)]
+pub struct Alter {
+    keyspace: Bytes,
+    evictor: Option<Evictor>,
+    sample_size: Option<u8>,
+    weight: Option<u32>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -29,14 +70,37 @@ pub struct Set {
     key: Bytes,
     value: Bytes,
     expire_at: Option<u64>,
+    // milliseconds, resolved to a deadline by the keyspace clock when the
+    // command runs
+    expire_after: Option<u64>,
     if_not_exists: bool,
     if_exists: bool,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct MSet {
+    keyspace: Bytes,
+    pairs: Vec<(Bytes, Bytes)>,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Get {
     keyspace: Bytes,
     key: Bytes,
+    with_version: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CSet {
+    keyspace: Bytes,
+    key: Bytes,
+    value: Bytes,
+    version: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct XGet {
+    keys: Vec<(Bytes, Bytes)>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -56,496 +120,4533 @@ pub struct Count {
     keyspace: Bytes,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct Range {
+    keyspace: Bytes,
+    start: Bytes,
+    end: Bytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DelRange {
+    keyspace: Bytes,
+    start: Bytes,
+    end: Bytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CountByPrefix {
+    keyspace: Bytes,
+    prefix: Bytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EvictStats {
+    keyspace: Bytes,
+}
+
+// `EVICTOR PAUSE` and `EVICTOR RESUME`
+#[derive(Debug, PartialEq)]
+pub struct EvictorControl {
+    keyspace: Bytes,
+}
+
+// `SAMPLE`, spot-checks `count` keys picked at random
+#[derive(Debug, PartialEq)]
+pub struct Sample {
+    keyspace: Bytes,
+    count: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Sum {
+    keyspace: Bytes,
+    pattern: Option<Bytes>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Avg {
+    keyspace: Bytes,
+    pattern: Option<Bytes>,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Ttl {
     keyspace: Bytes,
     key: Bytes,
 }
 
+// `timestamp` is a unix timestamp in milliseconds
 #[derive(Debug, PartialEq)]
-pub enum Command {
-    Create(Create),
-    Set(Set),
-    Get(Get),
-    Del(Del),
-    Drop(Drop),
-    Count(Count),
-    Ttl(Ttl),
-    Ping,
-    Keyspaces,
+pub struct ExpireAt {
+    keyspace: Bytes,
+    key: Bytes,
+    timestamp: u64,
 }
 
-#[derive(Debug, Error)]
-pub enum ParseCommandError {
-    #[error("invalid command format")]
-    InvalidFormat,
+#[derive(Debug, PartialEq)]
+pub struct Type {
+    keyspace: Bytes,
+    key: Bytes,
+}
 
-    #[error("wrong number of arguments for '{0}' command")]
-    WrongArgCount(String),
+#[derive(Debug, PartialEq)]
+pub struct MemoryUsage {
+    keyspace: Bytes,
+    key: Bytes,
+}
 
-    #[error(transparent)]
-    Utf8Error(#[from] Utf8Error),
+// `PIN` and `UNPIN`
+#[derive(Debug, PartialEq)]
+pub struct Pin {
+    keyspace: Bytes,
+    key: Bytes,
+}
 
-    #[error("invalid argument '{0}' for '{1}' command")]
-    InvalidArg(String, String),
+#[derive(Debug, PartialEq)]
+pub struct Undelete {
+    keyspace: Bytes,
+    key: Bytes,
+}
 
-    #[error("invalid value '{0}' for argument '{1}' for '{2}' command")]
-    InvalidArgValue(String, String, String),
+#[derive(Debug, PartialEq)]
+pub struct Dump {
+    keyspace: Bytes,
+    key: Bytes,
+}
 
-    #[error(transparent)]
-    SystemTimeError(#[from] SystemTimeError),
+// `payload` is what `DUMP` returned, `replace` overwrites a key that exists
+#[derive(Debug, PartialEq)]
+pub struct Restore {
+    keyspace: Bytes,
+    key: Bytes,
+    payload: Bytes,
+    replace: bool,
+    // seconds, in place of the time to live of the payload, see
+    // `Command::resolve_expiry`
+    expire_at: Option<u64>,
+}
 
-    #[error("unknown command '{0}'")]
-    UnknownCommand(String),
+// `OBJECT IDLETIME` and `OBJECT META`
+#[derive(Debug, PartialEq)]
+pub struct Object {
+    keyspace: Bytes,
+    key: Bytes,
 }
 
-impl Parser {
-    pub fn new(frame: Frame) -> Result<Self, ParseCommandError> {
-        match frame {
-            Frame::Array(tokens) => Ok(Parser {
-                tokens: tokens.into_iter().peekable(),
-            }),
-            _ => Err(ParseCommandError::InvalidFormat),
-        }
-    }
+// `LPUSH` and `RPUSH`, the values are pushed one after the other
+#[derive(Debug, PartialEq)]
+pub struct Push {
+    keyspace: Bytes,
+    key: Bytes,
+    values: Vec<Bytes>,
+}
 
-    pub fn next(&mut self) -> Option<Frame> {
-        self.tokens.next()
-    }
+// `LPOP` and `RPOP`
+#[derive(Debug, PartialEq)]
+pub struct Pop {
+    keyspace: Bytes,
+    key: Bytes,
+}
 
-    pub fn has_remaining(&mut self) -> bool {
-        self.tokens.peek().is_some()
-    }
+#[derive(Debug, PartialEq)]
+pub struct LRange {
+    keyspace: Bytes,
+    key: Bytes,
+    start: i64,
+    stop: i64,
+}
 
-    pub fn next_as_string(&mut self) -> Result<Option<String>, ParseCommandError> {
-        let frame = match self.next() {
-            Some(frame) => frame,
-            None => return Ok(None),
-        };
+#[derive(Debug, PartialEq)]
+pub struct LLen {
+    keyspace: Bytes,
+    key: Bytes,
+}
 
-        match frame {
-            Frame::String(data) => Ok(Some(str::from_utf8(&data[..])?.to_string())),
-            _ => Err(ParseCommandError::InvalidFormat),
-        }
-    }
+#[derive(Debug, PartialEq)]
+pub struct HSet {
+    keyspace: Bytes,
+    key: Bytes,
+    pairs: Vec<(Bytes, Bytes)>,
+}
 
-    pub fn next_as_bytes(&mut self) -> Result<Option<Bytes>, ParseCommandError> {
-        let frame = match self.next() {
-            Some(frame) => frame,
-            None => return Ok(None),
-        };
+#[derive(Debug, PartialEq)]
+pub struct HGet {
+    keyspace: Bytes,
+    key: Bytes,
+    field: Bytes,
+}
 
-        match frame {
-            Frame::String(data) => Ok(Some(data)),
-            _ => Err(ParseCommandError::InvalidFormat),
-        }
-    }
+#[derive(Debug, PartialEq)]
+pub struct HDel {
+    keyspace: Bytes,
+    key: Bytes,
+    fields: Vec<Bytes>,
 }
 
-impl Create {
-    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
-        let keyspace = parser
-            .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?;
+#[derive(Debug, PartialEq)]
+pub struct HGetAll {
+    keyspace: Bytes,
+    key: Bytes,
+}
 
-        let mut command = Create {
-            keyspace,
-            evictor: Evictor::Nop,
-            if_not_exists: false,
-        };
+#[derive(Debug, PartialEq)]
+pub struct ZAdd {
+    keyspace: Bytes,
+    key: Bytes,
+    pairs: Vec<(f64, Bytes)>,
+}
 
-        if !parser.has_remaining() {
-            return Ok(command);
-        };
+#[derive(Debug, PartialEq)]
+pub struct ZRange {
+    keyspace: Bytes,
+    key: Bytes,
+    start: i64,
+    stop: i64,
+    with_scores: bool,
+}
 
-        while parser.has_remaining() {
-            let token = parser
-                .next_as_string()?
-                .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?
-                .to_lowercase();
+#[derive(Debug, PartialEq)]
+pub struct ZScore {
+    keyspace: Bytes,
+    key: Bytes,
+    member: Bytes,
+}
 
-            if matches!(token.as_str(), "evictor") {
-                let value = parser
-                    .next_as_string()?
-                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?
-                    .to_lowercase();
-                match value.as_str() {
-                    "nop" => command.evictor = Evictor::Nop,
-                    "random" => command.evictor = Evictor::Random,
-                    "lru" => command.evictor = Evictor::Lru,
-                    _ => {
-                        return Err(ParseCommandError::InvalidArgValue(
-                            value,
-                            token,
-                            "create".to_string(),
-                        ))
-                    }
-                }
-            } else if matches!(token.as_str(), "if") {
-                let not_token = parser
-                    .next_as_string()?
-                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?
-                    .to_lowercase();
-                let exists_token = parser
-                    .next_as_string()?
-                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?
-                    .to_lowercase();
+#[derive(Debug, PartialEq)]
+pub struct ZRem {
+    keyspace: Bytes,
+    key: Bytes,
+    members: Vec<Bytes>,
+}
 
-                if !matches!(not_token.as_str(), "not") {
-                    return Err(ParseCommandError::InvalidArg(
-                        not_token,
-                        "create".to_string(),
-                    ));
-                }
+#[derive(Debug, PartialEq)]
+pub struct SetBit {
+    keyspace: Bytes,
+    key: Bytes,
+    offset: u64,
+    bit: bool,
+}
 
-                if !matches!(exists_token.as_str(), "exists") {
-                    return Err(ParseCommandError::InvalidArg(
-                        exists_token,
-                        "create".to_string(),
-                    ));
-                }
+#[derive(Debug, PartialEq)]
+pub struct GetBit {
+    keyspace: Bytes,
+    key: Bytes,
+    offset: u64,
+}
 
-                if !command.if_not_exists {
-                    command.if_not_exists = true
-                } else {
-                    return Err(ParseCommandError::InvalidFormat);
-                }
-            } else {
-                return Err(ParseCommandError::InvalidArg(token, "create".to_string()));
-            }
+// the range is a range of bytes, not bits
+#[derive(Debug, PartialEq)]
+pub struct BitCount {
+    keyspace: Bytes,
+    key: Bytes,
+    range: Option<(i64, i64)>,
+}
+
+// the range is inclusive
+#[derive(Debug, PartialEq)]
+pub struct GetRange {
+    keyspace: Bytes,
+    key: Bytes,
+    start: i64,
+    end: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SetRange {
+    keyspace: Bytes,
+    key: Bytes,
+    offset: u64,
+    value: Bytes,
+}
+
+// the id is generated by the server when it is `None`
+#[derive(Debug, PartialEq)]
+pub struct XAdd {
+    keyspace: Bytes,
+    key: Bytes,
+    id: Option<StreamId>,
+    fields: Vec<(Bytes, Bytes)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct XRange {
+    keyspace: Bytes,
+    key: Bytes,
+    start: StreamId,
+    end: StreamId,
+    count: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct XLen {
+    keyspace: Bytes,
+    key: Bytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Ping {
+    message: Option<Bytes>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Echo {
+    message: Bytes,
+}
+
+// `USE`, `None` stops using a keyspace
+#[derive(Debug, PartialEq)]
+pub struct Use {
+    keyspace: Option<Bytes>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WatchKey {
+    keyspace: Bytes,
+    key: Bytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Watch {
+    keyspace: Bytes,
+    key: Bytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Batch {
+    token: Bytes,
+    commands: Vec<Command>,
+}
+
+// `path` is relative to the data dir unless it is absolute
+#[derive(Debug, PartialEq)]
+pub struct Export {
+    keyspace: Bytes,
+    path: String,
+    format: ExportFormat,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FlushAll {
+    drop: bool,
+    async_: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ScheduleAdd {
+    name: Bytes,
+    schedule: Schedule,
+    command: Vec<Bytes>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ScheduleRemove {
+    name: Bytes,
+}
+
+// `TASKS STATUS` and `TASKS CANCEL`
+#[derive(Debug, PartialEq)]
+pub struct TaskId {
+    id: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ViewCreate {
+    view: Bytes,
+    keyspace: Bytes,
+}
+
+// `COMMAND DOCS`, the names are lowercase
+#[derive(Debug, PartialEq)]
+pub struct CommandDocs {
+    names: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ViewDrop {
+    view: Bytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DebugPopulate {
+    keyspace: Bytes,
+    count: u64,
+    size: Option<usize>,
+}
+
+// `DEBUG QUICKCHECK`
+#[derive(Debug, PartialEq)]
+pub struct DebugQuickCheck {
+    keyspace: Bytes,
+}
+
+// `DEBUG PROTOCOL ON|OFF`, traces the raw bytes of the connection
+#[derive(Debug, PartialEq)]
+pub struct DebugProtocol {
+    enabled: bool,
+}
+
+// `run_at` is in milliseconds since the unix epoch, given by `DELAY AT` in
+// place of the delay. `DELAY DONE` is appended to the append only file once
+// the delayed command ran, see `Command::resolve_expiry`.
+#[derive(Debug, PartialEq)]
+pub struct Delay {
+    delay: u64,
+    run_at: Option<u64>,
+    done: bool,
+    command: Vec<Bytes>,
+}
+
+// `EXPLAIN`, the explained command is parsed but never executed
+#[derive(Debug, PartialEq)]
+pub struct Explain {
+    command: Box<Command>,
+}
+
+#[cfg(feature = "lua")]
+#[derive(Debug, PartialEq)]
+pub struct Eval {
+    script: Bytes,
+    args: Vec<Bytes>,
+}
+
+#[cfg(feature = "wasm")]
+#[derive(Debug, PartialEq)]
+pub struct FunctionLoad {
+    library: Bytes,
+    module: Bytes,
+}
+
+#[cfg(feature = "wasm")]
+#[derive(Debug, PartialEq)]
+pub struct FunctionDelete {
+    library: Bytes,
+}
+
+#[cfg(feature = "wasm")]
+#[derive(Debug, PartialEq)]
+pub struct Fcall {
+    library: Bytes,
+    function: Bytes,
+    args: Vec<i64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Create(Create),
+    Alter(Alter),
+    Set(Set),
+    MSet(MSet),
+    CSet(CSet),
+    Get(Get),
+    XGet(XGet),
+    Del(Del),
+    Drop(Drop),
+    Count(Count),
+    Range(Range),
+    DelRange(DelRange),
+    CountByPrefix(CountByPrefix),
+    Sample(Sample),
+    EvictStats(EvictStats),
+    EvictorPause(EvictorControl),
+    EvictorResume(EvictorControl),
+    Sum(Sum),
+    Avg(Avg),
+    Ttl(Ttl),
+    ExpireAt(ExpireAt),
+    Type(Type),
+    MemoryUsage(MemoryUsage),
+    ObjectIdleTime(Object),
+    ObjectMeta(Object),
+    Pin(Pin),
+    Unpin(Pin),
+    Undelete(Undelete),
+    Dump(Dump),
+    Restore(Restore),
+    LPush(Push),
+    RPush(Push),
+    LPop(Pop),
+    RPop(Pop),
+    LRange(LRange),
+    LLen(LLen),
+    HSet(HSet),
+    HGet(HGet),
+    HDel(HDel),
+    HGetAll(HGetAll),
+    ZAdd(ZAdd),
+    ZRange(ZRange),
+    ZScore(ZScore),
+    ZRem(ZRem),
+    SetBit(SetBit),
+    GetBit(GetBit),
+    BitCount(BitCount),
+    GetRange(GetRange),
+    SetRange(SetRange),
+    XAdd(XAdd),
+    XRange(XRange),
+    XLen(XLen),
+    Ping(Ping),
+    Echo(Echo),
+    Info,
+    ClientInfo,
+    Keyspaces,
+    FlushAll(FlushAll),
+    Save,
+    BgSave,
+    Export(Export),
+    Fsync,
+    ScheduleAdd(ScheduleAdd),
+    ScheduleRemove(ScheduleRemove),
+    ScheduleList,
+    TasksList,
+    TasksStatus(TaskId),
+    TasksCancel(TaskId),
+    Delay(Delay),
+    Explain(Explain),
+    WatchKey(WatchKey),
+    WatchLifecycle,
+    Use(Use),
+    Watch(Watch),
+    Unwatch,
+    Multi,
+    Exec,
+    Discard,
+    Batch(Batch),
+    ViewCreate(ViewCreate),
+    ViewDrop(ViewDrop),
+    ViewList,
+    DebugPopulate(DebugPopulate),
+    DebugQuickCheck(DebugQuickCheck),
+    DebugProtocol(DebugProtocol),
+    CommandDocs(CommandDocs),
+    #[cfg(feature = "lua")]
+    Eval(Eval),
+    #[cfg(feature = "wasm")]
+    FunctionLoad(FunctionLoad),
+    #[cfg(feature = "wasm")]
+    FunctionDelete(FunctionDelete),
+    #[cfg(feature = "wasm")]
+    FunctionList,
+    #[cfg(feature = "wasm")]
+    Fcall(Fcall),
+}
+
+#[derive(Debug, Error)]
+pub enum ParseCommandError {
+    #[error("invalid command format")]
+    InvalidFormat,
+
+    #[error("wrong number of arguments for '{0}' command")]
+    WrongArgCount(String),
+
+    #[error(transparent)]
+    Utf8Error(#[from] Utf8Error),
+
+    #[error("invalid argument '{0}' for '{1}' command")]
+    InvalidArg(String, String),
+
+    #[error("invalid value '{0}' for argument '{1}' for '{2}' command")]
+    InvalidArgValue(String, String, String),
+
+    #[error("unknown command '{0}'")]
+    UnknownCommand(String),
+
+    #[error("too many arguments for '{0}' command, at most {1} are allowed")]
+    TooManyArgs(String, usize),
+
+    #[error("request of {0} bytes is over the limit of {1} bytes")]
+    RequestTooLarge(usize, usize),
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Create(_) => "create",
+            Command::Alter(_) => "alter",
+            Command::Set(_) => "set",
+            Command::MSet(_) => "mset",
+            Command::CSet(_) => "cset",
+            Command::Get(_) => "get",
+            Command::XGet(_) => "xget",
+            Command::Del(_) => "del",
+            Command::Drop(_) => "drop",
+            Command::Count(_) => "count",
+            Command::Range(_) => "range",
+            Command::DelRange(_) => "delrange",
+            Command::CountByPrefix(_) => "countbyprefix",
+            Command::Sample(_) => "sample",
+            Command::EvictStats(_) => "evictstats",
+            Command::EvictorPause(_) | Command::EvictorResume(_) => "evictor",
+            Command::Sum(_) => "sum",
+            Command::Avg(_) => "avg",
+            Command::Ttl(_) => "ttl",
+            Command::ExpireAt(_) => "expireat",
+            Command::Type(_) => "type",
+            Command::MemoryUsage(_) => "memory",
+            Command::ObjectIdleTime(_) | Command::ObjectMeta(_) => "object",
+            Command::Pin(_) => "pin",
+            Command::Unpin(_) => "unpin",
+            Command::Undelete(_) => "undelete",
+            Command::Dump(_) => "dump",
+            Command::Restore(_) => "restore",
+            Command::LPush(_) => "lpush",
+            Command::RPush(_) => "rpush",
+            Command::LPop(_) => "lpop",
+            Command::RPop(_) => "rpop",
+            Command::LRange(_) => "lrange",
+            Command::LLen(_) => "llen",
+            Command::HSet(_) => "hset",
+            Command::HGet(_) => "hget",
+            Command::HDel(_) => "hdel",
+            Command::HGetAll(_) => "hgetall",
+            Command::ZAdd(_) => "zadd",
+            Command::ZRange(_) => "zrange",
+            Command::ZScore(_) => "zscore",
+            Command::ZRem(_) => "zrem",
+            Command::SetBit(_) => "setbit",
+            Command::GetBit(_) => "getbit",
+            Command::BitCount(_) => "bitcount",
+            Command::GetRange(_) => "getrange",
+            Command::SetRange(_) => "setrange",
+            Command::XAdd(_) => "xadd",
+            Command::XRange(_) => "xrange",
+            Command::XLen(_) => "xlen",
+            Command::Ping(_) => "ping",
+            Command::Echo(_) => "echo",
+            Command::Info => "info",
+            Command::ClientInfo => "client",
+            Command::Keyspaces => "keyspaces",
+            Command::FlushAll(_) => "flushall",
+            Command::Save => "save",
+            Command::Export(_) => "export",
+            Command::BgSave => "bgsave",
+            Command::Fsync => "fsync",
+            Command::ScheduleAdd(_) | Command::ScheduleRemove(_) | Command::ScheduleList => {
+                "schedule"
+            }
+            Command::TasksList | Command::TasksStatus(_) | Command::TasksCancel(_) => "tasks",
+            Command::Delay(_) => "delay",
+            Command::Explain(_) => "explain",
+            Command::WatchKey(_) => "watchkey",
+            Command::WatchLifecycle => "watchlifecycle",
+            Command::Use(_) => "use",
+            Command::Watch(_) => "watch",
+            Command::Unwatch => "unwatch",
+            Command::Multi => "multi",
+            Command::Exec => "exec",
+            Command::Discard => "discard",
+            Command::Batch(_) => "batch",
+            Command::ViewCreate(_) | Command::ViewDrop(_) | Command::ViewList => "view",
+            Command::DebugPopulate(_) | Command::DebugQuickCheck(_) | Command::DebugProtocol(_) => {
+                "debug"
+            }
+            Command::CommandDocs(_) => "command",
+            #[cfg(feature = "lua")]
+            Command::Eval(_) => "eval",
+            #[cfg(feature = "wasm")]
+            Command::FunctionLoad(_) | Command::FunctionDelete(_) | Command::FunctionList => {
+                "function"
+            }
+            #[cfg(feature = "wasm")]
+            Command::Fcall(_) => "fcall",
+        }
+    }
+
+    // admin commands don't touch any keyspace data and are executed outside
+    // the data lane, so they keep working when the server is saturated.
+    pub fn is_admin(&self) -> bool {
+        matches!(
+            self,
+            Command::Ping(_)
+                | Command::Echo(_)
+                | Command::Info
+                | Command::ClientInfo
+                | Command::WatchLifecycle
+                | Command::Use(_)
+                | Command::DebugProtocol(_)
+                | Command::TasksList
+                | Command::TasksStatus(_)
+                | Command::TasksCancel(_)
+                | Command::CommandDocs(_)
+                | Command::Explain(_)
+        )
+    }
+
+    // these commands run while no other command runs, either because they are
+    // atomic or because they must not race with themselves.
+    pub fn is_exclusive(&self) -> bool {
+        match self {
+            Command::Batch(_) | Command::Save => true,
+            #[cfg(feature = "lua")]
+            Command::Eval(_) => true,
+            _ => false,
+        }
+    }
+
+    // returns the keyspace and key pairs that the command reads or writes
+    pub fn keys(&self) -> Vec<(Bytes, Bytes)> {
+        match self {
+            Command::Set(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::MSet(cmd) => cmd
+                .pairs()
+                .iter()
+                .map(|(key, _)| (cmd.keyspace(), key.clone()))
+                .collect(),
+            Command::CSet(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::Get(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::XGet(cmd) => cmd.keys().to_vec(),
+            Command::Del(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::Ttl(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::ExpireAt(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::Type(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::MemoryUsage(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::ObjectIdleTime(cmd) | Command::ObjectMeta(cmd) => {
+                vec![(cmd.keyspace(), cmd.key())]
+            }
+            Command::Pin(cmd) | Command::Unpin(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::Undelete(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::Dump(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::Restore(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::LPush(cmd) | Command::RPush(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::LPop(cmd) | Command::RPop(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::LRange(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::LLen(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::HSet(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::HGet(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::HDel(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::HGetAll(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::ZAdd(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::ZRange(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::ZScore(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::ZRem(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::SetBit(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::GetBit(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::BitCount(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::GetRange(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::SetRange(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::XAdd(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::XRange(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::XLen(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::Watch(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::WatchKey(cmd) => vec![(cmd.keyspace(), cmd.key())],
+            Command::Batch(cmd) => cmd.commands().iter().flat_map(Command::keys).collect(),
+            // these work on whole keyspaces or on no keyspace at all, the
+            // commands `DELAY` and `SCHEDULE ADD` hold are only parsed once
+            // they run and `EXPLAIN` never runs the command it holds
+            Command::Create(_)
+            | Command::Alter(_)
+            | Command::Drop(_)
+            | Command::Count(_)
+            | Command::Range(_)
+            | Command::DelRange(_)
+            | Command::CountByPrefix(_)
+            | Command::Sample(_)
+            | Command::EvictStats(_)
+            | Command::EvictorPause(_)
+            | Command::EvictorResume(_)
+            | Command::Sum(_)
+            | Command::Avg(_)
+            | Command::Export(_)
+            | Command::FlushAll(_)
+            | Command::ViewCreate(_)
+            | Command::ViewDrop(_)
+            | Command::ViewList
+            | Command::DebugPopulate(_)
+            | Command::DebugQuickCheck(_)
+            | Command::DebugProtocol(_)
+            | Command::Ping(_)
+            | Command::Echo(_)
+            | Command::Info
+            | Command::ClientInfo
+            | Command::Keyspaces
+            | Command::Save
+            | Command::BgSave
+            | Command::Fsync
+            | Command::ScheduleAdd(_)
+            | Command::ScheduleRemove(_)
+            | Command::ScheduleList
+            | Command::TasksList
+            | Command::TasksStatus(_)
+            | Command::TasksCancel(_)
+            | Command::Delay(_)
+            | Command::Explain(_)
+            | Command::WatchLifecycle
+            | Command::Use(_)
+            | Command::Unwatch
+            | Command::Multi
+            | Command::Exec
+            | Command::Discard
+            | Command::CommandDocs(_) => Vec::new(),
+            // the keys a script works on are only known while it runs
+            #[cfg(feature = "lua")]
+            Command::Eval(_) => Vec::new(),
+            #[cfg(feature = "wasm")]
+            Command::FunctionLoad(_)
+            | Command::FunctionDelete(_)
+            | Command::FunctionList
+            | Command::Fcall(_) => Vec::new(),
+        }
+    }
+
+    // returns the keyspaces the command works on, `None` when the command
+    // works on every keyspace or when they can't be known before it runs.
+    pub fn keyspaces(&self) -> Option<Vec<Bytes>> {
+        let keyspace = match self {
+            Command::Info
+            | Command::Keyspaces
+            | Command::FlushAll(_)
+            | Command::Save
+            | Command::BgSave
+            | Command::Fsync
+            | Command::Exec => return None,
+            #[cfg(feature = "lua")]
+            Command::Eval(_) => return None,
+            #[cfg(feature = "wasm")]
+            Command::Fcall(_) => return None,
+            Command::Create(cmd) => cmd.keyspace(),
+            Command::Alter(cmd) => cmd.keyspace(),
+            Command::Drop(cmd) => cmd.keyspace(),
+            Command::Count(cmd) => cmd.keyspace(),
+            Command::Range(cmd) => cmd.keyspace(),
+            Command::DelRange(cmd) => cmd.keyspace(),
+            Command::CountByPrefix(cmd) => cmd.keyspace(),
+            Command::Sample(cmd) => cmd.keyspace(),
+            Command::EvictStats(cmd) => cmd.keyspace(),
+            Command::EvictorPause(cmd) | Command::EvictorResume(cmd) => cmd.keyspace(),
+            Command::Sum(cmd) => cmd.keyspace(),
+            Command::Avg(cmd) => cmd.keyspace(),
+            Command::ViewCreate(cmd) => cmd.keyspace(),
+            Command::DebugPopulate(cmd) => cmd.keyspace(),
+            Command::DebugQuickCheck(cmd) => cmd.keyspace(),
+            Command::Export(cmd) => cmd.keyspace(),
+            Command::Use(cmd) => return Some(cmd.keyspace().into_iter().collect()),
+            Command::Batch(cmd) => {
+                let mut keyspaces = Vec::new();
+                for cmd in cmd.commands() {
+                    for keyspace in cmd.keyspaces()? {
+                        if !keyspaces.contains(&keyspace) {
+                            keyspaces.push(keyspace);
+                        }
+                    }
+                }
+                return Some(keyspaces);
+            }
+            cmd => {
+                let mut keyspaces = Vec::new();
+                for (keyspace, _) in cmd.keys() {
+                    if !keyspaces.contains(&keyspace) {
+                        keyspaces.push(keyspace);
+                    }
+                }
+                return Some(keyspaces);
+            }
+        };
+        Some(vec![keyspace])
+    }
+
+    // the writes kept in the append only file, a transaction is kept as the
+    // commands it ran and temporary keyspaces go away with their connection.
+    pub fn is_appended(&self) -> bool {
+        match self {
+            Command::Create(cmd) => !cmd.temp(),
+            Command::Exec | Command::TasksCancel(_) => false,
+            cmd => cmd.is_write(),
+        }
+    }
+
+    // a relative expiry or delay is resolved by the clock when the command
+    // runs, and would be resolved again when the command is replayed from the
+    // append only file. resolves it to its deadline at `now`, the time since
+    // the unix epoch, and returns the frame to append in place of `frame`, the
+    // one the command was parsed from.
+    pub fn resolve_expiry(&mut self, frame: Frame, now: Duration) -> Frame {
+        match self {
+            Command::Set(cmd) => match cmd.expire_after.take() {
+                Some(millis) => {
+                    cmd.expire_at = Some((now + Duration::from_millis(millis)).as_secs());
+                    cmd.frame()
+                }
+                None => frame,
+            },
+            Command::Restore(cmd) if cmd.expire_at.is_none() => {
+                // a payload which can't be read fails the command, which
+                // isn't appended then
+                match snapshot::undump_ttl(&cmd.payload) {
+                    Ok(Some(ttl)) => {
+                        cmd.expire_at = Some(snapshot::ttl_deadline(now, ttl));
+                        cmd.frame()
+                    }
+                    _ => frame,
+                }
+            }
+            Command::Delay(cmd) if cmd.run_at.is_none() => {
+                cmd.run_at = Some((now + Duration::from_millis(cmd.delay)).as_millis() as u64);
+                cmd.frame()
+            }
+            Command::Batch(cmd) => match frame {
+                // the commands follow the name and the token of the batch
+                Frame::Array(mut tokens) => {
+                    for (command, token) in cmd.commands.iter_mut().zip(tokens.iter_mut().skip(2)) {
+                        let frame = mem::replace(token, Frame::Null);
+                        *token = command.resolve_expiry(frame, now);
+                    }
+                    Frame::Array(tokens)
+                }
+                frame => frame,
+            },
+            _ => frame,
+        }
+    }
+
+    // whether the command changes any data or state of the server, commands
+    // whose effects can't be known before they run count as writes.
+    pub fn is_write(&self) -> bool {
+        match self {
+            Command::Create(_)
+            | Command::Alter(_)
+            | Command::Set(_)
+            | Command::MSet(_)
+            | Command::CSet(_)
+            | Command::Del(_)
+            | Command::ExpireAt(_)
+            | Command::Pin(_)
+            | Command::Unpin(_)
+            | Command::Undelete(_)
+            | Command::Restore(_)
+            | Command::EvictorPause(_)
+            | Command::EvictorResume(_)
+            | Command::Drop(_)
+            | Command::DelRange(_)
+            | Command::LPush(_)
+            | Command::RPush(_)
+            | Command::LPop(_)
+            | Command::RPop(_)
+            | Command::HSet(_)
+            | Command::HDel(_)
+            | Command::ZAdd(_)
+            | Command::ZRem(_)
+            | Command::SetBit(_)
+            | Command::SetRange(_)
+            | Command::XAdd(_)
+            | Command::FlushAll(_)
+            | Command::ScheduleAdd(_)
+            | Command::ScheduleRemove(_)
+            | Command::TasksCancel(_)
+            | Command::Delay(_)
+            | Command::Exec
+            | Command::ViewCreate(_)
+            | Command::ViewDrop(_)
+            | Command::DebugPopulate(_) => true,
+            Command::Batch(cmd) => cmd.commands().iter().any(Command::is_write),
+            #[cfg(feature = "lua")]
+            Command::Eval(_) => true,
+            #[cfg(feature = "wasm")]
+            Command::FunctionLoad(_) | Command::FunctionDelete(_) | Command::Fcall(_) => true,
+            Command::Get(_)
+            | Command::XGet(_)
+            | Command::Count(_)
+            | Command::Range(_)
+            | Command::CountByPrefix(_)
+            | Command::Sample(_)
+            | Command::EvictStats(_)
+            | Command::Sum(_)
+            | Command::Avg(_)
+            | Command::Ttl(_)
+            | Command::Type(_)
+            | Command::MemoryUsage(_)
+            | Command::ObjectIdleTime(_)
+            | Command::ObjectMeta(_)
+            | Command::Dump(_)
+            | Command::LRange(_)
+            | Command::LLen(_)
+            | Command::HGet(_)
+            | Command::HGetAll(_)
+            | Command::ZRange(_)
+            | Command::ZScore(_)
+            | Command::GetBit(_)
+            | Command::BitCount(_)
+            | Command::GetRange(_)
+            | Command::XRange(_)
+            | Command::XLen(_)
+            | Command::Ping(_)
+            | Command::Echo(_)
+            | Command::Info
+            | Command::ClientInfo
+            | Command::Keyspaces
+            | Command::Save
+            | Command::BgSave
+            | Command::Fsync
+            | Command::Export(_)
+            | Command::ScheduleList
+            | Command::TasksList
+            | Command::TasksStatus(_)
+            | Command::Explain(_)
+            | Command::WatchKey(_)
+            | Command::WatchLifecycle
+            | Command::Use(_)
+            | Command::Watch(_)
+            | Command::Unwatch
+            | Command::Multi
+            | Command::Discard
+            | Command::ViewList
+            | Command::DebugQuickCheck(_)
+            | Command::DebugProtocol(_)
+            | Command::CommandDocs(_) => false,
+            #[cfg(feature = "wasm")]
+            Command::FunctionList => false,
+        }
+    }
+
+    // a rough estimate of what running the command costs, `low` for commands
+    // that take the same time whatever their input, `medium` for commands
+    // that grow with their arguments or with the value of a key and `high`
+    // for commands that go through whole keyspaces or run arbitrary code.
+    pub fn cost(&self) -> &'static str {
+        match self {
+            Command::Drop(_)
+            | Command::Range(_)
+            | Command::DelRange(_)
+            | Command::CountByPrefix(_)
+            | Command::Sum(_)
+            | Command::Avg(_)
+            | Command::FlushAll(_)
+            | Command::Save
+            | Command::BgSave
+            | Command::Export(_)
+            | Command::Exec
+            | Command::ViewCreate(_)
+            | Command::DebugPopulate(_)
+            | Command::DebugQuickCheck(_) => "high",
+            #[cfg(feature = "lua")]
+            Command::Eval(_) => "high",
+            #[cfg(feature = "wasm")]
+            Command::FunctionLoad(_) | Command::Fcall(_) => "high",
+            Command::MSet(_)
+            | Command::XGet(_)
+            | Command::Sample(_)
+            | Command::MemoryUsage(_)
+            | Command::ObjectMeta(_)
+            | Command::Dump(_)
+            | Command::Restore(_)
+            | Command::LPush(_)
+            | Command::RPush(_)
+            | Command::LRange(_)
+            | Command::HSet(_)
+            | Command::HDel(_)
+            | Command::HGetAll(_)
+            | Command::ZAdd(_)
+            | Command::ZRange(_)
+            | Command::ZRem(_)
+            | Command::BitCount(_)
+            | Command::GetRange(_)
+            | Command::SetRange(_)
+            | Command::XAdd(_)
+            | Command::XRange(_) => "medium",
+            Command::Batch(cmd) => {
+                let costs: Vec<_> = cmd.commands().iter().map(Command::cost).collect();
+                ["high", "medium"]
+                    .into_iter()
+                    .find(|cost| costs.contains(cost))
+                    .unwrap_or("low")
+            }
+            _ => "low",
+        }
+    }
+}
+
+impl Parser {
+    pub fn new(frame: Frame) -> Result<Self, ParseCommandError> {
+        match frame {
+            Frame::Array(tokens) => Ok(Parser {
+                tokens: tokens.into_iter().peekable(),
+            }),
+            _ => Err(ParseCommandError::InvalidFormat),
+        }
+    }
+
+    pub fn next(&mut self) -> Option<Frame> {
+        self.tokens.next()
+    }
+
+    pub fn has_remaining(&mut self) -> bool {
+        self.tokens.peek().is_some()
+    }
+
+    pub fn next_as_string(&mut self) -> Result<Option<String>, ParseCommandError> {
+        let frame = match self.next() {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+
+        match frame {
+            Frame::String(data) => Ok(Some(str::from_utf8(&data[..])?.to_string())),
+            _ => Err(ParseCommandError::InvalidFormat),
+        }
+    }
+
+    pub fn next_as_array(&mut self) -> Result<Option<Vec<Frame>>, ParseCommandError> {
+        let frame = match self.next() {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+
+        match frame {
+            Frame::Array(data) => Ok(Some(data)),
+            _ => Err(ParseCommandError::InvalidFormat),
+        }
+    }
+
+    pub fn next_as_bytes(&mut self) -> Result<Option<Bytes>, ParseCommandError> {
+        let frame = match self.next() {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+
+        match frame {
+            Frame::String(data) => Ok(Some(data)),
+            _ => Err(ParseCommandError::InvalidFormat),
+        }
+    }
+}
+
+impl Create {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?;
+
+        let mut command = Create {
+            keyspace,
+            evictor: Evictor::Nop,
+            if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: KeyspaceConfig::default().sample_size,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: KeyspaceConfig::default().weight,
+        };
+
+        if !parser.has_remaining() {
+            return Ok(command);
+        };
+
+        let mut cache_ttl = None;
+
+        while parser.has_remaining() {
+            let token = parser
+                .next_as_string()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?
+                .to_lowercase();
+
+            if matches!(token.as_str(), "evictor") {
+                command.evictor = parse_evictor(parser, token, "create")?;
+            } else if matches!(token.as_str(), "if") {
+                let not_token = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?
+                    .to_lowercase();
+                let exists_token = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?
+                    .to_lowercase();
+
+                if !matches!(not_token.as_str(), "not") {
+                    return Err(ParseCommandError::InvalidArg(
+                        not_token,
+                        "create".to_string(),
+                    ));
+                }
+
+                if !matches!(exists_token.as_str(), "exists") {
+                    return Err(ParseCommandError::InvalidArg(
+                        exists_token,
+                        "create".to_string(),
+                    ));
+                }
+
+                if !command.if_not_exists {
+                    command.if_not_exists = true
+                } else {
+                    return Err(ParseCommandError::InvalidFormat);
+                }
+            } else if matches!(token.as_str(), "ordered") {
+                let value = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?
+                    .to_lowercase();
+                match value.as_str() {
+                    "yes" => command.ordered = true,
+                    "no" => command.ordered = false,
+                    _ => {
+                        return Err(ParseCommandError::InvalidArgValue(
+                            value,
+                            token,
+                            "create".to_string(),
+                        ))
+                    }
+                }
+            } else if matches!(token.as_str(), "retention") {
+                let value = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?;
+                let retention = value.parse::<u64>().map_err(|_| {
+                    ParseCommandError::InvalidArgValue(value, token, "create".to_string())
+                })?;
+                command.retention = Some(retention);
+            } else if matches!(token.as_str(), "samples") {
+                command.sample_size = parse_sample_size(parser, token, "create")?;
+            } else if matches!(token.as_str(), "upstream") {
+                let address = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?;
+                if command.upstream.is_some() {
+                    return Err(ParseCommandError::InvalidFormat);
+                }
+                command.upstream = Some(UpstreamConfig {
+                    address,
+                    cache_ttl: DEFAULT_UPSTREAM_CACHE_TTL,
+                });
+            } else if matches!(token.as_str(), "cache") {
+                let value = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?;
+                let ttl = value.parse::<u64>().map_err(|_| {
+                    ParseCommandError::InvalidArgValue(value, token, "create".to_string())
+                })?;
+                if cache_ttl.replace(ttl).is_some() {
+                    return Err(ParseCommandError::InvalidFormat);
+                }
+            } else if matches!(token.as_str(), "canary") {
+                let keyspace = parser
+                    .next_as_bytes()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?;
+                if command.canary_of.replace(keyspace).is_some() {
+                    return Err(ParseCommandError::InvalidFormat);
+                }
+            } else if matches!(token.as_str(), "tombstone") {
+                let value = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?;
+                let grace = value.parse::<u64>().map_err(|_| {
+                    ParseCommandError::InvalidArgValue(value, token, "create".to_string())
+                })?;
+                command.tombstone = Some(grace);
+            } else if matches!(token.as_str(), "spill") {
+                if !command.spill {
+                    command.spill = true
+                } else {
+                    return Err(ParseCommandError::InvalidFormat);
+                }
+            } else if matches!(token.as_str(), "maxmem") {
+                command.max_memory = parse_memory_size(parser, token, "create")?;
+            } else if matches!(token.as_str(), "maxkeys") {
+                let value = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("create".to_string()))?;
+                command.max_keys = match value.parse::<u64>() {
+                    Ok(max_keys) if max_keys > 0 => max_keys,
+                    _ => {
+                        return Err(ParseCommandError::InvalidArgValue(
+                            value,
+                            token,
+                            "create".to_string(),
+                        ))
+                    }
+                };
+            } else if matches!(token.as_str(), "weight") {
+                command.weight = parse_weight(parser, token, "create")?;
+            } else if matches!(token.as_str(), "temp") {
+                if !command.temp {
+                    command.temp = true
+                } else {
+                    return Err(ParseCommandError::InvalidFormat);
+                }
+            } else {
+                return Err(ParseCommandError::InvalidArg(token, "create".to_string()));
+            }
+        }
+
+        // retention drops keys by range, which needs the keys to be sorted
+        if command.retention.is_some() && !command.ordered {
+            return Err(ParseCommandError::InvalidArg(
+                "retention".to_string(),
+                "create".to_string(),
+            ));
+        }
+
+        // the budget is enforced by evicting keys, which a nop evictor never does
+        if command.max_memory > 0 && command.evictor == Evictor::Nop {
+            return Err(ParseCommandError::InvalidArg(
+                "maxmem".to_string(),
+                "create".to_string(),
+            ));
+        }
+
+        // values are spilled as they are evicted
+        if command.spill && command.evictor == Evictor::Nop {
+            return Err(ParseCommandError::InvalidArg(
+                "spill".to_string(),
+                "create".to_string(),
+            ));
+        }
+
+        // the cache time only applies to values fetched from an upstream
+        match (&mut command.upstream, cache_ttl) {
+            (Some(upstream), Some(ttl)) => upstream.cache_ttl = ttl,
+            (None, Some(_)) => {
+                return Err(ParseCommandError::InvalidArg(
+                    "cache".to_string(),
+                    "create".to_string(),
+                ))
+            }
+            _ => {}
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+    pub fn evictor(&self) -> Evictor {
+        self.evictor
+    }
+    pub fn if_not_exists(&self) -> bool {
+        self.if_not_exists
+    }
+    pub fn temp(&self) -> bool {
+        self.temp
+    }
+    pub fn ordered(&self) -> bool {
+        self.ordered
+    }
+    pub fn retention(&self) -> Option<u64> {
+        self.retention
+    }
+    pub fn sample_size(&self) -> u8 {
+        self.sample_size
+    }
+    pub fn upstream(&self) -> Option<&UpstreamConfig> {
+        self.upstream.as_ref()
+    }
+    pub fn canary_of(&self) -> Option<Bytes> {
+        self.canary_of.clone()
+    }
+    pub fn max_memory(&self) -> u64 {
+        self.max_memory
+    }
+    pub fn max_keys(&self) -> u64 {
+        self.max_keys
+    }
+    pub fn tombstone(&self) -> Option<u64> {
+        self.tombstone
+    }
+    pub fn spill(&self) -> bool {
+        self.spill
+    }
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+
+    pub fn config(&self) -> KeyspaceConfig {
+        KeyspaceConfig {
+            evictor: self.evictor,
+            ordered: self.ordered,
+            retention: self.retention,
+            sample_size: self.sample_size,
+            upstream: self.upstream.clone(),
+            canary_of: self.canary_of.clone(),
+            max_memory: self.max_memory,
+            max_keys: self.max_keys,
+            tombstone: self.tombstone,
+            spill: self.spill,
+            weight: self.weight,
+        }
+    }
+}
+
+// `token` is the option the value belongs to
+fn parse_evictor(
+    parser: &mut Parser,
+    token: String,
+    command: &str,
+) -> Result<Evictor, ParseCommandError> {
+    let value = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount(command.to_string()))?
+        .to_lowercase();
+    Evictor::from_bytes(value.as_bytes())
+        .ok_or_else(|| ParseCommandError::InvalidArgValue(value, token, command.to_string()))
+}
+
+// a number of bytes, optionally followed by a `kb`, `mb` or `gb` unit
+fn parse_memory_size(
+    parser: &mut Parser,
+    token: String,
+    command: &str,
+) -> Result<u64, ParseCommandError> {
+    let value = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount(command.to_string()))?;
+    let lowercase = value.to_lowercase();
+    let (number, unit) = match lowercase.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => lowercase.split_at(idx),
+        None => (lowercase.as_str(), ""),
+    };
+    let multiplier = match unit {
+        "" | "b" => Some(1),
+        "kb" => Some(1024),
+        "mb" => Some(1024 * 1024),
+        "gb" => Some(1024 * 1024 * 1024),
+        _ => None,
+    };
+    match (number.parse::<u64>(), multiplier) {
+        (Ok(number), Some(multiplier)) if number > 0 => number
+            .checked_mul(multiplier)
+            .ok_or_else(|| ParseCommandError::InvalidArgValue(value, token, command.to_string())),
+        _ => Err(ParseCommandError::InvalidArgValue(
+            value,
+            token,
+            command.to_string(),
+        )),
+    }
+}
+
+fn parse_sample_size(
+    parser: &mut Parser,
+    token: String,
+    command: &str,
+) -> Result<u8, ParseCommandError> {
+    let value = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount(command.to_string()))?;
+    match value.parse::<u8>() {
+        Ok(sample_size) if sample_size > 0 => Ok(sample_size),
+        _ => Err(ParseCommandError::InvalidArgValue(
+            value,
+            token,
+            command.to_string(),
+        )),
+    }
+}
+
+// 0 is allowed, it keeps the keyspace out of the server max memory eviction
+fn parse_weight(
+    parser: &mut Parser,
+    token: String,
+    command: &str,
+) -> Result<u32, ParseCommandError> {
+    let value = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount(command.to_string()))?;
+    value
+        .parse::<u32>()
+        .map_err(|_| ParseCommandError::InvalidArgValue(value, token, command.to_string()))
+}
+
+impl Alter {
+    // `EV` and `SS` are short for `EVICTOR` and `SAMPLES`
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("alter".to_string()))?;
+
+        let mut command = Alter {
+            keyspace,
+            evictor: None,
+            sample_size: None,
+            weight: None,
+        };
+
+        while let Some(token) = parser.next_as_string()? {
+            let token = token.to_lowercase();
+            match token.as_str() {
+                "evictor" | "ev" if command.evictor.is_none() => {
+                    command.evictor = Some(parse_evictor(parser, token, "alter")?)
+                }
+                "samples" | "ss" if command.sample_size.is_none() => {
+                    command.sample_size = Some(parse_sample_size(parser, token, "alter")?)
+                }
+                "weight" if command.weight.is_none() => {
+                    command.weight = Some(parse_weight(parser, token, "alter")?)
+                }
+                "evictor" | "ev" | "samples" | "ss" | "weight" => {
+                    return Err(ParseCommandError::InvalidFormat)
+                }
+                _ => return Err(ParseCommandError::InvalidArg(token, "alter".to_string())),
+            }
+        }
+
+        if command.evictor.is_none() && command.sample_size.is_none() && command.weight.is_none() {
+            return Err(ParseCommandError::WrongArgCount("alter".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn evictor(&self) -> Option<Evictor> {
+        self.evictor
+    }
+
+    pub fn sample_size(&self) -> Option<u8> {
+        self.sample_size
+    }
+
+    pub fn weight(&self) -> Option<u32> {
+        self.weight
+    }
+}
+
+impl Set {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?;
+
+        let value = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?;
+
+        let mut command = Set {
+            keyspace,
+            key,
+            value,
+            expire_at: None,
+            expire_after: None,
+            if_not_exists: false,
+            if_exists: false,
+        };
+
+        if !parser.has_remaining() {
+            return Ok(command);
+        }
+
+        while parser.has_remaining() {
+            let token = parser
+                .next_as_string()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?
+                .to_lowercase();
+
+            if matches!(token.as_str(), "expire") {
+                let at_or_after_token = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?
+                    .to_lowercase();
+                if matches!(at_or_after_token.as_str(), "at") {
+                    let value = parser
+                        .next_as_string()?
+                        .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?;
+                    let timestamp = value.parse::<u64>().map_err(|_| {
+                        ParseCommandError::InvalidArgValue(value, token, "set".to_string())
+                    })?;
+                    if command.expire_at.is_some() || command.expire_after.is_some() {
+                        return Err(ParseCommandError::InvalidFormat);
+                    }
+                    command.expire_at = Some(timestamp);
+                } else if matches!(at_or_after_token.as_str(), "after") {
+                    let value = parser
+                        .next_as_string()?
+                        .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?;
+
+                    let millis = value.parse::<u64>().map_err(|_| {
+                        ParseCommandError::InvalidArgValue(value, token, "set".to_string())
+                    })?;
+
+                    if command.expire_at.is_some() || command.expire_after.is_some() {
+                        return Err(ParseCommandError::InvalidFormat);
+                    }
+                    command.expire_after = Some(millis);
+                } else {
+                    return Err(ParseCommandError::InvalidArg(
+                        at_or_after_token,
+                        "set".to_string(),
+                    ));
+                }
+            } else if matches!(token.as_str(), "if") {
+                let not_or_exists_token = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?
+                    .to_lowercase();
+
+                if matches!(not_or_exists_token.as_str(), "not") {
+                    let exists_token = parser
+                        .next_as_string()?
+                        .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?
+                        .to_lowercase();
+                    if matches!(exists_token.as_str(), "exists") {
+                        if !command.if_not_exists && !command.if_exists {
+                            command.if_not_exists = true
+                        } else {
+                            return Err(ParseCommandError::InvalidFormat);
+                        }
+                    } else {
+                        return Err(ParseCommandError::InvalidArg(
+                            exists_token,
+                            "set".to_string(),
+                        ));
+                    }
+                } else if matches!(not_or_exists_token.as_str(), "exists") {
+                    if !command.if_not_exists && !command.if_exists {
+                        command.if_exists = true
+                    } else {
+                        return Err(ParseCommandError::InvalidFormat);
+                    }
+                } else {
+                    return Err(ParseCommandError::InvalidArg(
+                        not_or_exists_token,
+                        "set".to_string(),
+                    ));
+                }
+            } else if matches!(token.as_str(), "nx") {
+                if !command.if_not_exists && !command.if_exists {
+                    command.if_not_exists = true
+                } else {
+                    return Err(ParseCommandError::InvalidFormat);
+                }
+            } else if matches!(token.as_str(), "xx") {
+                if !command.if_not_exists && !command.if_exists {
+                    command.if_exists = true
+                } else {
+                    return Err(ParseCommandError::InvalidFormat);
+                }
+            } else {
+                return Err(ParseCommandError::InvalidArg(token, "set".to_string()));
+            }
+        }
+
+        Ok(command)
+    }
+
+    // the command with its expiry, as it is appended to the append only file
+    fn frame(&self) -> Frame {
+        let mut tokens = vec![
+            Frame::String(Bytes::from_static(b"set")),
+            Frame::String(self.keyspace.clone()),
+            Frame::String(self.key.clone()),
+            Frame::String(self.value.clone()),
+        ];
+        if let Some(expire_at) = self.expire_at {
+            push_expire_at(&mut tokens, expire_at);
+        }
+        if self.if_exists {
+            tokens.push(Frame::String(Bytes::from_static(b"xx")));
+        }
+        if self.if_not_exists {
+            tokens.push(Frame::String(Bytes::from_static(b"nx")));
+        }
+        Frame::Array(tokens)
+    }
+
+    pub fn expire_at(&self) -> Option<u64> {
+        self.expire_at
+    }
+
+    pub fn expire_after(&self) -> Option<u64> {
+        self.expire_after
+    }
+
+    pub fn if_exists(&self) -> bool {
+        self.if_exists
+    }
+
+    pub fn if_not_exists(&self) -> bool {
+        self.if_not_exists
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn value(&self) -> Bytes {
+        self.value.clone()
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+}
+
+impl MSet {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("mset".to_string()))?;
+
+        let mut command = MSet {
+            keyspace,
+            pairs: Vec::new(),
+        };
+
+        while let Some(key) = parser.next_as_bytes()? {
+            let value = parser
+                .next_as_bytes()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("mset".to_string()))?;
+            command.pairs.push((key, value));
+        }
+
+        if command.pairs.is_empty() {
+            return Err(ParseCommandError::WrongArgCount("mset".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn pairs(&self) -> &[(Bytes, Bytes)] {
+        &self.pairs
+    }
+
+    pub fn into_pairs(self) -> Vec<(Bytes, Bytes)> {
+        self.pairs
+    }
+}
+
+impl Get {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("get".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("get".to_string()))?;
+
+        let mut command = Get {
+            keyspace,
+            key,
+            with_version: false,
+        };
+
+        if let Some(token) = parser.next_as_string()? {
+            match token.to_lowercase().as_str() {
+                "withversion" => command.with_version = true,
+                _ => return Err(ParseCommandError::InvalidArg(token, "get".to_string())),
+            }
+        }
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("get".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn with_version(&self) -> bool {
+        self.with_version
+    }
+}
+
+impl CSet {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("cset".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("cset".to_string()))?;
+
+        let value = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("cset".to_string()))?;
+
+        let version = parser
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("cset".to_string()))?;
+        let version = version.parse::<u64>().map_err(|_| {
+            ParseCommandError::InvalidArgValue(version, "version".to_string(), "cset".to_string())
+        })?;
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("cset".to_string()));
+        }
+
+        Ok(CSet {
+            keyspace,
+            key,
+            value,
+            version,
+        })
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn value(&self) -> Bytes {
+        self.value.clone()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl XGet {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let mut command = XGet { keys: Vec::new() };
+
+        while let Some(pair) = parser.next_as_array()? {
+            let mut pair = Parser {
+                tokens: pair.into_iter().peekable(),
+            };
+
+            let keyspace = pair
+                .next_as_bytes()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("xget".to_string()))?;
+
+            let key = pair
+                .next_as_bytes()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("xget".to_string()))?;
+
+            if pair.has_remaining() {
+                return Err(ParseCommandError::WrongArgCount("xget".to_string()));
+            }
+
+            command.keys.push((keyspace, key));
+        }
+
+        if command.keys.is_empty() {
+            return Err(ParseCommandError::WrongArgCount("xget".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keys(&self) -> &[(Bytes, Bytes)] {
+        &self.keys
+    }
+}
+
+impl Del {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("del".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("del".to_string()))?;
+
+        let command = Del { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("del".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl Drop {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("drop".to_string()))?;
+
+        let mut command = Drop {
+            keyspace,
+            if_exists: false,
+        };
+
+        if !parser.has_remaining() {
+            return Ok(command);
+        }
+
+        while parser.has_remaining() {
+            let key = parser
+                .next_as_string()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("drop".to_string()))?
+                .to_lowercase();
+
+            if matches!(key.as_str(), "if") {
+                let exists_token = parser
+                    .next_as_string()?
+                    .ok_or_else(|| ParseCommandError::WrongArgCount("drop".to_string()))?
+                    .to_lowercase();
+                if matches!(exists_token.as_str(), "exists") {
+                    if !command.if_exists {
+                        command.if_exists = true
+                    } else {
+                        return Err(ParseCommandError::InvalidFormat);
+                    }
+                } else {
+                    return Err(ParseCommandError::InvalidArg(
+                        exists_token,
+                        "set".to_string(),
+                    ));
+                }
+            } else {
+                return Err(ParseCommandError::InvalidArg(key, "drop".to_string()));
+            }
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn if_exists(&self) -> bool {
+        self.if_exists
+    }
+}
+
+impl Count {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("count".to_string()))?;
+
+        let command = Count { keyspace };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("count".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+}
+
+impl Range {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("range".to_string()))?;
+
+        let start = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("range".to_string()))?;
+
+        let end = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("range".to_string()))?;
+
+        let command = Range {
+            keyspace,
+            start,
+            end,
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("range".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn start(&self) -> Bytes {
+        self.start.clone()
+    }
+
+    pub fn end(&self) -> Bytes {
+        self.end.clone()
+    }
+}
+
+impl DelRange {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("delrange".to_string()))?;
+
+        let start = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("delrange".to_string()))?;
+
+        let end = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("delrange".to_string()))?;
+
+        let command = DelRange {
+            keyspace,
+            start,
+            end,
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("delrange".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn start(&self) -> Bytes {
+        self.start.clone()
+    }
+
+    pub fn end(&self) -> Bytes {
+        self.end.clone()
+    }
+}
+
+impl CountByPrefix {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("countbyprefix".to_string()))?;
+
+        let prefix = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("countbyprefix".to_string()))?;
+
+        let command = CountByPrefix { keyspace, prefix };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount(
+                "countbyprefix".to_string(),
+            ));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn prefix(&self) -> Bytes {
+        self.prefix.clone()
+    }
+}
+
+impl EvictStats {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("evictstats".to_string()))?;
+
+        let command = EvictStats { keyspace };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("evictstats".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+}
+
+impl EvictorControl {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("evictor".to_string()))?;
+
+        let command = EvictorControl { keyspace };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("evictor".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+}
+
+impl Sample {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("sample".to_string()))?;
+
+        let value = parser
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("sample".to_string()))?;
+        let count = match value.parse::<usize>() {
+            Ok(count) if count > 0 && count <= MAX_SAMPLE_COUNT => count,
+            _ => {
+                return Err(ParseCommandError::InvalidArgValue(
+                    value,
+                    "count".to_string(),
+                    "sample".to_string(),
+                ))
+            }
+        };
+
+        let command = Sample { keyspace, count };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("sample".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl Sum {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let (keyspace, pattern) = parse_aggregate(parser, "sum")?;
+        Ok(Sum { keyspace, pattern })
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn pattern(&self) -> Option<Bytes> {
+        self.pattern.clone()
+    }
+}
+
+impl Avg {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let (keyspace, pattern) = parse_aggregate(parser, "avg")?;
+        Ok(Avg { keyspace, pattern })
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn pattern(&self) -> Option<Bytes> {
+        self.pattern.clone()
+    }
+}
+
+// parses the `<keyspace> [MATCH <pattern>]` arguments shared by the aggregate
+// commands.
+fn parse_aggregate(
+    parser: &mut Parser,
+    name: &str,
+) -> Result<(Bytes, Option<Bytes>), ParseCommandError> {
+    let keyspace = parser
+        .next_as_bytes()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount(name.to_string()))?;
+
+    if !parser.has_remaining() {
+        return Ok((keyspace, None));
+    }
+
+    let token = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount(name.to_string()))?
+        .to_lowercase();
+
+    if !matches!(token.as_str(), "match") {
+        return Err(ParseCommandError::InvalidArg(token, name.to_string()));
+    }
+
+    let pattern = parser
+        .next_as_bytes()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount(name.to_string()))?;
+
+    if parser.has_remaining() {
+        return Err(ParseCommandError::WrongArgCount(name.to_string()));
+    }
+
+    Ok((keyspace, Some(pattern)))
+}
+
+impl Ttl {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("ttl".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("ttl".to_string()))?;
+
+        let command = Ttl { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("ttl".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl ExpireAt {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("expireat".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("expireat".to_string()))?;
+
+        let value = parser
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("expireat".to_string()))?;
+        let timestamp = value.parse::<u64>().map_err(|_| {
+            ParseCommandError::InvalidArgValue(
+                value,
+                "timestamp".to_string(),
+                "expireat".to_string(),
+            )
+        })?;
+
+        let command = ExpireAt {
+            keyspace,
+            key,
+            timestamp,
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("expireat".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+impl Type {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("type".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("type".to_string()))?;
+
+        let command = Type { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("type".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl MemoryUsage {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("memory".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("memory".to_string()))?;
+
+        let command = MemoryUsage { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("memory".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl Pin {
+    fn parse(parser: &mut Parser, name: &str) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount(name.to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount(name.to_string()))?;
+
+        let command = Pin { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount(name.to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl Undelete {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("undelete".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("undelete".to_string()))?;
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("undelete".to_string()));
+        }
+
+        Ok(Undelete { keyspace, key })
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl Dump {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("dump".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("dump".to_string()))?;
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("dump".to_string()));
+        }
+
+        Ok(Dump { keyspace, key })
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl Restore {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("restore".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("restore".to_string()))?;
+
+        let payload = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("restore".to_string()))?;
+
+        let mut command = Restore {
+            keyspace,
+            key,
+            payload,
+            replace: false,
+            expire_at: None,
+        };
+
+        while let Some(token) = parser.next_as_string()? {
+            match token.to_lowercase().as_str() {
+                "replace" if !command.replace => command.replace = true,
+                "expire" if command.expire_at.is_none() => {
+                    let at = parser
+                        .next_as_string()?
+                        .ok_or_else(|| ParseCommandError::WrongArgCount("restore".to_string()))?;
+                    if at.to_lowercase() != "at" {
+                        return Err(ParseCommandError::InvalidArg(at, "restore".to_string()));
+                    }
+                    let value = parser
+                        .next_as_string()?
+                        .ok_or_else(|| ParseCommandError::WrongArgCount("restore".to_string()))?;
+                    let timestamp = value.parse::<u64>().map_err(|_| {
+                        ParseCommandError::InvalidArgValue(value, token, "restore".to_string())
+                    })?;
+                    command.expire_at = Some(timestamp);
+                }
+                _ => {
+                    return Err(ParseCommandError::InvalidArg(token, "restore".to_string()));
+                }
+            }
+        }
+
+        Ok(command)
+    }
+
+    // the command with its expiry, as it is appended to the append only file
+    fn frame(&self) -> Frame {
+        let mut tokens = vec![
+            Frame::String(Bytes::from_static(b"restore")),
+            Frame::String(self.keyspace.clone()),
+            Frame::String(self.key.clone()),
+            Frame::String(self.payload.clone()),
+        ];
+        if self.replace {
+            tokens.push(Frame::String(Bytes::from_static(b"replace")));
+        }
+        if let Some(expire_at) = self.expire_at {
+            push_expire_at(&mut tokens, expire_at);
+        }
+        Frame::Array(tokens)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+
+    pub fn replace(&self) -> bool {
+        self.replace
+    }
+
+    pub fn expire_at(&self) -> Option<u64> {
+        self.expire_at
+    }
+}
+
+fn push_expire_at(tokens: &mut Vec<Frame>, expire_at: u64) {
+    tokens.push(Frame::String(Bytes::from_static(b"expire")));
+    tokens.push(Frame::String(Bytes::from_static(b"at")));
+    tokens.push(Frame::String(Bytes::from(expire_at.to_string())));
+}
+
+impl Object {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("object".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("object".to_string()))?;
+
+        let command = Object { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("object".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl Push {
+    fn parse(parser: &mut Parser, name: &str) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount(name.to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount(name.to_string()))?;
+
+        let mut command = Push {
+            keyspace,
+            key,
+            values: Vec::new(),
+        };
+
+        while let Some(value) = parser.next_as_bytes()? {
+            command.values.push(value);
+        }
+
+        if command.values.is_empty() {
+            return Err(ParseCommandError::WrongArgCount(name.to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn values(&self) -> &[Bytes] {
+        &self.values
+    }
+}
+
+impl Pop {
+    fn parse(parser: &mut Parser, name: &str) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount(name.to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount(name.to_string()))?;
+
+        let command = Pop { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount(name.to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl LRange {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("lrange".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("lrange".to_string()))?;
+
+        let start = parse_index(parser, "start", "lrange")?;
+        let stop = parse_index(parser, "stop", "lrange")?;
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("lrange".to_string()));
+        }
+
+        Ok(LRange {
+            keyspace,
+            key,
+            start,
+            stop,
+        })
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn start(&self) -> i64 {
+        self.start
+    }
+
+    pub fn stop(&self) -> i64 {
+        self.stop
+    }
+}
+
+// indexes start at 0, negative indexes count from the end of the list or the
+// sorted set
+fn parse_index(parser: &mut Parser, name: &str, command: &str) -> Result<i64, ParseCommandError> {
+    let value = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount(command.to_string()))?;
+    value.parse::<i64>().map_err(|_| {
+        ParseCommandError::InvalidArgValue(value, name.to_string(), command.to_string())
+    })
+}
+
+impl LLen {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("llen".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("llen".to_string()))?;
+
+        let command = LLen { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("llen".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl HSet {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("hset".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("hset".to_string()))?;
+
+        let mut command = HSet {
+            keyspace,
+            key,
+            pairs: Vec::new(),
+        };
+
+        while let Some(field) = parser.next_as_bytes()? {
+            let value = parser
+                .next_as_bytes()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("hset".to_string()))?;
+            command.pairs.push((field, value));
+        }
+
+        if command.pairs.is_empty() {
+            return Err(ParseCommandError::WrongArgCount("hset".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn pairs(&self) -> &[(Bytes, Bytes)] {
+        &self.pairs
+    }
+}
+
+impl HGet {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("hget".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("hget".to_string()))?;
+
+        let field = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("hget".to_string()))?;
+
+        let command = HGet {
+            keyspace,
+            key,
+            field,
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("hget".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn field(&self) -> Bytes {
+        self.field.clone()
+    }
+}
+
+impl HDel {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("hdel".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("hdel".to_string()))?;
+
+        let mut command = HDel {
+            keyspace,
+            key,
+            fields: Vec::new(),
+        };
+
+        while let Some(field) = parser.next_as_bytes()? {
+            command.fields.push(field);
+        }
+
+        if command.fields.is_empty() {
+            return Err(ParseCommandError::WrongArgCount("hdel".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn fields(&self) -> &[Bytes] {
+        &self.fields
+    }
+}
+
+impl HGetAll {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("hgetall".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("hgetall".to_string()))?;
+
+        let command = HGetAll { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("hgetall".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl ZAdd {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("zadd".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("zadd".to_string()))?;
+
+        let mut command = ZAdd {
+            keyspace,
+            key,
+            pairs: Vec::new(),
+        };
+
+        while let Some(score) = parser.next_as_string()? {
+            let member = parser
+                .next_as_bytes()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("zadd".to_string()))?;
+            let parsed = score.parse::<f64>().map_err(|_| {
+                ParseCommandError::InvalidArgValue(
+                    score.clone(),
+                    "score".to_string(),
+                    "zadd".to_string(),
+                )
+            })?;
+            if parsed.is_nan() {
+                return Err(ParseCommandError::InvalidArgValue(
+                    score,
+                    "score".to_string(),
+                    "zadd".to_string(),
+                ));
+            }
+            command.pairs.push((parsed, member));
+        }
+
+        if command.pairs.is_empty() {
+            return Err(ParseCommandError::WrongArgCount("zadd".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn pairs(&self) -> &[(f64, Bytes)] {
+        &self.pairs
+    }
+}
+
+impl ZRange {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("zrange".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("zrange".to_string()))?;
+
+        let start = parse_index(parser, "start", "zrange")?;
+        let stop = parse_index(parser, "stop", "zrange")?;
+
+        let mut command = ZRange {
+            keyspace,
+            key,
+            start,
+            stop,
+            with_scores: false,
+        };
+
+        if let Some(token) = parser.next_as_string()? {
+            match token.to_lowercase().as_str() {
+                "withscores" => command.with_scores = true,
+                _ => return Err(ParseCommandError::InvalidArg(token, "zrange".to_string())),
+            }
+        }
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("zrange".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn start(&self) -> i64 {
+        self.start
+    }
+
+    pub fn stop(&self) -> i64 {
+        self.stop
+    }
+
+    pub fn with_scores(&self) -> bool {
+        self.with_scores
+    }
+}
+
+impl ZScore {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("zscore".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("zscore".to_string()))?;
+
+        let member = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("zscore".to_string()))?;
+
+        let command = ZScore {
+            keyspace,
+            key,
+            member,
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("zscore".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn member(&self) -> Bytes {
+        self.member.clone()
+    }
+}
+
+impl ZRem {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("zrem".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("zrem".to_string()))?;
+
+        let mut command = ZRem {
+            keyspace,
+            key,
+            members: Vec::new(),
+        };
+
+        while let Some(member) = parser.next_as_bytes()? {
+            command.members.push(member);
+        }
+
+        if command.members.is_empty() {
+            return Err(ParseCommandError::WrongArgCount("zrem".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn members(&self) -> &[Bytes] {
+        &self.members
+    }
+}
+
+impl SetBit {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("setbit".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("setbit".to_string()))?;
+
+        let offset = parse_offset(parser, MAX_BIT_OFFSET, "setbit")?;
+
+        let bit = parser
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("setbit".to_string()))?;
+        let bit = match bit.as_str() {
+            "0" => false,
+            "1" => true,
+            _ => {
+                return Err(ParseCommandError::InvalidArgValue(
+                    bit,
+                    "bit".to_string(),
+                    "setbit".to_string(),
+                ))
+            }
+        };
+
+        let command = SetBit {
+            keyspace,
+            key,
+            offset,
+            bit,
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("setbit".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn bit(&self) -> bool {
+        self.bit
+    }
+}
+
+impl GetBit {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("getbit".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("getbit".to_string()))?;
+
+        let offset = parse_offset(parser, MAX_BIT_OFFSET, "getbit")?;
+
+        let command = GetBit {
+            keyspace,
+            key,
+            offset,
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("getbit".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+// offsets are capped so a single command can't grow a value past 512mb, the
+// limit is in bits or bytes depending on the command
+fn parse_offset(parser: &mut Parser, limit: u64, command: &str) -> Result<u64, ParseCommandError> {
+    let value = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount(command.to_string()))?;
+    match value.parse::<u64>() {
+        Ok(offset) if offset < limit => Ok(offset),
+        _ => Err(ParseCommandError::InvalidArgValue(
+            value,
+            "offset".to_string(),
+            command.to_string(),
+        )),
+    }
+}
+
+impl BitCount {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("bitcount".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("bitcount".to_string()))?;
+
+        let mut command = BitCount {
+            keyspace,
+            key,
+            range: None,
+        };
+
+        if parser.has_remaining() {
+            let start = parse_index(parser, "start", "bitcount")?;
+            let end = parse_index(parser, "end", "bitcount")?;
+            command.range = Some((start, end));
+        }
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("bitcount".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn range(&self) -> Option<(i64, i64)> {
+        self.range
+    }
+}
+
+impl GetRange {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("getrange".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("getrange".to_string()))?;
+
+        let start = parse_index(parser, "start", "getrange")?;
+        let end = parse_index(parser, "end", "getrange")?;
+
+        let command = GetRange {
+            keyspace,
+            key,
+            start,
+            end,
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("getrange".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn start(&self) -> i64 {
+        self.start
+    }
+
+    pub fn end(&self) -> i64 {
+        self.end
+    }
+}
+
+impl SetRange {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("setrange".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("setrange".to_string()))?;
+
+        let offset = parse_offset(parser, MAX_VALUE_SIZE, "setrange")?;
+
+        let value = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("setrange".to_string()))?;
+
+        if offset + value.len() as u64 > MAX_VALUE_SIZE {
+            return Err(ParseCommandError::InvalidArgValue(
+                offset.to_string(),
+                "offset".to_string(),
+                "setrange".to_string(),
+            ));
+        }
+
+        let command = SetRange {
+            keyspace,
+            key,
+            offset,
+            value,
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("setrange".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn value(&self) -> Bytes {
+        self.value.clone()
+    }
+}
+
+impl XAdd {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("xadd".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("xadd".to_string()))?;
+
+        let id = parser
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("xadd".to_string()))?;
+        let id = match id.as_str() {
+            "*" => None,
+            _ => Some(StreamId::parse(&id, 0).map_err(|_| {
+                ParseCommandError::InvalidArgValue(id, "id".to_string(), "xadd".to_string())
+            })?),
+        };
+
+        let mut command = XAdd {
+            keyspace,
+            key,
+            id,
+            fields: Vec::new(),
+        };
+
+        while let Some(field) = parser.next_as_bytes()? {
+            let value = parser
+                .next_as_bytes()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("xadd".to_string()))?;
+            command.fields.push((field, value));
+        }
+
+        if command.fields.is_empty() {
+            return Err(ParseCommandError::WrongArgCount("xadd".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn id(&self) -> Option<StreamId> {
+        self.id
+    }
+
+    pub fn fields(&self) -> &[(Bytes, Bytes)] {
+        &self.fields
+    }
+}
+
+impl XRange {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("xrange".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("xrange".to_string()))?;
+
+        let start = parse_stream_bound(parser, "start", 0)?;
+        let end = parse_stream_bound(parser, "end", u64::MAX)?;
+
+        let mut command = XRange {
+            keyspace,
+            key,
+            start,
+            end,
+            count: None,
+        };
+
+        if let Some(token) = parser.next_as_string()? {
+            if token.to_lowercase() != "count" {
+                return Err(ParseCommandError::InvalidArg(token, "xrange".to_string()));
+            }
+            let value = parser
+                .next_as_string()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("xrange".to_string()))?;
+            let count = value.parse::<usize>().map_err(|_| {
+                ParseCommandError::InvalidArgValue(value, token, "xrange".to_string())
+            })?;
+            command.count = Some(count);
+        }
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("xrange".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+
+    pub fn start(&self) -> StreamId {
+        self.start
+    }
+
+    pub fn end(&self) -> StreamId {
+        self.end
+    }
+
+    pub fn count(&self) -> Option<usize> {
+        self.count
+    }
+}
+
+// `-` and `+` are the first and the last possible ids. a bound without a
+// sequence number covers the whole millisecond.
+fn parse_stream_bound(
+    parser: &mut Parser,
+    name: &str,
+    default_seq: u64,
+) -> Result<StreamId, ParseCommandError> {
+    let value = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("xrange".to_string()))?;
+    match value.as_str() {
+        "-" => Ok(StreamId::MIN),
+        "+" => Ok(StreamId::MAX),
+        _ => StreamId::parse(&value, default_seq).map_err(|_| {
+            ParseCommandError::InvalidArgValue(value, name.to_string(), "xrange".to_string())
+        }),
+    }
+}
+
+impl XLen {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("xlen".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("xlen".to_string()))?;
+
+        let command = XLen { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("xlen".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl Ping {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let message = parser.next_as_bytes()?;
+
+        let command = Ping { message };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("ping".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    // the reply is the message if one was given, otherwise `PONG`
+    pub fn reply(&self) -> Bytes {
+        self.message
+            .clone()
+            .unwrap_or_else(|| Bytes::from_static(b"PONG"))
+    }
+}
+
+impl Echo {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let message = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("echo".to_string()))?;
+
+        let command = Echo { message };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("echo".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn message(&self) -> Bytes {
+        self.message.clone()
+    }
+}
+
+impl Use {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser.next_as_bytes()?;
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("use".to_string()));
+        }
+        Ok(Use { keyspace })
+    }
+
+    pub fn keyspace(&self) -> Option<Bytes> {
+        self.keyspace.clone()
+    }
+}
+
+impl WatchKey {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("watchkey".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("watchkey".to_string()))?;
+
+        let command = WatchKey { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("watchkey".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl Watch {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("watch".to_string()))?;
+
+        let key = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("watch".to_string()))?;
+
+        let command = Watch { keyspace, key };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("watch".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn key(&self) -> Bytes {
+        self.key.clone()
+    }
+}
+
+impl Batch {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let token = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("batch".to_string()))?;
+
+        let mut command = Batch {
+            token,
+            commands: Vec::new(),
+        };
+
+        while let Some(tokens) = parser.next_as_array()? {
+            let cmd = parse(Frame::Array(tokens))?;
+            // commands which depend on the connection or nest batches can't be
+            // part of a batch
+            if matches!(
+                cmd,
+                Command::Batch(_)
+                    | Command::Multi
+                    | Command::Exec
+                    | Command::Discard
+                    | Command::Watch(_)
+                    | Command::Unwatch
+                    | Command::WatchKey(_)
+                    | Command::WatchLifecycle
+                    | Command::Use(_)
+                    | Command::ClientInfo
+                    | Command::DebugProtocol(_)
+            ) {
+                return Err(ParseCommandError::InvalidArg(
+                    cmd.name().to_string(),
+                    "batch".to_string(),
+                ));
+            }
+            command.commands.push(cmd);
+        }
+
+        if command.commands.is_empty() {
+            return Err(ParseCommandError::WrongArgCount("batch".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn token(&self) -> Bytes {
+        self.token.clone()
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn into_commands(self) -> Vec<Command> {
+        self.commands
+    }
+}
+
+impl Export {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("export".to_string()))?;
+
+        let path = parser
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("export".to_string()))?;
+
+        let mut command = Export {
+            keyspace,
+            path,
+            format: ExportFormat::default(),
+        };
+
+        if let Some(token) = parser.next_as_string()? {
+            if token.to_lowercase() != "format" {
+                return Err(ParseCommandError::InvalidArg(token, "export".to_string()));
+            }
+            let value = parser
+                .next_as_string()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("export".to_string()))?;
+            command.format = ExportFormat::from_bytes(value.as_bytes()).ok_or_else(|| {
+                ParseCommandError::InvalidArgValue(
+                    value,
+                    "format".to_string(),
+                    "export".to_string(),
+                )
+            })?;
+        }
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("export".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn format(&self) -> ExportFormat {
+        self.format
+    }
+}
+
+impl FlushAll {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let mut command = FlushAll {
+            drop: false,
+            async_: false,
+        };
+
+        while parser.has_remaining() {
+            let token = parser
+                .next_as_string()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("flushall".to_string()))?
+                .to_lowercase();
+
+            if matches!(token.as_str(), "drop") {
+                if !command.drop {
+                    command.drop = true
+                } else {
+                    return Err(ParseCommandError::InvalidFormat);
+                }
+            } else if matches!(token.as_str(), "async") {
+                if !command.async_ {
+                    command.async_ = true
+                } else {
+                    return Err(ParseCommandError::InvalidFormat);
+                }
+            } else {
+                return Err(ParseCommandError::InvalidArg(token, "flushall".to_string()));
+            }
+        }
+
+        Ok(command)
+    }
+
+    pub fn drop(&self) -> bool {
+        self.drop
+    }
+
+    pub fn async_(&self) -> bool {
+        self.async_
+    }
+}
+
+impl ScheduleAdd {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let name = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("schedule".to_string()))?;
+
+        let expression = parser
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("schedule".to_string()))?;
+
+        let schedule = Schedule::from_str(&expression).map_err(|_| {
+            ParseCommandError::InvalidArgValue(
+                expression.clone(),
+                "expression".to_string(),
+                "schedule".to_string(),
+            )
+        })?;
+
+        let command = parse_deferred_command(parser, "schedule")?;
+
+        Ok(ScheduleAdd {
+            name,
+            schedule,
+            command,
+        })
+    }
+
+    pub fn name(&self) -> Bytes {
+        self.name.clone()
+    }
+
+    pub fn schedule(&self) -> Schedule {
+        self.schedule.clone()
+    }
+
+    pub fn command(&self) -> Vec<Bytes> {
+        self.command.clone()
+    }
+}
+
+impl ScheduleRemove {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let name = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("schedule".to_string()))?;
+
+        let command = ScheduleRemove { name };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("schedule".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn name(&self) -> Bytes {
+        self.name.clone()
+    }
+}
+
+impl TaskId {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let value = parser
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("tasks".to_string()))?;
+
+        let id = value.parse::<u64>().map_err(|_| {
+            ParseCommandError::InvalidArgValue(value, "id".to_string(), "tasks".to_string())
+        })?;
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("tasks".to_string()));
+        }
+
+        Ok(TaskId { id })
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl ViewCreate {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let view = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("view".to_string()))?;
+
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("view".to_string()))?;
+
+        let command = ViewCreate { view, keyspace };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("view".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn view(&self) -> Bytes {
+        self.view.clone()
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+}
+
+impl ViewDrop {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let view = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("view".to_string()))?;
+
+        let command = ViewDrop { view };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("view".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn view(&self) -> Bytes {
+        self.view.clone()
+    }
+}
+
+impl CommandDocs {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let mut command = CommandDocs { names: Vec::new() };
+        while let Some(name) = parser.next_as_string()? {
+            command.names.push(name.to_lowercase());
+        }
+        Ok(command)
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+fn parse_command(parser: &mut Parser) -> Result<Command, ParseCommandError> {
+    let subcommand = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("command".to_string()))?
+        .to_lowercase();
+
+    match subcommand.as_str() {
+        "docs" => Ok(Command::CommandDocs(CommandDocs::parse(parser)?)),
+        _ => Err(ParseCommandError::InvalidArg(
+            subcommand,
+            "command".to_string(),
+        )),
+    }
+}
+
+fn parse_client(parser: &mut Parser) -> Result<Command, ParseCommandError> {
+    let subcommand = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("client".to_string()))?
+        .to_lowercase();
+
+    match subcommand.as_str() {
+        "info" => parse_no_args(parser, "client", Command::ClientInfo),
+        _ => Err(ParseCommandError::InvalidArg(
+            subcommand,
+            "client".to_string(),
+        )),
+    }
+}
+
+fn parse_memory(parser: &mut Parser) -> Result<Command, ParseCommandError> {
+    let subcommand = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("memory".to_string()))?
+        .to_lowercase();
+
+    match subcommand.as_str() {
+        "usage" => Ok(Command::MemoryUsage(MemoryUsage::parse(parser)?)),
+        _ => Err(ParseCommandError::InvalidArg(
+            subcommand,
+            "memory".to_string(),
+        )),
+    }
+}
+
+fn parse_object(parser: &mut Parser) -> Result<Command, ParseCommandError> {
+    let subcommand = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("object".to_string()))?
+        .to_lowercase();
+
+    match subcommand.as_str() {
+        "idletime" => Ok(Command::ObjectIdleTime(Object::parse(parser)?)),
+        "meta" => Ok(Command::ObjectMeta(Object::parse(parser)?)),
+        _ => Err(ParseCommandError::InvalidArg(
+            subcommand,
+            "object".to_string(),
+        )),
+    }
+}
+
+fn parse_evictor_control(parser: &mut Parser) -> Result<Command, ParseCommandError> {
+    let subcommand = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("evictor".to_string()))?
+        .to_lowercase();
+
+    match subcommand.as_str() {
+        "pause" => Ok(Command::EvictorPause(EvictorControl::parse(parser)?)),
+        "resume" => Ok(Command::EvictorResume(EvictorControl::parse(parser)?)),
+        _ => Err(ParseCommandError::InvalidArg(
+            subcommand,
+            "evictor".to_string(),
+        )),
+    }
+}
+
+fn parse_view(parser: &mut Parser) -> Result<Command, ParseCommandError> {
+    let subcommand = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("view".to_string()))?
+        .to_lowercase();
+
+    match subcommand.as_str() {
+        "create" => Ok(Command::ViewCreate(ViewCreate::parse(parser)?)),
+        "drop" => Ok(Command::ViewDrop(ViewDrop::parse(parser)?)),
+        "list" => {
+            if parser.has_remaining() {
+                return Err(ParseCommandError::WrongArgCount("view".to_string()));
+            }
+            Ok(Command::ViewList)
+        }
+        _ => Err(ParseCommandError::InvalidArg(
+            subcommand,
+            "view".to_string(),
+        )),
+    }
+}
+
+impl DebugPopulate {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("debug".to_string()))?;
+
+        let value = parser
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("debug".to_string()))?;
+        let count = value.parse::<u64>().map_err(|_| {
+            ParseCommandError::InvalidArgValue(value, "count".to_string(), "debug".to_string())
+        })?;
+
+        let size = match parser.next_as_string()? {
+            Some(value) => Some(value.parse::<usize>().map_err(|_| {
+                ParseCommandError::InvalidArgValue(value, "size".to_string(), "debug".to_string())
+            })?),
+            None => None,
+        };
+
+        let command = DebugPopulate {
+            keyspace,
+            count,
+            size,
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("debug".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn size(&self) -> Option<usize> {
+        self.size
+    }
+}
+
+impl DebugQuickCheck {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let keyspace = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("debug".to_string()))?;
+
+        let command = DebugQuickCheck { keyspace };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("debug".to_string()));
+        }
+
+        Ok(command)
+    }
+
+    pub fn keyspace(&self) -> Bytes {
+        self.keyspace.clone()
+    }
+}
+
+impl DebugProtocol {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let value = parser
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("debug".to_string()))?;
+
+        let enabled = match value.to_lowercase().as_str() {
+            "on" => true,
+            "off" => false,
+            _ => return Err(ParseCommandError::InvalidArg(value, "debug".to_string())),
+        };
+
+        if parser.has_remaining() {
+            return Err(ParseCommandError::WrongArgCount("debug".to_string()));
         }
 
-        Ok(command)
+        Ok(DebugProtocol { enabled })
     }
 
-    pub fn keyspace(&self) -> Bytes {
-        self.keyspace.clone()
-    }
-    pub fn evictor(&self) -> Evictor {
-        self.evictor
-    }
-    pub fn if_not_exists(&self) -> bool {
-        self.if_not_exists
+    pub fn enabled(&self) -> bool {
+        self.enabled
     }
 }
 
-impl Set {
-    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
-        let keyspace = parser
-            .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?;
+fn parse_debug(parser: &mut Parser) -> Result<Command, ParseCommandError> {
+    let subcommand = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("debug".to_string()))?
+        .to_lowercase();
 
-        let key = parser
-            .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?;
+    match subcommand.as_str() {
+        "populate" => Ok(Command::DebugPopulate(DebugPopulate::parse(parser)?)),
+        "quickcheck" => Ok(Command::DebugQuickCheck(DebugQuickCheck::parse(parser)?)),
+        "protocol" => Ok(Command::DebugProtocol(DebugProtocol::parse(parser)?)),
+        _ => Err(ParseCommandError::InvalidArg(
+            subcommand,
+            "debug".to_string(),
+        )),
+    }
+}
 
+impl Delay {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
         let value = parser
-            .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?;
+            .next_as_string()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("delay".to_string()))?;
 
-        let mut command = Set {
-            keyspace,
-            key,
-            value,
-            expire_at: None,
-            if_not_exists: false,
-            if_exists: false,
+        let mut command = Delay {
+            delay: 0,
+            run_at: None,
+            done: false,
+            command: Vec::new(),
         };
 
-        if !parser.has_remaining() {
-            return Ok(command);
+        let token = value.to_lowercase();
+        if matches!(token.as_str(), "at" | "done") {
+            let value = parser
+                .next_as_string()?
+                .ok_or_else(|| ParseCommandError::WrongArgCount("delay".to_string()))?;
+            let run_at = value.parse::<u64>().map_err(|_| {
+                ParseCommandError::InvalidArgValue(value, token.clone(), "delay".to_string())
+            })?;
+            command.run_at = Some(run_at);
+            command.done = token == "done";
+        } else {
+            command.delay = value.parse::<u64>().map_err(|_| {
+                ParseCommandError::InvalidArgValue(value, "delay".to_string(), "delay".to_string())
+            })?;
         }
 
-        while parser.has_remaining() {
-            let token = parser
-                .next_as_string()?
-                .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?
-                .to_lowercase();
+        command.command = parse_deferred_command(parser, "delay")?;
 
-            if matches!(token.as_str(), "expire") {
-                let at_or_after_token = parser
-                    .next_as_string()?
-                    .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?
-                    .to_lowercase();
-                if matches!(at_or_after_token.as_str(), "at") {
-                    let value = parser
-                        .next_as_string()?
-                        .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?;
-                    let timestamp = value.parse::<u64>().map_err(|_| {
-                        ParseCommandError::InvalidArgValue(value, token, "set".to_string())
-                    })?;
-                    match command.expire_at {
-                        Some(_) => return Err(ParseCommandError::InvalidFormat),
-                        None => command.expire_at = Some(timestamp),
-                    }
-                } else if matches!(at_or_after_token.as_str(), "after") {
-                    let value = parser
-                        .next_as_string()?
-                        .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?;
+        Ok(command)
+    }
 
-                    let millis = value.parse::<u64>().map_err(|_| {
-                        ParseCommandError::InvalidArgValue(value, token, "set".to_string())
-                    })?;
+    // the `DELAY AT` the command is appended as, or `DELAY DONE` once it ran
+    fn frame(&self) -> Frame {
+        let token: &'static [u8] = if self.done { b"done" } else { b"at" };
+        let mut tokens = vec![
+            Frame::String(Bytes::from_static(b"delay")),
+            Frame::String(Bytes::from_static(token)),
+            Frame::String(Bytes::from(self.run_at.unwrap_or_default().to_string())),
+        ];
+        tokens.extend(self.command.iter().cloned().map(Frame::String));
+        Frame::Array(tokens)
+    }
 
-                    let timestamp = SystemTime::now()
-                        .add(Duration::from_millis(millis))
-                        .duration_since(UNIX_EPOCH)?
-                        .as_secs();
+    pub fn delay(&self) -> u64 {
+        self.delay
+    }
 
-                    match command.expire_at {
-                        Some(_) => return Err(ParseCommandError::InvalidFormat),
-                        None => command.expire_at = Some(timestamp),
-                    }
-                } else {
-                    return Err(ParseCommandError::InvalidArg(
-                        at_or_after_token,
-                        "set".to_string(),
-                    ));
-                }
-            } else if matches!(token.as_str(), "if") {
-                let not_or_exists_token = parser
-                    .next_as_string()?
-                    .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?
-                    .to_lowercase();
+    pub fn run_at(&self) -> Option<u64> {
+        self.run_at
+    }
 
-                if matches!(not_or_exists_token.as_str(), "not") {
-                    let exists_token = parser
-                        .next_as_string()?
-                        .ok_or_else(|| ParseCommandError::WrongArgCount("set".to_string()))?
-                        .to_lowercase();
-                    if matches!(exists_token.as_str(), "exists") {
-                        if !command.if_not_exists && !command.if_exists {
-                            command.if_not_exists = true
-                        } else {
-                            return Err(ParseCommandError::InvalidFormat);
-                        }
-                    } else {
-                        return Err(ParseCommandError::InvalidArg(
-                            exists_token,
-                            "set".to_string(),
-                        ));
-                    }
-                } else if matches!(not_or_exists_token.as_str(), "exists") {
-                    if !command.if_not_exists && !command.if_exists {
-                        command.if_exists = true
-                    } else {
-                        return Err(ParseCommandError::InvalidFormat);
-                    }
-                } else {
-                    return Err(ParseCommandError::InvalidArg(
-                        not_or_exists_token,
-                        "set".to_string(),
-                    ));
-                }
-            } else {
-                return Err(ParseCommandError::InvalidArg(token, "set".to_string()));
-            }
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    pub fn command(&self) -> Vec<Bytes> {
+        self.command.clone()
+    }
+}
+
+impl Explain {
+    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
+        let mut tokens = Vec::new();
+        while let Some(token) = parser.next() {
+            tokens.push(token);
         }
 
-        Ok(command)
+        if tokens.is_empty() {
+            return Err(ParseCommandError::WrongArgCount("explain".to_string()));
+        }
+
+        let command = Box::new(parse(Frame::Array(tokens))?);
+        Ok(Explain { command })
     }
 
-    pub fn expire_at(&self) -> Option<u64> {
-        self.expire_at
+    pub fn command(&self) -> &Command {
+        &self.command
     }
 
-    pub fn if_exists(&self) -> bool {
-        self.if_exists
+    // describes the command, `keyspaces` is null when the command works on
+    // every keyspace and `arguments` are the parsed arguments as debug output.
+    pub fn reply(&self) -> Frame {
+        let keyspaces = match self.command.keyspaces() {
+            Some(keyspaces) => Frame::Array(keyspaces.into_iter().map(Frame::String).collect()),
+            None => Frame::Null,
+        };
+        let keys = self
+            .command
+            .keys()
+            .into_iter()
+            .map(|(keyspace, key)| Frame::Array(vec![Frame::String(keyspace), Frame::String(key)]))
+            .collect();
+        Frame::Map(vec![
+            Frame::String(Bytes::from_static(b"command")),
+            Frame::String(Bytes::from_static(self.command.name().as_bytes())),
+            Frame::String(Bytes::from_static(b"keyspaces")),
+            keyspaces,
+            Frame::String(Bytes::from_static(b"keys")),
+            Frame::Array(keys),
+            Frame::String(Bytes::from_static(b"write")),
+            Frame::Boolean(self.command.is_write()),
+            Frame::String(Bytes::from_static(b"cost")),
+            Frame::String(Bytes::from_static(self.command.cost().as_bytes())),
+            Frame::String(Bytes::from_static(b"arguments")),
+            Frame::String(Bytes::from(format!("{:?}", self.command))),
+        ])
     }
+}
 
-    pub fn if_not_exists(&self) -> bool {
-        self.if_not_exists
+// collects the remaining tokens as a command that will be run at a later point,
+// the command is parsed right away so that invalid commands are rejected
+// upfront instead of failing when they are run.
+fn parse_deferred_command(
+    parser: &mut Parser,
+    name: &str,
+) -> Result<Vec<Bytes>, ParseCommandError> {
+    let mut command = Vec::new();
+    while let Some(token) = parser.next_as_bytes()? {
+        command.push(token);
     }
 
-    pub fn key(&self) -> Bytes {
-        self.key.clone()
+    if command.is_empty() {
+        return Err(ParseCommandError::WrongArgCount(name.to_string()));
     }
 
-    pub fn value(&self) -> Bytes {
-        self.value.clone()
+    parse(Frame::Array(
+        command.iter().cloned().map(Frame::String).collect(),
+    ))?;
+
+    Ok(command)
+}
+
+fn parse_schedule(parser: &mut Parser) -> Result<Command, ParseCommandError> {
+    let subcommand = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("schedule".to_string()))?
+        .to_lowercase();
+
+    match subcommand.as_str() {
+        "add" => Ok(Command::ScheduleAdd(ScheduleAdd::parse(parser)?)),
+        "remove" => Ok(Command::ScheduleRemove(ScheduleRemove::parse(parser)?)),
+        "list" => {
+            if parser.has_remaining() {
+                return Err(ParseCommandError::WrongArgCount("schedule".to_string()));
+            }
+            Ok(Command::ScheduleList)
+        }
+        _ => Err(ParseCommandError::InvalidArg(
+            subcommand,
+            "schedule".to_string(),
+        )),
     }
+}
 
-    pub fn keyspace(&self) -> Bytes {
-        self.keyspace.clone()
+fn parse_tasks(parser: &mut Parser) -> Result<Command, ParseCommandError> {
+    let subcommand = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("tasks".to_string()))?
+        .to_lowercase();
+
+    match subcommand.as_str() {
+        "list" => {
+            if parser.has_remaining() {
+                return Err(ParseCommandError::WrongArgCount("tasks".to_string()));
+            }
+            Ok(Command::TasksList)
+        }
+        "status" => Ok(Command::TasksStatus(TaskId::parse(parser)?)),
+        "cancel" => Ok(Command::TasksCancel(TaskId::parse(parser)?)),
+        _ => Err(ParseCommandError::InvalidArg(
+            subcommand,
+            "tasks".to_string(),
+        )),
     }
 }
 
-impl Get {
+#[cfg(feature = "wasm")]
+impl FunctionLoad {
     fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
-        let keyspace = parser
+        let library = parser
             .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("get".to_string()))?;
+            .ok_or_else(|| ParseCommandError::WrongArgCount("function".to_string()))?;
 
-        let key = parser
+        let module = parser
             .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("get".to_string()))?;
+            .ok_or_else(|| ParseCommandError::WrongArgCount("function".to_string()))?;
 
-        let command = Get { keyspace, key };
+        let command = FunctionLoad { library, module };
 
         if parser.has_remaining() {
-            return Err(ParseCommandError::WrongArgCount("get".to_string()));
+            return Err(ParseCommandError::WrongArgCount("function".to_string()));
         }
 
         Ok(command)
     }
 
-    pub fn keyspace(&self) -> Bytes {
-        self.keyspace.clone()
+    pub fn library(&self) -> Bytes {
+        self.library.clone()
     }
 
-    pub fn key(&self) -> Bytes {
-        self.key.clone()
+    pub fn module(&self) -> Bytes {
+        self.module.clone()
     }
 }
 
-impl Del {
+#[cfg(feature = "wasm")]
+impl FunctionDelete {
     fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
-        let keyspace = parser
-            .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("del".to_string()))?;
-
-        let key = parser
+        let library = parser
             .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("del".to_string()))?;
+            .ok_or_else(|| ParseCommandError::WrongArgCount("function".to_string()))?;
 
-        let command = Del { keyspace, key };
+        let command = FunctionDelete { library };
 
         if parser.has_remaining() {
-            return Err(ParseCommandError::WrongArgCount("del".to_string()));
+            return Err(ParseCommandError::WrongArgCount("function".to_string()));
         }
 
         Ok(command)
     }
 
-    pub fn keyspace(&self) -> Bytes {
-        self.keyspace.clone()
-    }
-
-    pub fn key(&self) -> Bytes {
-        self.key.clone()
+    pub fn library(&self) -> Bytes {
+        self.library.clone()
     }
 }
 
-impl Drop {
+#[cfg(feature = "wasm")]
+impl Fcall {
     fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
-        let keyspace = parser
+        let library = parser
             .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("drop".to_string()))?;
+            .ok_or_else(|| ParseCommandError::WrongArgCount("fcall".to_string()))?;
 
-        let mut command = Drop {
-            keyspace,
-            if_exists: false,
-        };
+        let function = parser
+            .next_as_bytes()?
+            .ok_or_else(|| ParseCommandError::WrongArgCount("fcall".to_string()))?;
 
-        if !parser.has_remaining() {
-            return Ok(command);
-        }
+        Self::parse_args(parser, library, function, "fcall")
+    }
+
+    fn parse_args(
+        parser: &mut Parser,
+        library: Bytes,
+        function: Bytes,
+        name: &str,
+    ) -> Result<Self, ParseCommandError> {
+        let mut command = Fcall {
+            library,
+            function,
+            args: Vec::new(),
+        };
 
         while parser.has_remaining() {
-            let key = parser
+            let value = parser
                 .next_as_string()?
-                .ok_or_else(|| ParseCommandError::WrongArgCount("drop".to_string()))?
-                .to_lowercase();
-
-            if matches!(key.as_str(), "if") {
-                let exists_token = parser
-                    .next_as_string()?
-                    .ok_or_else(|| ParseCommandError::WrongArgCount("drop".to_string()))?
-                    .to_lowercase();
-                if matches!(exists_token.as_str(), "exists") {
-                    if !command.if_exists {
-                        command.if_exists = true
-                    } else {
-                        return Err(ParseCommandError::InvalidFormat);
-                    }
-                } else {
-                    return Err(ParseCommandError::InvalidArg(
-                        exists_token,
-                        "set".to_string(),
-                    ));
-                }
-            } else {
-                return Err(ParseCommandError::InvalidArg(key, "drop".to_string()));
-            }
+                .ok_or_else(|| ParseCommandError::WrongArgCount(name.to_string()))?;
+            let arg = value.parse::<i64>().map_err(|_| {
+                ParseCommandError::InvalidArgValue(value, "args".to_string(), name.to_string())
+            })?;
+            command.args.push(arg);
         }
 
         Ok(command)
     }
 
-    pub fn keyspace(&self) -> Bytes {
-        self.keyspace.clone()
+    pub fn library(&self) -> Bytes {
+        self.library.clone()
     }
 
-    pub fn if_exists(&self) -> bool {
-        self.if_exists
+    pub fn function(&self) -> Bytes {
+        self.function.clone()
+    }
+
+    pub fn args(&self) -> Vec<i64> {
+        self.args.clone()
     }
 }
 
-impl Count {
+#[cfg(feature = "lua")]
+impl Eval {
     fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
-        let keyspace = parser
+        let script = parser
             .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("count".to_string()))?;
+            .ok_or_else(|| ParseCommandError::WrongArgCount("eval".to_string()))?;
 
-        let command = Count { keyspace };
+        let mut command = Eval {
+            script,
+            args: Vec::new(),
+        };
 
-        if parser.has_remaining() {
-            return Err(ParseCommandError::WrongArgCount("count".to_string()));
+        while let Some(arg) = parser.next_as_bytes()? {
+            command.args.push(arg);
         }
 
         Ok(command)
     }
 
-    pub fn keyspace(&self) -> Bytes {
-        self.keyspace.clone()
+    pub fn script(&self) -> Bytes {
+        self.script.clone()
+    }
+
+    pub fn args(&self) -> &[Bytes] {
+        &self.args
     }
 }
 
-impl Ttl {
-    fn parse(parser: &mut Parser) -> Result<Self, ParseCommandError> {
-        let keyspace = parser
-            .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("ttl".to_string()))?;
+#[cfg(feature = "wasm")]
+fn parse_function(parser: &mut Parser) -> Result<Command, ParseCommandError> {
+    let subcommand = parser
+        .next_as_string()?
+        .ok_or_else(|| ParseCommandError::WrongArgCount("function".to_string()))?
+        .to_lowercase();
 
-        let key = parser
-            .next_as_bytes()?
-            .ok_or_else(|| ParseCommandError::WrongArgCount("ttl".to_string()))?;
+    match subcommand.as_str() {
+        "load" => Ok(Command::FunctionLoad(FunctionLoad::parse(parser)?)),
+        "delete" => Ok(Command::FunctionDelete(FunctionDelete::parse(parser)?)),
+        "list" => {
+            if parser.has_remaining() {
+                return Err(ParseCommandError::WrongArgCount("function".to_string()));
+            }
+            Ok(Command::FunctionList)
+        }
+        _ => Err(ParseCommandError::InvalidArg(
+            subcommand,
+            "function".to_string(),
+        )),
+    }
+}
 
-        let command = Ttl { keyspace, key };
+// commands registered by plugins along with the library and function that
+// implement them
+#[cfg(feature = "wasm")]
+static PLUGIN_COMMANDS: LazyLock<RwLock<HashMap<String, (Bytes, Bytes)>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
 
-        if parser.has_remaining() {
-            return Err(ParseCommandError::WrongArgCount("ttl".to_string()));
-        }
+// registers a function of a plugin library as a command, calling the command
+// calls the function with the integer arguments of the command. built in
+// commands can't be replaced, they always take precedence.
+#[cfg(feature = "wasm")]
+pub fn register_plugin_command(name: &str, library: Bytes, function: Bytes) {
+    PLUGIN_COMMANDS
+        .write()
+        .insert(name.to_lowercase(), (library, function));
+}
 
-        Ok(command)
+// parses a command which doesn't take any arguments
+fn parse_no_args(
+    parser: &mut Parser,
+    name: &str,
+    command: Command,
+) -> Result<Command, ParseCommandError> {
+    if parser.has_remaining() {
+        return Err(ParseCommandError::WrongArgCount(name.to_string()));
     }
+    Ok(command)
+}
 
-    pub fn keyspace(&self) -> Bytes {
-        self.keyspace.clone()
-    }
+// parses a command sent on a connection that uses a keyspace, see `USE`.
+// `GET`, `SET` and `DEL` leave out their keyspace there, it is put back in
+// before the command is parsed.
+pub fn parse_in_keyspace(frame: Frame, keyspace: &Bytes) -> Result<Command, ParseCommandError> {
+    parse(in_keyspace(frame, keyspace))
+}
 
-    pub fn key(&self) -> Bytes {
-        self.key.clone()
+// adds the keyspace to the commands which can omit it, see `USE`
+pub fn in_keyspace(frame: Frame, keyspace: &Bytes) -> Frame {
+    match frame {
+        Frame::Array(mut tokens) => {
+            let name = match tokens.first() {
+                Some(Frame::String(name)) => name.to_ascii_lowercase(),
+                _ => Vec::new(),
+            };
+            if matches!(&name[..], b"get" | b"set" | b"del") {
+                tokens.insert(1, Frame::String(keyspace.clone()));
+            }
+            Frame::Array(tokens)
+        }
+        frame => frame,
     }
 }
 
 pub fn parse(frame: Frame) -> Result<Command, ParseCommandError> {
+    let command = registry::validate(&frame)?;
     let mut parser = Parser::new(frame)?;
-    let command = match parser.next().ok_or(ParseCommandError::InvalidFormat)? {
-        Frame::String(data) => str::from_utf8(&data[..])?.to_lowercase(),
-        _ => return Err(ParseCommandError::InvalidFormat),
-    };
+    parser.next();
 
     match command.as_str() {
         "create" => Ok(Command::Create(Create::parse(&mut parser)?)),
+        "alter" => Ok(Command::Alter(Alter::parse(&mut parser)?)),
         "set" => Ok(Command::Set(Set::parse(&mut parser)?)),
+        "mset" => Ok(Command::MSet(MSet::parse(&mut parser)?)),
+        "cset" => Ok(Command::CSet(CSet::parse(&mut parser)?)),
         "get" => Ok(Command::Get(Get::parse(&mut parser)?)),
+        "xget" => Ok(Command::XGet(XGet::parse(&mut parser)?)),
         "del" => Ok(Command::Del(Del::parse(&mut parser)?)),
         "drop" => Ok(Command::Drop(Drop::parse(&mut parser)?)),
         "count" => Ok(Command::Count(Count::parse(&mut parser)?)),
+        "range" => Ok(Command::Range(Range::parse(&mut parser)?)),
+        "delrange" => Ok(Command::DelRange(DelRange::parse(&mut parser)?)),
+        "countbyprefix" => Ok(Command::CountByPrefix(CountByPrefix::parse(&mut parser)?)),
+        "sample" => Ok(Command::Sample(Sample::parse(&mut parser)?)),
+        "evictstats" => Ok(Command::EvictStats(EvictStats::parse(&mut parser)?)),
+        "evictor" => parse_evictor_control(&mut parser),
+        "sum" => Ok(Command::Sum(Sum::parse(&mut parser)?)),
+        "avg" => Ok(Command::Avg(Avg::parse(&mut parser)?)),
         "ttl" => Ok(Command::Ttl(Ttl::parse(&mut parser)?)),
-        "ping" => Ok(Command::Ping),
+        "expireat" => Ok(Command::ExpireAt(ExpireAt::parse(&mut parser)?)),
+        "type" => Ok(Command::Type(Type::parse(&mut parser)?)),
+        "lpush" => Ok(Command::LPush(Push::parse(&mut parser, "lpush")?)),
+        "rpush" => Ok(Command::RPush(Push::parse(&mut parser, "rpush")?)),
+        "lpop" => Ok(Command::LPop(Pop::parse(&mut parser, "lpop")?)),
+        "rpop" => Ok(Command::RPop(Pop::parse(&mut parser, "rpop")?)),
+        "lrange" => Ok(Command::LRange(LRange::parse(&mut parser)?)),
+        "llen" => Ok(Command::LLen(LLen::parse(&mut parser)?)),
+        "hset" => Ok(Command::HSet(HSet::parse(&mut parser)?)),
+        "hget" => Ok(Command::HGet(HGet::parse(&mut parser)?)),
+        "hdel" => Ok(Command::HDel(HDel::parse(&mut parser)?)),
+        "hgetall" => Ok(Command::HGetAll(HGetAll::parse(&mut parser)?)),
+        "zadd" => Ok(Command::ZAdd(ZAdd::parse(&mut parser)?)),
+        "zrange" => Ok(Command::ZRange(ZRange::parse(&mut parser)?)),
+        "zscore" => Ok(Command::ZScore(ZScore::parse(&mut parser)?)),
+        "zrem" => Ok(Command::ZRem(ZRem::parse(&mut parser)?)),
+        "setbit" => Ok(Command::SetBit(SetBit::parse(&mut parser)?)),
+        "getbit" => Ok(Command::GetBit(GetBit::parse(&mut parser)?)),
+        "bitcount" => Ok(Command::BitCount(BitCount::parse(&mut parser)?)),
+        "getrange" => Ok(Command::GetRange(GetRange::parse(&mut parser)?)),
+        "setrange" => Ok(Command::SetRange(SetRange::parse(&mut parser)?)),
+        "xadd" => Ok(Command::XAdd(XAdd::parse(&mut parser)?)),
+        "xrange" => Ok(Command::XRange(XRange::parse(&mut parser)?)),
+        "xlen" => Ok(Command::XLen(XLen::parse(&mut parser)?)),
+        "ping" => Ok(Command::Ping(Ping::parse(&mut parser)?)),
+        "echo" => Ok(Command::Echo(Echo::parse(&mut parser)?)),
+        "info" => {
+            if parser.has_remaining() {
+                return Err(ParseCommandError::WrongArgCount("info".to_string()));
+            }
+            Ok(Command::Info)
+        }
         "keyspaces" => Ok(Command::Keyspaces),
-        _ => Err(ParseCommandError::UnknownCommand(command)),
+        "flushall" => Ok(Command::FlushAll(FlushAll::parse(&mut parser)?)),
+        "save" => parse_no_args(&mut parser, "save", Command::Save),
+        "bgsave" => parse_no_args(&mut parser, "bgsave", Command::BgSave),
+        "fsync" => parse_no_args(&mut parser, "fsync", Command::Fsync),
+        "export" => Ok(Command::Export(Export::parse(&mut parser)?)),
+        "schedule" => parse_schedule(&mut parser),
+        "tasks" => parse_tasks(&mut parser),
+        "delay" => Ok(Command::Delay(Delay::parse(&mut parser)?)),
+        "explain" => Ok(Command::Explain(Explain::parse(&mut parser)?)),
+        "watchkey" => Ok(Command::WatchKey(WatchKey::parse(&mut parser)?)),
+        "watchlifecycle" => parse_no_args(&mut parser, "watchlifecycle", Command::WatchLifecycle),
+        "use" => Ok(Command::Use(Use::parse(&mut parser)?)),
+        "watch" => Ok(Command::Watch(Watch::parse(&mut parser)?)),
+        "unwatch" => parse_no_args(&mut parser, "unwatch", Command::Unwatch),
+        "multi" => parse_no_args(&mut parser, "multi", Command::Multi),
+        "exec" => parse_no_args(&mut parser, "exec", Command::Exec),
+        "discard" => parse_no_args(&mut parser, "discard", Command::Discard),
+        "batch" => Ok(Command::Batch(Batch::parse(&mut parser)?)),
+        "view" => parse_view(&mut parser),
+        "debug" => parse_debug(&mut parser),
+        "command" => parse_command(&mut parser),
+        "client" => parse_client(&mut parser),
+        "memory" => parse_memory(&mut parser),
+        "object" => parse_object(&mut parser),
+        "pin" => Ok(Command::Pin(Pin::parse(&mut parser, "pin")?)),
+        "unpin" => Ok(Command::Unpin(Pin::parse(&mut parser, "unpin")?)),
+        "undelete" => Ok(Command::Undelete(Undelete::parse(&mut parser)?)),
+        "dump" => Ok(Command::Dump(Dump::parse(&mut parser)?)),
+        "restore" => Ok(Command::Restore(Restore::parse(&mut parser)?)),
+        #[cfg(feature = "lua")]
+        "eval" => Ok(Command::Eval(Eval::parse(&mut parser)?)),
+        #[cfg(feature = "wasm")]
+        "function" => parse_function(&mut parser),
+        #[cfg(feature = "wasm")]
+        "fcall" => Ok(Command::Fcall(Fcall::parse(&mut parser)?)),
+        _ => {
+            #[cfg(feature = "wasm")]
+            if let Some((library, function)) = PLUGIN_COMMANDS.read().get(&command).cloned() {
+                return Ok(Command::Fcall(Fcall::parse_args(
+                    &mut parser,
+                    library,
+                    function,
+                    &command,
+                )?));
+            }
+            Err(ParseCommandError::UnknownCommand(command))
+        }
     }
 }
@@ -0,0 +1,231 @@
+use super::{ParseCommandError, MAX_VALUE_SIZE};
+use crate::frame::Frame;
+use std::str;
+
+// the most arguments a single command can have
+pub const MAX_ARGS: usize = 1024 * 1024;
+// the most bytes the arguments of a single command can add up to, enough for
+// the largest value along with the rest of its command
+pub const MAX_REQUEST_SIZE: usize = MAX_VALUE_SIZE as usize + 1024 * 1024;
+
+// the number of arguments every command takes, not counting its name but
+// counting its subcommand. `max` is `None` for commands that take any number
+// of arguments, only the commands marked with `arrays` take arrays, like the
+// commands of a batch, all other arguments are strings. commands that aren't
+// listed, like plugin commands, are left to their parsers.
+const COMMANDS: &[(&str, usize, Option<usize>, bool)] = &[
+    ("create", 1, None, false),
+    ("alter", 3, Some(7), false),
+    ("drop", 1, Some(3), false),
+    ("keyspaces", 0, Some(0), false),
+    ("flushall", 0, Some(2), false),
+    ("save", 0, Some(0), false),
+    ("bgsave", 0, Some(0), false),
+    ("fsync", 0, Some(0), false),
+    ("export", 2, Some(4), false),
+    ("count", 1, Some(1), false),
+    ("range", 3, Some(3), false),
+    ("delrange", 3, Some(3), false),
+    ("countbyprefix", 2, Some(2), false),
+    ("sample", 2, Some(2), false),
+    ("evictstats", 1, Some(1), false),
+    ("evictor", 2, Some(2), false),
+    ("sum", 1, Some(3), false),
+    ("avg", 1, Some(3), false),
+    ("set", 3, None, false),
+    ("mset", 3, None, false),
+    ("cset", 4, Some(4), false),
+    ("get", 2, Some(3), false),
+    ("xget", 1, None, true),
+    ("del", 2, Some(2), false),
+    ("ttl", 2, Some(2), false),
+    ("expireat", 3, Some(3), false),
+    ("pin", 2, Some(2), false),
+    ("unpin", 2, Some(2), false),
+    ("undelete", 2, Some(2), false),
+    ("dump", 2, Some(2), false),
+    ("restore", 3, Some(7), false),
+    ("type", 2, Some(2), false),
+    ("setbit", 4, Some(4), false),
+    ("getbit", 3, Some(3), false),
+    ("bitcount", 2, Some(4), false),
+    ("getrange", 4, Some(4), false),
+    ("setrange", 4, Some(4), false),
+    ("lpush", 3, None, false),
+    ("rpush", 3, None, false),
+    ("lpop", 2, Some(2), false),
+    ("rpop", 2, Some(2), false),
+    ("lrange", 4, Some(4), false),
+    ("llen", 2, Some(2), false),
+    ("hset", 4, None, false),
+    ("hget", 3, Some(3), false),
+    ("hdel", 3, None, false),
+    ("hgetall", 2, Some(2), false),
+    ("zadd", 4, None, false),
+    ("zrange", 4, Some(5), false),
+    ("zscore", 3, Some(3), false),
+    ("zrem", 3, None, false),
+    ("xadd", 5, None, false),
+    ("xrange", 4, Some(6), false),
+    ("xlen", 2, Some(2), false),
+    ("watch", 2, Some(2), false),
+    ("unwatch", 0, Some(0), false),
+    ("multi", 0, Some(0), false),
+    ("exec", 0, Some(0), false),
+    ("discard", 0, Some(0), false),
+    ("batch", 2, None, true),
+    ("view", 1, Some(3), false),
+    ("schedule", 1, None, false),
+    ("delay", 2, None, false),
+    ("watchkey", 2, Some(2), false),
+    ("watchlifecycle", 0, Some(0), false),
+    ("use", 0, Some(1), false),
+    ("ping", 0, Some(1), false),
+    ("echo", 1, Some(1), false),
+    ("info", 0, Some(0), false),
+    ("client", 1, Some(1), false),
+    ("memory", 3, Some(3), false),
+    ("object", 3, Some(3), false),
+    ("command", 1, None, false),
+    ("explain", 1, None, true),
+    ("debug", 2, Some(4), false),
+    ("tasks", 1, Some(2), false),
+    #[cfg(feature = "lua")]
+    ("eval", 1, None, false),
+    #[cfg(feature = "wasm")]
+    ("function", 1, Some(3), false),
+    #[cfg(feature = "wasm")]
+    ("fcall", 2, None, false),
+];
+
+// checks the shape of a command before its parser runs: the name has to be a
+// utf-8 string, the number of arguments has to fit the command and the limits,
+// and the arguments have to be strings, or arrays for the commands that take
+// them. returns the lowercased name of the command.
+pub fn validate(frame: &Frame) -> Result<String, ParseCommandError> {
+    let tokens = match frame {
+        Frame::Array(tokens) => tokens,
+        _ => return Err(ParseCommandError::InvalidFormat),
+    };
+    let name = match tokens.first() {
+        Some(Frame::String(name)) => str::from_utf8(name)?.to_lowercase(),
+        _ => return Err(ParseCommandError::InvalidFormat),
+    };
+
+    let args = &tokens[1..];
+    if args.len() > MAX_ARGS {
+        return Err(ParseCommandError::TooManyArgs(name, MAX_ARGS));
+    }
+    let size = request_size(frame);
+    if size > MAX_REQUEST_SIZE {
+        return Err(ParseCommandError::RequestTooLarge(size, MAX_REQUEST_SIZE));
+    }
+
+    let (min, max, arrays) = match COMMANDS.iter().find(|(known, ..)| *known == name) {
+        Some((_, min, max, arrays)) => (*min, *max, *arrays),
+        None => return Ok(name),
+    };
+    if args.len() < min || max.is_some_and(|max| args.len() > max) {
+        return Err(ParseCommandError::WrongArgCount(name));
+    }
+    for arg in args {
+        match arg {
+            Frame::String(_) => {}
+            Frame::Array(_) if arrays => {}
+            _ => return Err(ParseCommandError::InvalidFormat),
+        }
+    }
+    Ok(name)
+}
+
+// the number of bytes held by the strings of the frame
+fn request_size(frame: &Frame) -> usize {
+    match frame {
+        Frame::String(data) => data.len(),
+        Frame::Array(frames) => frames.iter().map(request_size).sum(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::docs;
+    use bytes::Bytes;
+
+    fn command(tokens: &[&str]) -> Frame {
+        Frame::Array(
+            tokens
+                .iter()
+                .map(|token| Frame::String(Bytes::copy_from_slice(token.as_bytes())))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn validate_given_documented_commands_knows_all_of_them() {
+        let docs = match docs::reply(&[]) {
+            Frame::Map(docs) => docs,
+            _ => unreachable!(),
+        };
+        for name in docs.iter().step_by(2) {
+            let name = match name {
+                Frame::String(name) => str::from_utf8(name).unwrap(),
+                _ => unreachable!(),
+            };
+            assert!(
+                COMMANDS.iter().any(|(known, ..)| *known == name),
+                "{} is missing",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn validate_given_wrong_number_of_args_returns_wrong_arg_count_error() {
+        assert_eq!(validate(&command(&["GET", "foo", "bar"])).unwrap(), "get");
+        assert!(matches!(
+            validate(&command(&["get", "foo"])),
+            Err(ParseCommandError::WrongArgCount(name)) if name == "get"
+        ));
+        assert!(matches!(
+            validate(&command(&["keyspaces", "foo"])),
+            Err(ParseCommandError::WrongArgCount(name)) if name == "keyspaces"
+        ));
+        // unknown commands are left to the parser
+        assert_eq!(validate(&command(&["foo", "bar"])).unwrap(), "foo");
+    }
+
+    #[test]
+    fn validate_given_unexpected_arg_types_returns_invalid_format_error() {
+        let frame = Frame::Array(vec![
+            Frame::String(Bytes::from("get")),
+            Frame::String(Bytes::from("foo")),
+            Frame::Integer(1),
+        ]);
+        assert!(matches!(
+            validate(&frame),
+            Err(ParseCommandError::InvalidFormat)
+        ));
+        let frame = Frame::Array(vec![
+            Frame::String(Bytes::from("xget")),
+            command(&["foo", "bar"]),
+        ]);
+        assert!(validate(&frame).is_ok());
+        let frame = Frame::Array(vec![Frame::String(Bytes::from_static(&[0xff]))]);
+        assert!(matches!(
+            validate(&frame),
+            Err(ParseCommandError::Utf8Error(_))
+        ));
+    }
+
+    #[test]
+    fn validate_given_too_many_args_returns_too_many_args_error() {
+        let mut tokens = vec![Frame::String(Bytes::from("mset"))];
+        tokens.resize(MAX_ARGS + 2, Frame::String(Bytes::from("foo")));
+        assert!(matches!(
+            validate(&Frame::Array(tokens)),
+            Err(ParseCommandError::TooManyArgs(name, MAX_ARGS)) if name == "mset"
+        ));
+    }
+}
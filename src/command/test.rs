@@ -1,12 +1,28 @@
-use super::parse;
+use super::{parse, parse_in_keyspace};
+#[cfg(feature = "lua")]
+use crate::command::Eval;
+#[cfg(feature = "wasm")]
+use crate::command::{register_plugin_command, Fcall, FunctionLoad};
 use crate::db::Evictor;
+use crate::export::ExportFormat;
 use crate::{
-    command::{Command, Count, Create, Del, Drop, Get, Set, Ttl},
+    command::{
+        docs, Alter, Avg, Batch, BitCount, CSet, Command, CommandDocs, Count, CountByPrefix,
+        Create, DebugPopulate, DebugProtocol, DebugQuickCheck, Del, DelRange, Delay, Drop, Dump,
+        Echo, EvictStats, EvictorControl, ExpireAt, Explain, Export, FlushAll, Get, GetRange, HGet,
+        HGetAll, HSet, LLen, LRange, MSet, MemoryUsage, Object, Pin, Ping, Pop, Push, Range,
+        Restore, Sample, ScheduleAdd, ScheduleRemove, Set, SetBit, SetRange, Sum, TaskId, Ttl,
+        Type, Undelete, Use, ViewCreate, ViewDrop, Watch, WatchKey, XAdd, XGet, XRange, ZAdd,
+        ZRange, ZRem,
+    },
     frame::Frame,
+    stream::StreamId,
+    upstream::UpstreamConfig,
 };
 use bytes::Bytes;
-use std::ops::Add;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use cron::Schedule;
+use std::str::FromStr;
+use std::time::Duration;
 
 fn get_frame_from_str(str: &'static str) -> Frame {
     Frame::String(Bytes::from(str))
@@ -43,6 +59,17 @@ fn parse_given_create_command_with_keyspace_and_no_config_returns_create() {
         Command::Create(Create {
             evictor: Evictor::Nop,
             if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
             keyspace: Bytes::from("foo")
         })
     );
@@ -82,6 +109,17 @@ fn parse_given_create_command_with_lru_evictor_returns_create() {
         Command::Create(Create {
             evictor: Evictor::Lru,
             if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
             keyspace: Bytes::from("foo")
         })
     );
@@ -100,6 +138,17 @@ fn parse_given_create_command_with_random_evictor_returns_create() {
         Command::Create(Create {
             evictor: Evictor::Random,
             if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
             keyspace: Bytes::from("foo")
         })
     );
@@ -118,6 +167,17 @@ fn parse_given_create_command_with_nop_evictor_returns_create() {
         Command::Create(Create {
             evictor: Evictor::Nop,
             if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
             keyspace: Bytes::from("foo")
         })
     );
@@ -177,11 +237,422 @@ fn parse_given_create_command_with_if_not_exists_flag_returns_create() {
         Command::Create(Create {
             evictor: Evictor::Lru,
             if_not_exists: true,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
             keyspace: Bytes::from("foo")
         })
     );
 }
 
+#[test]
+fn parse_given_create_command_with_temp_flag_returns_create() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("temp"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Create(Create {
+            evictor: Evictor::Nop,
+            if_not_exists: false,
+            temp: true,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
+            keyspace: Bytes::from("foo")
+        })
+    );
+}
+
+#[test]
+fn parse_given_create_command_with_ordered_flag_returns_create() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("ordered"),
+        get_frame_from_str("YES"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Create(Create {
+            evictor: Evictor::Nop,
+            if_not_exists: false,
+            temp: false,
+            ordered: true,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
+            keyspace: Bytes::from("foo")
+        })
+    );
+}
+
+#[test]
+fn parse_given_create_command_with_retention_returns_create() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("retention"),
+        get_frame_from_str("60000"),
+        get_frame_from_str("ordered"),
+        get_frame_from_str("yes"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Create(Create {
+            evictor: Evictor::Nop,
+            if_not_exists: false,
+            temp: false,
+            ordered: true,
+            retention: Some(60000),
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
+            keyspace: Bytes::from("foo")
+        })
+    );
+}
+
+#[test]
+fn parse_given_create_command_with_retention_without_ordered_returns_error() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("retention"),
+        get_frame_from_str("60000"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_create_command_with_samples_returns_create() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("evictor"),
+        get_frame_from_str("lru"),
+        get_frame_from_str("samples"),
+        get_frame_from_str("10"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Create(Create {
+            evictor: Evictor::Lru,
+            if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 10,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
+            keyspace: Bytes::from("foo")
+        })
+    );
+}
+
+#[test]
+fn parse_given_create_command_with_zero_samples_returns_error() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("samples"),
+        get_frame_from_str("0"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_create_command_with_upstream_returns_create() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("cache"),
+        get_frame_from_str("5000"),
+        get_frame_from_str("upstream"),
+        get_frame_from_str("127.0.0.1:1698"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Create(Create {
+            evictor: Evictor::Nop,
+            if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: Some(UpstreamConfig {
+                address: "127.0.0.1:1698".to_string(),
+                cache_ttl: 5000,
+            }),
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
+            keyspace: Bytes::from("foo")
+        })
+    );
+}
+
+#[test]
+fn parse_given_create_command_with_canary_returns_create() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo_lru"),
+        get_frame_from_str("canary"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("evictor"),
+        get_frame_from_str("lru"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Create(Create {
+            evictor: Evictor::Lru,
+            if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: Some(Bytes::from("foo")),
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
+            keyspace: Bytes::from("foo_lru")
+        })
+    );
+}
+
+#[test]
+fn parse_given_create_command_with_spill_returns_create() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("evictor"),
+        get_frame_from_str("lru"),
+        get_frame_from_str("spill"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Create(Create {
+            evictor: Evictor::Lru,
+            if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: true,
+            weight: 1,
+            keyspace: Bytes::from("foo")
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("spill"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_create_command_with_maxmem_returns_create() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("evictor"),
+        get_frame_from_str("lru"),
+        get_frame_from_str("maxmem"),
+        get_frame_from_str("256MB"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Create(Create {
+            evictor: Evictor::Lru,
+            if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 256 * 1024 * 1024,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
+            keyspace: Bytes::from("foo")
+        })
+    );
+}
+
+#[test]
+fn parse_given_create_command_with_maxkeys_returns_create() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("maxkeys"),
+        get_frame_from_str("1000"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Create(Create {
+            evictor: Evictor::Nop,
+            if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 1000,
+            tombstone: None,
+            spill: false,
+            weight: 1,
+            keyspace: Bytes::from("foo")
+        })
+    );
+}
+
+#[test]
+fn parse_given_create_command_with_invalid_maxkeys_returns_error() {
+    for maxkeys in ["0", "-1", "ten"] {
+        let command = vec![
+            get_frame_from_str("create"),
+            get_frame_from_str("foo"),
+            get_frame_from_str("maxkeys"),
+            get_frame_from_str(maxkeys),
+        ];
+        assert!(parse(Frame::Array(command)).is_err());
+    }
+}
+
+#[test]
+fn parse_given_create_command_with_weight_returns_create() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("evictor"),
+        get_frame_from_str("lru"),
+        get_frame_from_str("weight"),
+        get_frame_from_str("4"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Create(Create {
+            evictor: Evictor::Lru,
+            if_not_exists: false,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 4,
+            keyspace: Bytes::from("foo")
+        })
+    );
+}
+
+#[test]
+fn parse_given_create_command_with_invalid_maxmem_returns_error() {
+    for (evictor, maxmem) in [
+        ("lru", "256tb"),
+        ("lru", "0"),
+        ("lru", "mb"),
+        ("nop", "1mb"),
+    ] {
+        let command = vec![
+            get_frame_from_str("create"),
+            get_frame_from_str("foo"),
+            get_frame_from_str("evictor"),
+            get_frame_from_str(evictor),
+            get_frame_from_str("maxmem"),
+            get_frame_from_str(maxmem),
+        ];
+        assert!(parse(Frame::Array(command)).is_err());
+    }
+}
+
+#[test]
+fn parse_given_create_command_with_cache_without_upstream_returns_error() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("cache"),
+        get_frame_from_str("5000"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_create_command_with_invalid_ordered_value_returns_error() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("ordered"),
+        get_frame_from_str("maybe"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_create_command_with_duplicate_temp_flag_returns_error() {
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("temp"),
+        get_frame_from_str("temp"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
 #[test]
 fn parse_given_create_command_with_if_not_exists_flag_and_evictor_position_reversed_returns_create()
 {
@@ -199,6 +670,17 @@ fn parse_given_create_command_with_if_not_exists_flag_and_evictor_position_rever
         Command::Create(Create {
             evictor: Evictor::Lru,
             if_not_exists: true,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
             keyspace: Bytes::from("foo")
         })
     );
@@ -218,6 +700,17 @@ fn parse_given_create_command_with_if_not_exists_flag_and_default_evictor_return
         Command::Create(Create {
             evictor: Evictor::Nop,
             if_not_exists: true,
+            temp: false,
+            ordered: false,
+            retention: None,
+            sample_size: 3,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: 1,
             keyspace: Bytes::from("foo")
         })
     );
@@ -275,6 +768,7 @@ fn parse_given_set_command_without_config_returns_set() {
             value: Bytes::from("bar"),
             if_not_exists: false,
             expire_at: None,
+            expire_after: None,
             if_exists: false
         })
     );
@@ -338,6 +832,7 @@ fn parse_given_set_command_with_expire_at_returns_set() {
             value: Bytes::from("bar"),
             if_not_exists: false,
             expire_at: Some(1667041052),
+            expire_after: None,
             if_exists: false
         })
     );
@@ -395,12 +890,6 @@ fn parse_given_set_command_with_expire_after_returns_set() {
         get_frame_from_str("60000"),
     ];
 
-    let timestamp = SystemTime::now()
-        .add(Duration::from_millis(60000))
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-
     assert_eq!(
         parse(Frame::Array(command)).unwrap(),
         Command::Set(Set {
@@ -408,7 +897,8 @@ fn parse_given_set_command_with_expire_after_returns_set() {
             key: Bytes::from("foo"),
             value: Bytes::from("bar"),
             if_not_exists: false,
-            expire_at: Some(timestamp),
+            expire_at: None,
+            expire_after: Some(60000),
             if_exists: false
         })
     );
@@ -481,6 +971,7 @@ fn parse_given_set_command_with_if_exists_flag_returns_set() {
             value: Bytes::from("bar"),
             if_not_exists: false,
             expire_at: None,
+            expire_after: None,
             if_exists: true
         })
     );
@@ -539,6 +1030,7 @@ fn parse_given_set_command_with_if_not_exists_flag_returns_set() {
             value: Bytes::from("bar"),
             if_not_exists: true,
             expire_at: None,
+            expire_after: None,
             if_exists: false
         })
     );
@@ -564,18 +1056,13 @@ fn parse_given_set_command_with_both_if_exists_and_if_not_exists_returns_error()
 }
 
 #[test]
-fn parse_given_set_command_with_if_not_exists_and_tll_returns_set() {
+fn parse_given_set_command_with_nx_flag_returns_set() {
     let command = vec![
         get_frame_from_str("set"),
         get_frame_from_str("my_keyspace"),
         get_frame_from_str("foo"),
         get_frame_from_str("bar"),
-        get_frame_from_str("expire"),
-        get_frame_from_str("at"),
-        get_frame_from_str("1667041052"),
-        get_frame_from_str("if"),
-        get_frame_from_str("not"),
-        get_frame_from_str("exists"),
+        get_frame_from_str("nx"),
     ];
 
     assert_eq!(
@@ -585,24 +1072,21 @@ fn parse_given_set_command_with_if_not_exists_and_tll_returns_set() {
             key: Bytes::from("foo"),
             value: Bytes::from("bar"),
             if_not_exists: true,
-            expire_at: Some(1667041052),
+            expire_at: None,
+            expire_after: None,
             if_exists: false
         })
     );
 }
 
 #[test]
-fn parse_given_set_command_with_if_exists_and_tll_returns_set() {
+fn parse_given_set_command_with_xx_flag_returns_set() {
     let command = vec![
         get_frame_from_str("set"),
         get_frame_from_str("my_keyspace"),
         get_frame_from_str("foo"),
         get_frame_from_str("bar"),
-        get_frame_from_str("expire"),
-        get_frame_from_str("at"),
-        get_frame_from_str("1667041052"),
-        get_frame_from_str("if"),
-        get_frame_from_str("exists"),
+        get_frame_from_str("XX"),
     ];
 
     assert_eq!(
@@ -612,25 +1096,39 @@ fn parse_given_set_command_with_if_exists_and_tll_returns_set() {
             key: Bytes::from("foo"),
             value: Bytes::from("bar"),
             if_not_exists: false,
-            expire_at: Some(1667041052),
+            expire_at: None,
+            expire_after: None,
             if_exists: true
         })
     );
 }
 
 #[test]
-fn parse_given_set_command_with_if_not_exists_and_tll_reverse_order_returns_set() {
+fn parse_given_set_command_with_both_nx_and_xx_returns_error() {
+    let command = vec![
+        get_frame_from_str("set"),
+        get_frame_from_str("my_keyspace"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("nx"),
+        get_frame_from_str("xx"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_set_command_with_if_not_exists_and_tll_returns_set() {
     let command = vec![
         get_frame_from_str("set"),
         get_frame_from_str("my_keyspace"),
         get_frame_from_str("foo"),
         get_frame_from_str("bar"),
-        get_frame_from_str("if"),
-        get_frame_from_str("not"),
-        get_frame_from_str("exists"),
         get_frame_from_str("expire"),
         get_frame_from_str("at"),
         get_frame_from_str("1667041052"),
+        get_frame_from_str("if"),
+        get_frame_from_str("not"),
+        get_frame_from_str("exists"),
     ];
 
     assert_eq!(
@@ -641,23 +1139,24 @@ fn parse_given_set_command_with_if_not_exists_and_tll_reverse_order_returns_set(
             value: Bytes::from("bar"),
             if_not_exists: true,
             expire_at: Some(1667041052),
+            expire_after: None,
             if_exists: false
         })
     );
 }
 
 #[test]
-fn parse_given_set_command_with_if_exists_and_tll_reverse_order_returns_set() {
+fn parse_given_set_command_with_if_exists_and_tll_returns_set() {
     let command = vec![
         get_frame_from_str("set"),
         get_frame_from_str("my_keyspace"),
         get_frame_from_str("foo"),
         get_frame_from_str("bar"),
-        get_frame_from_str("if"),
-        get_frame_from_str("exists"),
         get_frame_from_str("expire"),
         get_frame_from_str("at"),
         get_frame_from_str("1667041052"),
+        get_frame_from_str("if"),
+        get_frame_from_str("exists"),
     ];
 
     assert_eq!(
@@ -668,11 +1167,104 @@ fn parse_given_set_command_with_if_exists_and_tll_reverse_order_returns_set() {
             value: Bytes::from("bar"),
             if_not_exists: false,
             expire_at: Some(1667041052),
+            expire_after: None,
             if_exists: true
         })
     );
 }
 
+#[test]
+fn parse_given_set_command_with_if_not_exists_and_tll_reverse_order_returns_set() {
+    let command = vec![
+        get_frame_from_str("set"),
+        get_frame_from_str("my_keyspace"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("if"),
+        get_frame_from_str("not"),
+        get_frame_from_str("exists"),
+        get_frame_from_str("expire"),
+        get_frame_from_str("at"),
+        get_frame_from_str("1667041052"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Set(Set {
+            keyspace: Bytes::from("my_keyspace"),
+            key: Bytes::from("foo"),
+            value: Bytes::from("bar"),
+            if_not_exists: true,
+            expire_at: Some(1667041052),
+            expire_after: None,
+            if_exists: false
+        })
+    );
+}
+
+#[test]
+fn parse_given_set_command_with_if_exists_and_tll_reverse_order_returns_set() {
+    let command = vec![
+        get_frame_from_str("set"),
+        get_frame_from_str("my_keyspace"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("if"),
+        get_frame_from_str("exists"),
+        get_frame_from_str("expire"),
+        get_frame_from_str("at"),
+        get_frame_from_str("1667041052"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Set(Set {
+            keyspace: Bytes::from("my_keyspace"),
+            key: Bytes::from("foo"),
+            value: Bytes::from("bar"),
+            if_not_exists: false,
+            expire_at: Some(1667041052),
+            expire_after: None,
+            if_exists: true
+        })
+    );
+}
+
+#[test]
+fn parse_given_mset_with_missing_value_returns_error() {
+    let command = vec![
+        get_frame_from_str("mset"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("a"),
+        get_frame_from_str("1"),
+        get_frame_from_str("b"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_mset_returns_mset() {
+    let command = vec![
+        get_frame_from_str("mset"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("a"),
+        get_frame_from_str("1"),
+        get_frame_from_str("b"),
+        get_frame_from_str("2"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::MSet(MSet {
+            keyspace: Bytes::from("foo"),
+            pairs: vec![
+                (Bytes::from("a"), Bytes::from("1")),
+                (Bytes::from("b"), Bytes::from("2")),
+            ],
+        })
+    );
+}
+
 #[test]
 fn parse_given_get_without_keyspace_returns_error() {
     let command = vec![get_frame_from_str("get")];
@@ -698,10 +1290,74 @@ fn parse_given_get_returns_get() {
         Command::Get(Get {
             keyspace: Bytes::from("foo"),
             key: Bytes::from("bar"),
+            with_version: false,
+        })
+    );
+}
+
+#[test]
+fn parse_given_get_with_version_returns_get() {
+    let command = vec![
+        get_frame_from_str("get"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("WITHVERSION"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Get(Get {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            with_version: true,
+        })
+    );
+}
+
+#[test]
+fn parse_given_get_with_unknown_flag_returns_error() {
+    let command = vec![
+        get_frame_from_str("get"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("baz"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_cset_returns_cset() {
+    let command = vec![
+        get_frame_from_str("cset"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("baz"),
+        get_frame_from_str("42"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::CSet(CSet {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            value: Bytes::from("baz"),
+            version: 42,
         })
     );
 }
 
+#[test]
+fn parse_given_cset_with_invalid_version_returns_error() {
+    let command = vec![
+        get_frame_from_str("cset"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("baz"),
+        get_frame_from_str("-1"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
 #[test]
 fn parse_given_del_without_keyspace_returns_error() {
     let command = vec![get_frame_from_str("del")];
@@ -731,6 +1387,107 @@ fn parse_given_del_returns_del() {
     );
 }
 
+#[test]
+fn parse_given_alter_returns_alter() {
+    let command = vec![
+        get_frame_from_str("alter"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("evictor"),
+        get_frame_from_str("lru"),
+        get_frame_from_str("SS"),
+        get_frame_from_str("5"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Alter(Alter {
+            keyspace: Bytes::from("foo"),
+            evictor: Some(Evictor::Lru),
+            sample_size: Some(5),
+            weight: None,
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("alter"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("ev"),
+        get_frame_from_str("nop"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Alter(Alter {
+            keyspace: Bytes::from("foo"),
+            evictor: Some(Evictor::Nop),
+            sample_size: None,
+            weight: None,
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("alter"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("ev"),
+        get_frame_from_str("ARC"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Alter(Alter {
+            keyspace: Bytes::from("foo"),
+            evictor: Some(Evictor::Arc),
+            sample_size: None,
+            weight: None,
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("alter"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("weight"),
+        get_frame_from_str("0"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Alter(Alter {
+            keyspace: Bytes::from("foo"),
+            evictor: None,
+            sample_size: None,
+            weight: Some(0),
+        })
+    );
+}
+
+#[test]
+fn parse_given_alter_with_invalid_settings_returns_error() {
+    let command = vec![get_frame_from_str("alter"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err());
+
+    let command = vec![
+        get_frame_from_str("alter"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("ss"),
+        get_frame_from_str("0"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err());
+
+    let command = vec![
+        get_frame_from_str("alter"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("ev"),
+        get_frame_from_str("lru"),
+        get_frame_from_str("evictor"),
+        get_frame_from_str("random"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err());
+
+    let command = vec![
+        get_frame_from_str("alter"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("ordered"),
+        get_frame_from_str("yes"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err());
+}
+
 #[test]
 fn parse_given_drop_without_keyspace_returns_error() {
     let command = vec![get_frame_from_str("drop")];
@@ -848,3 +1605,1924 @@ fn parse_given_ttl_returns_ttl() {
         })
     );
 }
+
+#[test]
+fn parse_given_expireat_returns_expireat() {
+    let command = vec![
+        get_frame_from_str("expireat"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("1700000000000"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::ExpireAt(ExpireAt {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            timestamp: 1700000000000,
+        })
+    );
+}
+
+#[test]
+fn parse_given_expireat_with_invalid_timestamp_returns_error() {
+    let command = vec![
+        get_frame_from_str("expireat"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("-1"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err());
+
+    let command = vec![
+        get_frame_from_str("expireat"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err());
+}
+
+#[test]
+fn parse_given_pin_returns_pin() {
+    let command = vec![
+        get_frame_from_str("pin"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Pin(Pin {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("unpin"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Unpin(Pin {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_undelete_returns_undelete() {
+    let command = vec![
+        get_frame_from_str("undelete"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Undelete(Undelete {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("create"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("tombstone"),
+        get_frame_from_str("60000"),
+    ];
+    match parse(Frame::Array(command)).unwrap() {
+        Command::Create(create) => assert_eq!(create.tombstone(), Some(60000)),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn parse_given_dump_returns_dump() {
+    let command = vec![
+        get_frame_from_str("dump"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Dump(Dump {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_restore_returns_restore() {
+    let command = vec![
+        get_frame_from_str("restore"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("payload"),
+        get_frame_from_str("REPLACE"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Restore(Restore {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            payload: Bytes::from("payload"),
+            replace: true,
+            expire_at: None,
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("restore"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("payload"),
+        get_frame_from_str("EXPIRE"),
+        get_frame_from_str("AT"),
+        get_frame_from_str("1700000000"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Restore(Restore {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            payload: Bytes::from("payload"),
+            replace: false,
+            expire_at: Some(1700000000),
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("restore"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("payload"),
+        get_frame_from_str("baz"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err());
+}
+
+#[test]
+fn resolve_expiry_given_set_expire_after_returns_set_expire_at() {
+    let mut command = parse(Frame::Array(vec![
+        get_frame_from_str("set"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("baz"),
+        get_frame_from_str("expire"),
+        get_frame_from_str("after"),
+        get_frame_from_str("1500"),
+        get_frame_from_str("nx"),
+    ]))
+    .unwrap();
+    let frame = command.resolve_expiry(Frame::Null, Duration::from_secs(1000));
+    let resolved = parse(frame).unwrap();
+    assert_eq!(resolved, command);
+    match resolved {
+        Command::Set(set) => {
+            assert_eq!(set.expire_at(), Some(1001));
+            assert_eq!(set.expire_after(), None);
+            assert!(set.if_not_exists());
+        }
+        _ => unreachable!(),
+    }
+
+    let mut command = parse(Frame::Array(vec![
+        get_frame_from_str("set"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("baz"),
+    ]))
+    .unwrap();
+    assert_eq!(
+        command.resolve_expiry(Frame::Null, Duration::from_secs(1000)),
+        Frame::Null
+    );
+}
+
+#[test]
+fn parse_given_pin_without_key_returns_error() {
+    let command = vec![get_frame_from_str("pin"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_type_returns_type() {
+    let command = vec![
+        get_frame_from_str("type"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Type(Type {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_memory_usage_returns_memory_usage() {
+    let command = vec![
+        get_frame_from_str("memory"),
+        get_frame_from_str("usage"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::MemoryUsage(MemoryUsage {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_object_returns_object() {
+    let command = vec![
+        get_frame_from_str("object"),
+        get_frame_from_str("idletime"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::ObjectIdleTime(Object {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("object"),
+        get_frame_from_str("META"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::ObjectMeta(Object {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_object_with_unknown_subcommand_returns_error() {
+    let command = vec![
+        get_frame_from_str("object"),
+        get_frame_from_str("encoding"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_lpush_without_values_returns_error() {
+    let command = vec![
+        get_frame_from_str("lpush"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_rpush_returns_rpush() {
+    let command = vec![
+        get_frame_from_str("rpush"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("a"),
+        get_frame_from_str("b"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::RPush(Push {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            values: vec![Bytes::from("a"), Bytes::from("b")],
+        })
+    );
+}
+
+#[test]
+fn parse_given_lpop_returns_lpop() {
+    let command = vec![
+        get_frame_from_str("lpop"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::LPop(Pop {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_lrange_with_non_integer_index_returns_error() {
+    let command = vec![
+        get_frame_from_str("lrange"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("0"),
+        get_frame_from_str("end"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_lrange_returns_lrange() {
+    let command = vec![
+        get_frame_from_str("lrange"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("0"),
+        get_frame_from_str("-1"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::LRange(LRange {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            start: 0,
+            stop: -1,
+        })
+    );
+}
+
+#[test]
+fn parse_given_llen_returns_llen() {
+    let command = vec![
+        get_frame_from_str("llen"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::LLen(LLen {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_hset_with_missing_value_returns_error() {
+    let command = vec![
+        get_frame_from_str("hset"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("name"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_hset_returns_hset() {
+    let command = vec![
+        get_frame_from_str("hset"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("name"),
+        get_frame_from_str("segment"),
+        get_frame_from_str("age"),
+        get_frame_from_str("1"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::HSet(HSet {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            pairs: vec![
+                (Bytes::from("name"), Bytes::from("segment")),
+                (Bytes::from("age"), Bytes::from("1")),
+            ],
+        })
+    );
+}
+
+#[test]
+fn parse_given_hget_returns_hget() {
+    let command = vec![
+        get_frame_from_str("hget"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("name"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::HGet(HGet {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            field: Bytes::from("name"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_hdel_without_fields_returns_error() {
+    let command = vec![
+        get_frame_from_str("hdel"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_hgetall_returns_hgetall() {
+    let command = vec![
+        get_frame_from_str("hgetall"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::HGetAll(HGetAll {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_zadd_with_invalid_score_returns_error() {
+    let command = vec![
+        get_frame_from_str("zadd"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("nan"),
+        get_frame_from_str("baz"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_zadd_returns_zadd() {
+    let command = vec![
+        get_frame_from_str("zadd"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("10"),
+        get_frame_from_str("alice"),
+        get_frame_from_str("-2.5"),
+        get_frame_from_str("bob"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::ZAdd(ZAdd {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            pairs: vec![(10.0, Bytes::from("alice")), (-2.5, Bytes::from("bob"))],
+        })
+    );
+}
+
+#[test]
+fn parse_given_zrange_with_scores_returns_zrange() {
+    let command = vec![
+        get_frame_from_str("zrange"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("0"),
+        get_frame_from_str("-1"),
+        get_frame_from_str("WITHSCORES"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::ZRange(ZRange {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            start: 0,
+            stop: -1,
+            with_scores: true,
+        })
+    );
+}
+
+#[test]
+fn parse_given_zrem_returns_zrem() {
+    let command = vec![
+        get_frame_from_str("zrem"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("alice"),
+        get_frame_from_str("bob"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::ZRem(ZRem {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            members: vec![Bytes::from("alice"), Bytes::from("bob")],
+        })
+    );
+}
+
+#[test]
+fn parse_given_setbit_with_invalid_bit_returns_error() {
+    let command = vec![
+        get_frame_from_str("setbit"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("7"),
+        get_frame_from_str("2"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_setbit_with_offset_past_limit_returns_error() {
+    let command = vec![
+        get_frame_from_str("setbit"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("4294967296"),
+        get_frame_from_str("1"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_setbit_returns_setbit() {
+    let command = vec![
+        get_frame_from_str("setbit"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("7"),
+        get_frame_from_str("1"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::SetBit(SetBit {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            offset: 7,
+            bit: true,
+        })
+    );
+}
+
+#[test]
+fn parse_given_bitcount_with_range_returns_bitcount() {
+    let command = vec![
+        get_frame_from_str("bitcount"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("1"),
+        get_frame_from_str("-1"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::BitCount(BitCount {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            range: Some((1, -1)),
+        })
+    );
+}
+
+#[test]
+fn parse_given_bitcount_with_start_only_returns_error() {
+    let command = vec![
+        get_frame_from_str("bitcount"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("1"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_getrange_returns_getrange() {
+    let command = vec![
+        get_frame_from_str("getrange"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("0"),
+        get_frame_from_str("-1"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::GetRange(GetRange {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            start: 0,
+            end: -1,
+        })
+    );
+}
+
+#[test]
+fn parse_given_setrange_returns_setrange() {
+    let command = vec![
+        get_frame_from_str("setrange"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("6"),
+        get_frame_from_str("baz"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::SetRange(SetRange {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            offset: 6,
+            value: Bytes::from("baz"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_setrange_past_max_value_size_returns_error() {
+    let command = vec![
+        get_frame_from_str("setrange"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        // 2 bytes short of 512mb
+        get_frame_from_str("536870910"),
+        get_frame_from_str("baz"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_xadd_with_generated_id_returns_xadd() {
+    let command = vec![
+        get_frame_from_str("xadd"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("*"),
+        get_frame_from_str("temp"),
+        get_frame_from_str("21"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::XAdd(XAdd {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            id: None,
+            fields: vec![(Bytes::from("temp"), Bytes::from("21"))],
+        })
+    );
+}
+
+#[test]
+fn parse_given_xadd_with_invalid_id_returns_error() {
+    let command = vec![
+        get_frame_from_str("xadd"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("1-x"),
+        get_frame_from_str("temp"),
+        get_frame_from_str("21"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_xrange_returns_xrange() {
+    let command = vec![
+        get_frame_from_str("xrange"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("1700000000000"),
+        get_frame_from_str("+"),
+        get_frame_from_str("COUNT"),
+        get_frame_from_str("10"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::XRange(XRange {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+            start: StreamId::new(1700000000000, 0),
+            end: StreamId::MAX,
+            count: Some(10),
+        })
+    );
+}
+
+#[test]
+fn parse_given_command_docs_returns_command_docs() {
+    let command = vec![
+        get_frame_from_str("command"),
+        get_frame_from_str("docs"),
+        get_frame_from_str("GET"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::CommandDocs(CommandDocs {
+            names: vec!["get".to_string()],
+        })
+    );
+}
+
+#[test]
+fn docs_reply_given_names_returns_known_commands_only() {
+    let reply = docs::reply(&["get".to_string(), "foo".to_string()]);
+    assert_eq!(
+        reply,
+        Frame::Map(vec![
+            get_frame_from_str("get"),
+            Frame::Map(vec![
+                get_frame_from_str("group"),
+                get_frame_from_str("string"),
+                get_frame_from_str("summary"),
+                get_frame_from_str("Returns the value of a key"),
+            ]),
+        ])
+    );
+}
+
+#[test]
+fn parse_given_xget_without_keys_returns_error() {
+    let command = vec![get_frame_from_str("xget")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_xget_with_non_array_pair_returns_error() {
+    let command = vec![get_frame_from_str("xget"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_xget_with_incomplete_pair_returns_error() {
+    let command = vec![
+        get_frame_from_str("xget"),
+        Frame::Array(vec![get_frame_from_str("foo")]),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_xget_with_extra_element_in_pair_returns_error() {
+    let command = vec![
+        get_frame_from_str("xget"),
+        Frame::Array(vec![
+            get_frame_from_str("foo"),
+            get_frame_from_str("bar"),
+            get_frame_from_str("baz"),
+        ]),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_xget_returns_xget() {
+    let command = vec![
+        get_frame_from_str("xget"),
+        Frame::Array(vec![get_frame_from_str("foo"), get_frame_from_str("bar")]),
+        Frame::Array(vec![get_frame_from_str("baz"), get_frame_from_str("qux")]),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::XGet(XGet {
+            keys: vec![
+                (Bytes::from("foo"), Bytes::from("bar")),
+                (Bytes::from("baz"), Bytes::from("qux")),
+            ],
+        })
+    );
+}
+
+#[test]
+fn parse_given_ping_returns_ping() {
+    let command = vec![get_frame_from_str("ping")];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Ping(Ping { message: None })
+    );
+}
+
+#[test]
+fn parse_given_ping_with_message_returns_ping() {
+    let command = vec![get_frame_from_str("ping"), get_frame_from_str("hello")];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Ping(Ping {
+            message: Some(Bytes::from("hello"))
+        })
+    );
+}
+
+#[test]
+fn parse_given_ping_with_multiple_messages_returns_error() {
+    let command = vec![
+        get_frame_from_str("ping"),
+        get_frame_from_str("hello"),
+        get_frame_from_str("world"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_echo_returns_echo() {
+    let command = vec![get_frame_from_str("echo"), get_frame_from_str("hello")];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Echo(Echo {
+            message: Bytes::from("hello")
+        })
+    );
+}
+
+#[test]
+fn parse_given_echo_without_message_returns_error() {
+    let command = vec![get_frame_from_str("echo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_info_returns_info() {
+    let command = vec![get_frame_from_str("info")];
+    assert_eq!(parse(Frame::Array(command)).unwrap(), Command::Info);
+}
+
+#[test]
+fn parse_given_save_and_bgsave_returns_save_and_bgsave() {
+    let save = parse(Frame::Array(vec![get_frame_from_str("save")])).unwrap();
+    let bgsave = parse(Frame::Array(vec![get_frame_from_str("bgsave")])).unwrap();
+    assert_eq!(save, Command::Save);
+    assert_eq!(bgsave, Command::BgSave);
+    assert!(save.is_exclusive());
+    assert!(!bgsave.is_exclusive());
+}
+
+#[test]
+fn parse_given_export_returns_export() {
+    let command = vec![
+        get_frame_from_str("export"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("foo.csv"),
+        get_frame_from_str("FORMAT"),
+        get_frame_from_str("csv"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Export(Export {
+            keyspace: Bytes::from("foo"),
+            path: "foo.csv".to_string(),
+            format: ExportFormat::Csv,
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("export"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("foo.xml"),
+        get_frame_from_str("format"),
+        get_frame_from_str("xml"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err());
+}
+
+#[test]
+fn parse_given_fsync_returns_fsync() {
+    let fsync = parse(Frame::Array(vec![get_frame_from_str("fsync")])).unwrap();
+    assert_eq!(fsync, Command::Fsync);
+    assert!(!fsync.is_appended());
+
+    let command = vec![get_frame_from_str("fsync"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_save_with_args_returns_error() {
+    let command = vec![get_frame_from_str("save"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_client_info_returns_client_info() {
+    let command = vec![get_frame_from_str("client"), get_frame_from_str("INFO")];
+    assert_eq!(parse(Frame::Array(command)).unwrap(), Command::ClientInfo);
+}
+
+#[test]
+fn parse_given_client_info_with_args_returns_error() {
+    let command = vec![
+        get_frame_from_str("client"),
+        get_frame_from_str("info"),
+        get_frame_from_str("foo"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn is_admin_given_admin_and_data_commands_returns_whether_command_is_admin() {
+    let info = parse(Frame::Array(vec![get_frame_from_str("info")])).unwrap();
+    let count = parse(Frame::Array(vec![
+        get_frame_from_str("count"),
+        get_frame_from_str("foo"),
+    ]))
+    .unwrap();
+    assert!(info.is_admin());
+    assert!(!count.is_admin());
+}
+
+#[test]
+fn parse_given_info_with_args_returns_error() {
+    let command = vec![get_frame_from_str("info"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_watchkey_without_key_returns_error() {
+    let command = vec![get_frame_from_str("watchkey"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_watchkey_returns_watchkey() {
+    let command = vec![
+        get_frame_from_str("watchkey"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::WatchKey(WatchKey {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_watchlifecycle_returns_watchlifecycle() {
+    let command = vec![get_frame_from_str("watchlifecycle")];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::WatchLifecycle
+    );
+
+    let command = vec![
+        get_frame_from_str("watchlifecycle"),
+        get_frame_from_str("foo"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err());
+}
+
+#[test]
+fn parse_given_use_returns_use() {
+    let command = vec![get_frame_from_str("use"), get_frame_from_str("foo")];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Use(Use {
+            keyspace: Some(Bytes::from("foo"))
+        })
+    );
+
+    let command = vec![get_frame_from_str("use")];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Use(Use { keyspace: None })
+    );
+
+    let command = vec![
+        get_frame_from_str("use"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err());
+}
+
+#[test]
+fn parse_in_keyspace_given_get_set_and_del_adds_keyspace() {
+    let keyspace = Bytes::from("foo");
+    let command = vec![get_frame_from_str("GET"), get_frame_from_str("bar")];
+    assert_eq!(
+        parse_in_keyspace(Frame::Array(command), &keyspace).unwrap(),
+        Command::Get(Get {
+            keyspace: keyspace.clone(),
+            key: Bytes::from("bar"),
+            with_version: false,
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("set"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("baz"),
+    ];
+    assert_eq!(
+        parse_in_keyspace(Frame::Array(command), &keyspace).unwrap(),
+        Command::Set(Set {
+            keyspace: keyspace.clone(),
+            key: Bytes::from("bar"),
+            value: Bytes::from("baz"),
+            expire_at: None,
+            expire_after: None,
+            if_not_exists: false,
+            if_exists: false,
+        })
+    );
+
+    let command = vec![get_frame_from_str("del"), get_frame_from_str("bar")];
+    assert_eq!(
+        parse_in_keyspace(Frame::Array(command), &keyspace).unwrap(),
+        Command::Del(Del {
+            keyspace: keyspace.clone(),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_in_keyspace_given_other_command_leaves_it_as_is() {
+    let command = vec![
+        get_frame_from_str("ttl"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("baz"),
+    ];
+    assert_eq!(
+        parse_in_keyspace(Frame::Array(command), &Bytes::from("foo")).unwrap(),
+        Command::Ttl(Ttl {
+            keyspace: Bytes::from("bar"),
+            key: Bytes::from("baz"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_watch_returns_watch() {
+    let command = vec![
+        get_frame_from_str("watch"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Watch(Watch {
+            keyspace: Bytes::from("foo"),
+            key: Bytes::from("bar"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_watch_without_key_returns_error() {
+    let command = vec![get_frame_from_str("watch"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_multi_and_exec_returns_multi_and_exec() {
+    let multi = vec![get_frame_from_str("MULTI")];
+    assert_eq!(parse(Frame::Array(multi)).unwrap(), Command::Multi);
+
+    let exec = vec![get_frame_from_str("exec")];
+    assert_eq!(parse(Frame::Array(exec)).unwrap(), Command::Exec);
+}
+
+#[test]
+fn parse_given_exec_with_args_returns_error() {
+    let command = vec![get_frame_from_str("exec"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_batch_returns_batch() {
+    let command = vec![
+        get_frame_from_str("batch"),
+        get_frame_from_str("token"),
+        Frame::Array(vec![
+            get_frame_from_str("del"),
+            get_frame_from_str("foo"),
+            get_frame_from_str("bar"),
+        ]),
+        Frame::Array(vec![get_frame_from_str("count"), get_frame_from_str("foo")]),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Batch(Batch {
+            token: Bytes::from("token"),
+            commands: vec![
+                Command::Del(Del {
+                    keyspace: Bytes::from("foo"),
+                    key: Bytes::from("bar"),
+                }),
+                Command::Count(Count {
+                    keyspace: Bytes::from("foo"),
+                }),
+            ],
+        })
+    );
+}
+
+#[test]
+fn parse_given_batch_without_commands_returns_error() {
+    let command = vec![get_frame_from_str("batch"), get_frame_from_str("token")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_nested_batch_returns_error() {
+    let command = vec![
+        get_frame_from_str("batch"),
+        get_frame_from_str("token"),
+        Frame::Array(vec![
+            get_frame_from_str("batch"),
+            get_frame_from_str("other_token"),
+            Frame::Array(vec![get_frame_from_str("count"), get_frame_from_str("foo")]),
+        ]),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_view_create_returns_view_create() {
+    let command = vec![
+        get_frame_from_str("view"),
+        get_frame_from_str("create"),
+        get_frame_from_str("foo_view"),
+        get_frame_from_str("foo"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::ViewCreate(ViewCreate {
+            view: Bytes::from("foo_view"),
+            keyspace: Bytes::from("foo"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_view_create_without_keyspace_returns_error() {
+    let command = vec![
+        get_frame_from_str("view"),
+        get_frame_from_str("create"),
+        get_frame_from_str("foo_view"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_view_drop_returns_view_drop() {
+    let command = vec![
+        get_frame_from_str("view"),
+        get_frame_from_str("drop"),
+        get_frame_from_str("foo_view"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::ViewDrop(ViewDrop {
+            view: Bytes::from("foo_view"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_view_list_returns_view_list() {
+    let command = vec![get_frame_from_str("view"), get_frame_from_str("list")];
+    assert_eq!(parse(Frame::Array(command)).unwrap(), Command::ViewList);
+}
+
+#[test]
+fn parse_given_invalid_view_subcommand_returns_error() {
+    let command = vec![get_frame_from_str("view"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_range_returns_range() {
+    let command = vec![
+        get_frame_from_str("range"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("a"),
+        get_frame_from_str("b"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Range(Range {
+            keyspace: Bytes::from("foo"),
+            start: Bytes::from("a"),
+            end: Bytes::from("b"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_range_without_end_returns_error() {
+    let command = vec![
+        get_frame_from_str("range"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("a"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_delrange_returns_delrange() {
+    let command = vec![
+        get_frame_from_str("delrange"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("a"),
+        get_frame_from_str("b"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::DelRange(DelRange {
+            keyspace: Bytes::from("foo"),
+            start: Bytes::from("a"),
+            end: Bytes::from("b"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_delrange_with_extra_args_returns_error() {
+    let command = vec![
+        get_frame_from_str("delrange"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("a"),
+        get_frame_from_str("b"),
+        get_frame_from_str("c"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_countbyprefix_returns_countbyprefix() {
+    let command = vec![
+        get_frame_from_str("countbyprefix"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("user:"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::CountByPrefix(CountByPrefix {
+            keyspace: Bytes::from("foo"),
+            prefix: Bytes::from("user:"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_countbyprefix_without_prefix_returns_error() {
+    let command = vec![
+        get_frame_from_str("countbyprefix"),
+        get_frame_from_str("foo"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_evictstats_returns_evictstats() {
+    let command = vec![get_frame_from_str("evictstats"), get_frame_from_str("foo")];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::EvictStats(EvictStats {
+            keyspace: Bytes::from("foo"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_evictor_returns_evictor_pause_or_resume() {
+    let command = vec![
+        get_frame_from_str("evictor"),
+        get_frame_from_str("pause"),
+        get_frame_from_str("foo"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::EvictorPause(EvictorControl {
+            keyspace: Bytes::from("foo"),
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("evictor"),
+        get_frame_from_str("RESUME"),
+        get_frame_from_str("foo"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::EvictorResume(EvictorControl {
+            keyspace: Bytes::from("foo"),
+        })
+    );
+
+    let command = vec![
+        get_frame_from_str("evictor"),
+        get_frame_from_str("stop"),
+        get_frame_from_str("foo"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_sample_returns_sample() {
+    let command = vec![
+        get_frame_from_str("sample"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("10"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Sample(Sample {
+            keyspace: Bytes::from("foo"),
+            count: 10,
+        })
+    );
+}
+
+#[test]
+fn parse_given_sample_with_invalid_count_returns_error() {
+    for count in ["0", "1001", "-1", "bar"] {
+        let command = vec![
+            get_frame_from_str("sample"),
+            get_frame_from_str("foo"),
+            get_frame_from_str(count),
+        ];
+        assert!(parse(Frame::Array(command)).is_err())
+    }
+}
+
+#[test]
+fn parse_given_sum_returns_sum() {
+    let command = vec![get_frame_from_str("sum"), get_frame_from_str("foo")];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Sum(Sum {
+            keyspace: Bytes::from("foo"),
+            pattern: None,
+        })
+    );
+}
+
+#[test]
+fn parse_given_avg_with_match_returns_avg() {
+    let command = vec![
+        get_frame_from_str("avg"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("MATCH"),
+        get_frame_from_str("score:*"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Avg(Avg {
+            keyspace: Bytes::from("foo"),
+            pattern: Some(Bytes::from("score:*")),
+        })
+    );
+}
+
+#[test]
+fn parse_given_sum_with_invalid_arg_returns_error() {
+    let command = vec![
+        get_frame_from_str("sum"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("where"),
+        get_frame_from_str("score:*"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_sum_with_incomplete_match_returns_error() {
+    let command = vec![
+        get_frame_from_str("sum"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("match"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_debug_populate_returns_debug_populate() {
+    let command = vec![
+        get_frame_from_str("debug"),
+        get_frame_from_str("populate"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("1000"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::DebugPopulate(DebugPopulate {
+            keyspace: Bytes::from("foo"),
+            count: 1000,
+            size: None,
+        })
+    );
+}
+
+#[test]
+fn parse_given_debug_populate_with_size_returns_debug_populate() {
+    let command = vec![
+        get_frame_from_str("debug"),
+        get_frame_from_str("populate"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("1000"),
+        get_frame_from_str("128"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::DebugPopulate(DebugPopulate {
+            keyspace: Bytes::from("foo"),
+            count: 1000,
+            size: Some(128),
+        })
+    );
+}
+
+#[test]
+fn parse_given_debug_quickcheck_returns_debug_quickcheck() {
+    let command = vec![
+        get_frame_from_str("debug"),
+        get_frame_from_str("quickcheck"),
+        get_frame_from_str("foo"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::DebugQuickCheck(DebugQuickCheck {
+            keyspace: Bytes::from("foo"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_debug_protocol_returns_debug_protocol() {
+    let command = vec![
+        get_frame_from_str("debug"),
+        get_frame_from_str("protocol"),
+        get_frame_from_str("ON"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::DebugProtocol(DebugProtocol { enabled: true })
+    );
+
+    let command = vec![
+        get_frame_from_str("debug"),
+        get_frame_from_str("protocol"),
+        get_frame_from_str("yes"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_debug_populate_with_invalid_count_returns_error() {
+    let command = vec![
+        get_frame_from_str("debug"),
+        get_frame_from_str("populate"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_flushall_returns_flushall() {
+    let command = vec![get_frame_from_str("flushall")];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::FlushAll(FlushAll {
+            drop: false,
+            async_: false,
+        })
+    );
+}
+
+#[test]
+fn parse_given_flushall_with_drop_and_async_returns_flushall() {
+    let command = vec![
+        get_frame_from_str("flushall"),
+        get_frame_from_str("async"),
+        get_frame_from_str("drop"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::FlushAll(FlushAll {
+            drop: true,
+            async_: true,
+        })
+    );
+}
+
+#[test]
+fn parse_given_flushall_with_duplicate_async_returns_error() {
+    let command = vec![
+        get_frame_from_str("flushall"),
+        get_frame_from_str("async"),
+        get_frame_from_str("async"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_flushall_with_unknown_arg_returns_error() {
+    let command = vec![get_frame_from_str("flushall"), get_frame_from_str("foo")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_schedule_without_subcommand_returns_error() {
+    let command = vec![get_frame_from_str("schedule")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_schedule_add_with_invalid_expression_returns_error() {
+    let command = vec![
+        get_frame_from_str("schedule"),
+        get_frame_from_str("add"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+        get_frame_from_str("count"),
+        get_frame_from_str("baz"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_schedule_add_without_command_returns_error() {
+    let command = vec![
+        get_frame_from_str("schedule"),
+        get_frame_from_str("add"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("0 0 0 * * *"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_schedule_add_with_invalid_command_returns_error() {
+    let command = vec![
+        get_frame_from_str("schedule"),
+        get_frame_from_str("add"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("0 0 0 * * *"),
+        get_frame_from_str("count"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_schedule_add_returns_schedule_add() {
+    let command = vec![
+        get_frame_from_str("schedule"),
+        get_frame_from_str("add"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("0 0 0 * * *"),
+        get_frame_from_str("count"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::ScheduleAdd(ScheduleAdd {
+            name: Bytes::from("foo"),
+            schedule: Schedule::from_str("0 0 0 * * *").unwrap(),
+            command: vec![Bytes::from("count"), Bytes::from("bar")],
+        })
+    );
+}
+
+#[test]
+fn parse_given_schedule_remove_returns_schedule_remove() {
+    let command = vec![
+        get_frame_from_str("schedule"),
+        get_frame_from_str("remove"),
+        get_frame_from_str("foo"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::ScheduleRemove(ScheduleRemove {
+            name: Bytes::from("foo"),
+        })
+    );
+}
+
+#[test]
+fn parse_given_schedule_list_returns_schedule_list() {
+    let command = vec![get_frame_from_str("schedule"), get_frame_from_str("list")];
+    assert_eq!(parse(Frame::Array(command)).unwrap(), Command::ScheduleList);
+}
+
+#[test]
+fn parse_given_tasks_returns_tasks() {
+    let command = vec![get_frame_from_str("tasks"), get_frame_from_str("list")];
+    assert_eq!(parse(Frame::Array(command)).unwrap(), Command::TasksList);
+
+    let command = vec![
+        get_frame_from_str("tasks"),
+        get_frame_from_str("CANCEL"),
+        get_frame_from_str("12"),
+    ];
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::TasksCancel(TaskId { id: 12 })
+    );
+
+    let command = vec![
+        get_frame_from_str("tasks"),
+        get_frame_from_str("status"),
+        get_frame_from_str("foo"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_delay_with_invalid_delay_returns_error() {
+    let command = vec![
+        get_frame_from_str("delay"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("ping"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_delay_without_command_returns_error() {
+    let command = vec![get_frame_from_str("delay"), get_frame_from_str("1000")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[test]
+fn parse_given_delay_returns_delay() {
+    let command = vec![
+        get_frame_from_str("delay"),
+        get_frame_from_str("1000"),
+        get_frame_from_str("del"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Delay(Delay {
+            delay: 1000,
+            run_at: None,
+            done: false,
+            command: vec![Bytes::from("del"), Bytes::from("foo"), Bytes::from("bar")],
+        })
+    );
+}
+
+#[test]
+fn parse_given_delay_at_returns_delay() {
+    let command = vec![
+        get_frame_from_str("delay"),
+        get_frame_from_str("AT"),
+        get_frame_from_str("1700000000000"),
+        get_frame_from_str("ping"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Delay(Delay {
+            delay: 0,
+            run_at: Some(1700000000000),
+            done: false,
+            command: vec![Bytes::from("ping")],
+        })
+    );
+}
+
+#[test]
+fn parse_given_explain_returns_explain() {
+    let command = vec![
+        get_frame_from_str("explain"),
+        get_frame_from_str("del"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Explain(Explain {
+            command: Box::new(Command::Del(Del {
+                keyspace: Bytes::from("foo"),
+                key: Bytes::from("bar"),
+            })),
+        })
+    );
+}
+
+#[test]
+fn parse_given_explain_with_invalid_command_returns_error() {
+    let command = vec![get_frame_from_str("explain")];
+    assert!(parse(Frame::Array(command)).is_err());
+
+    let command = vec![get_frame_from_str("explain"), get_frame_from_str("del")];
+    assert!(parse(Frame::Array(command)).is_err());
+}
+
+#[test]
+fn explain_given_command_describes_it() {
+    let command = vec![
+        get_frame_from_str("mset"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("a"),
+        get_frame_from_str("1"),
+        get_frame_from_str("b"),
+        get_frame_from_str("2"),
+    ];
+    let cmd = parse(Frame::Array(command)).unwrap();
+    assert_eq!(cmd.keyspaces(), Some(vec![Bytes::from("foo")]));
+    assert!(cmd.is_write());
+    assert_eq!(cmd.cost(), "medium");
+
+    let command = vec![get_frame_from_str("flushall")];
+    let cmd = parse(Frame::Array(command)).unwrap();
+    assert_eq!(cmd.keyspaces(), None);
+    assert_eq!(cmd.cost(), "high");
+
+    let command = vec![
+        get_frame_from_str("get"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("a"),
+    ];
+    let cmd = parse(Frame::Array(command)).unwrap();
+    assert!(!cmd.is_write());
+    assert_eq!(cmd.cost(), "low");
+}
+
+#[test]
+fn is_appended_given_writes_returns_whether_they_are_kept() {
+    let create = |args: &[&'static str]| {
+        let command = args.iter().map(|arg| get_frame_from_str(arg)).collect();
+        parse(Frame::Array(command)).unwrap()
+    };
+    assert!(create(&["create", "foo"]).is_appended());
+    assert!(!create(&["create", "foo", "temp"]).is_appended());
+    assert!(create(&["del", "foo", "a"]).is_appended());
+    assert!(!create(&["exec"]).is_appended());
+    assert!(!create(&["get", "foo", "a"]).is_appended());
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn parse_given_function_without_subcommand_returns_error() {
+    let command = vec![get_frame_from_str("function")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn parse_given_function_load_without_module_returns_error() {
+    let command = vec![
+        get_frame_from_str("function"),
+        get_frame_from_str("load"),
+        get_frame_from_str("foo"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn parse_given_function_load_returns_function_load() {
+    let command = vec![
+        get_frame_from_str("function"),
+        get_frame_from_str("load"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("(module)"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::FunctionLoad(FunctionLoad {
+            library: Bytes::from("foo"),
+            module: Bytes::from("(module)"),
+        })
+    );
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn parse_given_function_list_returns_function_list() {
+    let command = vec![get_frame_from_str("function"), get_frame_from_str("list")];
+    assert_eq!(parse(Frame::Array(command)).unwrap(), Command::FunctionList);
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn parse_given_fcall_with_non_integer_arg_returns_error() {
+    let command = vec![
+        get_frame_from_str("fcall"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("add"),
+        get_frame_from_str("bar"),
+    ];
+    assert!(parse(Frame::Array(command)).is_err())
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn parse_given_fcall_returns_fcall() {
+    let command = vec![
+        get_frame_from_str("fcall"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("add"),
+        get_frame_from_str("1"),
+        get_frame_from_str("-2"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Fcall(Fcall {
+            library: Bytes::from("foo"),
+            function: Bytes::from("add"),
+            args: vec![1, -2],
+        })
+    );
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn parse_given_plugin_command_returns_fcall() {
+    register_plugin_command("Mul", Bytes::from("math"), Bytes::from("mul"));
+    let command = vec![
+        get_frame_from_str("MUL"),
+        get_frame_from_str("6"),
+        get_frame_from_str("7"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Fcall(Fcall {
+            library: Bytes::from("math"),
+            function: Bytes::from("mul"),
+            args: vec![6, 7],
+        })
+    );
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn parse_given_eval_returns_eval() {
+    let command = vec![
+        get_frame_from_str("eval"),
+        get_frame_from_str("return ARGV[1]"),
+        get_frame_from_str("foo"),
+        get_frame_from_str("bar"),
+    ];
+
+    assert_eq!(
+        parse(Frame::Array(command)).unwrap(),
+        Command::Eval(Eval {
+            script: Bytes::from("return ARGV[1]"),
+            args: vec![Bytes::from("foo"), Bytes::from("bar")],
+        })
+    );
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn parse_given_eval_without_script_returns_error() {
+    let command = vec![get_frame_from_str("eval")];
+    assert!(parse(Frame::Array(command)).is_err())
+}
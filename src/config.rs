@@ -1,3 +1,5 @@
+use crate::aof::AppendFsync;
+use crate::netfilter::NetFilter;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader};
 use std::net::{AddrParseError, IpAddr, Ipv4Addr};
@@ -9,6 +11,39 @@ const PORT_LABEL: &str = "port";
 const MAX_MEMORY_LABEL: &str = "max_memory";
 const CONNECTION_BUFFER_SIZE_LABEL: &str = "connection_buffer_size";
 const BIND_LABEL: &str = "bind";
+const WASM_FUEL_LABEL: &str = "wasm_fuel";
+const RATE_LIMIT_LABEL: &str = "rate_limit";
+const RATE_LIMIT_BURST_LABEL: &str = "rate_limit_burst";
+const MAX_CONCURRENT_COMMANDS_LABEL: &str = "max_concurrent_commands";
+const ALLOW_LABEL: &str = "allow";
+const DENY_LABEL: &str = "deny";
+const CRASH_REPORT_DIR_LABEL: &str = "crash_report_dir";
+const LUA_MAX_INSTRUCTIONS_LABEL: &str = "lua_max_instructions";
+const PLUGIN_LABEL: &str = "plugin";
+const OUTPUT_BUFFER_SOFT_LIMIT_LABEL: &str = "output_buffer_soft_limit";
+const OUTPUT_BUFFER_HARD_LIMIT_LABEL: &str = "output_buffer_hard_limit";
+const MAX_PINNED_MEMORY_PERCENT_LABEL: &str = "max_pinned_memory_percent";
+const CHUNK_SIZE_LABEL: &str = "chunk_size";
+const MIRROR_LABEL: &str = "mirror";
+const MIRROR_SAMPLE_PERCENT_LABEL: &str = "mirror_sample_percent";
+const WARMUP_PERCENT_LABEL: &str = "warmup_percent";
+const SPILL_DIR_LABEL: &str = "spill_dir";
+const MAX_COMMAND_MEMORY_LABEL: &str = "max_command_memory";
+const GRPC_PORT_LABEL: &str = "grpc_port";
+const HTTP_PORT_LABEL: &str = "http_port";
+const EVICTION_BATCH_SIZE_LABEL: &str = "eviction_batch_size";
+const WEBSOCKET_PORT_LABEL: &str = "websocket_port";
+const EVICTION_HIGH_WATERMARK_LABEL: &str = "eviction_high_watermark";
+const EVICTION_LOW_WATERMARK_LABEL: &str = "eviction_low_watermark";
+const MEMCACHED_PORT_LABEL: &str = "memcached_port";
+const MEMCACHED_KEYSPACE_LABEL: &str = "memcached_keyspace";
+const QUIC_PORT_LABEL: &str = "quic_port";
+const QUIC_CERT_FILE_LABEL: &str = "quic_cert_file";
+const QUIC_KEY_FILE_LABEL: &str = "quic_key_file";
+const RESULT_CACHE_TTL_LABEL: &str = "result_cache_ttl";
+const DATA_DIR_LABEL: &str = "data_dir";
+const APPENDONLY_LABEL: &str = "appendonly";
+const APPENDFSYNC_LABEL: &str = "appendfsync";
 
 #[derive(Debug)]
 pub struct ServerConfig {
@@ -16,6 +51,46 @@ pub struct ServerConfig {
     max_memory: u64,
     connection_buffer_size: usize,
     bind: IpAddr,
+    wasm_fuel: u64,
+    rate_limit: u64,
+    rate_limit_burst: u64,
+    max_concurrent_commands: usize,
+    net_filter: NetFilter,
+    crash_report_dir: String,
+    lua_max_instructions: u64,
+    plugins: Vec<String>,
+    output_buffer_soft_limit: usize,
+    output_buffer_hard_limit: usize,
+    max_pinned_memory_percent: u8,
+    chunk_size: usize,
+    mirror: String,
+    mirror_sample_percent: u8,
+    warmup_percent: u8,
+    spill_dir: String,
+    max_command_memory: usize,
+    grpc_port: u16,
+    http_port: u16,
+    eviction_batch_size: usize,
+    websocket_port: u16,
+    // percentages of max memory, eviction starts over the high watermark and
+    // goes on until memory is back under the low watermark
+    eviction_high_watermark: u8,
+    eviction_low_watermark: u8,
+    memcached_port: u16,
+    memcached_keyspace: String,
+    quic_port: u16,
+    quic_cert_file: String,
+    quic_key_file: String,
+    // milliseconds, 0 turns the result cache off
+    result_cache_ttl: u64,
+    // where `SAVE` and `BGSAVE` write the snapshot
+    data_dir: String,
+    // whether writes are appended to the append only file in the data dir
+    appendonly: bool,
+    appendfsync: AppendFsync,
+    // whether the snapshot in the data dir is loaded at startup, only set
+    // from the command line
+    load_snapshot: bool,
 }
 
 #[derive(Debug, Error)]
@@ -34,6 +109,58 @@ pub enum ServerConfigError {
 
     #[error(transparent)]
     AddrParseError(#[from] AddrParseError),
+
+    #[error(transparent)]
+    NetParseError(#[from] ipnet::AddrParseError),
+
+    #[error("eviction_low_watermark ({0}) is above eviction_high_watermark ({1})")]
+    InvalidWatermarks(u8, u8),
+}
+
+// the settings used for the directives missing from the config file, also
+// handy when embedding a `Db` without a config file
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            port: 1698,
+            max_memory: 0,
+            connection_buffer_size: 4096,
+            bind: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            wasm_fuel: 1_000_000,
+            rate_limit: 0,
+            rate_limit_burst: 0,
+            max_concurrent_commands: 0,
+            net_filter: NetFilter::default(),
+            crash_report_dir: ".".to_string(),
+            lua_max_instructions: 10_000_000,
+            plugins: Vec::new(),
+            output_buffer_soft_limit: 8 * 1024 * 1024,
+            output_buffer_hard_limit: 0,
+            max_pinned_memory_percent: 50,
+            chunk_size: 0,
+            mirror: String::new(),
+            mirror_sample_percent: 100,
+            warmup_percent: 0,
+            spill_dir: String::new(),
+            max_command_memory: 0,
+            grpc_port: 0,
+            http_port: 0,
+            eviction_batch_size: 16,
+            websocket_port: 0,
+            eviction_high_watermark: 100,
+            eviction_low_watermark: 90,
+            memcached_port: 0,
+            memcached_keyspace: "memcached".to_string(),
+            quic_port: 0,
+            quic_cert_file: String::new(),
+            quic_key_file: String::new(),
+            result_cache_ttl: 0,
+            data_dir: ".".to_string(),
+            appendonly: false,
+            appendfsync: AppendFsync::EverySec,
+            load_snapshot: false,
+        }
+    }
 }
 
 impl ServerConfig {
@@ -43,12 +170,7 @@ impl ServerConfig {
     }
 
     fn parse(reader: BufReader<File>) -> Result<ServerConfig, ServerConfigError> {
-        let mut config = ServerConfig {
-            port: 1698,
-            max_memory: 0,
-            connection_buffer_size: 4096,
-            bind: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-        };
+        let mut config = ServerConfig::default();
         for maybe_line in reader.lines() {
             let line = &maybe_line?;
             if line.trim().starts_with('#') || line.trim().is_empty() {
@@ -88,6 +210,126 @@ impl ServerConfig {
                     let bind = IpAddr::from_str(tokens[1])?;
                     config.bind = bind
                 }
+                WASM_FUEL_LABEL => {
+                    let wasm_fuel = tokens[1].parse::<u64>()?;
+                    config.wasm_fuel = wasm_fuel;
+                }
+                RATE_LIMIT_LABEL => {
+                    let rate_limit = tokens[1].parse::<u64>()?;
+                    config.rate_limit = rate_limit;
+                }
+                RATE_LIMIT_BURST_LABEL => {
+                    let rate_limit_burst = tokens[1].parse::<u64>()?;
+                    config.rate_limit_burst = rate_limit_burst;
+                }
+                MAX_CONCURRENT_COMMANDS_LABEL => {
+                    let max_concurrent_commands = tokens[1].parse::<usize>()?;
+                    config.max_concurrent_commands = max_concurrent_commands;
+                }
+                ALLOW_LABEL => config.net_filter.set_allow(tokens[1])?,
+                DENY_LABEL => config.net_filter.set_deny(tokens[1])?,
+                CRASH_REPORT_DIR_LABEL => config.crash_report_dir = tokens[1].to_string(),
+                LUA_MAX_INSTRUCTIONS_LABEL => {
+                    let lua_max_instructions = tokens[1].parse::<u64>()?;
+                    config.lua_max_instructions = lua_max_instructions;
+                }
+                PLUGIN_LABEL => config.plugins.push(tokens[1].to_string()),
+                OUTPUT_BUFFER_SOFT_LIMIT_LABEL => {
+                    let output_buffer_soft_limit = tokens[1].parse::<usize>()?;
+                    config.output_buffer_soft_limit = output_buffer_soft_limit;
+                }
+                OUTPUT_BUFFER_HARD_LIMIT_LABEL => {
+                    let output_buffer_hard_limit = tokens[1].parse::<usize>()?;
+                    config.output_buffer_hard_limit = output_buffer_hard_limit;
+                }
+                MAX_PINNED_MEMORY_PERCENT_LABEL => {
+                    let max_pinned_memory_percent = tokens[1].parse::<u8>()?;
+                    if max_pinned_memory_percent > 100 {
+                        return Err(ServerConfigError::InvalidFormat(line.clone()));
+                    }
+                    config.max_pinned_memory_percent = max_pinned_memory_percent;
+                }
+                CHUNK_SIZE_LABEL => {
+                    let chunk_size = tokens[1].parse::<usize>()?;
+                    config.chunk_size = chunk_size;
+                }
+                MIRROR_LABEL => config.mirror = tokens[1].to_string(),
+                MIRROR_SAMPLE_PERCENT_LABEL => {
+                    let mirror_sample_percent = tokens[1].parse::<u8>()?;
+                    if mirror_sample_percent > 100 {
+                        return Err(ServerConfigError::InvalidFormat(line.clone()));
+                    }
+                    config.mirror_sample_percent = mirror_sample_percent;
+                }
+                WARMUP_PERCENT_LABEL => {
+                    let warmup_percent = tokens[1].parse::<u8>()?;
+                    if warmup_percent > 100 {
+                        return Err(ServerConfigError::InvalidFormat(line.clone()));
+                    }
+                    config.warmup_percent = warmup_percent;
+                }
+                SPILL_DIR_LABEL => config.spill_dir = tokens[1].to_string(),
+                MAX_COMMAND_MEMORY_LABEL => {
+                    let max_command_memory = tokens[1].parse::<usize>()?;
+                    config.max_command_memory = max_command_memory;
+                }
+                RESULT_CACHE_TTL_LABEL => {
+                    let result_cache_ttl = tokens[1].parse::<u64>()?;
+                    config.result_cache_ttl = result_cache_ttl;
+                }
+                DATA_DIR_LABEL => config.data_dir = tokens[1].to_string(),
+                APPENDONLY_LABEL => match tokens[1] {
+                    "yes" => config.appendonly = true,
+                    "no" => config.appendonly = false,
+                    _ => return Err(ServerConfigError::InvalidFormat(line.clone())),
+                },
+                APPENDFSYNC_LABEL => match tokens[1] {
+                    "always" => config.appendfsync = AppendFsync::Always,
+                    "everysec" => config.appendfsync = AppendFsync::EverySec,
+                    "no" => config.appendfsync = AppendFsync::No,
+                    _ => return Err(ServerConfigError::InvalidFormat(line.clone())),
+                },
+                GRPC_PORT_LABEL => {
+                    let grpc_port = tokens[1].parse::<u16>()?;
+                    config.grpc_port = grpc_port;
+                }
+                HTTP_PORT_LABEL => {
+                    let http_port = tokens[1].parse::<u16>()?;
+                    config.http_port = http_port;
+                }
+                EVICTION_BATCH_SIZE_LABEL => {
+                    let eviction_batch_size = tokens[1].parse::<usize>()?;
+                    if eviction_batch_size == 0 {
+                        return Err(ServerConfigError::InvalidFormat(line.clone()));
+                    }
+                    config.eviction_batch_size = eviction_batch_size;
+                }
+                WEBSOCKET_PORT_LABEL => {
+                    let websocket_port = tokens[1].parse::<u16>()?;
+                    config.websocket_port = websocket_port;
+                }
+                EVICTION_HIGH_WATERMARK_LABEL | EVICTION_LOW_WATERMARK_LABEL => {
+                    let watermark = tokens[1].parse::<u8>()?;
+                    if watermark == 0 || watermark > 100 {
+                        return Err(ServerConfigError::InvalidFormat(line.clone()));
+                    }
+                    if tokens[0] == EVICTION_HIGH_WATERMARK_LABEL {
+                        config.eviction_high_watermark = watermark;
+                    } else {
+                        config.eviction_low_watermark = watermark;
+                    }
+                }
+                MEMCACHED_PORT_LABEL => {
+                    let memcached_port = tokens[1].parse::<u16>()?;
+                    config.memcached_port = memcached_port;
+                }
+                MEMCACHED_KEYSPACE_LABEL => config.memcached_keyspace = tokens[1].to_string(),
+                QUIC_PORT_LABEL => {
+                    let quic_port = tokens[1].parse::<u16>()?;
+                    config.quic_port = quic_port;
+                }
+                QUIC_CERT_FILE_LABEL => config.quic_cert_file = tokens[1].to_string(),
+                QUIC_KEY_FILE_LABEL => config.quic_key_file = tokens[1].to_string(),
                 _ => {
                     return Err(ServerConfigError::UnknownDirective(
                         tokens[0].to_string(),
@@ -97,6 +339,12 @@ impl ServerConfig {
             }
         }
 
+        if config.eviction_low_watermark > config.eviction_high_watermark {
+            return Err(ServerConfigError::InvalidWatermarks(
+                config.eviction_low_watermark,
+                config.eviction_high_watermark,
+            ));
+        }
         Ok(config)
     }
 
@@ -115,4 +363,150 @@ impl ServerConfig {
     pub fn bind(&self) -> String {
         self.bind.to_string()
     }
+
+    pub fn wasm_fuel(&self) -> u64 {
+        self.wasm_fuel
+    }
+
+    pub fn rate_limit(&self) -> u64 {
+        self.rate_limit
+    }
+
+    pub fn max_concurrent_commands(&self) -> usize {
+        self.max_concurrent_commands
+    }
+
+    pub fn net_filter(&self) -> &NetFilter {
+        &self.net_filter
+    }
+
+    pub fn lua_max_instructions(&self) -> u64 {
+        self.lua_max_instructions
+    }
+
+    pub fn crash_report_dir(&self) -> &str {
+        &self.crash_report_dir
+    }
+
+    pub fn plugins(&self) -> &[String] {
+        &self.plugins
+    }
+
+    pub fn output_buffer_soft_limit(&self) -> usize {
+        self.output_buffer_soft_limit
+    }
+
+    pub fn output_buffer_hard_limit(&self) -> usize {
+        self.output_buffer_hard_limit
+    }
+
+    pub fn max_pinned_memory_percent(&self) -> u8 {
+        self.max_pinned_memory_percent
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn mirror(&self) -> &str {
+        &self.mirror
+    }
+
+    pub fn mirror_sample_percent(&self) -> u8 {
+        self.mirror_sample_percent
+    }
+
+    pub fn warmup_percent(&self) -> u8 {
+        self.warmup_percent
+    }
+
+    pub fn spill_dir(&self) -> &str {
+        &self.spill_dir
+    }
+
+    pub fn max_command_memory(&self) -> usize {
+        self.max_command_memory
+    }
+
+    pub fn result_cache_ttl(&self) -> u64 {
+        self.result_cache_ttl
+    }
+
+    pub fn data_dir(&self) -> &str {
+        &self.data_dir
+    }
+
+    pub fn set_data_dir(&mut self, data_dir: String) {
+        self.data_dir = data_dir;
+    }
+
+    pub fn appendonly(&self) -> bool {
+        self.appendonly
+    }
+
+    pub fn appendfsync(&self) -> AppendFsync {
+        self.appendfsync
+    }
+
+    pub fn load_snapshot(&self) -> bool {
+        self.load_snapshot
+    }
+
+    pub fn set_load_snapshot(&mut self, load_snapshot: bool) {
+        self.load_snapshot = load_snapshot;
+    }
+
+    pub fn grpc_port(&self) -> u16 {
+        self.grpc_port
+    }
+
+    pub fn http_port(&self) -> u16 {
+        self.http_port
+    }
+
+    pub fn eviction_batch_size(&self) -> usize {
+        self.eviction_batch_size
+    }
+
+    pub fn websocket_port(&self) -> u16 {
+        self.websocket_port
+    }
+
+    pub fn eviction_high_watermark(&self) -> u8 {
+        self.eviction_high_watermark
+    }
+
+    pub fn eviction_low_watermark(&self) -> u8 {
+        self.eviction_low_watermark
+    }
+
+    pub fn memcached_port(&self) -> u16 {
+        self.memcached_port
+    }
+
+    pub fn memcached_keyspace(&self) -> &str {
+        &self.memcached_keyspace
+    }
+
+    pub fn quic_port(&self) -> u16 {
+        self.quic_port
+    }
+
+    pub fn quic_cert_file(&self) -> &str {
+        &self.quic_cert_file
+    }
+
+    pub fn quic_key_file(&self) -> &str {
+        &self.quic_key_file
+    }
+
+    // when no burst is configured a connection can burst up to a second worth
+    // of commands.
+    pub fn rate_limit_burst(&self) -> u64 {
+        if self.rate_limit_burst == 0 {
+            self.rate_limit
+        } else {
+            self.rate_limit_burst
+        }
+    }
 }
@@ -1,20 +1,53 @@
 use crate::frame::{
-    self, Frame, ParseFrameError, ARRAY_IDENT, BOOLEAN_IDENT, DOUBLE_IDENT, ERROR_IDENT,
-    INTEGER_IDENT, MAP_IDENT, STRING_IDENT,
+    self, Frame, ParseFrameError, Partial, ARRAY_IDENT, BOOLEAN_IDENT, CHUNKED_LEN, CHUNK_IDENT,
+    DOUBLE_IDENT, ERROR_IDENT, INTEGER_IDENT, MAP_IDENT, STRING_IDENT,
 };
-use async_recursion::async_recursion;
-use bytes::{Buf, Bytes, BytesMut};
-use std::io::{self, Cursor};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::collections::VecDeque;
+use std::io::Cursor;
 use thiserror::Error;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tracing::info;
 
 #[derive(Debug)]
 pub struct Connection<T>
 where
     T: AsyncRead + AsyncWrite + Unpin + Send,
 {
-    stream: T,
+    reader: ReadHalf<T>,
+    writer: WriteHalf<T>,
     buf: BytesMut,
+    // the chunked strings of the frame being read
+    partial: Partial,
+    // encoded frames waiting to be written
+    out: Output,
+    // strings longer than this are written as chunked strings, 0 writes all
+    // strings whole
+    chunk_size: usize,
+    soft_output_limit: usize,
+    hard_output_limit: usize,
+    bytes_read: u64,
+    bytes_written: u64,
+    // the id of the connection while the bytes read and written are logged,
+    // see `DEBUG PROTOCOL`
+    trace: Option<u64>,
+}
+
+// strings up to this size are copied into the output, larger ones are queued
+// as they are so the value isn't held twice while it is written out
+const COPY_LIMIT: usize = 4096;
+
+// the most bytes of a single read or frame dumped while tracing, so large
+// values don't flood the logs
+const TRACE_DUMP_LIMIT: usize = 4096;
+
+// the output waiting to be written. frames are encoded into `buf`, which is
+// queued as a whole when a large string has to be queued after it.
+#[derive(Debug, Default)]
+struct Output {
+    queued: VecDeque<Bytes>,
+    buf: BytesMut,
+    len: usize,
 }
 
 #[derive(Debug, Error)]
@@ -30,128 +63,373 @@ pub enum ConnectionError {
 
     #[error("malformed frame received for write")]
     MalformedFrameForWrite,
+
+    #[error("output buffer of {0} bytes is over the hard limit, closing the connection")]
+    OutputLimitExceeded(usize),
 }
 
+// the client only uses part of the connection, the rest is used by the server
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
 impl<T> Connection<T>
 where
     T: AsyncRead + AsyncWrite + Unpin + Send,
 {
     pub fn new(stream: T, buf_size: usize) -> Self {
+        let (reader, writer) = io::split(stream);
         Connection {
-            stream,
+            reader,
+            writer,
             buf: BytesMut::with_capacity(buf_size),
+            partial: Partial::default(),
+            out: Output::default(),
+            chunk_size: 0,
+            soft_output_limit: 0,
+            hard_output_limit: 0,
+            bytes_read: 0,
+            bytes_written: 0,
+            trace: None,
         }
     }
 
+    // limits the output queued for a client which doesn't read its replies,
+    // reading and producing pause over the soft limit and the connection is
+    // closed over the hard limit. a limit of 0 disables it.
+    pub fn with_output_limits(mut self, soft: usize, hard: usize) -> Self {
+        self.soft_output_limit = soft;
+        self.hard_output_limit = hard;
+        self
+    }
+
+    // writes strings longer than `chunk_size` as chunked strings, so the peer
+    // can read them piece by piece. a chunk size of 0 writes all strings whole.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    // reads the next frame, the queued output is written out meanwhile. no
+    // more frames are read while the output is paused, so a client that
+    // doesn't read its replies can't queue up more work. it is safe to cancel,
+    // the bytes read and written so far are kept in the buffers.
     pub async fn read_frame(&mut self) -> Result<Option<Frame>, ConnectionError> {
         loop {
+            if self.is_output_paused() {
+                self.write_some().await?;
+                continue;
+            }
+
             if let Some(frame) = self.parse_frame()? {
                 return Ok(Some(frame));
             }
 
-            if self.stream.read_buf(&mut self.buf).await? == 0 {
+            let n = tokio::select! {
+                res = self.reader.read_buf(&mut self.buf) => res?,
+                res = self.out.write_to(&mut self.writer), if !self.out.is_empty() => {
+                    self.bytes_written += res? as u64;
+                    continue;
+                }
+            };
+            if n == 0 {
                 if self.buf.is_empty() {
+                    // the client may only have closed its side of the
+                    // connection, it still gets the replies it asked for
+                    self.flush().await?;
                     return Ok(None);
                 } else {
                     return Err(ConnectionError::Reset);
                 }
             }
+            if let Some(id) = self.trace {
+                let read = &self.buf[self.buf.len() - n..];
+                info!(
+                    "connection {} read {} bytes at offset {}\n{}",
+                    id,
+                    n,
+                    self.bytes_read,
+                    hex_dump(self.bytes_read, read)
+                );
+            }
+            self.bytes_read += n as u64;
         }
     }
 
     fn parse_frame(&mut self) -> Result<Option<Frame>, ConnectionError> {
-        let mut cursor = Cursor::new(&self.buf[..]);
-        match frame::parse(&mut cursor) {
-            Ok(frame) => {
-                self.buf.advance(cursor.position() as usize);
-                Ok(Some(frame))
-            }
+        match frame::parse_buf(&mut self.buf, &mut self.partial) {
+            Ok(frame) => Ok(Some(frame)),
             Err(ParseFrameError::Incomplete) => Ok(None),
             Err(e) => Err(e.into()),
         }
     }
 
-    #[async_recursion]
+    // writes the frame and waits until it is written out along with any
+    // output queued before it
+    #[cfg(test)]
     pub async fn write_frame(&mut self, frame: &Frame) -> Result<(), ConnectionError> {
-        match frame {
-            Frame::String(data) => {
-                let len = data.len();
-                self.stream.write_u8(STRING_IDENT).await?;
-                self.stream
-                    .write_all(format!("{}\r\n", len).as_bytes())
-                    .await?;
-                self.stream.write_all(data).await?;
-                self.stream.write_all(b"\r\n").await?;
-            }
-            Frame::Integer(data) => {
-                self.stream.write_u8(INTEGER_IDENT).await?;
-                self.stream
-                    .write_all(format!("{}\r\n", data).as_bytes())
-                    .await?;
-            }
-            Frame::Boolean(data) => {
-                self.stream.write_u8(BOOLEAN_IDENT).await?;
-                if *data {
-                    self.stream
-                        .write_all(format!("{}\r\n", 1).as_bytes())
-                        .await?;
+        self.queue_frame(frame)?;
+        self.flush().await
+    }
+
+    // queues the frame for writing without waiting for it to be written, the
+    // output is written out by `flush` and while waiting in `read_frame`.
+    // frames are encoded before anything is written, so when a write is
+    // cancelled the rest of the frame stays queued and goes out ahead of the
+    // next frame instead of being cut short. fails once the queued output
+    // goes over the hard limit, the connection should be closed then.
+    pub fn queue_frame(&mut self, frame: &Frame) -> Result<(), ConnectionError> {
+        // checked upfront so a malformed frame doesn't leave part of it queued
+        check(frame)?;
+        if let Some(id) = self.trace {
+            // encoded on its own to be dumped, the output may already hold
+            // other frames
+            let mut traced = Output::default();
+            encode(frame, &mut traced, self.chunk_size);
+            let offset = self.bytes_written + self.out.len() as u64;
+            let data: Vec<u8> = traced
+                .queued
+                .iter()
+                .chain([&traced.buf.freeze()])
+                .flat_map(|data| data.iter().copied())
+                .collect();
+            info!(
+                "connection {} queued {} bytes at offset {}\n{}",
+                id,
+                data.len(),
+                offset,
+                hex_dump(offset, &data)
+            );
+        }
+        encode(frame, &mut self.out, self.chunk_size);
+        if self.hard_output_limit > 0 && self.out.len() > self.hard_output_limit {
+            return Err(ConnectionError::OutputLimitExceeded(self.out.len()));
+        }
+        Ok(())
+    }
+
+    pub fn queue_error(&mut self, error: impl std::error::Error) -> Result<(), ConnectionError> {
+        self.queue_frame(&Frame::Error(Bytes::from(error.to_string())))
+    }
+
+    pub async fn flush(&mut self) -> Result<(), ConnectionError> {
+        while !self.out.is_empty() {
+            self.write_some().await?;
+        }
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    async fn write_some(&mut self) -> Result<(), ConnectionError> {
+        let n = self.out.write_to(&mut self.writer).await?;
+        self.bytes_written += n as u64;
+        Ok(())
+    }
+
+    // writes out the queued output and closes the writing side of the
+    // connection
+    pub async fn close(&mut self) -> Result<(), ConnectionError> {
+        self.flush().await?;
+        self.writer.shutdown().await?;
+        Ok(())
+    }
+
+    // number of bytes queued for writing which the client hasn't taken yet
+    pub fn pending_output(&self) -> usize {
+        self.out.len()
+    }
+
+    // true when so much output is queued that no more should be produced
+    // until the client catches up
+    pub fn is_output_paused(&self) -> bool {
+        self.soft_output_limit > 0 && self.out.len() >= self.soft_output_limit
+    }
+
+    // counts the frames that were received but not read yet, a client that
+    // pipelines its commands has several of them buffered
+    pub fn buffered_frames(&self) -> usize {
+        let mut cursor = Cursor::new(&self.buf[..]);
+        let mut frames = 0;
+        while frame::parse(&mut cursor).is_ok() {
+            frames += 1;
+        }
+        frames
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    // logs hex dumps of the bytes read and of the frames written along with
+    // their offsets in the stream while `id` is set, offsets count from the
+    // start of the connection like `bytes_in` and `bytes_out` do
+    pub fn set_trace(&mut self, id: Option<u64>) {
+        self.trace = id;
+    }
+}
+
+// formats the data like `hexdump -C`, 16 bytes per line prefixed with their
+// offset in the stream
+fn hex_dump(offset: u64, data: &[u8]) -> String {
+    let mut lines = Vec::new();
+    for (i, line) in data[..data.len().min(TRACE_DUMP_LIMIT)]
+        .chunks(16)
+        .enumerate()
+    {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let text: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
                 } else {
-                    self.stream
-                        .write_all(format!("{}\r\n", 0).as_bytes())
-                        .await?;
+                    '.'
                 }
+            })
+            .collect();
+        lines.push(format!(
+            "{:08x}  {:<47}  |{}|",
+            offset + (i * 16) as u64,
+            hex.join(" "),
+            text
+        ));
+    }
+    if data.len() > TRACE_DUMP_LIMIT {
+        lines.push(format!("... {} more bytes", data.len() - TRACE_DUMP_LIMIT));
+    }
+    lines.join("\n")
+}
+
+impl Output {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn put_u8(&mut self, n: u8) {
+        self.buf.put_u8(n);
+        self.len += 1;
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        self.buf.put_slice(src);
+        self.len += src.len();
+    }
+
+    fn put_bytes(&mut self, data: Bytes) {
+        if data.len() <= COPY_LIMIT {
+            return self.put_slice(&data);
+        }
+        if !self.buf.is_empty() {
+            self.queued.push_back(self.buf.split().freeze());
+        }
+        self.len += data.len();
+        self.queued.push_back(data);
+    }
+
+    // writes out some of the output, cancelling it writes nothing
+    async fn write_to<W>(&mut self, writer: &mut W) -> io::Result<usize>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        if self.queued.is_empty() {
+            self.queued.push_back(self.buf.split().freeze());
+        }
+        let n = match self.queued.front_mut() {
+            Some(front) => {
+                let n = writer.write(front).await?;
+                front.advance(n);
+                if front.is_empty() {
+                    self.queued.pop_front();
+                }
+                n
             }
-            Frame::Null => {
-                self.stream.write_all(b"-\r\n").await?;
+            None => 0,
+        };
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        self.len -= n;
+        Ok(n)
+    }
+}
+
+fn check(frame: &Frame) -> Result<(), ConnectionError> {
+    match frame {
+        Frame::Array(array) => array.iter().try_for_each(check),
+        Frame::Map(map) => {
+            if map.len() % 2 != 0 {
+                return Err(ConnectionError::MalformedFrameForWrite);
             }
-            Frame::Double(data) => {
-                self.stream.write_u8(DOUBLE_IDENT).await?;
-                self.stream
-                    .write_all(format!("{}\r\n", data).as_bytes())
-                    .await?;
+            map.iter().try_for_each(check)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn encode(frame: &Frame, out: &mut Output, chunk_size: usize) {
+    match frame {
+        Frame::String(data) if chunk_size > 0 && data.len() > chunk_size => {
+            out.put_u8(STRING_IDENT);
+            out.put_slice(CHUNKED_LEN);
+            out.put_slice(b"\r\n");
+            for start in (0..data.len()).step_by(chunk_size) {
+                let chunk = data.slice(start..data.len().min(start + chunk_size));
+                out.put_u8(CHUNK_IDENT);
+                out.put_slice(format!("{}\r\n", chunk.len()).as_bytes());
+                out.put_bytes(chunk);
+                out.put_slice(b"\r\n");
             }
-            Frame::Error(data) => {
-                let len = data.len();
-                self.stream.write_u8(ERROR_IDENT).await?;
-                self.stream
-                    .write_all(format!("{}\r\n", len).as_bytes())
-                    .await?;
-                self.stream.write_all(data).await?;
-                self.stream.write_all(b"\r\n").await?;
+            out.put_u8(CHUNK_IDENT);
+            out.put_slice(b"0\r\n");
+        }
+        Frame::String(data) => {
+            out.put_u8(STRING_IDENT);
+            out.put_slice(format!("{}\r\n", data.len()).as_bytes());
+            out.put_bytes(data.clone());
+            out.put_slice(b"\r\n");
+        }
+        Frame::Integer(data) => {
+            out.put_u8(INTEGER_IDENT);
+            out.put_slice(format!("{}\r\n", data).as_bytes());
+        }
+        Frame::Boolean(data) => {
+            out.put_u8(BOOLEAN_IDENT);
+            if *data {
+                out.put_slice(b"1\r\n");
+            } else {
+                out.put_slice(b"0\r\n");
             }
-            Frame::Array(array) => {
-                self.stream.write_u8(ARRAY_IDENT).await?;
-                self.stream
-                    .write_all(format!("{}\r\n", array.len()).as_bytes())
-                    .await?;
-                for value in array {
-                    self.write_frame(value).await?;
-                }
+        }
+        Frame::Null => out.put_slice(b"-\r\n"),
+        Frame::Double(data) => {
+            out.put_u8(DOUBLE_IDENT);
+            out.put_slice(format!("{}\r\n", data).as_bytes());
+        }
+        Frame::Error(data) => {
+            out.put_u8(ERROR_IDENT);
+            out.put_slice(format!("{}\r\n", data.len()).as_bytes());
+            out.put_slice(data);
+            out.put_slice(b"\r\n");
+        }
+        Frame::Array(array) => {
+            out.put_u8(ARRAY_IDENT);
+            out.put_slice(format!("{}\r\n", array.len()).as_bytes());
+            for value in array {
+                encode(value, out, chunk_size);
             }
-            Frame::Map(map) => {
-                if map.len() % 2 != 0 {
-                    return Err(ConnectionError::MalformedFrameForWrite);
-                }
-                self.stream.write_u8(MAP_IDENT).await?;
-                self.stream
-                    .write_all(format!("{}\r\n", map.len() / 2).as_bytes())
-                    .await?;
-                for value in map {
-                    self.write_frame(value).await?;
-                }
+        }
+        Frame::Map(map) => {
+            out.put_u8(MAP_IDENT);
+            out.put_slice(format!("{}\r\n", map.len() / 2).as_bytes());
+            for value in map {
+                encode(value, out, chunk_size);
             }
         }
-
-        self.stream.flush().await?;
-        Ok(())
-    }
-
-    pub async fn write_error(
-        &mut self,
-        error: impl std::error::Error,
-    ) -> Result<(), ConnectionError> {
-        self.write_frame(&Frame::Error(Bytes::from(error.to_string())))
-            .await
     }
 }
 
@@ -501,4 +779,190 @@ mod tests {
             .await
             .unwrap();
     }
+
+    #[tokio::test]
+    async fn read_frame_given_pipelined_frames_counts_buffered_frames_and_bytes() {
+        let mock = Builder::new()
+            .read(b"*1\r\n$4\r\nping\r\n*1\r\n$4\r\nping\r\n*1\r\n$2")
+            .write(b"%1\r\n")
+            .build();
+        let mut connection = Connection::new(mock, 1024);
+        connection.read_frame().await.unwrap();
+        assert_eq!(connection.buffered_frames(), 1);
+        assert_eq!(connection.bytes_read(), 34);
+        connection.write_frame(&Frame::Integer(1)).await.unwrap();
+        assert_eq!(connection.bytes_written(), 4);
+    }
+
+    #[tokio::test]
+    async fn queue_frame_given_output_over_hard_limit_returns_output_limit_exceeded_error() {
+        let mock = Builder::new().build();
+        let mut connection = Connection::new(mock, 1024).with_output_limits(0, 8);
+        connection.queue_frame(&Frame::Integer(1)).unwrap();
+        match connection.queue_frame(&Frame::String(Bytes::from("foo"))) {
+            Err(ConnectionError::OutputLimitExceeded(13)) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn queue_frame_given_malformed_map_drops_partial_frame() {
+        let mock = Builder::new().build();
+        let mut connection = Connection::new(mock, 1024);
+        connection.queue_frame(&Frame::Integer(1)).unwrap();
+        assert!(connection
+            .queue_frame(&Frame::Array(vec![
+                Frame::Integer(2),
+                Frame::Map(vec![Frame::Null]),
+            ]))
+            .is_err());
+        assert_eq!(connection.pending_output(), 4);
+    }
+
+    #[tokio::test]
+    async fn read_frame_given_output_over_soft_limit_writes_before_reading() {
+        // the mock fails if the frame is read before the output is written
+        let mock = Builder::new()
+            .write(b"%1\r\n%2\r\n")
+            .read(b"*1\r\n$4\r\nping\r\n")
+            .build();
+        let mut connection = Connection::new(mock, 1024).with_output_limits(8, 0);
+        connection.queue_frame(&Frame::Integer(1)).unwrap();
+        assert!(!connection.is_output_paused());
+        connection.queue_frame(&Frame::Integer(2)).unwrap();
+        assert!(connection.is_output_paused());
+        assert!(connection.read_frame().await.unwrap().is_some());
+        assert_eq!(connection.pending_output(), 0);
+        assert_eq!(connection.bytes_written(), 8);
+    }
+
+    // a write cancelled at any point, like a connection task dropped on
+    // shutdown, must not leave half a frame for the next frame to follow
+    #[tokio::test]
+    async fn write_frame_given_cancelled_write_keeps_frames_whole() {
+        let first = Frame::String(Bytes::from("hello world"));
+        let expected = b"$11\r\nhello world\r\n%2\r\n";
+        for capacity in 1..18 {
+            let (mut client, server) = io::duplex(capacity);
+            let mut connection = Connection::new(server, 1024);
+            {
+                let mut write = tokio_test::task::spawn(connection.write_frame(&first));
+                assert!(write.poll().is_pending());
+            }
+            let mut received = vec![0; expected.len()];
+            let (written, read) = tokio::join!(
+                connection.write_frame(&Frame::Integer(2)),
+                client.read_exact(&mut received)
+            );
+            written.unwrap();
+            read.unwrap();
+            assert_eq!(&received[..], &expected[..], "capacity {}", capacity);
+        }
+    }
+
+    #[tokio::test]
+    async fn read_frame_given_cancelled_read_keeps_partial_frame() {
+        let frame = b"*2\r\n$3\r\nget\r\n$3\r\nfoo\r\n";
+        for at in 1..frame.len() {
+            let (mut client, server) = io::duplex(1024);
+            let mut connection = Connection::new(server, 1024);
+            client.write_all(&frame[..at]).await.unwrap();
+            {
+                let mut read = tokio_test::task::spawn(connection.read_frame());
+                assert!(read.poll().is_pending());
+            }
+            client.write_all(&frame[at..]).await.unwrap();
+            assert_eq!(
+                connection.read_frame().await.unwrap(),
+                Some(Frame::Array(vec![
+                    Frame::String(Bytes::from("get")),
+                    Frame::String(Bytes::from("foo")),
+                ])),
+                "cancelled at {}",
+                at
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn write_frame_given_string_over_chunk_size_writes_chunked_string() {
+        let mock = Builder::new()
+            .write(b"*2\r\n$?\r\n;4\r\nhell\r\n;1\r\no\r\n;0\r\n$2\r\nhi\r\n")
+            .build();
+        let mut connection = Connection::new(mock, 1024).with_chunk_size(4);
+        connection
+            .write_frame(&Frame::Array(vec![
+                Frame::String(Bytes::from("hello")),
+                Frame::String(Bytes::from("hi")),
+            ]))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn write_frame_given_large_string_keeps_frames_in_order() {
+        let value = Bytes::from(vec![b'a'; 3 * COPY_LIMIT]);
+        let (mut client, server) = io::duplex(1024);
+        let mut connection = Connection::new(server, 1024).with_chunk_size(2 * COPY_LIMIT);
+        connection.queue_frame(&Frame::Integer(1)).unwrap();
+        connection
+            .queue_frame(&Frame::String(value.clone()))
+            .unwrap();
+        connection.queue_frame(&Frame::Integer(2)).unwrap();
+        let mut expected = BytesMut::new();
+        expected.put_slice(b"%1\r\n$?\r\n");
+        for chunk in value.chunks(2 * COPY_LIMIT) {
+            expected.put_slice(format!(";{}\r\n", chunk.len()).as_bytes());
+            expected.put_slice(chunk);
+            expected.put_slice(b"\r\n");
+        }
+        expected.put_slice(b";0\r\n%2\r\n");
+        assert_eq!(connection.pending_output(), expected.len());
+        let mut received = vec![0; expected.len()];
+        let (flushed, read) = tokio::join!(connection.flush(), client.read_exact(&mut received));
+        flushed.unwrap();
+        read.unwrap();
+        assert_eq!(&received[..], &expected[..]);
+    }
+
+    #[tokio::test]
+    async fn read_frame_given_chunked_string_in_pieces_returns_string() {
+        let mock = Builder::new()
+            .read(b"*2\r\n$3\r\nget\r\n$?\r\n;3\r\nfo")
+            .read(b"o\r\n;3\r\nbar")
+            .read(b"\r\n;0\r\n")
+            .build();
+        let mut connection = Connection::new(mock, 1024);
+        assert_eq!(
+            connection.read_frame().await.unwrap(),
+            Some(Frame::Array(vec![
+                Frame::String(Bytes::from("get")),
+                Frame::String(Bytes::from("foobar")),
+            ]))
+        );
+    }
+
+    #[test]
+    fn hex_dump_given_data_formats_lines_with_offsets() {
+        assert_eq!(
+            hex_dump(32, b"*2\r\n$3\r\nget\r\n$1\r\nk\r\n"),
+            "00000020  2a 32 0d 0a 24 33 0d 0a 67 65 74 0d 0a 24 31 0d  |*2..$3..get..$1.|\n\
+             00000030  0a 6b 0d 0a                                      |.k..|"
+        );
+    }
+
+    #[tokio::test]
+    async fn close_given_queued_output_writes_it_before_closing() {
+        let (mut client, server) = io::duplex(4);
+        let mut connection = Connection::new(server, 1024);
+        connection
+            .queue_frame(&Frame::String(Bytes::from("foo")))
+            .unwrap();
+        connection.queue_frame(&Frame::Null).unwrap();
+        let mut received = Vec::new();
+        let (closed, read) = tokio::join!(connection.close(), client.read_to_end(&mut received));
+        closed.unwrap();
+        read.unwrap();
+        assert_eq!(received, b"$3\r\nfoo\r\n-\r\n");
+    }
 }
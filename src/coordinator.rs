@@ -0,0 +1,76 @@
+use crate::db::Db;
+use crossbeam::sync::WaitGroup;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tracing::debug;
+
+// decides which keyspaces evict keys while the server is over max memory.
+// every evict event is worth `batch` keys for each keyspace that can be
+// evicted from, the keys are then shared out in proportion to the memory of a
+// keyspace times its weight, so the keyspaces holding the most memory give up
+// the most keys and a keyspace with weight 0 is left alone.
+pub async fn run(
+    db: Arc<Db>,
+    mut evict: broadcast::Receiver<usize>,
+    mut done: broadcast::Receiver<()>,
+    wg: WaitGroup,
+) {
+    loop {
+        tokio::select! {
+            _ = done.recv() => {
+                debug!("stopping eviction coordinator, shutdown signal received");
+                break;
+            }
+            batch = evict.recv() => {
+                let batch = match batch {
+                    Ok(batch) => batch,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let keyspaces: Vec<_> = db
+                    .evictable_keyspaces()
+                    .into_iter()
+                    .filter(|(_, keyspace)| keyspace.weight() > 0)
+                    .collect();
+                let scores: Vec<u64> = keyspaces
+                    .iter()
+                    .map(|(_, keyspace)| keyspace.memory().saturating_mul(keyspace.weight() as u64))
+                    .collect();
+                let keys = batch.saturating_mul(keyspaces.len());
+                for ((name, keyspace), count) in keyspaces.iter().zip(shares(&scores, keys)) {
+                    if count > 0 {
+                        debug!("asking keyspace {:?} to evict {} keys", name, count);
+                        keyspace.request_eviction(count);
+                    }
+                }
+            }
+        }
+    }
+    drop(wg)
+}
+
+// splits `keys` in proportion to the scores, rounding up so a keyspace with
+// any score evicts at least one key
+fn shares(scores: &[u64], keys: usize) -> Vec<usize> {
+    let total: u128 = scores.iter().map(|&score| score as u128).sum();
+    if total == 0 {
+        return vec![0; scores.len()];
+    }
+    scores
+        .iter()
+        .map(|&score| (score as u128 * keys as u128).div_ceil(total) as usize)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_given_scores_splits_keys_in_proportion() {
+        assert_eq!(shares(&[300, 100, 0], 8), vec![6, 2, 0]);
+        assert_eq!(shares(&[1, 1000], 10), vec![1, 10]);
+        assert_eq!(shares(&[0, 0], 10), vec![0, 0]);
+        assert_eq!(shares(&[], 10), Vec::<usize>::new());
+    }
+}
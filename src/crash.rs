@@ -0,0 +1,95 @@
+use crate::config::ServerConfig;
+use chrono::Utc;
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::thread;
+
+// installs a panic hook which writes a crash report to the configured crash
+// report dir before handing the panic over to the default hook, so operators
+// have something useful to attach to bug reports.
+pub fn install(cfg: &ServerConfig) {
+    let dir = PathBuf::from(cfg.crash_report_dir());
+    let config = config_summary(cfg);
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let now = Utc::now();
+        let report = build_report(info, &config, &Backtrace::force_capture().to_string());
+        let path = dir.join(format!(
+            "segment-crash-{}.log",
+            now.format("%Y%m%dT%H%M%S%.3fZ")
+        ));
+        match fs::write(&path, report) {
+            Ok(_) => eprintln!("crash report written to {}", path.display()),
+            Err(e) => eprintln!(
+                "unable to write crash report to {}, error = {}",
+                path.display(),
+                e
+            ),
+        }
+        default_hook(info);
+    }));
+}
+
+fn config_summary(cfg: &ServerConfig) -> String {
+    format!(
+        "bind = {}\nport = {}\nmax_memory = {}\nconnection_buffer_size = {}\nrate_limit = {}\nrate_limit_burst = {}\nmax_concurrent_commands = {}\noutput_buffer_soft_limit = {}\noutput_buffer_hard_limit = {}\nmax_pinned_memory_percent = {}\nchunk_size = {}\nmirror = {}\nmirror_sample_percent = {}\nwarmup_percent = {}\nspill_dir = {}\nmax_command_memory = {}\nresult_cache_ttl = {}\ndata_dir = {}\nappendonly = {}\nappendfsync = {}\ngrpc_port = {}\nhttp_port = {}\neviction_batch_size = {}\nwebsocket_port = {}\neviction_high_watermark = {}\neviction_low_watermark = {}\nmemcached_port = {}\nmemcached_keyspace = {}\nquic_port = {}\nquic_cert_file = {}\nquic_key_file = {}\n",
+        cfg.bind(),
+        cfg.port(),
+        cfg.max_memory(),
+        cfg.connection_buffer_size(),
+        cfg.rate_limit(),
+        cfg.rate_limit_burst(),
+        cfg.max_concurrent_commands(),
+        cfg.output_buffer_soft_limit(),
+        cfg.output_buffer_hard_limit(),
+        cfg.max_pinned_memory_percent(),
+        cfg.chunk_size(),
+        cfg.mirror(),
+        cfg.mirror_sample_percent(),
+        cfg.warmup_percent(),
+        cfg.spill_dir(),
+        cfg.max_command_memory(),
+        cfg.result_cache_ttl(),
+        cfg.data_dir(),
+        cfg.appendonly(),
+        cfg.appendfsync().as_str(),
+        cfg.grpc_port(),
+        cfg.http_port(),
+        cfg.eviction_batch_size(),
+        cfg.websocket_port(),
+        cfg.eviction_high_watermark(),
+        cfg.eviction_low_watermark(),
+        cfg.memcached_port(),
+        cfg.memcached_keyspace(),
+        cfg.quic_port(),
+        cfg.quic_cert_file(),
+        cfg.quic_key_file(),
+    )
+}
+
+fn build_report(info: &PanicHookInfo, config: &str, backtrace: &str) -> String {
+    let message = match info.payload().downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match info.payload().downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic payload".to_string(),
+        },
+    };
+    let location = info
+        .location()
+        .map(|location| location.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    format!(
+        "# segment crash report\n\nversion = {}\ntime = {}\nthread = {}\nmessage = {}\nlocation = {}\n\n# config\n\n{}\n# backtrace\n\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        Utc::now().to_rfc3339(),
+        thread::current().name().unwrap_or("unnamed"),
+        message,
+        location,
+        config,
+        backtrace,
+    )
+}
@@ -1,59 +1,311 @@
 use crate::{
-    command::{Command, Count, Create, Del, Drop, Get, Set, Ttl},
+    adaptive::{Adaptive, Candidate},
+    allocator,
+    aof::{Aof, AofError, AofReader, Removals},
+    batch::BatchLog,
+    clock::{Clock, SystemClock},
+    command::{
+        self, docs, Alter, Avg, Batch, BitCount, CSet, Command, Count, CountByPrefix, Create,
+        DebugPopulate, DebugQuickCheck, Del, DelRange, Delay, Drop, Dump, EvictStats,
+        EvictorControl, ExpireAt, Export, FlushAll, Get, GetBit, GetRange, HDel, HGet, HGetAll,
+        HSet, LLen, LRange, MSet, MemoryUsage, Object, Pin, Pop, Push, Range, Restore, Sample,
+        ScheduleAdd, ScheduleRemove, Set, SetBit, SetRange, Sum, TaskId, Ttl, Type, Undelete,
+        ViewCreate, ViewDrop, Watch, XAdd, XGet, XLen, XRange, ZAdd, ZRange, ZRem, ZScore,
+    },
+    config::ServerConfig,
     connection::ConnectionError,
+    export::{self, ExportError, ExportFormat, ExportWriter},
     frame::Frame,
+    lifecycle::{Lifecycle, LifecycleEvent},
+    pattern,
+    recency::Recency,
+    resultcache::ResultCache,
+    scheduler::{Scheduler, SchedulerError},
+    secondchance::SecondChance,
+    segmented::Segmented,
+    snapshot::{self, KeyspaceEncoder, SaveState, SnapshotError, SnapshotReader, SnapshotWriter},
+    sortedset::SortedSet,
+    spill::{self, Spill, SpillError},
+    stats::Stats,
+    store::{self, Queues, Store, Weigh},
+    stream::{Stream, StreamId},
+    tasks::{Task, Tasks},
+    trace::KeyEvent,
+    upstream::{Upstream, UpstreamConfig, UpstreamError},
 };
+#[cfg(feature = "lua")]
+use crate::{
+    command::Eval,
+    script::{self, ScriptError},
+};
+#[cfg(feature = "wasm")]
+use crate::{
+    command::{Fcall, FunctionDelete, FunctionLoad},
+    function::{FunctionError, Functions},
+};
+use async_recursion::async_recursion;
 use bytes::Bytes;
 use crossbeam::sync::WaitGroup;
 use parking_lot::{Mutex, RwLock};
+#[cfg(feature = "lua")]
+use std::cell::RefCell;
+use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    mem,
+    ops::Deref,
     str::{self, Utf8Error},
     time::Duration,
 };
 use std::{
-    sync::Arc,
-    time::{Instant, SystemTime, SystemTimeError, UNIX_EPOCH},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    sync::{Arc, OnceLock},
+    time::Instant,
 };
 use thiserror::Error;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, oneshot, RwLock as ExecLock, Semaphore};
+use tokio::task;
 use tokio::time;
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 static EXPIRING_EVICTOR_SAMPLE_SIZE: u8 = 5;
-static MAX_MEMORY_EVICTOR_SAMPLE_SIZE: u8 = 3;
+static DEFAULT_SAMPLE_SIZE: u8 = 3;
+pub static DEFAULT_EVICTION_WEIGHT: u32 = 1;
+static AGGREGATE_CHUNK_SIZE: usize = 1000;
+static POPULATE_CHUNK_SIZE: u64 = 1000;
+// keys copied at a time by `VIEW CREATE`, see `Keyspace::snapshot`
+static SNAPSHOT_CHUNK_SIZE: usize = 1000;
+// every value gets a new version when it is written, versions are unique
+// across keyspaces so a key that is deleted and set again never gets its old
+// version back.
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+static BATCH_LOG_CAPACITY: usize = 10_000;
+// how often keyspaces with a memory budget check whether they are over it
+const KEYSPACE_MEMORY_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+// the most discrepancies `DEBUG QUICKCHECK` describes
+const QUICKCHECK_MAX_DETAILS: usize = 100;
+// the most bytes of a string `SAMPLE` returns
+const SAMPLE_VALUE_LIMIT: usize = 256;
+
+// the data held by a key, commands that expect data of another type fail with
+// a `WrongType` error.
+#[derive(Debug)]
+pub enum Data {
+    Blob(Bytes),
+    List(VecDeque<Bytes>),
+    Hash(HashMap<Bytes, Bytes>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+// a write applied through `Keyspace::apply_batch`
+#[derive(Debug, Clone)]
+pub enum Op {
+    Set {
+        key: Bytes,
+        value: Bytes,
+        expire_at: Option<u64>,
+    },
+    Del {
+        key: Bytes,
+    },
+}
+
+// a value removed by `DEL` from a keyspace with tombstones, it counts towards
+// the memory of the keyspace until it is purged
+#[derive(Debug)]
+struct Tombstone {
+    value: Value,
+    // unix timestamp in milliseconds
+    deleted_at: u64,
+}
 
 #[derive(Debug)]
 pub struct Value {
-    data: Bytes,
+    data: Data,
     last_accessed: Instant,
+    // set when the value is accessed in a keyspace with the clock evictor,
+    // which doesn't keep `last_accessed` up to date
+    referenced: bool,
     expire_at: Option<u64>,
+    version: u64,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum Evictor {
+    #[default]
     Nop,
     Random,
     Lru,
+    Arc,
+    Slru,
+    Clock,
+    ExactLru,
+}
+
+// settings of a new keyspace, see `CREATE`
+#[derive(Debug, Clone)]
+pub struct KeyspaceConfig {
+    pub evictor: Evictor,
+    pub ordered: bool,
+    // retention in milliseconds, only ordered keyspaces can have one
+    pub retention: Option<u64>,
+    // number of keys the max memory evictor looks at to pick the key to evict
+    pub sample_size: u8,
+    // misses of `GET` are read through from the upstream when there is one
+    pub upstream: Option<UpstreamConfig>,
+    // the keyspace whose writes and reads this keyspace gets a copy of, see
+    // `CREATE ... CANARY`
+    pub canary_of: Option<Bytes>,
+    // bytes the keys and values of the keyspace may take up, 0 for no limit
+    pub max_memory: u64,
+    // keys the keyspace may hold, 0 for no limit. a new key past the limit
+    // makes the evictor evict a key, or is refused without an evictor.
+    pub max_keys: u64,
+    // milliseconds deleted keys can be restored for, see `UNDELETE`
+    pub tombstone: Option<u64>,
+    // whether evicted values are kept on disk, see `spill_dir` in the config
+    pub spill: bool,
+    // how much of the server max memory eviction the keyspace takes on
+    // relative to its size, see `CREATE ... WEIGHT`
+    pub weight: u32,
 }
 
 #[derive(Debug)]
 pub struct Keyspace {
-    store: Arc<Mutex<HashMap<Bytes, Value>>>,
+    store: Arc<Mutex<Store<Value>>>,
     expiring: Arc<Mutex<HashMap<Bytes, u64>>>,
-    evictor: Evictor,
+    // keys the max memory evictor leaves alone, see `PIN`
+    pinned: Arc<Mutex<HashSet<Bytes>>>,
+    eviction: Arc<Mutex<Eviction>>,
+    eviction_stats: Arc<EvictionStats>,
+    // where evicted values go instead of being dropped, see `CREATE ... SPILL`
+    spill: Option<Arc<Spill>>,
+    // stops the max memory evictor, `None` while it isn't running
+    stop_evictor: Mutex<Option<oneshot::Sender<()>>>,
+    // set while the max memory evictor is paused, see `EVICTOR PAUSE`
+    evictor_paused: Arc<AtomicBool>,
     wg: WaitGroup,
     done: broadcast::Receiver<()>,
     drop: broadcast::Sender<()>,
-    evict: broadcast::Receiver<()>,
+    // the number of keys to evict, sent by the eviction coordinator while the
+    // server is over max memory
+    evict: broadcast::Sender<usize>,
+    owner: Option<u64>,
+    retention: Option<u64>,
+    upstream: Option<Upstream>,
+    canary_of: Option<Bytes>,
+    max_memory: u64,
+    max_keys: u64,
+    // values removed by `DEL` which can still be restored, with the time they
+    // were deleted at
+    tombstones: Mutex<Store<Tombstone>>,
+    tombstone: Option<u64>,
+    // keyspaces which shadow this one, dropped canaries are forgotten the next
+    // time the canaries are looked at
+    canaries: Mutex<Vec<KeyspaceRef>>,
+    // `GET` hits and misses, so canaries can be compared with the keyspace
+    // they shadow
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stats: Arc<Stats>,
+    dropped: AtomicBool,
+    // whether accesses only set the referenced bit of values, see
+    // `Keyspace::touch`
+    second_chance: AtomicBool,
+    // expiry deadlines and accesses are taken from it, see `Db::with_clock`
+    clock: Arc<dyn Clock>,
+    // where the keys which expire or are evicted are appended, `None` for a
+    // keyspace that isn't kept in the append only file
+    removals: Option<Removals>,
+}
+
+// the settings of the max memory evictor of a keyspace, they are read on every
+// eviction so `ALTER` can change them while the evictor runs
+#[derive(Debug, Clone, Copy)]
+struct Eviction {
+    evictor: Evictor,
+    sample_size: u8,
+    weight: u32,
+}
+
+// what the max memory evictor of a keyspace did, see `EVICTSTATS`
+#[derive(Debug, Default)]
+struct EvictionStats {
+    evicted: AtomicU64,
+    // the keys looked at to pick the evicted ones
+    sampled: AtomicU64,
+    // the bytes taken by the evicted keys and their values
+    reclaimed: AtomicU64,
+    // the evicted values kept on disk
+    spilled: AtomicU64,
+}
+
+// a handle to a keyspace which, unlike the keyspaces lock, can be held across
+// awaits. the handle doesn't hold any lock itself, every keyspace operation
+// locks the keyspace only while it runs, so holding a handle never blocks
+// other commands. the keyspace can be dropped while a handle is held, it stays
+// usable through the handle but writes made after that are lost, check
+// `is_dropped` where that matters.
+#[derive(Debug, Clone)]
+pub struct KeyspaceRef(Arc<Keyspace>);
+
+// a frozen copy of a keyspace, commands that read a keyspace fall back to
+// views so long running reads don't hold the lock of the live keyspace.
+#[derive(Debug)]
+pub struct View {
+    keyspace: Bytes,
+    store: HashMap<Bytes, Bytes>,
+    created_at: u64,
+    // views never change, a view created again under the same name gets a
+    // new epoch though
+    epoch: u64,
+}
+
+// the version of a key when it was watched, `None` if the key didn't exist
+#[derive(Debug)]
+pub struct WatchedVersion {
+    keyspace: Bytes,
+    key: Bytes,
+    version: Option<u64>,
 }
 
 #[derive(Debug)]
 pub struct Db {
-    keyspaces: RwLock<HashMap<Bytes, Keyspace>>,
+    keyspaces: RwLock<HashMap<Bytes, KeyspaceRef>>,
+    views: RwLock<HashMap<Bytes, Arc<View>>>,
     done: broadcast::Receiver<()>,
     wg: WaitGroup,
-    evict: broadcast::Receiver<()>,
+    scheduler: Scheduler,
+    // the long running commands, see `TASKS`
+    tasks: Tasks,
+    result_cache: ResultCache,
+    key_events: broadcast::Sender<KeyEvent>,
+    lifecycle: Lifecycle,
+    stats: Arc<Stats>,
+    data_lane: Option<Semaphore>,
+    // commands hold the lock shared while transactions hold it exclusively,
+    // so the commands of a transaction don't interleave with other commands.
+    exec_lock: ExecLock<()>,
+    batches: Mutex<BatchLog>,
+    // share of max memory pinned keys may take up across all keyspaces
+    max_pinned_memory_percent: u8,
+    spill_dir: String,
+    // bytes a single command may hold while building its result, 0 for no
+    // limit
+    max_command_memory: usize,
+    #[cfg(feature = "lua")]
+    lua_max_instructions: u64,
+    #[cfg(feature = "wasm")]
+    functions: Functions,
+    clock: Arc<dyn Clock>,
+    // where `SAVE` and `BGSAVE` write the snapshot
+    data_dir: String,
+    save_state: Arc<SaveState>,
+    // set once the append only file was replayed, see `appendonly` in the
+    // config. shared with the keyspaces, see `Removals`
+    aof: Arc<OnceLock<Aof>>,
 }
 
 #[derive(Debug, Error)]
@@ -67,66 +319,463 @@ pub enum ExecuteCommandError {
     #[error("keyspace '{0}' does not exist")]
     KeyspaceDoesNotExist(String),
 
+    #[error("view '{0}' already exists")]
+    ViewExists(String),
+
+    #[error("view '{0}' does not exist")]
+    ViewDoesNotExist(String),
+
+    #[error("result of '{0}' does not fit in a 64 bit integer")]
+    IntegerOverflow(String),
+
+    #[error("keyspace '{0}' is not ordered")]
+    KeyspaceNotOrdered(String),
+
+    #[error("'{0}' can only be used from a connection")]
+    ConnectionRequired(String),
+
+    #[error("'{0}' without 'multi'")]
+    TransactionRequired(String),
+
+    #[error("'{0}' is not allowed inside 'multi'")]
+    NotAllowedInTransaction(String),
+
+    #[error("operation against a key holding a {0} value")]
+    WrongType(String),
+
+    #[error("pinned keys would take more than {0}% of max_memory")]
+    PinnedMemoryExceeded(u8),
+
+    #[error("keyspace holds its max of {0} keys, see 'create ... maxkeys'")]
+    KeyspaceFull(u64),
+
+    #[error("key already exists, see 'restore ... replace'")]
+    KeyExists,
+
+    #[error("id '{0}' is not greater than the last id of the stream")]
+    StreamIdNotIncreasing(String),
+
+    #[error("ERRMEM command needs more than {0} bytes of memory, see max_command_memory")]
+    CommandMemoryExceeded(usize),
+
+    #[error("task '{0}' does not exist")]
+    TaskDoesNotExist(u64),
+
+    #[error("task '{0}' was cancelled")]
+    TaskCancelled(u64),
+
+    #[error("the append only file is off, see appendonly")]
+    AppendOnlyOff,
+
     #[error(transparent)]
     Utf8Error(#[from] Utf8Error),
 
     #[error(transparent)]
-    SystemTimeError(#[from] SystemTimeError),
+    SchedulerError(#[from] SchedulerError),
+
+    #[error(transparent)]
+    UpstreamError(#[from] UpstreamError),
+
+    #[error(transparent)]
+    SpillError(#[from] SpillError),
+
+    #[error(transparent)]
+    SnapshotError(#[from] SnapshotError),
+
+    #[error(transparent)]
+    ExportError(#[from] ExportError),
+
+    #[cfg(feature = "wasm")]
+    #[error(transparent)]
+    FunctionError(#[from] FunctionError),
+
+    #[cfg(feature = "lua")]
+    #[error(transparent)]
+    ScriptError(#[from] ScriptError),
+
+    #[cfg(feature = "lua")]
+    #[error("'{0}' can't be called from a script")]
+    NotAllowedInScript(String),
 }
 
 impl Db {
-    pub fn new(
+    pub fn new(cfg: &ServerConfig, done: broadcast::Receiver<()>, wg: WaitGroup) -> Self {
+        Db::with_clock(cfg, done, wg, Arc::new(SystemClock))
+    }
+
+    // takes the time from `clock` instead of the system clock, for tests and
+    // embedders which want to control when keys expire
+    pub fn with_clock(
+        cfg: &ServerConfig,
         done: broadcast::Receiver<()>,
         wg: WaitGroup,
-        evict: broadcast::Receiver<()>,
+        clock: Arc<dyn Clock>,
     ) -> Self {
+        // values spilled before a restart belong to keyspaces that are gone
+        if !cfg.spill_dir().is_empty() {
+            spill::clean(Path::new(cfg.spill_dir()));
+        }
         Db {
             keyspaces: RwLock::new(HashMap::new()),
+            views: RwLock::new(HashMap::new()),
             done,
             wg,
-            evict,
+            scheduler: Scheduler::new(),
+            tasks: Tasks::default(),
+            result_cache: ResultCache::new(Duration::from_millis(cfg.result_cache_ttl())),
+            key_events: broadcast::channel(1024).0,
+            lifecycle: Lifecycle::default(),
+            stats: Arc::new(Stats::new(cfg.max_memory())),
+            data_lane: match cfg.max_concurrent_commands() {
+                0 => None,
+                permits => Some(Semaphore::new(permits)),
+            },
+            exec_lock: ExecLock::new(()),
+            batches: Mutex::new(BatchLog::new(BATCH_LOG_CAPACITY)),
+            max_pinned_memory_percent: cfg.max_pinned_memory_percent(),
+            spill_dir: cfg.spill_dir().to_string(),
+            max_command_memory: cfg.max_command_memory(),
+            #[cfg(feature = "lua")]
+            lua_max_instructions: cfg.lua_max_instructions(),
+            #[cfg(feature = "wasm")]
+            functions: Functions::new(cfg.wasm_fuel()),
+            clock,
+            data_dir: cfg.data_dir().to_string(),
+            save_state: Arc::new(SaveState::default()),
+            aof: Arc::new(OnceLock::new()),
         }
     }
 
     pub async fn execute(&self, command: Command) -> Result<Frame, ExecuteCommandError> {
+        // admin commands don't touch any keyspace data, so they neither wait
+        // for the data lane nor for an exclusive command to finish
+        if command.is_admin() {
+            return self.dispatch(command).await;
+        }
+        // the permit is held until the command is done, the semaphore is never
+        // closed so acquiring it can't fail.
+        let _permit = match &self.data_lane {
+            Some(lane) => lane.acquire().await.ok(),
+            None => None,
+        };
+        let _shared;
+        let _exclusive;
+        if command.is_exclusive() {
+            _exclusive = self.exec_lock.write().await;
+        } else {
+            _shared = self.exec_lock.read().await;
+        }
+        self.dispatch(command).await
+    }
+
+    // runs the commands of a transaction one after the other without letting
+    // any other command in between, the transaction is aborted and `Null` is
+    // returned if any of the watched keys has changed since it was watched.
+    // failing commands don't abort the transaction, their errors are returned
+    // in place of their results.
+    pub async fn execute_transaction(
+        &self,
+        watched: &[WatchedVersion],
+        commands: Vec<Command>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let _permit = match &self.data_lane {
+            Some(lane) => lane.acquire().await.ok(),
+            None => None,
+        };
+        let _guard = self.exec_lock.write().await;
+
+        for watched_version in watched {
+            if self.version(&watched_version.keyspace, &watched_version.key)?
+                != watched_version.version
+            {
+                return Ok(Frame::Null);
+            }
+        }
+
+        Ok(self.dispatch_all(commands).await)
+    }
+
+    // runs the commands one after the other and returns their results, errors
+    // are returned in place of the results of the failing commands.
+    async fn dispatch_all(&self, commands: Vec<Command>) -> Frame {
+        let mut results = Vec::with_capacity(commands.len());
+        for command in commands {
+            match self.dispatch(command).await {
+                Ok(frame) => results.push(frame),
+                Err(e) => results.push(Frame::Error(Bytes::from(e.to_string()))),
+            }
+        }
+        Frame::Array(results)
+    }
+
+    // records the current version of the key, a transaction started after this
+    // only goes through if the key still has the same version by then.
+    pub fn watch(&self, cmd: &Watch) -> Result<WatchedVersion, ExecuteCommandError> {
+        Ok(WatchedVersion {
+            keyspace: cmd.keyspace(),
+            key: cmd.key(),
+            version: self.version(&cmd.keyspace(), &cmd.key())?,
+        })
+    }
+
+    fn version(&self, keyspace: &Bytes, key: &Bytes) -> Result<Option<u64>, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        match handle.get(keyspace) {
+            Some(ks) => ks.version(key),
+            None => Ok(None),
+        }
+    }
+
+    #[async_recursion]
+    async fn dispatch(&self, command: Command) -> Result<Frame, ExecuteCommandError> {
         match command {
-            Command::Create(cmd) => self.exec_create(&cmd).await,
+            Command::Create(cmd) => {
+                if cmd.temp() {
+                    return Err(ExecuteCommandError::ConnectionRequired(
+                        "create temp".to_string(),
+                    ));
+                }
+                self.exec_create(&cmd, None).await
+            }
+            Command::Alter(cmd) => self.exec_alter(&cmd),
             Command::Drop(cmd) => self.exec_drop(&cmd),
             Command::Keyspaces => self.exec_keyspaces(),
+            Command::FlushAll(cmd) => self.exec_flush_all(&cmd),
+            Command::Save => self.exec_save(),
+            Command::BgSave => self.exec_bgsave(),
+            Command::Export(cmd) => self.exec_export(&cmd).await,
+            Command::Fsync => self.exec_fsync().await,
             Command::Set(cmd) => self.exec_set(&cmd),
-            Command::Ping => Ok(Frame::String(Bytes::from_static(b"PONG"))),
-            Command::Get(cmd) => self.exec_get(&cmd),
+            Command::MSet(cmd) => self.exec_mset(cmd),
+            Command::Batch(cmd) => Ok(self.exec_batch(cmd).await),
+            Command::CSet(cmd) => self.exec_cset(&cmd),
+            Command::Ping(cmd) => Ok(Frame::String(cmd.reply())),
+            Command::Echo(cmd) => Ok(Frame::String(cmd.message())),
+            Command::Info => self.exec_info(),
+            Command::Get(cmd) => self.exec_get_through(&cmd).await,
+            Command::XGet(cmd) => self.exec_xget(&cmd),
             Command::Del(cmd) => self.exec_del(&cmd),
             Command::Count(cmd) => self.exec_count(&cmd),
+            Command::Range(cmd) => self.exec_range(&cmd),
+            Command::DelRange(cmd) => self.exec_del_range(&cmd),
+            Command::CountByPrefix(cmd) => self.exec_count_by_prefix(&cmd).await,
+            Command::Sample(cmd) => self.exec_sample(&cmd),
+            Command::EvictStats(cmd) => self.exec_evict_stats(&cmd),
+            Command::EvictorPause(cmd) => self.exec_evictor_pause(&cmd, true),
+            Command::EvictorResume(cmd) => self.exec_evictor_pause(&cmd, false),
+            Command::Sum(cmd) => self.exec_sum(&cmd).await,
+            Command::Avg(cmd) => self.exec_avg(&cmd).await,
             Command::Ttl(cmd) => self.exec_ttl(&cmd),
+            Command::ExpireAt(cmd) => self.exec_expire_at(&cmd),
+            Command::Type(cmd) => self.exec_type(&cmd),
+            Command::MemoryUsage(cmd) => self.exec_memory_usage(&cmd),
+            Command::ObjectIdleTime(cmd) => self.exec_object(&cmd, false),
+            Command::ObjectMeta(cmd) => self.exec_object(&cmd, true),
+            Command::Pin(cmd) => self.exec_pin(&cmd),
+            Command::Unpin(cmd) => self.exec_unpin(&cmd),
+            Command::Undelete(cmd) => self.exec_undelete(&cmd),
+            Command::Dump(cmd) => self.exec_dump(&cmd),
+            Command::Restore(cmd) => self.exec_restore(&cmd),
+            Command::LPush(cmd) => self.exec_push(&cmd, true),
+            Command::RPush(cmd) => self.exec_push(&cmd, false),
+            Command::LPop(cmd) => self.exec_pop(&cmd, true),
+            Command::RPop(cmd) => self.exec_pop(&cmd, false),
+            Command::LRange(cmd) => self.exec_lrange(&cmd),
+            Command::LLen(cmd) => self.exec_llen(&cmd),
+            Command::HSet(cmd) => self.exec_hset(&cmd),
+            Command::HGet(cmd) => self.exec_hget(&cmd),
+            Command::HDel(cmd) => self.exec_hdel(&cmd),
+            Command::HGetAll(cmd) => self.exec_hgetall(&cmd),
+            Command::ZAdd(cmd) => self.exec_zadd(&cmd),
+            Command::ZRange(cmd) => self.exec_zrange(&cmd),
+            Command::ZScore(cmd) => self.exec_zscore(&cmd),
+            Command::ZRem(cmd) => self.exec_zrem(&cmd),
+            Command::SetBit(cmd) => self.exec_setbit(&cmd),
+            Command::GetBit(cmd) => self.exec_getbit(&cmd),
+            Command::BitCount(cmd) => self.exec_bitcount(&cmd),
+            Command::GetRange(cmd) => self.exec_getrange(&cmd),
+            Command::SetRange(cmd) => self.exec_setrange(&cmd),
+            Command::XAdd(cmd) => self.exec_xadd(&cmd),
+            Command::XRange(cmd) => self.exec_xrange(&cmd),
+            Command::XLen(cmd) => self.exec_xlen(&cmd),
+            Command::ScheduleAdd(cmd) => self.exec_schedule_add(&cmd),
+            Command::ScheduleRemove(cmd) => self.exec_schedule_remove(&cmd),
+            Command::ScheduleList => Ok(self.scheduler.list()?),
+            Command::TasksList => Ok(self.tasks.list()),
+            Command::TasksStatus(cmd) => self.exec_tasks_status(&cmd),
+            Command::TasksCancel(cmd) => self.exec_tasks_cancel(&cmd),
+            Command::Delay(cmd) => self.exec_delay(&cmd),
+            Command::Explain(cmd) => Ok(cmd.reply()),
+            Command::WatchKey(_)
+            | Command::WatchLifecycle
+            | Command::Use(_)
+            | Command::Watch(_)
+            | Command::Unwatch
+            | Command::Multi
+            | Command::Exec
+            | Command::Discard
+            | Command::ClientInfo
+            | Command::DebugProtocol(_) => Err(ExecuteCommandError::ConnectionRequired(
+                command.name().to_string(),
+            )),
+            Command::ViewCreate(cmd) => self.exec_view_create(&cmd).await,
+            Command::ViewDrop(cmd) => self.exec_view_drop(&cmd),
+            Command::ViewList => self.exec_view_list(),
+            Command::DebugPopulate(cmd) => self.exec_debug_populate(&cmd).await,
+            Command::DebugQuickCheck(cmd) => self.exec_debug_quick_check(&cmd),
+            Command::CommandDocs(cmd) => Ok(docs::reply(cmd.names())),
+            #[cfg(feature = "lua")]
+            Command::Eval(cmd) => self.exec_eval(&cmd),
+            #[cfg(feature = "wasm")]
+            Command::FunctionLoad(cmd) => self.exec_function_load(&cmd).await,
+            #[cfg(feature = "wasm")]
+            Command::FunctionDelete(cmd) => self.exec_function_delete(&cmd),
+            #[cfg(feature = "wasm")]
+            Command::FunctionList => Ok(self.functions.list()?),
+            #[cfg(feature = "wasm")]
+            Command::Fcall(cmd) => self.exec_fcall(&cmd).await,
         }
     }
 
-    async fn exec_create(&self, cmd: &Create) -> Result<Frame, ExecuteCommandError> {
+    // creates a keyspace that is owned by the given connection, such keyspaces
+    // are dropped using `drop_owned_keyspaces` once the connection is closed.
+    pub async fn create_temp_keyspace(
+        &self,
+        cmd: &Create,
+        owner: u64,
+    ) -> Result<Frame, ExecuteCommandError> {
+        self.exec_create(cmd, Some(owner)).await
+    }
+
+    pub fn drop_owned_keyspaces(&self, owner: u64) {
         let mut handle = self.keyspaces.write();
-        if handle.contains_key(&cmd.keyspace()) {
-            if cmd.if_not_exists() {
-                return Ok(Frame::Boolean(false));
-            } else {
-                return Err(ExecuteCommandError::KeyspaceExists(
-                    str::from_utf8(&cmd.keyspace()[..])?.to_string(),
-                ));
+        handle.retain(|name, keyspace| {
+            let owned = keyspace.owner() == Some(owner);
+            if owned {
+                keyspace.mark_dropped();
+                self.lifecycle
+                    .publish(LifecycleEvent::KeyspaceDropped(name.clone()));
             }
+            !owned
+        });
+    }
+
+    // returns a handle to the keyspace, see `KeyspaceRef` for what can and
+    // can't be done with it.
+    pub fn keyspace(&self, name: &Bytes) -> Option<KeyspaceRef> {
+        self.keyspaces.read().get(name).cloned()
+    }
+
+    async fn exec_create(
+        &self,
+        cmd: &Create,
+        owner: Option<u64>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let (_, created) = self.create_keyspace(cmd.keyspace(), &cmd.config(), owner)?;
+        if !created && !cmd.if_not_exists() {
+            return Err(ExecuteCommandError::KeyspaceExists(
+                str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+            ));
         }
+        Ok(Frame::Boolean(created))
+    }
 
-        let ks = Keyspace::new(
-            self.done.resubscribe(),
-            self.wg.clone(),
-            cmd.evictor(),
-            self.evict.resubscribe(),
-        );
+    // returns the keyspace with the given name along with whether it was
+    // created, the keyspace is created with the given config if it doesn't
+    // exist yet. the lookup and the creation happen under the same lock so
+    // concurrent callers always end up with the same keyspace. a dropped
+    // keyspace stays usable through its handles until the last one is gone.
+    pub fn get_or_create(
+        &self,
+        name: Bytes,
+        config: &KeyspaceConfig,
+    ) -> Result<(KeyspaceRef, bool), ExecuteCommandError> {
+        self.create_keyspace(name, config, None)
+    }
+
+    fn create_keyspace(
+        &self,
+        name: Bytes,
+        config: &KeyspaceConfig,
+        owner: Option<u64>,
+    ) -> Result<(KeyspaceRef, bool), ExecuteCommandError> {
+        let mut handle = self.keyspaces.write();
+        if let Some(ks) = handle.get(&name) {
+            return Ok((ks.clone(), false));
+        }
+
+        if config.retention.is_some() && !config.ordered {
+            return Err(ExecuteCommandError::KeyspaceNotOrdered(
+                str::from_utf8(&name[..])?.to_string(),
+            ));
+        }
+
+        // views are read through the same name as keyspaces, so the names
+        // can't be shared between them.
+        if self.views.read().contains_key(&name) {
+            return Err(ExecuteCommandError::ViewExists(
+                str::from_utf8(&name[..])?.to_string(),
+            ));
+        }
+
+        let shadowed = match &config.canary_of {
+            Some(source) => match handle.get(source) {
+                Some(source) => Some(source.clone()),
+                None => {
+                    return Err(ExecuteCommandError::KeyspaceDoesNotExist(
+                        str::from_utf8(&source[..])?.to_string(),
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        let spill = match (config.spill, self.spill_dir.as_str()) {
+            (false, _) => None,
+            (true, "") => return Err(SpillError::NotConfigured.into()),
+            (true, root) => Some(Spill::open(Path::new(root), &name)?),
+        };
+
+        let ks = KeyspaceRef(Arc::new(
+            Keyspace::new(
+                config,
+                spill,
+                owner,
+                self.done.resubscribe(),
+                self.wg.clone(),
+                self.stats.clone(),
+                self.clock.clone(),
+            )
+            .with_removals(Removals::new(
+                name.clone(),
+                config.canary_of.clone(),
+                self.aof.clone(),
+            )),
+        ));
 
         ks.start_expiring_evictor();
         ks.start_max_memory_evictor();
 
-        handle.insert(cmd.keyspace(), ks);
+        if let Some(source) = shadowed {
+            source.canaries.lock().push(ks.clone());
+        }
+        handle.insert(name.clone(), ks.clone());
+        self.lifecycle
+            .publish(LifecycleEvent::KeyspaceCreated(name));
 
-        Ok(Frame::Boolean(true))
+        Ok((ks, true))
+    }
+
+    fn exec_alter(&self, cmd: &Alter) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            ks.alter(cmd.evictor(), cmd.sample_size(), cmd.weight());
+            return Ok(Frame::Boolean(true));
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
 
     fn exec_drop(&self, cmd: &Drop) -> Result<Frame, ExecuteCommandError> {
@@ -140,10 +789,97 @@ impl Db {
                 ));
             }
         }
-        handle.remove(&cmd.keyspace());
+        if let Some(keyspace) = handle.remove(&cmd.keyspace()) {
+            keyspace.mark_dropped();
+            self.lifecycle
+                .publish(LifecycleEvent::KeyspaceDropped(cmd.keyspace()));
+        }
         Ok(Frame::Boolean(true))
     }
 
+    fn exec_info(&self) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        let mut total_keys = 0;
+        let mut keyspaces = Vec::with_capacity(handle.len() * 2);
+        for (name, keyspace) in handle.iter() {
+            let keys = keyspace.len();
+            total_keys += keys;
+            keyspaces.push(Frame::String(name.clone()));
+            keyspaces.push(Frame::Integer(keys as i64));
+        }
+        drop(handle);
+
+        let stats = &self.stats;
+        Ok(Frame::Map(vec![
+            Frame::String(Bytes::from_static(b"server")),
+            Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"uptime")),
+                Frame::Integer(stats.uptime().as_secs() as i64),
+                Frame::String(Bytes::from_static(b"state")),
+                Frame::String(Bytes::from_static(self.lifecycle.state().as_bytes())),
+            ]),
+            Frame::String(Bytes::from_static(b"clients")),
+            Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"connected")),
+                Frame::Integer(stats.connections() as i64),
+                Frame::String(Bytes::from_static(b"total")),
+                Frame::Integer(stats.total_connections() as i64),
+                Frame::String(Bytes::from_static(b"throttled_commands")),
+                Frame::Integer(stats.throttled_commands() as i64),
+                Frame::String(Bytes::from_static(b"mirrored_commands")),
+                Frame::Integer(stats.mirrored_commands() as i64),
+                Frame::String(Bytes::from_static(b"mirror_dropped_commands")),
+                Frame::Integer(stats.mirror_dropped_commands() as i64),
+            ]),
+            Frame::String(Bytes::from_static(b"memory")),
+            Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"used")),
+                Frame::Integer(allocator::allocated().unwrap_or_else(|| stats.memory()) as i64),
+                Frame::String(Bytes::from_static(b"allocator")),
+                Frame::String(Bytes::from_static(allocator::name().as_bytes())),
+                Frame::String(Bytes::from_static(b"data")),
+                Frame::Integer(stats.data_memory() as i64),
+                Frame::String(Bytes::from_static(b"max")),
+                Frame::Integer(stats.max_memory() as i64),
+            ]),
+            Frame::String(Bytes::from_static(b"keys")),
+            Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"total")),
+                Frame::Integer(total_keys as i64),
+                Frame::String(Bytes::from_static(b"expired")),
+                Frame::Integer(stats.expired_keys() as i64),
+                Frame::String(Bytes::from_static(b"evicted")),
+                Frame::Integer(stats.evicted_keys() as i64),
+            ]),
+            Frame::String(Bytes::from_static(b"result_cache")),
+            Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"entries")),
+                Frame::Integer(self.result_cache.len() as i64),
+                Frame::String(Bytes::from_static(b"hits")),
+                Frame::Integer(stats.result_cache_hits() as i64),
+                Frame::String(Bytes::from_static(b"misses")),
+                Frame::Integer(stats.result_cache_misses() as i64),
+            ]),
+            Frame::String(Bytes::from_static(b"persistence")),
+            Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"saving")),
+                Frame::Boolean(self.save_state.is_saving()),
+                Frame::String(Bytes::from_static(b"last_save")),
+                Frame::Integer(self.save_state.last_save() as i64),
+                Frame::String(Bytes::from_static(b"last_save_keys")),
+                Frame::Integer(self.save_state.last_save_keys() as i64),
+                Frame::String(Bytes::from_static(b"last_save_status")),
+                Frame::String(Bytes::from_static(if self.save_state.last_save_failed() {
+                    b"failed"
+                } else {
+                    b"ok"
+                })),
+            ]),
+            Frame::String(Bytes::from_static(b"keyspaces")),
+            Frame::Map(keyspaces),
+        ]))
+    }
+
     fn exec_keyspaces(&self) -> Result<Frame, ExecuteCommandError> {
         let handle = self.keyspaces.read();
         let mut keyspaces = Vec::with_capacity(handle.keys().count());
@@ -156,6 +892,49 @@ impl Db {
                 map.push(name);
                 map.push(Frame::String(Bytes::from_static(b"evictor")));
                 map.push(evictor);
+                map.push(Frame::String(Bytes::from_static(b"ordered")));
+                map.push(Frame::Boolean(keyspace.ordered()));
+                map.push(Frame::String(Bytes::from_static(b"retention")));
+                map.push(match keyspace.retention() {
+                    Some(retention) => Frame::Integer(retention as i64),
+                    None => Frame::Null,
+                });
+                map.push(Frame::String(Bytes::from_static(b"sample_size")));
+                map.push(Frame::Integer(keyspace.sample_size() as i64));
+                map.push(Frame::String(Bytes::from_static(b"pinned")));
+                map.push(Frame::Integer(keyspace.pinned_count() as i64));
+                map.push(Frame::String(Bytes::from_static(b"upstream")));
+                map.push(match keyspace.upstream() {
+                    Some(upstream) => Frame::String(Bytes::from(upstream.address.clone())),
+                    None => Frame::Null,
+                });
+                map.push(Frame::String(Bytes::from_static(b"canary_of")));
+                map.push(match keyspace.canary_of() {
+                    Some(source) => Frame::String(source.clone()),
+                    None => Frame::Null,
+                });
+                map.push(Frame::String(Bytes::from_static(b"memory")));
+                map.push(Frame::Integer(keyspace.memory() as i64));
+                map.push(Frame::String(Bytes::from_static(b"max_memory")));
+                map.push(Frame::Integer(keyspace.max_memory() as i64));
+                map.push(Frame::String(Bytes::from_static(b"max_keys")));
+                map.push(Frame::Integer(keyspace.max_keys() as i64));
+                map.push(Frame::String(Bytes::from_static(b"weight")));
+                map.push(Frame::Integer(keyspace.weight() as i64));
+                map.push(Frame::String(Bytes::from_static(b"tombstone")));
+                map.push(match keyspace.tombstone() {
+                    Some(grace) => Frame::Integer(grace as i64),
+                    None => Frame::Null,
+                });
+                map.push(Frame::String(Bytes::from_static(b"spilled")));
+                map.push(match keyspace.spilled() {
+                    Some(spilled) => Frame::Integer(spilled as i64),
+                    None => Frame::Null,
+                });
+                map.push(Frame::String(Bytes::from_static(b"hits")));
+                map.push(Frame::Integer(keyspace.hits() as i64));
+                map.push(Frame::String(Bytes::from_static(b"misses")));
+                map.push(Frame::Integer(keyspace.misses() as i64));
                 keyspaces.push(Frame::Map(map))
             } else {
                 continue;
@@ -164,19 +943,227 @@ impl Db {
         Ok(Frame::Array(keyspaces))
     }
 
+    fn exec_flush_all(&self, cmd: &FlushAll) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.keyspaces.write();
+        let (dropped, flushed) = if cmd.drop() {
+            (std::mem::take(&mut *handle), Vec::new())
+        } else {
+            let flushed: Vec<_> = handle.values().map(|keyspace| keyspace.flush()).collect();
+            (HashMap::new(), flushed)
+        };
+        drop(handle);
+        for (name, keyspace) in dropped.iter() {
+            keyspace.mark_dropped();
+            self.lifecycle
+                .publish(LifecycleEvent::KeyspaceDropped(name.clone()));
+        }
+
+        // freeing a lot of keys can take a while, with async we hand the
+        // flushed data over to the blocking pool and return right away.
+        if cmd.async_() {
+            tokio::task::spawn_blocking(move || {
+                drop(dropped);
+                drop(flushed);
+            });
+        }
+
+        Ok(Frame::Boolean(true))
+    }
+
+    // writes the snapshot before replying, other commands wait until it is
+    // written. replies with the number of keys saved.
+    fn exec_save(&self) -> Result<Frame, ExecuteCommandError> {
+        let saving = self.save_state.start().ok_or(SnapshotError::InProgress)?;
+        let task = self.tasks.start("save", Bytes::new());
+        match save(self.persisted_keyspaces(), &self.snapshot_path(), &task) {
+            Ok(keys) => {
+                saving.done(self.clock.now().as_secs(), keys);
+                task.done();
+                Ok(Frame::Integer(keys as i64))
+            }
+            Err(ExecuteCommandError::TaskCancelled(id)) => {
+                Err(ExecuteCommandError::TaskCancelled(id))
+            }
+            Err(e) => {
+                saving.failed();
+                Err(e)
+            }
+        }
+    }
+
+    // replies once the writes appended so far are synced to disk, for the
+    // writes which can't be lost even with `appendfsync everysec`
+    async fn exec_fsync(&self) -> Result<Frame, ExecuteCommandError> {
+        let aof = self.aof.get().ok_or(ExecuteCommandError::AppendOnlyOff)?;
+        aof.sync().await;
+        Ok(Frame::Boolean(true))
+    }
+
+    // writes the snapshot on the blocking pool, replies with the id of the
+    // task right away, see `TASKS STATUS`
+    fn exec_bgsave(&self) -> Result<Frame, ExecuteCommandError> {
+        let saving = self.save_state.start().ok_or(SnapshotError::InProgress)?;
+        let task = self.tasks.start("bgsave", Bytes::new());
+        let id = task.id();
+        let keyspaces = self.persisted_keyspaces();
+        let path = self.snapshot_path();
+        let clock = self.clock.clone();
+        let wg = self.wg.clone();
+        task::spawn_blocking(move || {
+            match save(keyspaces, &path, &task) {
+                Ok(keys) => {
+                    saving.done(clock.now().as_secs(), keys);
+                    task.done();
+                }
+                Err(ExecuteCommandError::TaskCancelled(_)) => {}
+                Err(e) => {
+                    error!("background save failed, error = {}", e);
+                    saving.failed();
+                }
+            }
+            drop(wg);
+        });
+        Ok(Frame::Integer(id as i64))
+    }
+
+    // writes the keys of the keyspace to a file in the exports dir, see
+    // `ExportWriter`. the keyspace is encoded like a save does, so the file
+    // is written on the blocking pool without holding the keyspace. replies
+    // with the number of keys exported once the file is written.
+    async fn exec_export(&self, cmd: &Export) -> Result<Frame, ExecuteCommandError> {
+        let path = export::resolve(&self.data_dir, &self.spill_dir, cmd.path())?;
+        let encoder = match self.keyspaces.read().get(&cmd.keyspace()) {
+            Some(ks) => ks.encode(&cmd.keyspace()),
+            None => {
+                return Err(ExecuteCommandError::KeyspaceDoesNotExist(
+                    str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+                ))
+            }
+        };
+        let task = self.tasks.start("export", cmd.keyspace());
+        task.set_total(encoder.keys());
+        let format = cmd.format();
+        let wg = self.wg.clone();
+        let keys = task::spawn_blocking(move || {
+            let keys = export(encoder, &path, format, task);
+            drop(wg);
+            keys
+        })
+        .await
+        .map_err(ExportError::from)??;
+        Ok(Frame::Integer(keys as i64))
+    }
+
+    // the snapshot in the data dir, `None` when there is no snapshot
+    pub fn open_snapshot(&self) -> Result<Option<SnapshotReader>, ExecuteCommandError> {
+        Ok(SnapshotReader::open(&self.snapshot_path())?)
+    }
+
+    // restores the keyspaces and their keys from the snapshot, keys which
+    // expired since the snapshot was written are left out. keys written while
+    // the snapshot loads are newer than the snapshot, they are kept.
+    // `progress` is told how much of the snapshot was read in percent every
+    // few keys, see `warmup_percent` in the config. returns the number of keys
+    // restored.
+    pub fn load_snapshot(
+        &self,
+        mut reader: SnapshotReader,
+        mut progress: impl FnMut(u8),
+    ) -> Result<u64, ExecuteCommandError> {
+        let current_time = self.clock.now().as_secs();
+        let mut keys = 0;
+        let mut read = 0;
+        while let Some((name, config)) = reader.next_keyspace()? {
+            let (keyspace, _) = self.create_keyspace(name, &config, None)?;
+            while let Some((key, expire_at, data)) = reader.next_entry()? {
+                read += 1;
+                if read % SNAPSHOT_CHUNK_SIZE == 0 {
+                    progress(reader.percent_read()?);
+                }
+                if expire_at.is_some_and(|expiry| expiry < current_time) {
+                    continue;
+                }
+                if keyspace.restore(key, expire_at, data) {
+                    keys += 1;
+                }
+            }
+        }
+        progress(100);
+        Ok(keys)
+    }
+
+    // temporary keyspaces go away with their connection, they aren't saved
+    fn persisted_keyspaces(&self) -> Vec<(Bytes, KeyspaceRef)> {
+        self.keyspaces
+            .read()
+            .iter()
+            .filter(|(_, keyspace)| keyspace.owner().is_none())
+            .map(|(name, keyspace)| (name.clone(), keyspace.clone()))
+            .collect()
+    }
+
+    fn snapshot_path(&self) -> PathBuf {
+        Path::new(&self.data_dir).join(snapshot::FILE_NAME)
+    }
+
     fn exec_set(&self, cmd: &Set) -> Result<Frame, ExecuteCommandError> {
         let handle = self.keyspaces.read();
         let keyspace = handle.get(&cmd.keyspace());
         if let Some(ks) = keyspace {
-            if cmd.if_exists() || cmd.if_not_exists() {
-                if cmd.if_exists() {
-                    return ks.set_if_exists(cmd.key(), cmd.value(), cmd.expire_at());
-                } else {
-                    return ks.set_if_not_exists(cmd.key(), cmd.value(), cmd.expire_at());
-                }
-            } else {
-                return ks.set(cmd.key(), cmd.value(), cmd.expire_at());
+            let reply = set(ks, cmd)?;
+            // canaries apply the write to their own keys, what they reply
+            // doesn't matter
+            for canary in ks.canaries() {
+                let _ = set(&canary, cmd);
+            }
+            return Ok(reply);
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_mset(&self, cmd: MSet) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            let ops = cmd
+                .into_pairs()
+                .into_iter()
+                .map(|(key, value)| Op::Set {
+                    key,
+                    value,
+                    expire_at: None,
+                })
+                .collect::<Vec<_>>();
+            for canary in ks.canaries() {
+                canary.apply_batch(ops.iter().map(Op::clone).collect());
             }
+            ks.apply_batch(ops);
+            return Ok(Frame::Boolean(true));
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    // a batch is applied at most once per token, retries get the results of
+    // the first attempt. has to run under the exclusive lock.
+    async fn exec_batch(&self, cmd: Batch) -> Frame {
+        let token = cmd.token();
+        if let Some(result) = self.batches.lock().get(&token) {
+            return result.clone();
+        }
+        let result = self.dispatch_all(cmd.into_commands()).await;
+        self.batches.lock().insert(token, result.clone());
+        result
+    }
+
+    fn exec_cset(&self, cmd: &CSet) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.compare_and_set(cmd.key(), cmd.value(), cmd.version());
         }
 
         Err(ExecuteCommandError::KeyspaceDoesNotExist(
@@ -188,7 +1175,27 @@ impl Db {
         let handle = self.keyspaces.read();
         let keyspace = handle.get(&cmd.keyspace());
         if let Some(ks) = keyspace {
-            return ks.get(cmd.key());
+            let reply = get(ks, cmd)?;
+            ks.record_read(&reply);
+            // canaries are read too so their evictors see the same accesses
+            for canary in ks.canaries() {
+                if let Ok(reply) = canary.get(cmd.key()) {
+                    canary.record_read(&reply);
+                }
+            }
+            return Ok(reply);
+        }
+        drop(handle);
+
+        // views are frozen copies and don't keep the versions of their values
+        if let Some(view) = self.view(&cmd.keyspace()) {
+            if cmd.with_version() {
+                return Ok(match view.get(cmd.key())? {
+                    Frame::Null => Frame::Null,
+                    data => Frame::Array(vec![data, Frame::Null]),
+                });
+            }
+            return view.get(cmd.key());
         }
 
         Err(ExecuteCommandError::KeyspaceDoesNotExist(
@@ -196,10 +1203,48 @@ impl Db {
         ))
     }
 
+    // a miss on a keyspace with an upstream is fetched from the upstream and
+    // cached, the keyspace handle is used so no lock is held while waiting on
+    // the upstream.
+    async fn exec_get_through(&self, cmd: &Get) -> Result<Frame, ExecuteCommandError> {
+        let reply = self.exec_get(cmd)?;
+        if reply != Frame::Null {
+            return Ok(reply);
+        }
+        let ks = match self.keyspace(&cmd.keyspace()) {
+            Some(ks) => ks,
+            None => return Ok(reply),
+        };
+        if !ks.fetch(&cmd.keyspace(), cmd.key()).await? {
+            return Ok(reply);
+        }
+        get(&ks, cmd)
+    }
+
+    fn exec_xget(&self, cmd: &XGet) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        let mut values = Vec::with_capacity(cmd.keys().len());
+        for (keyspace, key) in cmd.keys() {
+            if let Some(ks) = handle.get(keyspace) {
+                values.push(ks.get(key.clone())?);
+            } else if let Some(view) = self.view(keyspace) {
+                values.push(view.get(key.clone())?);
+            } else {
+                return Err(ExecuteCommandError::KeyspaceDoesNotExist(
+                    str::from_utf8(&keyspace[..])?.to_string(),
+                ));
+            }
+        }
+        Ok(Frame::Array(values))
+    }
+
     fn exec_del(&self, cmd: &Del) -> Result<Frame, ExecuteCommandError> {
         let handle = self.keyspaces.read();
         let keyspace = handle.get(&cmd.keyspace());
         if let Some(ks) = keyspace {
+            for canary in ks.canaries() {
+                let _ = canary.del(cmd.key());
+            }
             return ks.del(cmd.key());
         }
 
@@ -214,294 +1259,3872 @@ impl Db {
         if let Some(ks) = keyspace {
             return ks.count();
         }
+        drop(handle);
+
+        if let Some(view) = self.view(&cmd.keyspace()) {
+            return view.count();
+        }
 
         Err(ExecuteCommandError::KeyspaceDoesNotExist(
             str::from_utf8(&cmd.keyspace()[..])?.to_string(),
         ))
     }
 
-    fn exec_ttl(&self, cmd: &Ttl) -> Result<Frame, ExecuteCommandError> {
+    fn exec_range(&self, cmd: &Range) -> Result<Frame, ExecuteCommandError> {
         let handle = self.keyspaces.read();
         let keyspace = handle.get(&cmd.keyspace());
         if let Some(ks) = keyspace {
-            return ks.ttl(cmd.key());
+            let mut memory = CommandMemory::new(self.max_command_memory);
+            return match ks.range(cmd.start(), cmd.end(), &mut memory)? {
+                Some(frame) => Ok(frame),
+                None => Err(ExecuteCommandError::KeyspaceNotOrdered(
+                    str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+                )),
+            };
         }
 
         Err(ExecuteCommandError::KeyspaceDoesNotExist(
             str::from_utf8(&cmd.keyspace()[..])?.to_string(),
         ))
     }
-}
 
-impl Keyspace {
-    pub fn new(
-        done: broadcast::Receiver<()>,
-        wg: WaitGroup,
-        evictor: Evictor,
-        evict: broadcast::Receiver<()>,
-    ) -> Self {
-        let (drop_tx, _) = broadcast::channel(1);
-        Keyspace {
-            store: Arc::new(Mutex::new(HashMap::new())),
-            expiring: Arc::new(Mutex::new(HashMap::new())),
-            evictor,
-            done,
-            wg,
-            drop: drop_tx,
-            evict,
+    fn exec_del_range(&self, cmd: &DelRange) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        let keyspace = handle.get(&cmd.keyspace());
+        if let Some(ks) = keyspace {
+            return match ks.del_range(cmd.start(), cmd.end()) {
+                Some(frame) => Ok(frame),
+                None => Err(ExecuteCommandError::KeyspaceNotOrdered(
+                    str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+                )),
+            };
         }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
-    pub fn set_if_not_exists(
+
+    async fn exec_count_by_prefix(
         &self,
-        key: Bytes,
-        value: Bytes,
-        expire_at: Option<u64>,
+        cmd: &CountByPrefix,
     ) -> Result<Frame, ExecuteCommandError> {
-        let handle = self.store.lock();
-        let val = handle.get(&key);
-        if val.is_some() {
-            return Ok(Frame::Boolean(false));
+        let prefix = cmd.prefix();
+        let key = vec![
+            Bytes::from_static(b"countbyprefix"),
+            cmd.keyspace(),
+            prefix.clone(),
+        ];
+        self.cached(key, &cmd.keyspace(), async {
+            let mut count = 0;
+            self.aggregate(
+                "countbyprefix",
+                &cmd.keyspace(),
+                |key| key.starts_with(&prefix),
+                |_| count += 1,
+            )
+            .await?;
+            Ok(Frame::Integer(count))
+        })
+        .await
+    }
+
+    fn exec_sample(&self, cmd: &Sample) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.sample(cmd.count());
         }
-        drop(handle);
-        self.set(key, value, expire_at)
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
 
-    pub fn set_if_exists(
-        &self,
-        key: Bytes,
-        value: Bytes,
-        expire_at: Option<u64>,
-    ) -> Result<Frame, ExecuteCommandError> {
-        let handle = self.store.lock();
-        let val = handle.get(&key);
-        if val.is_none() {
-            return Ok(Frame::Boolean(false));
+    fn exec_evict_stats(&self, cmd: &EvictStats) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return Ok(ks.evict_stats());
         }
-        drop(handle);
-        self.set(key, value, expire_at)
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
 
-    pub fn set(
+    fn exec_evictor_pause(
         &self,
-        key: Bytes,
-        value: Bytes,
-        expire_at: Option<u64>,
+        cmd: &EvictorControl,
+        paused: bool,
     ) -> Result<Frame, ExecuteCommandError> {
-        let mut handle = self.store.lock();
-        let value = Value::new(value, expire_at);
-        handle.insert(key.clone(), value);
-        if let Some(expiry) = expire_at {
-            let mut expring_handle = self.expiring.lock();
-            expring_handle.insert(key, expiry);
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return Ok(Frame::Boolean(ks.set_evictor_paused(paused)));
         }
-        Ok(Frame::Boolean(true))
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
 
-    pub fn get(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
-        let mut handle = self.store.lock();
-        if let Some(val) = handle.get_mut(&key) {
-            val.touch();
-            if let Some(expiry) = val.expire_at() {
-                let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-                if expiry < current_time {
-                    handle.remove(&key);
-                    return Ok(Frame::Null);
-                }
+    async fn exec_sum(&self, cmd: &Sum) -> Result<Frame, ExecuteCommandError> {
+        let key = [Bytes::from_static(b"sum"), cmd.keyspace()]
+            .into_iter()
+            .chain(cmd.pattern())
+            .collect();
+        self.cached(key, &cmd.keyspace(), async {
+            let (total, _) = self
+                .sum_integers("sum", &cmd.keyspace(), cmd.pattern())
+                .await?;
+            let total = i64::try_from(total)
+                .map_err(|_| ExecuteCommandError::IntegerOverflow("sum".to_string()))?;
+            Ok(Frame::Integer(total))
+        })
+        .await
+    }
+
+    async fn exec_avg(&self, cmd: &Avg) -> Result<Frame, ExecuteCommandError> {
+        let key = [Bytes::from_static(b"avg"), cmd.keyspace()]
+            .into_iter()
+            .chain(cmd.pattern())
+            .collect();
+        self.cached(key, &cmd.keyspace(), async {
+            let (total, count) = self
+                .sum_integers("avg", &cmd.keyspace(), cmd.pattern())
+                .await?;
+            if count == 0 {
+                return Ok(Frame::Null);
             }
-            return Ok(Frame::String(val.data()));
-        }
-        Ok(Frame::Null)
+            Ok(Frame::Double(total as f64 / count as f64))
+        })
+        .await
     }
 
-    pub fn del(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
-        let mut handle = self.store.lock();
-        let result = handle.remove(&key);
-        Ok(Frame::Boolean(result.is_some()))
+    // serves the result from the result cache while the keyspace it reads
+    // wasn't written to since it was cached, otherwise computes the result
+    // and caches it
+    async fn cached(
+        &self,
+        key: Vec<Bytes>,
+        keyspace: &Bytes,
+        result: impl Future<Output = Result<Frame, ExecuteCommandError>>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        if !self.result_cache.is_enabled() {
+            return result.await;
+        }
+        if let Some(frame) = self.result_cache.get(&key, |keyspace| self.epoch(keyspace)) {
+            self.stats.record_result_cache_hit();
+            return Ok(frame);
+        }
+        self.stats.record_result_cache_miss();
+        let epoch = self.epoch(keyspace);
+        let frame = result.await?;
+        self.result_cache
+            .insert(key, vec![(keyspace.clone(), epoch)], frame.clone());
+        Ok(frame)
     }
 
-    pub fn count(&self) -> Result<Frame, ExecuteCommandError> {
-        let handle = self.store.lock();
-        let count = handle.iter().count();
-        Ok(Frame::Integer(count as i64))
+    // the epoch of the keyspace or view, see `Store::epoch`. 0 when there is
+    // no such keyspace, epochs start at 1.
+    fn epoch(&self, keyspace: &Bytes) -> u64 {
+        if let Some(ks) = self.keyspace(keyspace) {
+            return ks.epoch();
+        }
+        self.view(keyspace).map_or(0, |view| view.epoch)
     }
 
-    pub fn ttl(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
-        let mut handle = self.store.lock();
-        if let Some(val) = handle.get_mut(&key) {
-            val.touch();
-            if let Some(expiry) = val.expire_at() {
-                let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-                if expiry <= current_time {
-                    handle.remove(&key);
-                    return Ok(Frame::Null);
-                } else {
-                    return Ok(Frame::Integer(((expiry - current_time) * 1000) as i64));
+    // sums the values that are integers, other values are skipped. returns the
+    // total along with the number of values that were summed.
+    async fn sum_integers(
+        &self,
+        command: &'static str,
+        keyspace: &Bytes,
+        pattern: Option<Bytes>,
+    ) -> Result<(i128, u64), ExecuteCommandError> {
+        let mut total: i128 = 0;
+        let mut count = 0;
+        self.aggregate(
+            command,
+            keyspace,
+            |key| match &pattern {
+                Some(pattern) => pattern::matches(pattern, key),
+                None => true,
+            },
+            |data| {
+                let value = str::from_utf8(data)
+                    .ok()
+                    .and_then(|value| value.parse::<i64>().ok());
+                if let Some(value) = value {
+                    total += value as i128;
+                    count += 1;
                 }
-            }
-            return Ok(Frame::Null);
-        }
-        Ok(Frame::Null)
+            },
+        )
+        .await?;
+        Ok((total, count))
     }
-    fn start_expiring_evictor(&self) {
-        let mut done = self.done.resubscribe();
-        let wg = self.wg.clone();
-        let expiring = self.expiring.clone();
-        let store = self.store.clone();
-        let mut drop_rx = self.drop.subscribe();
-        tokio::spawn(async move {
-            debug!("expiring evictor started");
-            loop {
-                tokio::select! {
-                    _ = done.recv() => {
-                        drop(wg);
-                        debug!("shutting down expiring evictor, shutdown signal received");
-                        break;
+
+    // feeds the values of all the keys accepted by the filter to `f`. on a live
+    // keyspace the matching keys are collected first and their values are then
+    // read in chunks, the locks are released in between chunks so writers
+    // aren't blocked for the whole scan. keys written after the scan started may
+    // or may not be seen, use a view when a consistent result is needed. the
+    // scan runs as a task, it stops in between chunks when it is cancelled.
+    async fn aggregate(
+        &self,
+        command: &'static str,
+        keyspace: &Bytes,
+        filter: impl Fn(&[u8]) -> bool,
+        mut f: impl FnMut(&[u8]),
+    ) -> Result<(), ExecuteCommandError> {
+        let ks = match self.keyspace(keyspace) {
+            Some(ks) => ks,
+            None => {
+                let view = match self.view(keyspace) {
+                    Some(view) => view,
+                    None => {
+                        return Err(ExecuteCommandError::KeyspaceDoesNotExist(
+                            str::from_utf8(&keyspace[..])?.to_string(),
+                        ))
                     }
-                    _ = drop_rx.recv() => {
-                        drop(wg);
-                        debug!("shutting down expiring evictor, keyspace is dropped");
-                        break;
+                };
+                let task = self.tasks.start(command, keyspace.clone());
+                task.set_total(view.store.len() as u64);
+                for (idx, (key, data)) in view.store.iter().enumerate() {
+                    if filter(key) {
+                        f(data);
                     }
-                    _ = time::sleep(Duration::from_millis(500)) => {
-                        let mut expring_handle = expiring.lock();
-                        let mut store_handle = store.lock();
-                        let mut expired_keys = Vec::with_capacity(5);
-
-                        for (idx, (key, expiry)) in expring_handle.iter().enumerate() {
-                            if idx >= EXPIRING_EVICTOR_SAMPLE_SIZE as usize {
-                                break;
-                            }
-
-                            let current_time = match SystemTime::now().duration_since(UNIX_EPOCH) {
-                                Ok(time) => time.as_secs(),
-                                Err(e) => {
-                                    error!("{}", e);
-                                    break;
-                                }
-                            };
-                            if *expiry <= current_time {
-                                expired_keys.push(key.clone());
-                                store_handle.remove(key);
-                            }
-                        }
-
-                        for key in expired_keys {
-                            expring_handle.remove(&key);
+                    if (idx + 1) % AGGREGATE_CHUNK_SIZE == 0 {
+                        task.advance(AGGREGATE_CHUNK_SIZE as u64);
+                        task::yield_now().await;
+                        if task.is_cancelled() {
+                            return Err(ExecuteCommandError::TaskCancelled(task.id()));
                         }
                     }
                 }
+                task.done();
+                return Ok(());
             }
-        });
+        };
+
+        // the keyspace is let go in between chunks, writes go on while the
+        // keys are read
+        let task = self.tasks.start(command, keyspace.clone());
+        let mut left = ks.len();
+        task.set_total(left as u64);
+        let mut memory = CommandMemory::new(self.max_command_memory);
+        let mut seen = HashSet::new();
+        let mut cursor = usize::MAX;
+        loop {
+            if task.is_cancelled() {
+                return Err(ExecuteCommandError::TaskCancelled(task.id()));
+            }
+            // the keyspace was dropped in the middle of the scan
+            if ks.is_dropped() {
+                return Err(ExecuteCommandError::KeyspaceDoesNotExist(
+                    str::from_utf8(&keyspace[..])?.to_string(),
+                ));
+            }
+            let values;
+            (values, cursor) = ks.values(cursor, &filter, &mut seen, &mut memory)?;
+            for data in values {
+                f(&data);
+            }
+            task.advance((left - cursor.min(left)) as u64);
+            left = cursor.min(left);
+            if cursor == 0 {
+                break;
+            }
+            task::yield_now().await;
+        }
+        task.done();
+        Ok(())
     }
 
-    fn start_max_memory_evictor(&self) {
-        if self.evictor == Evictor::Nop {
-            return;
+    fn exec_push(&self, cmd: &Push, front: bool) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.push(cmd.key(), cmd.values(), front);
         }
-        let mut done = self.done.resubscribe();
-        let mut drop_rx = self.drop.subscribe();
-        let mut evict_rx = self.evict.resubscribe();
-        let wg = self.wg.clone();
-        let store = self.store.clone();
-        let evictor = self.evictor;
-        tokio::spawn(async move {
-            debug!("max memory evictor started");
-            loop {
-                tokio::select! {
-                    _ = done.recv() => {
-                        drop(wg);
-                        debug!("shutting down max memory evictor, shutdown signal received");
-                        break;
-                    }
-                    _ = drop_rx.recv() => {
-                        drop(wg);
-                        debug!("shutting down max memory evictor, keyspace is dropped");
-                        break;
-                    }
-                    _ = evict_rx.recv() => {
-                        match evictor {
-                            Evictor::Lru => {
-                                let mut handle = store.lock();
-                                let mut lru = Instant::now();
-                                let mut to_evict: Option<Bytes> = None;
-                                for (idx, (key, value)) in handle.iter().enumerate() {
-                                    if idx >= MAX_MEMORY_EVICTOR_SAMPLE_SIZE as usize {
-                                        break;
-                                    }
 
-                                    let last_accessed = value.last_accessed();
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
 
-                                    if last_accessed < lru {
-                                        lru = last_accessed;
-                                        to_evict = Some(key.clone());
-                                    }
-                                }
+    fn exec_pop(&self, cmd: &Pop, front: bool) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.pop(cmd.key(), front);
+        }
 
-                                if let Some(key) = to_evict {
-                                    debug!("key '{:?}' evicted using lru policy", key);
-                                    handle.remove(&key);
-                                }
-                            },
-                            Evictor::Random => {
-                                let mut handle = store.lock();
-                                let mut to_evict: Option<Bytes> = None;
-                                for (idx, key) in handle.keys().enumerate() {
-                                    if idx >= MAX_MEMORY_EVICTOR_SAMPLE_SIZE as usize {
-                                        break;
-                                    }
-                                    to_evict = Some(key.clone());
-                                }
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
 
-                                if let Some(key) = to_evict {
-                                    debug!("key '{:?}' evicted using random policy", key);
-                                    handle.remove(&key);
-                                }
-                            },
-                            _ => unreachable!(),
-                        }
-                    }
-                }
-            }
-        });
+    fn exec_lrange(&self, cmd: &LRange) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.list_range(cmd.key(), cmd.start(), cmd.stop());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
 
-    pub fn evictor(&self) -> Evictor {
-        self.evictor
+    fn exec_llen(&self, cmd: &LLen) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.list_len(cmd.key());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
-}
 
-impl Value {
-    pub fn new(data: Bytes, expire_at: Option<u64>) -> Self {
-        Value {
-            data,
-            last_accessed: Instant::now(),
-            expire_at,
+    fn exec_hset(&self, cmd: &HSet) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.hash_set(cmd.key(), cmd.pairs());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_hget(&self, cmd: &HGet) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.hash_get(cmd.key(), &cmd.field());
         }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
 
-    pub fn touch(&mut self) {
-        self.last_accessed = Instant::now();
+    fn exec_hdel(&self, cmd: &HDel) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.hash_del(cmd.key(), cmd.fields());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
 
-    pub fn data(&self) -> Bytes {
-        self.data.clone()
+    fn exec_hgetall(&self, cmd: &HGetAll) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.hash_get_all(cmd.key());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
 
-    pub fn expire_at(&self) -> Option<u64> {
-        self.expire_at
+    fn exec_zadd(&self, cmd: &ZAdd) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.sorted_set_add(cmd.key(), cmd.pairs());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
 
-    pub fn last_accessed(&self) -> Instant {
-        self.last_accessed
+    fn exec_zrange(&self, cmd: &ZRange) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.sorted_set_range(cmd.key(), cmd.start(), cmd.stop(), cmd.with_scores());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
     }
-}
 
-impl Evictor {
-    pub fn as_bytes(&self) -> &[u8] {
-        match self {
-            Evictor::Lru => b"LRU",
-            Evictor::Nop => b"NOP",
-            Evictor::Random => b"RANDOM",
+    fn exec_zscore(&self, cmd: &ZScore) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.sorted_set_score(cmd.key(), &cmd.member());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_zrem(&self, cmd: &ZRem) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.sorted_set_remove(cmd.key(), cmd.members());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_setbit(&self, cmd: &SetBit) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.set_bit(cmd.key(), cmd.offset(), cmd.bit());
         }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_getbit(&self, cmd: &GetBit) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.get_bit(cmd.key(), cmd.offset());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_bitcount(&self, cmd: &BitCount) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.bit_count(cmd.key(), cmd.range());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_getrange(&self, cmd: &GetRange) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.get_range(cmd.key(), cmd.start(), cmd.end());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_setrange(&self, cmd: &SetRange) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.set_range(cmd.key(), cmd.offset(), cmd.value());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_xadd(&self, cmd: &XAdd) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.stream_add(cmd.key(), cmd.id(), cmd.fields());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_xrange(&self, cmd: &XRange) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.stream_range(cmd.key(), cmd.start(), cmd.end(), cmd.count());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_xlen(&self, cmd: &XLen) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.stream_len(cmd.key());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_ttl(&self, cmd: &Ttl) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        let keyspace = handle.get(&cmd.keyspace());
+        if let Some(ks) = keyspace {
+            return ks.ttl(cmd.key());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_expire_at(&self, cmd: &ExpireAt) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.expire_at(cmd.key(), cmd.timestamp());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_type(&self, cmd: &Type) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.type_name(cmd.key());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_memory_usage(&self, cmd: &MemoryUsage) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.memory_usage(cmd.key());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_object(&self, cmd: &Object, meta: bool) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return if meta {
+                ks.object_meta(cmd.key())
+            } else {
+                ks.object_idle_time(cmd.key())
+            };
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    // the pinned keys of every keyspace count towards the limit, the memory
+    // they take is estimated like `MEMORY USAGE` does.
+    fn exec_pin(&self, cmd: &Pin) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            let budget = match self.stats.max_memory() {
+                0 => None,
+                max_memory => {
+                    let limit = max_memory * self.max_pinned_memory_percent as u64 / 100;
+                    let pinned: usize = handle.values().map(|ks| ks.pinned_memory()).sum();
+                    Some((limit as usize).saturating_sub(pinned))
+                }
+            };
+            return ks
+                .pin(cmd.key(), budget)
+                .ok_or(ExecuteCommandError::PinnedMemoryExceeded(
+                    self.max_pinned_memory_percent,
+                ));
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_unpin(&self, cmd: &Pin) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return Ok(ks.unpin(cmd.key()));
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_undelete(&self, cmd: &Undelete) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.undelete(cmd.key());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_dump(&self, cmd: &Dump) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return ks.dump(cmd.key());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_restore(&self, cmd: &Restore) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            let (ttl, data) = snapshot::undump(cmd.payload())?;
+            let expire_at = cmd
+                .expire_at()
+                .or_else(|| ttl.map(|ttl| snapshot::ttl_deadline(self.clock.now(), ttl)));
+            let reply = ks.restore_dump(cmd.key(), expire_at, data, cmd.replace())?;
+            // the payload is decoded again for every canary, values aren't
+            // cloned
+            for canary in ks.canaries() {
+                if let Ok((_, data)) = snapshot::undump(cmd.payload()) {
+                    let _ = canary.restore_dump(cmd.key(), expire_at, data, cmd.replace());
+                }
+            }
+            return Ok(reply);
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    // the keys are copied a chunk at a time and the keyspace is let go in
+    // between, writes to keys that were already copied don't show in the view
+    // while writes to the ones that weren't do
+    async fn exec_view_create(&self, cmd: &ViewCreate) -> Result<Frame, ExecuteCommandError> {
+        check_view_name(&self.keyspaces.read(), &self.views.read(), &cmd.view())?;
+        let ks = match self.keyspace(&cmd.keyspace()) {
+            Some(ks) => ks,
+            None => {
+                return Err(ExecuteCommandError::KeyspaceDoesNotExist(
+                    str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+                ))
+            }
+        };
+
+        let created_at = self.clock.now().as_secs();
+        let mut store = HashMap::new();
+        let mut memory = CommandMemory::new(self.max_command_memory);
+        let mut cursor = usize::MAX;
+        loop {
+            cursor = ks.snapshot(cursor, &mut store, &mut memory)?;
+            if cursor == 0 {
+                break;
+            }
+            task::yield_now().await;
+        }
+        if ks.is_dropped() {
+            return Err(ExecuteCommandError::KeyspaceDoesNotExist(
+                str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+            ));
+        }
+
+        // the name may have been taken while the keys were copied
+        let keyspaces = self.keyspaces.read();
+        let mut views = self.views.write();
+        check_view_name(&keyspaces, &views, &cmd.view())?;
+        let view = View {
+            keyspace: cmd.keyspace(),
+            store,
+            created_at,
+            epoch: store::next_epoch(),
+        };
+        views.insert(cmd.view(), Arc::new(view));
+        Ok(Frame::Boolean(true))
+    }
+
+    fn exec_view_drop(&self, cmd: &ViewDrop) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.views.write();
+        if handle.remove(&cmd.view()).is_none() {
+            return Err(ExecuteCommandError::ViewDoesNotExist(
+                str::from_utf8(&cmd.view()[..])?.to_string(),
+            ));
+        }
+        Ok(Frame::Boolean(true))
+    }
+
+    fn exec_view_list(&self) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.views.read();
+        let mut views = Vec::with_capacity(handle.len());
+        for (name, view) in handle.iter() {
+            views.push(Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"name")),
+                Frame::String(name.clone()),
+                Frame::String(Bytes::from_static(b"keyspace")),
+                Frame::String(view.keyspace.clone()),
+                Frame::String(Bytes::from_static(b"keys")),
+                Frame::Integer(view.store.len() as i64),
+                Frame::String(Bytes::from_static(b"created_at")),
+                Frame::Integer(view.created_at as i64),
+            ]));
+        }
+        Ok(Frame::Array(views))
+    }
+
+    // the view is handed out as an `Arc` so that the views lock isn't held
+    // while the view is being read.
+    fn view(&self, name: &Bytes) -> Option<Arc<View>> {
+        self.views.read().get(name).cloned()
+    }
+
+    // inserts the keys in chunks and yields in between, so populating a large
+    // number of keys doesn't block the other clients of the keyspace.
+    async fn exec_debug_populate(&self, cmd: &DebugPopulate) -> Result<Frame, ExecuteCommandError> {
+        let ks = match self.keyspace(&cmd.keyspace()) {
+            Some(ks) => ks,
+            None => {
+                return Err(ExecuteCommandError::KeyspaceDoesNotExist(
+                    str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+                ))
+            }
+        };
+        let task = self.tasks.start("debug", cmd.keyspace());
+        task.set_total(cmd.count());
+        let mut added = 0;
+        let mut start = 0;
+        while start < cmd.count() {
+            if task.is_cancelled() {
+                return Err(ExecuteCommandError::TaskCancelled(task.id()));
+            }
+            if ks.is_dropped() {
+                return Err(ExecuteCommandError::KeyspaceDoesNotExist(
+                    str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+                ));
+            }
+            let end = cmd.count().min(start + POPULATE_CHUNK_SIZE);
+            added += ks.populate(start, end, cmd.size());
+            task.advance(end - start);
+            start = end;
+            task::yield_now().await;
+        }
+        task.done();
+        Ok(Frame::Integer(added as i64))
+    }
+
+    fn exec_debug_quick_check(&self, cmd: &DebugQuickCheck) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.keyspaces.read();
+        if let Some(ks) = handle.get(&cmd.keyspace()) {
+            return Ok(ks.quick_check());
+        }
+
+        Err(ExecuteCommandError::KeyspaceDoesNotExist(
+            str::from_utf8(&cmd.keyspace()[..])?.to_string(),
+        ))
+    }
+
+    fn exec_tasks_status(&self, cmd: &TaskId) -> Result<Frame, ExecuteCommandError> {
+        self.tasks
+            .status(cmd.id())
+            .ok_or(ExecuteCommandError::TaskDoesNotExist(cmd.id()))
+    }
+
+    fn exec_tasks_cancel(&self, cmd: &TaskId) -> Result<Frame, ExecuteCommandError> {
+        self.tasks
+            .cancel(cmd.id())
+            .map(Frame::Boolean)
+            .ok_or(ExecuteCommandError::TaskDoesNotExist(cmd.id()))
+    }
+
+    fn exec_schedule_add(&self, cmd: &ScheduleAdd) -> Result<Frame, ExecuteCommandError> {
+        Ok(self
+            .scheduler
+            .add(cmd.name(), cmd.schedule(), cmd.command())?)
+    }
+
+    fn exec_schedule_remove(&self, cmd: &ScheduleRemove) -> Result<Frame, ExecuteCommandError> {
+        Ok(self.scheduler.remove(cmd.name())?)
+    }
+
+    fn exec_delay(&self, cmd: &Delay) -> Result<Frame, ExecuteCommandError> {
+        let run_at = cmd.run_at().unwrap_or_else(|| {
+            (self.clock.now() + Duration::from_millis(cmd.delay())).as_millis() as u64
+        });
+        if cmd.done() {
+            return Ok(self.scheduler.done(run_at, &cmd.command())?);
+        }
+        Ok(self.scheduler.delay(run_at, cmd.command())?)
+    }
+
+    // key events are only worth building when someone is watching
+    pub fn is_tracing_keys(&self) -> bool {
+        self.key_events.receiver_count() > 0
+    }
+
+    pub fn publish_key_event(&self, event: KeyEvent) {
+        // an error only means that the last watcher went away in the meantime
+        let _ = self.key_events.send(event);
+    }
+
+    pub fn subscribe_key_events(&self) -> broadcast::Receiver<KeyEvent> {
+        self.key_events.subscribe()
+    }
+
+    // runs a command like `execute`, `frame` is what the command was parsed
+    // from and is appended to the append only file once the command ran
+    // successfully, when the command is a write
+    pub async fn execute_appended(
+        &self,
+        mut command: Command,
+        frame: Option<Frame>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let (aof, frame) = match (self.aof.get(), frame) {
+            (Some(aof), Some(frame)) if command.is_appended() => (aof, frame),
+            _ => return self.execute(command).await,
+        };
+        let order = aof.order(command.keyspaces().as_deref()).await;
+        let frame = command.resolve_expiry(frame, self.clock.now());
+        let result = self.execute(command).await;
+        let synced = match result {
+            Ok(_) => aof.queue(frame).await,
+            Err(_) => None,
+        };
+        drop(order);
+        if let Some(synced) = synced {
+            let _ = synced.await;
+        }
+        result
+    }
+
+    // runs a transaction like `execute_transaction`, `frames` are what the
+    // commands were parsed from, `None` for the ones which aren't appended
+    pub async fn execute_transaction_appended(
+        &self,
+        watched: &[WatchedVersion],
+        mut commands: Vec<Command>,
+        frames: Vec<Option<Frame>>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let aof = match self.aof.get() {
+            Some(aof) => aof,
+            None => return self.execute_transaction(watched, commands).await,
+        };
+        let order = aof.order(transaction_keyspaces(&commands).as_deref()).await;
+        let now = self.clock.now();
+        let frames: Vec<_> = commands
+            .iter_mut()
+            .zip(frames)
+            .map(|(command, frame)| Some(command.resolve_expiry(frame?, now)))
+            .collect();
+        let result = self.execute_transaction(watched, commands).await;
+        let mut synced = Vec::new();
+        // an aborted transaction didn't run any of its commands
+        if matches!(result, Ok(ref frame) if *frame != Frame::Null) {
+            for frame in frames.into_iter().flatten() {
+                synced.extend(aof.queue(frame).await);
+            }
+        }
+        drop(order);
+        for synced in synced {
+            let _ = synced.await;
+        }
+        result
+    }
+
+    // runs the commands of the append only file read by `reader`, returns the
+    // number of commands replayed
+    pub async fn replay(&self, reader: &mut AofReader) -> Result<u64, AofError> {
+        let mut replayed = 0;
+        while let Some(frame) = reader.next_command()? {
+            // only the commands which ran successfully were appended, a
+            // command failing now is logged and the replay goes on
+            match command::parse(frame) {
+                Ok(cmd) => {
+                    if let Err(e) = self.execute(cmd).await {
+                        debug!("replayed command failed, error = {}", e);
+                    }
+                }
+                Err(e) => warn!("skipping command of the append only file, error = {}", e),
+            }
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+
+    // the writes run from now on are appended to `aof`
+    pub fn start_appending(&self, aof: Aof) {
+        let _ = self.aof.set(aof);
+    }
+
+    // whether the frames of the commands have to be kept to be appended to
+    // the append only file, see `execute_appended`
+    pub fn is_appending(&self) -> bool {
+        self.aof.get().is_some()
+    }
+
+    pub fn publish_lifecycle_event(&self, event: LifecycleEvent) {
+        self.lifecycle.publish(event)
+    }
+
+    pub fn subscribe_lifecycle_events(&self) -> broadcast::Receiver<(LifecycleEvent, u128)> {
+        self.lifecycle.subscribe()
+    }
+
+    // the name of the last server lifecycle event, see `INFO`
+    pub fn state(&self) -> &'static str {
+        self.lifecycle.state()
+    }
+
+    // whether the server is waiting for its keyspaces to be warmed up, see
+    // `warmup_percent` in the config
+    pub fn is_warming(&self) -> bool {
+        self.state() == LifecycleEvent::Warming.name()
+    }
+
+    // drops the keys that are past the retention of their keyspace, `now` is a
+    // unix timestamp in milliseconds.
+    pub fn enforce_retention(&self, now: u64) {
+        let handle = self.keyspaces.read();
+        for (name, keyspace) in handle.iter() {
+            let removed = keyspace.enforce_retention(now);
+            if removed > 0 {
+                debug!(
+                    "{} keys past retention dropped from keyspace '{:?}'",
+                    removed, name
+                );
+            }
+        }
+    }
+
+    // drops the deleted values that can no longer be restored, `now` is a unix
+    // timestamp in milliseconds.
+    pub fn purge_tombstones(&self, now: u64) {
+        let handle = self.keyspaces.read();
+        for (name, keyspace) in handle.iter() {
+            let purged = keyspace.purge_tombstones(now);
+            if purged > 0 {
+                debug!("{} tombstones purged from keyspace '{:?}'", purged, name);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
+    }
+
+    // the estimated bytes taken by the keys and values of all keyspaces, see
+    // `Store::memory`
+    pub fn memory(&self) -> u64 {
+        let keyspaces: Vec<_> = self.keyspaces.read().values().cloned().collect();
+        keyspaces.iter().map(|keyspace| keyspace.memory()).sum()
+    }
+
+    // the keyspaces the server max memory eviction can evict keys of, see
+    // `Keyspace::is_evictable`
+    pub fn evictable_keyspaces(&self) -> Vec<(Bytes, KeyspaceRef)> {
+        self.keyspaces
+            .read()
+            .iter()
+            .filter(|(_, keyspace)| keyspace.is_evictable())
+            .map(|(name, keyspace)| (name.clone(), keyspace.clone()))
+            .collect()
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    // loads the plugin modules and registers the functions they export as
+    // commands, a plugin library is named after its file. returns the names
+    // of the registered commands.
+    #[cfg(feature = "wasm")]
+    pub async fn load_plugins(&self, paths: &[String]) -> Result<Vec<String>, ExecuteCommandError> {
+        let mut commands = Vec::new();
+        for path in paths {
+            let module = std::fs::read(path)
+                .map_err(|e| FunctionError::PluginRead(path.clone(), e.to_string()))?;
+            let library = match Path::new(path).file_stem() {
+                Some(stem) => Bytes::from(stem.to_string_lossy().to_string()),
+                None => Bytes::from(path.clone()),
+            };
+            self.functions
+                .load(library.clone(), Bytes::from(module))
+                .await?;
+            for function in self.functions.exports(&library).unwrap_or_default() {
+                crate::command::register_plugin_command(
+                    &function,
+                    library.clone(),
+                    Bytes::from(function.clone()),
+                );
+                commands.push(function);
+            }
+        }
+        Ok(commands)
+    }
+
+    // scripts run under the exclusive lock, which makes them atomic. only the
+    // commands which don't have to wait for anything can be called from them.
+    #[cfg(feature = "lua")]
+    fn exec_eval(&self, cmd: &Eval) -> Result<Frame, ExecuteCommandError> {
+        let key: Vec<Bytes> = [Bytes::from_static(b"eval"), cmd.script()]
+            .into_iter()
+            .chain(cmd.args().iter().cloned())
+            .collect();
+        if self.result_cache.is_enabled() {
+            if let Some(frame) = self.result_cache.get(&key, |keyspace| self.epoch(keyspace)) {
+                self.stats.record_result_cache_hit();
+                return Ok(frame);
+            }
+            self.stats.record_result_cache_miss();
+        }
+        // the keyspaces read by the script along with their epochs, `None`
+        // once the script runs a command that isn't a plain read
+        let read = RefCell::new(Some(Vec::new()));
+        let frame = script::eval(
+            &cmd.script(),
+            cmd.args(),
+            self.lua_max_instructions,
+            |tokens| {
+                let frames = tokens.into_iter().map(Frame::String).collect();
+                let command = command::parse(Frame::Array(frames)).map_err(|e| e.to_string())?;
+                let mut read = read.borrow_mut();
+                match (command.keyspaces(), read.as_mut()) {
+                    (Some(keyspaces), Some(read)) if !command.is_write() => {
+                        for keyspace in keyspaces {
+                            let epoch = self.epoch(&keyspace);
+                            read.push((keyspace, epoch));
+                        }
+                    }
+                    _ => *read = None,
+                }
+                drop(read);
+                self.dispatch_script(command).map_err(|e| e.to_string())
+            },
+        )?;
+        if let Some(read) = read.into_inner() {
+            self.result_cache.insert(key, read, frame.clone());
+        }
+        Ok(frame)
+    }
+
+    #[cfg(feature = "lua")]
+    fn dispatch_script(&self, command: Command) -> Result<Frame, ExecuteCommandError> {
+        match command {
+            Command::Get(cmd) => self.exec_get(&cmd),
+            Command::Set(cmd) => self.exec_set(&cmd),
+            Command::CSet(cmd) => self.exec_cset(&cmd),
+            Command::Del(cmd) => self.exec_del(&cmd),
+            Command::Ttl(cmd) => self.exec_ttl(&cmd),
+            Command::ExpireAt(cmd) => self.exec_expire_at(&cmd),
+            Command::Count(cmd) => self.exec_count(&cmd),
+            cmd => Err(ExecuteCommandError::NotAllowedInScript(
+                cmd.name().to_string(),
+            )),
+        }
+    }
+
+    #[cfg(feature = "wasm")]
+    async fn exec_function_load(&self, cmd: &FunctionLoad) -> Result<Frame, ExecuteCommandError> {
+        Ok(self.functions.load(cmd.library(), cmd.module()).await?)
+    }
+
+    #[cfg(feature = "wasm")]
+    fn exec_function_delete(&self, cmd: &FunctionDelete) -> Result<Frame, ExecuteCommandError> {
+        Ok(self.functions.delete(cmd.library())?)
+    }
+
+    #[cfg(feature = "wasm")]
+    async fn exec_fcall(&self, cmd: &Fcall) -> Result<Frame, ExecuteCommandError> {
+        Ok(self
+            .functions
+            .call(cmd.library(), cmd.function(), cmd.args())
+            .await?)
+    }
+}
+
+impl Deref for KeyspaceRef {
+    type Target = Keyspace;
+
+    fn deref(&self) -> &Keyspace {
+        &self.0
+    }
+}
+
+impl Keyspace {
+    pub fn new(
+        config: &KeyspaceConfig,
+        spill: Option<Spill>,
+        owner: Option<u64>,
+        done: broadcast::Receiver<()>,
+        wg: WaitGroup,
+        stats: Arc<Stats>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let (drop_tx, _) = broadcast::channel(1);
+        let (evict_tx, _) = broadcast::channel(1);
+        let mut store = Store::with_clock(config.ordered, clock.clone());
+        store.set_queues(config.evictor.queues());
+        Keyspace {
+            store: Arc::new(Mutex::new(store)),
+            expiring: Arc::new(Mutex::new(HashMap::new())),
+            pinned: Arc::new(Mutex::new(HashSet::new())),
+            eviction: Arc::new(Mutex::new(Eviction {
+                evictor: config.evictor,
+                sample_size: config.sample_size,
+                weight: config.weight,
+            })),
+            eviction_stats: Arc::new(EvictionStats::default()),
+            spill: spill.map(Arc::new),
+            stop_evictor: Mutex::new(None),
+            evictor_paused: Arc::new(AtomicBool::new(false)),
+            done,
+            wg,
+            drop: drop_tx,
+            evict: evict_tx,
+            owner,
+            retention: config.retention,
+            upstream: config.upstream.clone().map(Upstream::new),
+            canary_of: config.canary_of.clone(),
+            max_memory: config.max_memory,
+            max_keys: config.max_keys,
+            tombstones: Mutex::new(Store::new(false)),
+            tombstone: config.tombstone,
+            canaries: Mutex::new(Vec::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            stats,
+            dropped: AtomicBool::new(false),
+            second_chance: AtomicBool::new(config.evictor == Evictor::Clock),
+            clock,
+            removals: None,
+        }
+    }
+
+    // appends the keys the keyspace expires or evicts to the append only
+    // file once there is one
+    pub fn with_removals(mut self, removals: Removals) -> Self {
+        self.removals = Some(removals);
+        self
+    }
+
+    fn record_expired(&self, key: &Bytes) {
+        self.stats.record_expired(1);
+        if let Some(removals) = &self.removals {
+            removals.append(key);
+        }
+    }
+    pub fn upstream(&self) -> Option<&UpstreamConfig> {
+        self.upstream.as_ref().map(Upstream::config)
+    }
+
+    pub fn canary_of(&self) -> Option<&Bytes> {
+        self.canary_of.as_ref()
+    }
+
+    pub fn max_memory(&self) -> u64 {
+        self.max_memory
+    }
+
+    pub fn max_keys(&self) -> u64 {
+        self.max_keys
+    }
+
+    // the estimated bytes taken by the keys and values of the keyspace,
+    // tombstones included
+    pub fn memory(&self) -> u64 {
+        let memory = self.store.lock().memory();
+        (memory + self.tombstones.lock().memory()) as u64
+    }
+
+    // the canaries which still exist, see `CREATE ... CANARY`
+    pub fn canaries(&self) -> Vec<KeyspaceRef> {
+        let mut canaries = self.canaries.lock();
+        canaries.retain(|canary| !canary.is_dropped());
+        canaries.clone()
+    }
+
+    fn record_read(&self, reply: &Frame) {
+        if *reply == Frame::Null {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    // reads a missing key through from the upstream and caches it, returns
+    // whether the upstream had the key. a write that lands while the key is
+    // fetched wins over the fetched value.
+    pub async fn fetch(&self, keyspace: &Bytes, key: Bytes) -> Result<bool, ExecuteCommandError> {
+        let upstream = match &self.upstream {
+            Some(upstream) => upstream,
+            None => return Ok(false),
+        };
+        let value = match upstream.get(keyspace, &key).await? {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+        // expiry is kept in seconds, rounding up keeps short cache times from
+        // expiring the value right away
+        let expire_at = match upstream.config().cache_ttl {
+            0 => None,
+            ttl => {
+                let now = self.clock.now().as_millis() as u64;
+                Some((now + ttl).div_ceil(1000))
+            }
+        };
+        self.set_if_not_exists(key, value, expire_at)?;
+        Ok(true)
+    }
+
+    pub fn set_if_not_exists(
+        &self,
+        key: Bytes,
+        value: Bytes,
+        expire_at: Option<u64>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.store.lock();
+        if handle.contains_key(&key) || self.is_spilled(&key) {
+            return Ok(Frame::Boolean(false));
+        }
+        drop(handle);
+        self.set(key, value, expire_at)
+    }
+
+    pub fn set_if_exists(
+        &self,
+        key: Bytes,
+        value: Bytes,
+        expire_at: Option<u64>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.store.lock();
+        if !handle.contains_key(&key) && !self.is_spilled(&key) {
+            return Ok(Frame::Boolean(false));
+        }
+        drop(handle);
+        self.set(key, value, expire_at)
+    }
+
+    pub fn set(
+        &self,
+        key: Bytes,
+        value: Bytes,
+        expire_at: Option<u64>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.make_room(&mut handle, &key)?;
+        let value = Value::new(value, expire_at, self.clock.instant());
+        self.discard_spilled(&key);
+        handle.insert(key.clone(), value);
+        if let Some(expiry) = expire_at {
+            let mut expring_handle = self.expiring.lock();
+            expring_handle.insert(key, expiry);
+        }
+        Ok(Frame::Boolean(true))
+    }
+
+    // applies the writes in order while holding the keyspace lock once, so
+    // readers never see a part of the batch. returns the reply of every write,
+    // the same replies `set` and `del` return.
+    pub fn apply_batch(&self, ops: Vec<Op>) -> Vec<Frame> {
+        // the expiring evictor takes the expiring lock before the store lock
+        let mut expiring = self.expiring.lock();
+        let mut handle = self.store.lock();
+        ops.into_iter()
+            .map(|op| match op {
+                Op::Set {
+                    key,
+                    value,
+                    expire_at,
+                } => {
+                    if let Err(e) = self.make_room(&mut handle, &key) {
+                        return Frame::Error(Bytes::from(e.to_string()));
+                    }
+                    self.discard_spilled(&key);
+                    handle.insert(
+                        key.clone(),
+                        Value::new(value, expire_at, self.clock.instant()),
+                    );
+                    match expire_at {
+                        Some(expiry) => expiring.insert(key, expiry),
+                        None => expiring.remove(&key),
+                    };
+                    Frame::Boolean(true)
+                }
+                Op::Del { key } => {
+                    expiring.remove(&key);
+                    let spilled = self.discard_spilled(&key);
+                    Frame::Boolean(handle.remove(&key).is_some() || spilled)
+                }
+            })
+            .collect()
+    }
+
+    // writes the value only if the key still has the given version, a version
+    // of 0 means that the key must not exist. versions start at 1 so no value
+    // ever has version 0.
+    pub fn compare_and_set(
+        &self,
+        key: Bytes,
+        value: Bytes,
+        version: u64,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let current_time = self.clock.now().as_secs();
+        let mut handle = self.store.lock();
+        self.fault_in(&mut handle, &key);
+        let current_version = match handle.get(&key) {
+            Some(val) if val.expire_at().is_some_and(|expiry| expiry < current_time) => 0,
+            Some(val) => val.version(),
+            None => 0,
+        };
+        if current_version != version {
+            return Ok(Frame::Boolean(false));
+        }
+        self.make_room(&mut handle, &key)?;
+        handle.insert(key, Value::new(value, None, self.clock.instant()));
+        Ok(Frame::Boolean(true))
+    }
+
+    pub fn get(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        self.read(key, |val| Ok(Frame::String(val.blob()?)))
+    }
+
+    // returns the value along with its version, which can be passed to `CSET`
+    pub fn get_with_version(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        self.read(key, |val| {
+            Ok(Frame::Array(vec![
+                Frame::String(val.blob()?),
+                Frame::Integer(val.version() as i64),
+            ]))
+        })
+    }
+
+    fn read(
+        &self,
+        key: Bytes,
+        f: impl FnOnce(&Value) -> Result<Frame, ExecuteCommandError>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        self.lookup(key, true, f)
+    }
+
+    // like `read`, but the key isn't marked as accessed so looking at it
+    // doesn't change what the lru evictor picks
+    fn peek(
+        &self,
+        key: Bytes,
+        f: impl FnOnce(&Value) -> Result<Frame, ExecuteCommandError>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        self.lookup(key, false, f)
+    }
+
+    // marks the value as accessed. with the clock evictor only the referenced
+    // bit is set, which spares reads from getting the time.
+    fn touch(&self, val: &mut Value) {
+        if self.second_chance.load(Ordering::Relaxed) {
+            val.referenced = true;
+        } else {
+            val.touch(self.clock.instant());
+        }
+    }
+
+    fn lookup(
+        &self,
+        key: Bytes,
+        touch: bool,
+        f: impl FnOnce(&Value) -> Result<Frame, ExecuteCommandError>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.fault_in(&mut handle, &key);
+        if let Some(val) = handle.get_mut_same_weight(&key) {
+            if touch {
+                self.touch(val);
+            }
+            if let Some(expiry) = val.expire_at() {
+                let current_time = self.clock.now().as_secs();
+                if expiry < current_time {
+                    handle.remove(&key);
+                    self.record_expired(&key);
+                    return Ok(Frame::Null);
+                }
+            }
+            return f(val);
+        }
+        Ok(Frame::Null)
+    }
+
+    // pushes the values to the front or the back of the list, creating the
+    // list if the key doesn't exist. returns the length of the list.
+    pub fn push(
+        &self,
+        key: Bytes,
+        values: &[Bytes],
+        front: bool,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        if !handle.contains_key(&key) {
+            self.make_room(&mut handle, &key)?;
+            handle.insert(
+                key.clone(),
+                Value::with_data(Data::List(VecDeque::new()), None, self.clock.instant()),
+            );
+        }
+        let val = handle.get_mut(&key).expect("key was just inserted");
+        self.touch(val);
+        let list = val.list_mut()?;
+        for value in values {
+            if front {
+                list.push_front(value.clone());
+            } else {
+                list.push_back(value.clone());
+            }
+        }
+        Ok(Frame::Integer(list.len() as i64))
+    }
+
+    // pops a value from the front or the back of the list, the key is removed
+    // once the list is empty.
+    pub fn pop(&self, key: Bytes, front: bool) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        let val = match handle.get_mut(&key) {
+            Some(val) => val,
+            None => return Ok(Frame::Null),
+        };
+        self.touch(val);
+        let list = val.list_mut()?;
+        let value = if front {
+            list.pop_front()
+        } else {
+            list.pop_back()
+        };
+        if list.is_empty() {
+            handle.remove(&key);
+        }
+        Ok(value.map_or(Frame::Null, Frame::String))
+    }
+
+    // returns the values between the start and the stop index, both inclusive.
+    // negative indexes count from the end of the list, out of range indexes
+    // are clamped to the list.
+    pub fn list_range(
+        &self,
+        key: Bytes,
+        start: i64,
+        stop: i64,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        let list = match handle.get_mut(&key) {
+            Some(val) => {
+                self.touch(val);
+                val.list()?
+            }
+            None => return Ok(Frame::Array(Vec::new())),
+        };
+        let (start, stop) = match clamp_range(list.len(), start, stop) {
+            Some(range) => range,
+            None => return Ok(Frame::Array(Vec::new())),
+        };
+        Ok(Frame::Array(
+            list.range(start..=stop)
+                .cloned()
+                .map(Frame::String)
+                .collect(),
+        ))
+    }
+
+    pub fn list_len(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        match handle.get(&key) {
+            Some(val) => Ok(Frame::Integer(val.list()?.len() as i64)),
+            None => Ok(Frame::Integer(0)),
+        }
+    }
+
+    // sets the fields of the hash, creating the hash if the key doesn't exist.
+    // returns the number of fields that didn't exist before.
+    pub fn hash_set(
+        &self,
+        key: Bytes,
+        pairs: &[(Bytes, Bytes)],
+    ) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        if !handle.contains_key(&key) {
+            self.make_room(&mut handle, &key)?;
+            handle.insert(
+                key.clone(),
+                Value::with_data(Data::Hash(HashMap::new()), None, self.clock.instant()),
+            );
+        }
+        let val = handle.get_mut(&key).expect("key was just inserted");
+        self.touch(val);
+        let hash = val.hash_mut()?;
+        let mut added = 0;
+        for (field, value) in pairs {
+            if hash.insert(field.clone(), value.clone()).is_none() {
+                added += 1;
+            }
+        }
+        Ok(Frame::Integer(added))
+    }
+
+    pub fn hash_get(&self, key: Bytes, field: &Bytes) -> Result<Frame, ExecuteCommandError> {
+        self.read(key, |val| {
+            Ok(val
+                .hash()?
+                .get(field)
+                .map_or(Frame::Null, |value| Frame::String(value.clone())))
+        })
+    }
+
+    // removes the fields from the hash, the key is removed once the hash is
+    // empty. returns the number of fields removed.
+    pub fn hash_del(&self, key: Bytes, fields: &[Bytes]) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        let val = match handle.get_mut(&key) {
+            Some(val) => val,
+            None => return Ok(Frame::Integer(0)),
+        };
+        self.touch(val);
+        let hash = val.hash_mut()?;
+        let removed = fields
+            .iter()
+            .filter(|field| hash.remove(*field).is_some())
+            .count();
+        if hash.is_empty() {
+            handle.remove(&key);
+        }
+        Ok(Frame::Integer(removed as i64))
+    }
+
+    pub fn hash_get_all(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        match self.read(key, |val| {
+            val.hash()?;
+            Ok(val.to_frame())
+        })? {
+            Frame::Null => Ok(Frame::Map(Vec::new())),
+            frame => Ok(frame),
+        }
+    }
+
+    // adds the members to the sorted set or updates their score, creating the
+    // sorted set if the key doesn't exist. returns the number of members added.
+    pub fn sorted_set_add(
+        &self,
+        key: Bytes,
+        pairs: &[(f64, Bytes)],
+    ) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        if !handle.contains_key(&key) {
+            self.make_room(&mut handle, &key)?;
+            handle.insert(
+                key.clone(),
+                Value::with_data(
+                    Data::SortedSet(SortedSet::default()),
+                    None,
+                    self.clock.instant(),
+                ),
+            );
+        }
+        let val = handle.get_mut(&key).expect("key was just inserted");
+        self.touch(val);
+        let set = val.sorted_set_mut()?;
+        let mut added = 0;
+        for (score, member) in pairs {
+            if set.insert(member.clone(), *score) {
+                added += 1;
+            }
+        }
+        Ok(Frame::Integer(added))
+    }
+
+    // returns the members ranked between the start and the stop index, from the
+    // lowest to the highest score. indexes work the same way as in `list_range`.
+    pub fn sorted_set_range(
+        &self,
+        key: Bytes,
+        start: i64,
+        stop: i64,
+        with_scores: bool,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        let set = match handle.get_mut(&key) {
+            Some(val) => {
+                self.touch(val);
+                val.sorted_set()?
+            }
+            None => return Ok(Frame::Array(Vec::new())),
+        };
+        let (start, stop) = match clamp_range(set.len(), start, stop) {
+            Some(range) => range,
+            None => return Ok(Frame::Array(Vec::new())),
+        };
+        let mut frames = Vec::new();
+        for (member, score) in set.iter().skip(start).take(stop - start + 1) {
+            frames.push(Frame::String(member.clone()));
+            if with_scores {
+                frames.push(Frame::Double(score));
+            }
+        }
+        Ok(Frame::Array(frames))
+    }
+
+    pub fn sorted_set_score(
+        &self,
+        key: Bytes,
+        member: &Bytes,
+    ) -> Result<Frame, ExecuteCommandError> {
+        self.read(key, |val| {
+            Ok(val
+                .sorted_set()?
+                .score(member)
+                .map_or(Frame::Null, Frame::Double))
+        })
+    }
+
+    // removes the members from the sorted set, the key is removed once the
+    // sorted set is empty. returns the number of members removed.
+    pub fn sorted_set_remove(
+        &self,
+        key: Bytes,
+        members: &[Bytes],
+    ) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        let val = match handle.get_mut(&key) {
+            Some(val) => val,
+            None => return Ok(Frame::Integer(0)),
+        };
+        self.touch(val);
+        let set = val.sorted_set_mut()?;
+        let removed = members.iter().filter(|member| set.remove(member)).count();
+        if set.is_empty() {
+            handle.remove(&key);
+        }
+        Ok(Frame::Integer(removed as i64))
+    }
+
+    // sets or clears the bit at the offset, creating the value if the key
+    // doesn't exist and growing it with zero bytes when the offset is past its
+    // end. bits are numbered from the most significant bit of the first byte.
+    // returns the previous bit.
+    pub fn set_bit(
+        &self,
+        key: Bytes,
+        offset: u64,
+        bit: bool,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        if !handle.contains_key(&key) {
+            self.make_room(&mut handle, &key)?;
+            handle.insert(
+                key.clone(),
+                Value::new(Bytes::new(), None, self.clock.instant()),
+            );
+        }
+        let val = handle.get_mut(&key).expect("key was just inserted");
+        self.touch(val);
+        let blob = val.blob_mut()?;
+        // turning the blob into a vector only copies it when it is shared, with
+        // a reply that is still being written for example
+        let mut data = Vec::from(std::mem::take(blob));
+        let index = (offset / 8) as usize;
+        let mask = 0x80 >> (offset % 8);
+        if data.len() <= index {
+            data.resize(index + 1, 0);
+        }
+        let previous = data[index] & mask != 0;
+        if bit {
+            data[index] |= mask;
+        } else {
+            data[index] &= !mask;
+        }
+        *blob = Bytes::from(data);
+        Ok(Frame::Integer(previous as i64))
+    }
+
+    // bits past the end of the value are 0
+    pub fn get_bit(&self, key: Bytes, offset: u64) -> Result<Frame, ExecuteCommandError> {
+        match self.read(key, |val| {
+            let data = val.blob()?;
+            let bit = data
+                .get((offset / 8) as usize)
+                .is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0);
+            Ok(Frame::Integer(bit as i64))
+        })? {
+            Frame::Null => Ok(Frame::Integer(0)),
+            frame => Ok(frame),
+        }
+    }
+
+    // counts the set bits of the value, or of the bytes between the start and
+    // the end index. indexes work the same way as in `list_range`.
+    pub fn bit_count(
+        &self,
+        key: Bytes,
+        range: Option<(i64, i64)>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        match self.read(key, |val| {
+            let data = val.blob()?;
+            let (start, end) = match range {
+                Some((start, end)) => match clamp_range(data.len(), start, end) {
+                    Some(range) => range,
+                    None => return Ok(Frame::Integer(0)),
+                },
+                None if data.is_empty() => return Ok(Frame::Integer(0)),
+                None => (0, data.len() - 1),
+            };
+            let count: u32 = data[start..=end].iter().map(|byte| byte.count_ones()).sum();
+            Ok(Frame::Integer(count as i64))
+        })? {
+            Frame::Null => Ok(Frame::Integer(0)),
+            frame => Ok(frame),
+        }
+    }
+
+    // returns the bytes between the start and the end index, both inclusive.
+    // indexes work the same way as in `list_range`, the reply is empty when
+    // the key doesn't exist or the range is past the end of the value.
+    pub fn get_range(
+        &self,
+        key: Bytes,
+        start: i64,
+        end: i64,
+    ) -> Result<Frame, ExecuteCommandError> {
+        match self.read(key, |val| {
+            let data = val.blob()?;
+            match clamp_range(data.len(), start, end) {
+                Some((start, end)) => Ok(Frame::String(data.slice(start..=end))),
+                None => Ok(Frame::String(Bytes::new())),
+            }
+        })? {
+            Frame::Null => Ok(Frame::String(Bytes::new())),
+            frame => Ok(frame),
+        }
+    }
+
+    // overwrites the value starting at the offset, creating the value if the
+    // key doesn't exist and growing it with zero bytes when the offset is past
+    // its end. returns the length of the value.
+    pub fn set_range(
+        &self,
+        key: Bytes,
+        offset: u64,
+        value: Bytes,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        // writing nothing leaves the value as it is and doesn't create the key
+        if value.is_empty() {
+            return match handle.get(&key) {
+                Some(val) => Ok(Frame::Integer(val.blob()?.len() as i64)),
+                None => Ok(Frame::Integer(0)),
+            };
+        }
+        if !handle.contains_key(&key) {
+            self.make_room(&mut handle, &key)?;
+            handle.insert(
+                key.clone(),
+                Value::new(Bytes::new(), None, self.clock.instant()),
+            );
+        }
+        let val = handle.get_mut(&key).expect("key was just inserted");
+        self.touch(val);
+        let blob = val.blob_mut()?;
+        let mut data = Vec::from(std::mem::take(blob));
+        let start = offset as usize;
+        let end = start + value.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(&value);
+        let len = data.len();
+        *blob = Bytes::from(data);
+        Ok(Frame::Integer(len as i64))
+    }
+
+    // appends an entry to the stream, creating the stream if the key doesn't
+    // exist. returns the id of the entry.
+    pub fn stream_add(
+        &self,
+        key: Bytes,
+        id: Option<StreamId>,
+        fields: &[(Bytes, Bytes)],
+    ) -> Result<Frame, ExecuteCommandError> {
+        let now = self.clock.now().as_millis() as u64;
+        let mut handle = self.store.lock();
+        self.remove_if_expired(&mut handle, &key)?;
+        if !handle.contains_key(&key) {
+            self.make_room(&mut handle, &key)?;
+            handle.insert(
+                key.clone(),
+                Value::with_data(Data::Stream(Stream::default()), None, self.clock.instant()),
+            );
+        }
+        let val = handle.get_mut(&key).expect("key was just inserted");
+        self.touch(val);
+        let stream = val.stream_mut()?;
+        match stream.add(id, now, fields.to_vec()) {
+            Some(id) => Ok(Frame::String(Bytes::from(id.to_string()))),
+            None => Err(ExecuteCommandError::StreamIdNotIncreasing(
+                id.map_or_else(|| "*".to_string(), |id| id.to_string()),
+            )),
+        }
+    }
+
+    // returns the entries between the start and the end id, both inclusive,
+    // oldest first
+    pub fn stream_range(
+        &self,
+        key: Bytes,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        match self.read(key, |val| {
+            let entries = val
+                .stream()?
+                .range(start, end)
+                .take(count.unwrap_or(usize::MAX))
+                .map(|(id, entry)| entry_to_frame(id, entry))
+                .collect();
+            Ok(Frame::Array(entries))
+        })? {
+            Frame::Null => Ok(Frame::Array(Vec::new())),
+            frame => Ok(frame),
+        }
+    }
+
+    pub fn stream_len(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        match self.read(key, |val| Ok(Frame::Integer(val.stream()?.len() as i64)))? {
+            Frame::Null => Ok(Frame::Integer(0)),
+            frame => Ok(frame),
+        }
+    }
+
+    // spilled values are read back first, so writes build on the value the
+    // key had before it was evicted
+    fn remove_if_expired(
+        &self,
+        handle: &mut Store<Value>,
+        key: &Bytes,
+    ) -> Result<(), ExecuteCommandError> {
+        self.fault_in(handle, key);
+        let current_time = self.clock.now().as_secs();
+        let expired = handle
+            .get(key)
+            .and_then(|val| val.expire_at())
+            .is_some_and(|expiry| expiry < current_time);
+        if expired {
+            handle.remove(key);
+            self.expiring.lock().remove(key);
+            self.record_expired(key);
+        }
+        Ok(())
+    }
+
+    // returns the version of the key, or `None` if there is no such key or it
+    // has expired.
+    pub fn version(&self, key: &Bytes) -> Result<Option<u64>, ExecuteCommandError> {
+        let handle = self.store.lock();
+        let val = match handle.get(key) {
+            Some(val) => val,
+            None => return Ok(None),
+        };
+        if let Some(expiry) = val.expire_at() {
+            let current_time = self.clock.now().as_secs();
+            if expiry < current_time {
+                return Ok(None);
+            }
+        }
+        Ok(Some(val.version()))
+    }
+
+    pub fn del(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        // read back so a spilled value can be restored with `UNDELETE` too
+        self.fault_in(&mut handle, &key);
+        let result = handle.remove(&key);
+        self.pinned.lock().remove(&key);
+        let deleted = result.is_some();
+        if let (Some(value), Some(_)) = (result, self.tombstone) {
+            let deleted_at = self.clock.now().as_millis() as u64;
+            self.tombstones
+                .lock()
+                .insert(key, Tombstone { value, deleted_at });
+        }
+        Ok(Frame::Boolean(deleted))
+    }
+
+    // puts back a value deleted within the tombstone time of the keyspace,
+    // unless the key was written again since. returns whether the value was
+    // restored.
+    pub fn undelete(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        let grace = match self.tombstone {
+            Some(grace) => grace,
+            None => return Ok(Frame::Boolean(false)),
+        };
+        let now = self.clock.now().as_millis() as u64;
+        let mut handle = self.store.lock();
+        if handle.contains_key(&key) {
+            return Ok(Frame::Boolean(false));
+        }
+        self.make_room(&mut handle, &key)?;
+        let tombstone = match self.tombstones.lock().remove(&key) {
+            Some(tombstone) if now.saturating_sub(tombstone.deleted_at) <= grace => tombstone,
+            _ => return Ok(Frame::Boolean(false)),
+        };
+        if let Some(expiry) = tombstone.value.expire_at() {
+            self.expiring.lock().insert(key.clone(), expiry);
+        }
+        handle.insert(key, tombstone.value);
+        Ok(Frame::Boolean(true))
+    }
+
+    // drops the tombstones older than the tombstone time of the keyspace,
+    // `now` is a unix timestamp in milliseconds.
+    pub fn purge_tombstones(&self, now: u64) -> usize {
+        let grace = match self.tombstone {
+            Some(grace) => grace,
+            None => return 0,
+        };
+        let mut tombstones = self.tombstones.lock();
+        let purged: Vec<Bytes> = tombstones
+            .iter()
+            .filter(|(_, tombstone)| now.saturating_sub(tombstone.deleted_at) > grace)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in purged.iter() {
+            tombstones.remove(key);
+        }
+        purged.len()
+    }
+
+    pub fn tombstone(&self) -> Option<u64> {
+        self.tombstone
+    }
+
+    // serializes the value of the key along with its time to live, see
+    // `snapshot::dump`. null if the key doesn't exist. dumping a key doesn't
+    // count as an access, so dumping a whole keyspace leaves the evictor be.
+    pub fn dump(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        self.peek(key, |val| {
+            let ttl = val.expire_at().map(|expiry| {
+                let current_time = self.clock.now().as_secs();
+                expiry.saturating_sub(current_time) * 1000
+            });
+            Ok(Frame::String(snapshot::dump(ttl, val.data())))
+        })
+    }
+
+    // recreates a key from a payload of `DUMP`, `ttl` is in milliseconds. a
+    // key that exists is only overwritten with `replace`.
+    pub fn restore_dump(
+        &self,
+        key: Bytes,
+        expire_at: Option<u64>,
+        data: Data,
+        replace: bool,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let now = self.clock.now();
+        // the expiring evictor takes the expiring lock before the store lock
+        let mut expiring = self.expiring.lock();
+        let mut handle = self.store.lock();
+        self.fault_in(&mut handle, &key);
+        let expired = handle
+            .get(&key)
+            .and_then(|val| val.expire_at())
+            .is_some_and(|expiry| expiry < now.as_secs());
+        if expired {
+            handle.remove(&key);
+            expiring.remove(&key);
+            self.record_expired(&key);
+        }
+        if !replace && (handle.contains_key(&key) || self.is_spilled(&key)) {
+            return Err(ExecuteCommandError::KeyExists);
+        }
+        self.make_room(&mut handle, &key)?;
+        self.discard_spilled(&key);
+        let value = Value::with_data(data, expire_at, self.clock.instant());
+        handle.insert(key.clone(), value);
+        // a key replaced by one without a time to live must not expire at
+        // the time of the key it replaced
+        match expire_at {
+            Some(expiry) => expiring.insert(key, expiry),
+            None => expiring.remove(&key),
+        };
+        Ok(Frame::Boolean(true))
+    }
+
+    // keeps the max memory evictor away from the key, it still expires.
+    // returns whether the key exists, or `None` when the key doesn't fit in
+    // the given number of bytes left for pinned keys.
+    pub fn pin(&self, key: Bytes, budget: Option<usize>) -> Option<Frame> {
+        let handle = self.store.lock();
+        let mut pinned = self.pinned.lock();
+        let val = match handle.get(&key) {
+            Some(val) => val,
+            None => return Some(Frame::Boolean(false)),
+        };
+        if pinned.contains(&key) {
+            return Some(Frame::Boolean(true));
+        }
+        if budget.is_some_and(|budget| key.len() + val.memory_usage() > budget) {
+            return None;
+        }
+        pinned.insert(key);
+        Some(Frame::Boolean(true))
+    }
+
+    // returns whether the key was pinned
+    pub fn unpin(&self, key: Bytes) -> Frame {
+        Frame::Boolean(self.pinned.lock().remove(&key))
+    }
+
+    // pins of keys removed since they were pinned are forgotten here, most
+    // ways of removing a key don't look at the pins.
+    pub fn pinned_count(&self) -> usize {
+        let handle = self.store.lock();
+        let mut pinned = self.pinned.lock();
+        pinned.retain(|key| handle.contains_key(key));
+        pinned.len()
+    }
+
+    // an estimate of the memory taken by the pinned keys, see `pin`
+    pub fn pinned_memory(&self) -> usize {
+        let handle = self.store.lock();
+        let mut pinned = self.pinned.lock();
+        pinned.retain(|key| handle.contains_key(key));
+        pinned
+            .iter()
+            .filter_map(|key| Some(key.len() + handle.get(key)?.memory_usage()))
+            .sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // whether the keyspace was dropped from the db, it can still be reached
+    // through the handles that were taken before.
+    pub fn is_dropped(&self) -> bool {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn mark_dropped(&self) {
+        self.dropped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> Result<Frame, ExecuteCommandError> {
+        let handle = self.store.lock();
+        Ok(Frame::Integer(handle.len() as i64))
+    }
+
+    // returns a map of the keys in `start..end` along with their values in key
+    // order, or `None` if the keyspace isn't ordered. expired keys are skipped.
+    pub fn range(
+        &self,
+        start: Bytes,
+        end: Bytes,
+        memory: &mut CommandMemory,
+    ) -> Result<Option<Frame>, ExecuteCommandError> {
+        let current_time = self.clock.now().as_secs();
+        let handle = self.store.lock();
+        let entries = match handle.range(start, end) {
+            Some(entries) => entries,
+            None => return Ok(None),
+        };
+        let mut map = Vec::new();
+        for (key, val) in entries {
+            if val.expire_at().is_some_and(|expiry| expiry < current_time) {
+                continue;
+            }
+            memory.charge(key.len() + val.memory_usage())?;
+            map.push(Frame::String(key.clone()));
+            map.push(val.to_frame());
+        }
+        Ok(Some(Frame::Map(map)))
+    }
+
+    // returns up to `count` keys picked at random, each as a map of the key,
+    // the type and size of its value and, for strings, the start of the
+    // value. expired keys are skipped and the keys aren't marked as accessed.
+    pub fn sample(&self, count: usize) -> Result<Frame, ExecuteCommandError> {
+        let current_time = self.clock.now().as_secs();
+        let handle = self.store.lock();
+        let mut picked = random_indices(handle.len(), count).into_iter().peekable();
+        let mut sample = Vec::new();
+        for (i, (key, val)) in handle.iter().enumerate() {
+            match picked.peek() {
+                Some(next) if *next == i => picked.next(),
+                Some(_) => continue,
+                None => break,
+            };
+            if val.expire_at().is_some_and(|expiry| expiry < current_time) {
+                continue;
+            }
+            let value = match &val.data {
+                Data::Blob(data) => Frame::String(data.slice(..data.len().min(SAMPLE_VALUE_LIMIT))),
+                _ => Frame::Null,
+            };
+            sample.push(Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"key")),
+                Frame::String(key.clone()),
+                Frame::String(Bytes::from_static(b"type")),
+                Frame::String(Bytes::from_static(val.type_name().as_bytes())),
+                Frame::String(Bytes::from_static(b"size")),
+                Frame::Integer((key.len() + val.memory_usage()) as i64),
+                Frame::String(Bytes::from_static(b"value")),
+                value,
+            ]));
+        }
+        Ok(Frame::Array(sample))
+    }
+
+    // removes the keys in `start..end`, or returns `None` if the keyspace isn't
+    // ordered.
+    pub fn del_range(&self, start: Bytes, end: Bytes) -> Option<Frame> {
+        let mut handle = self.store.lock();
+        if let Some(spill) = &self.spill {
+            spill.discard_matching(|key| *key >= start && *key < end);
+        }
+        let removed = handle.remove_range(start, end)?;
+        Some(Frame::Integer(removed as i64))
+    }
+
+    pub fn ordered(&self) -> bool {
+        self.store.lock().is_ordered()
+    }
+
+    // returns the name of the type of the value, null if the key doesn't exist
+    pub fn type_name(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        self.read(key, |val| {
+            Ok(Frame::String(Bytes::from_static(
+                val.type_name().as_bytes(),
+            )))
+        })
+    }
+
+    // estimates the bytes taken by the key and its value, null if the key
+    // doesn't exist
+    pub fn memory_usage(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        let key_len = key.len();
+        self.read(key, |val| {
+            Ok(Frame::Integer((key_len + val.memory_usage()) as i64))
+        })
+    }
+
+    // returns the milliseconds since the key was last accessed, null if the
+    // key doesn't exist
+    pub fn object_idle_time(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        self.peek(key, |val| {
+            Ok(Frame::Integer(
+                self.clock
+                    .instant()
+                    .saturating_duration_since(val.last_accessed())
+                    .as_millis() as i64,
+            ))
+        })
+    }
+
+    // returns the metadata kept for the key, null if the key doesn't exist.
+    // neither this nor `object_idle_time` count as an access of the key.
+    pub fn object_meta(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        let key_len = key.len();
+        self.peek(key, |val| {
+            let ttl = match val.expire_at() {
+                Some(expiry) => {
+                    let current_time = self.clock.now().as_secs();
+                    Frame::Integer((expiry.saturating_sub(current_time) * 1000) as i64)
+                }
+                None => Frame::Null,
+            };
+            Ok(Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"type")),
+                Frame::String(Bytes::from_static(val.type_name().as_bytes())),
+                Frame::String(Bytes::from_static(b"version")),
+                Frame::Integer(val.version() as i64),
+                Frame::String(Bytes::from_static(b"idle_time")),
+                Frame::Integer(
+                    self.clock
+                        .instant()
+                        .saturating_duration_since(val.last_accessed())
+                        .as_millis() as i64,
+                ),
+                Frame::String(Bytes::from_static(b"ttl")),
+                ttl,
+                Frame::String(Bytes::from_static(b"size")),
+                Frame::Integer((key_len + val.memory_usage()) as i64),
+            ]))
+        })
+    }
+
+    // makes the key expire at the given unix timestamp in milliseconds, a
+    // timestamp in the past removes the key right away. expiry has a
+    // resolution of a second, the milliseconds are dropped. returns whether
+    // the key exists.
+    pub fn expire_at(&self, key: Bytes, timestamp: u64) -> Result<Frame, ExecuteCommandError> {
+        let now = self.clock.now();
+        // the expiring evictor takes the expiring lock before the store lock
+        let mut expiring = self.expiring.lock();
+        let mut handle = self.store.lock();
+        self.fault_in(&mut handle, &key);
+        let expired = match handle.get(&key) {
+            Some(val) => val.expire_at().is_some_and(|expiry| expiry < now.as_secs()),
+            None => return Ok(Frame::Boolean(false)),
+        };
+        if expired || u128::from(timestamp) <= now.as_millis() {
+            handle.remove(&key);
+            expiring.remove(&key);
+            self.record_expired(&key);
+            return Ok(Frame::Boolean(!expired));
+        }
+        let expiry = timestamp / 1000;
+        if let Some(val) = handle.get_mut(&key) {
+            val.set_expire_at(Some(expiry));
+        }
+        expiring.insert(key, expiry);
+        Ok(Frame::Boolean(true))
+    }
+
+    pub fn ttl(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        let mut handle = self.store.lock();
+        self.fault_in(&mut handle, &key);
+        if let Some(val) = handle.get_mut_same_weight(&key) {
+            self.touch(val);
+            if let Some(expiry) = val.expire_at() {
+                let current_time = self.clock.now().as_secs();
+                if expiry <= current_time {
+                    handle.remove(&key);
+                    self.record_expired(&key);
+                    return Ok(Frame::Null);
+                } else {
+                    return Ok(Frame::Integer(((expiry - current_time) * 1000) as i64));
+                }
+            }
+            return Ok(Frame::Null);
+        }
+        Ok(Frame::Null)
+    }
+    fn start_expiring_evictor(&self) {
+        let mut done = self.done.resubscribe();
+        let wg = self.wg.clone();
+        let expiring = self.expiring.clone();
+        let store = self.store.clone();
+        let stats = self.stats.clone();
+        let clock = self.clock.clone();
+        let removals = self.removals.clone();
+        let mut drop_rx = self.drop.subscribe();
+        tokio::spawn(async move {
+            debug!("expiring evictor started");
+            loop {
+                tokio::select! {
+                    _ = done.recv() => {
+                        drop(wg);
+                        debug!("shutting down expiring evictor, shutdown signal received");
+                        break;
+                    }
+                    _ = drop_rx.recv() => {
+                        drop(wg);
+                        debug!("shutting down expiring evictor, keyspace is dropped");
+                        break;
+                    }
+                    _ = time::sleep(Duration::from_millis(500)) => {
+                        let _order = match &removals {
+                            Some(removals) => removals.order().await,
+                            None => None,
+                        };
+                        let mut expring_handle = expiring.lock();
+                        let mut store_handle = store.lock();
+                        let mut expired_keys = Vec::with_capacity(5);
+
+                        for (idx, (key, expiry)) in expring_handle.iter().enumerate() {
+                            if idx >= EXPIRING_EVICTOR_SAMPLE_SIZE as usize {
+                                break;
+                            }
+
+                            let current_time = clock.now().as_secs();
+                            if *expiry <= current_time {
+                                expired_keys.push(key.clone());
+                                if store_handle.remove(key).is_some() {
+                                    stats.record_expired(1);
+                                    if let Some(removals) = &removals {
+                                        removals.append(key);
+                                    }
+                                }
+                            }
+                        }
+
+                        for key in expired_keys {
+                            expring_handle.remove(&key);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    // starts the max memory evictor, or stops it once the evictor is set to
+    // `NOP`. does nothing when it is already in the right state.
+    fn start_max_memory_evictor(&self) {
+        let mut stop_evictor = self.stop_evictor.lock();
+        let evictor = self.eviction.lock().evictor;
+        if evictor == Evictor::Nop {
+            if let Some(stop) = stop_evictor.take() {
+                let _ = stop.send(());
+            }
+            return;
+        }
+        if stop_evictor.is_some() {
+            return;
+        }
+        let (stop_tx, mut stop_rx) = oneshot::channel();
+        *stop_evictor = Some(stop_tx);
+        let mut done = self.done.resubscribe();
+        let mut drop_rx = self.drop.subscribe();
+        let mut evict_rx = self.evict.subscribe();
+        let wg = self.wg.clone();
+        let store = self.store.clone();
+        let pinned = self.pinned.clone();
+        let eviction = self.eviction.clone();
+        let eviction_stats = self.eviction_stats.clone();
+        let paused = self.evictor_paused.clone();
+        let spill = self.spill.clone();
+        let stats = self.stats.clone();
+        let removals = self.removals.clone();
+        let max_memory = self.max_memory;
+        tokio::spawn(async move {
+            debug!("max memory evictor started");
+            let mut budget = time::interval(KEYSPACE_MEMORY_CHECK_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = done.recv() => {
+                        drop(wg);
+                        debug!("shutting down max memory evictor, shutdown signal received");
+                        break;
+                    }
+                    _ = drop_rx.recv() => {
+                        drop(wg);
+                        debug!("shutting down max memory evictor, keyspace is dropped");
+                        break;
+                    }
+                    _ = &mut stop_rx => {
+                        drop(wg);
+                        debug!("shutting down max memory evictor, evictor is set to nop");
+                        break;
+                    }
+                    // the server is over max memory, evicting a batch of keys
+                    // rather than one lets eviction keep up with fast writers
+                    batch = evict_rx.recv() => {
+                        // a paused evictor drops the events, the server keeps
+                        // sending them while it is over max memory
+                        if paused.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        let batch = batch.unwrap_or(1);
+                        let _order = match &removals {
+                            Some(removals) => removals.order().await,
+                            None => None,
+                        };
+                        let Eviction { evictor, sample_size, .. } = *eviction.lock();
+                        let mut handle = store.lock();
+                        let pinned = pinned.lock();
+                        for _ in 0..batch {
+                            let (key, value) = match evict_one(&mut handle, &pinned, evictor, sample_size, &eviction_stats) {
+                                Some(evicted) => evicted,
+                                None => break,
+                            };
+                            stats.record_evicted();
+                            discard_evicted(spill.as_deref(), removals.as_ref(), &eviction_stats, key, value);
+                        }
+                    }
+                    // unlike the server max memory, the budget of the keyspace
+                    // is enforced right away, so keys are evicted until it fits
+                    _ = budget.tick(), if max_memory > 0 => {
+                        if paused.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        let _order = match &removals {
+                            Some(removals) => removals.order().await,
+                            None => None,
+                        };
+                        let Eviction { evictor, sample_size, .. } = *eviction.lock();
+                        let mut handle = store.lock();
+                        let pinned = pinned.lock();
+                        while handle.memory() as u64 > max_memory {
+                            let (key, value) = match evict_one(&mut handle, &pinned, evictor, sample_size, &eviction_stats) {
+                                Some(evicted) => evicted,
+                                None => break,
+                            };
+                            stats.record_evicted();
+                            discard_evicted(spill.as_deref(), removals.as_ref(), &eviction_stats, key, value);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn evictor(&self) -> Evictor {
+        self.eviction.lock().evictor
+    }
+
+    // pauses or resumes the max memory evictor, returns whether that changed
+    // anything. expired keys are still removed while it is paused.
+    pub fn set_evictor_paused(&self, paused: bool) -> bool {
+        self.evictor_paused.swap(paused, Ordering::Relaxed) != paused
+    }
+
+    // checks the bookkeeping of the keyspace against its keys, see
+    // `Store::check`. keys missing from the expiry index would never be
+    // expired in the background. entries of the index whose key is gone are
+    // fine, they are dropped by the expiring evictor.
+    pub fn quick_check(&self) -> Frame {
+        // the expiring evictor takes the expiring lock before the store lock
+        let expiring = self.expiring.lock();
+        let handle = self.store.lock();
+        let mut problems = handle.check();
+        problems.extend(self.tombstones.lock().check());
+        for (key, val) in handle.iter() {
+            let indexed = expiring.get(key).copied();
+            match (val.expire_at(), indexed) {
+                (Some(expiry), None) => problems.push(format!(
+                    "key '{}' expires at {} but isn't in the expiry index",
+                    String::from_utf8_lossy(key),
+                    expiry
+                )),
+                (expiry, Some(indexed)) if expiry != Some(indexed) => problems.push(format!(
+                    "key '{}' is indexed to expire at {} but expires at {:?}",
+                    String::from_utf8_lossy(key),
+                    indexed,
+                    expiry
+                )),
+                _ => {}
+            }
+        }
+        let count = problems.len();
+        problems.truncate(QUICKCHECK_MAX_DETAILS);
+        Frame::Map(vec![
+            Frame::String(Bytes::from_static(b"keys")),
+            Frame::Integer(handle.len() as i64),
+            Frame::String(Bytes::from_static(b"problems")),
+            Frame::Integer(count as i64),
+            Frame::String(Bytes::from_static(b"details")),
+            Frame::Array(
+                problems
+                    .into_iter()
+                    .map(|problem| Frame::String(Bytes::from(problem)))
+                    .collect(),
+            ),
+        ])
+    }
+
+    pub fn evict_stats(&self) -> Frame {
+        let stats = &self.eviction_stats;
+        Frame::Map(vec![
+            Frame::String(Bytes::from_static(b"evicted")),
+            Frame::Integer(stats.evicted.load(Ordering::Relaxed) as i64),
+            Frame::String(Bytes::from_static(b"sampled")),
+            Frame::Integer(stats.sampled.load(Ordering::Relaxed) as i64),
+            Frame::String(Bytes::from_static(b"reclaimed")),
+            Frame::Integer(stats.reclaimed.load(Ordering::Relaxed) as i64),
+            Frame::String(Bytes::from_static(b"spilled")),
+            Frame::Integer(stats.spilled.load(Ordering::Relaxed) as i64),
+            Frame::String(Bytes::from_static(b"paused")),
+            Frame::Boolean(self.evictor_paused.load(Ordering::Relaxed)),
+        ])
+    }
+
+    // the number of values on disk, `None` if the keyspace doesn't spill
+    pub fn spilled(&self) -> Option<usize> {
+        self.spill.as_ref().map(|spill| spill.len())
+    }
+
+    fn is_spilled(&self, key: &Bytes) -> bool {
+        self.spill.as_ref().is_some_and(|spill| spill.contains(key))
+    }
+
+    // forgets the value on disk of a key that is written or deleted, returns
+    // whether there was one
+    fn discard_spilled(&self, key: &Bytes) -> bool {
+        self.spill.as_ref().is_some_and(|spill| spill.discard(key))
+    }
+
+    // reads the value of the key back from disk into the store if it was
+    // spilled. a value that can't be read is lost, like any evicted value.
+    fn fault_in(&self, handle: &mut Store<Value>, key: &Bytes) {
+        let spill = match &self.spill {
+            Some(spill) => spill,
+            None => return,
+        };
+        // the value stays on disk when there is no room for it
+        if handle.contains_key(key) || self.make_room(handle, key).is_err() {
+            return;
+        }
+        match spill.take(key) {
+            Ok(Some(data)) => {
+                handle.insert(key.clone(), Value::new(data, None, self.clock.instant()));
+            }
+            Ok(None) => {}
+            Err(e) => debug!("failed to read spilled key '{:?}', error = {}", key, e),
+        }
+    }
+
+    // makes room for the key when it is new and the keyspace already holds
+    // `max_keys` keys, by evicting keys with the evictor of the keyspace. a
+    // keyspace without an evictor refuses the key instead, so does one whose
+    // keys are all pinned.
+    fn make_room(&self, handle: &mut Store<Value>, key: &Bytes) -> Result<(), ExecuteCommandError> {
+        if self.max_keys == 0 || handle.contains_key(key) {
+            return Ok(());
+        }
+        let Eviction {
+            evictor,
+            sample_size,
+            ..
+        } = *self.eviction.lock();
+        let pinned = self.pinned.lock();
+        while handle.len() as u64 >= self.max_keys {
+            let evicted = match evictor {
+                Evictor::Nop => None,
+                evictor => evict_one(handle, &pinned, evictor, sample_size, &self.eviction_stats),
+            };
+            let (key, value) = evicted.ok_or(ExecuteCommandError::KeyspaceFull(self.max_keys))?;
+            self.stats.record_evicted();
+            discard_evicted(
+                self.spill.as_deref(),
+                self.removals.as_ref(),
+                &self.eviction_stats,
+                key,
+                value,
+            );
+        }
+        Ok(())
+    }
+
+    // changes the settings of the max memory evictor, the keys are kept
+    pub fn alter(&self, evictor: Option<Evictor>, sample_size: Option<u8>, weight: Option<u32>) {
+        let mut eviction = self.eviction.lock();
+        if let Some(evictor) = evictor {
+            eviction.evictor = evictor;
+        }
+        if let Some(sample_size) = sample_size {
+            eviction.sample_size = sample_size;
+        }
+        if let Some(weight) = weight {
+            eviction.weight = weight;
+        }
+        let queues = eviction.evictor.queues();
+        self.second_chance
+            .store(eviction.evictor == Evictor::Clock, Ordering::Relaxed);
+        drop(eviction);
+        self.store.lock().set_queues(queues);
+        self.start_max_memory_evictor();
+    }
+
+    // inserts `key:<n>` keys with `value:<n>` values for every n in `start..end`,
+    // the values are padded with zeroes or truncated when a size is given.
+    // existing keys are left untouched, returns the number of keys added.
+    pub fn populate(&self, start: u64, end: u64, size: Option<usize>) -> u64 {
+        let mut handle = self.store.lock();
+        let mut added = 0;
+        for n in start..end {
+            let key = Bytes::from(format!("key:{}", n));
+            if handle.contains_key(&key) || self.is_spilled(&key) {
+                continue;
+            }
+            if self.make_room(&mut handle, &key).is_err() {
+                break;
+            }
+            let mut value = format!("value:{}", n).into_bytes();
+            if let Some(size) = size {
+                value.resize(size, 0);
+            }
+            handle.insert(
+                key,
+                Value::new(Bytes::from(value), None, self.clock.instant()),
+            );
+            added += 1;
+        }
+        added
+    }
+
+    // returns the values of a chunk of the keys `filter` accepts and the
+    // cursor of the next chunk, see `Store::chunk`. expired keys are skipped.
+    // a key can come up twice when keys are removed in between chunks, `seen`
+    // holds the keys read so far so every key is read once.
+    pub fn values(
+        &self,
+        cursor: usize,
+        filter: impl Fn(&[u8]) -> bool,
+        seen: &mut HashSet<Bytes>,
+        memory: &mut CommandMemory,
+    ) -> Result<(Vec<Bytes>, usize), ExecuteCommandError> {
+        let current_time = self.clock.now().as_secs();
+        let handle = self.store.lock();
+        let (chunk, cursor) = handle.chunk(cursor, AGGREGATE_CHUNK_SIZE);
+        let mut values = Vec::new();
+        for (key, val) in chunk {
+            if !filter(key) || seen.contains(key) {
+                continue;
+            }
+            memory.charge(key.len())?;
+            seen.insert(key.clone());
+            if val.expire_at().is_some_and(|expiry| expiry < current_time) {
+                continue;
+            }
+            if let Ok(data) = val.blob() {
+                values.push(data);
+            }
+        }
+        Ok((values, cursor))
+    }
+
+    // copies a chunk of the keys that haven't expired yet into `snapshot`,
+    // returns the cursor of the next chunk, see `Store::chunk`. the values are
+    // reference counted so only the keys are copied while the lock is held.
+    // the values are charged all the same, the copy keeps them around after
+    // they are evicted or overwritten.
+    pub fn snapshot(
+        &self,
+        cursor: usize,
+        snapshot: &mut HashMap<Bytes, Bytes>,
+        memory: &mut CommandMemory,
+    ) -> Result<usize, ExecuteCommandError> {
+        let current_time = self.clock.now().as_secs();
+        let handle = self.store.lock();
+        let (chunk, cursor) = handle.chunk(cursor, SNAPSHOT_CHUNK_SIZE);
+        for (key, val) in chunk {
+            if val.expire_at().is_some_and(|expiry| expiry < current_time) {
+                continue;
+            }
+            if let Ok(data) = val.blob() {
+                memory.charge(key.len() + data.len())?;
+                snapshot.insert(key.clone(), data);
+            }
+        }
+        Ok(cursor)
+    }
+
+    // the settings the keyspace would be created with again, as changed by
+    // `ALTER`
+    pub fn config(&self) -> KeyspaceConfig {
+        let eviction = *self.eviction.lock();
+        KeyspaceConfig {
+            evictor: eviction.evictor,
+            ordered: self.ordered(),
+            retention: self.retention,
+            sample_size: eviction.sample_size,
+            upstream: self.upstream().cloned(),
+            canary_of: self.canary_of.clone(),
+            max_memory: self.max_memory,
+            max_keys: self.max_keys,
+            tombstone: self.tombstone,
+            spill: self.spill.is_some(),
+            weight: eviction.weight,
+        }
+    }
+
+    // encodes the settings and the keys of the keyspace for a snapshot, the
+    // keys are read at once so the keyspace is encoded as it is at a point in
+    // time. expired keys are left out, spilled values are read back from disk.
+    pub fn encode(&self, name: &Bytes) -> KeyspaceEncoder {
+        let current_time = self.clock.now().as_secs();
+        let mut encoder = KeyspaceEncoder::new(name, &self.config());
+        let handle = self.store.lock();
+        for (key, val) in handle.iter() {
+            if val.expire_at().is_some_and(|expiry| expiry < current_time) {
+                continue;
+            }
+            encoder.entry(key, val.expire_at(), val.data());
+        }
+        if let Some(spill) = &self.spill {
+            for key in spill.keys() {
+                match spill.read(&key) {
+                    Ok(Some(data)) => encoder.entry(&key, None, &Data::Blob(data)),
+                    Ok(None) => {}
+                    Err(e) => debug!("failed to read spilled key '{:?}', error = {}", key, e),
+                }
+            }
+        }
+        encoder
+    }
+
+    // puts back a key read from a snapshot
+    // false when the key is already set, it was written after the snapshot
+    pub fn restore(&self, key: Bytes, expire_at: Option<u64>, data: Data) -> bool {
+        // the expiring evictor takes the expiring lock before the store lock
+        let mut expiring = self.expiring.lock();
+        let mut handle = self.store.lock();
+        if handle.contains_key(&key) {
+            return false;
+        }
+        if let Some(expiry) = expire_at {
+            expiring.insert(key.clone(), expiry);
+        }
+        handle.insert(key, Value::with_data(data, expire_at, self.clock.instant()));
+        true
+    }
+
+    pub fn owner(&self) -> Option<u64> {
+        self.owner
+    }
+
+    pub fn retention(&self) -> Option<u64> {
+        self.retention
+    }
+
+    pub fn sample_size(&self) -> u8 {
+        self.eviction.lock().sample_size
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.store.lock().epoch()
+    }
+
+    pub fn weight(&self) -> u32 {
+        self.eviction.lock().weight
+    }
+
+    // whether the server max memory eviction can evict keys of the keyspace,
+    // the budget of the keyspace is enforced by its evictor either way
+    pub fn is_evictable(&self) -> bool {
+        self.evictor() != Evictor::Nop
+            && !self.evictor_paused.load(Ordering::Relaxed)
+            && !self.is_dropped()
+    }
+
+    // asks the max memory evictor to evict `count` keys
+    pub fn request_eviction(&self, count: usize) {
+        let _ = self.evict.send(count);
+    }
+
+    // keys of keyspaces with a retention are expected to start with a unix
+    // timestamp in milliseconds, so every key that sorts before the cutoff
+    // timestamp is older than the retention and dropped in one range delete.
+    pub fn enforce_retention(&self, now: u64) -> usize {
+        let retention = match self.retention {
+            Some(retention) => retention,
+            None => return 0,
+        };
+        let cutoff = Bytes::from(now.saturating_sub(retention).to_string());
+        let mut handle = self.store.lock();
+        handle.remove_range(Bytes::new(), cutoff).unwrap_or(0)
+    }
+
+    // removes all the keys from the keyspace and returns the removed data, this
+    // lets the caller decide where the memory is freed.
+    pub fn flush(&self) -> (Store<Value>, HashMap<Bytes, u64>) {
+        let store = self.store.lock().take();
+        let expiring = std::mem::take(&mut *self.expiring.lock());
+        self.pinned.lock().clear();
+        self.tombstones.lock().take();
+        if let Some(spill) = &self.spill {
+            spill.clear();
+        }
+        (store, expiring)
+    }
+}
+
+impl View {
+    pub fn get(&self, key: Bytes) -> Result<Frame, ExecuteCommandError> {
+        match self.store.get(&key) {
+            Some(data) => Ok(Frame::String(data.clone())),
+            None => Ok(Frame::Null),
+        }
+    }
+
+    pub fn count(&self) -> Result<Frame, ExecuteCommandError> {
+        Ok(Frame::Integer(self.store.len() as i64))
+    }
+}
+
+impl Weigh for Value {
+    fn weight(&self) -> usize {
+        self.memory_usage()
+    }
+}
+
+impl Weigh for Tombstone {
+    fn weight(&self) -> usize {
+        self.value.memory_usage()
+    }
+}
+
+impl Data {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Data::Blob(_) => "string",
+            Data::List(_) => "list",
+            Data::Hash(_) => "hash",
+            Data::SortedSet(_) => "zset",
+            Data::Stream(_) => "stream",
+        }
+    }
+}
+
+impl Value {
+    // `now` is taken as the time the value was last accessed
+    pub fn new(data: Bytes, expire_at: Option<u64>, now: Instant) -> Self {
+        Self::with_data(Data::Blob(data), expire_at, now)
+    }
+
+    pub fn with_data(data: Data, expire_at: Option<u64>, now: Instant) -> Self {
+        Value {
+            data,
+            last_accessed: now,
+            referenced: false,
+            expire_at,
+            version: NEXT_VERSION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    pub fn touch(&mut self, now: Instant) {
+        self.last_accessed = now;
+    }
+
+    // an estimate of the memory taken by the value and its entry in the
+    // store, the key struct included. the buffer of the key is left to the
+    // caller. buffers shared with other values, like the ones of a blob still
+    // being written to a client, are counted in full.
+    pub fn memory_usage(&self) -> usize {
+        let entry = mem::size_of::<Bytes>() + mem::size_of::<Value>();
+        let data = match &self.data {
+            Data::Blob(data) => data.len(),
+            Data::List(list) => {
+                list.capacity() * mem::size_of::<Bytes>()
+                    + list.iter().map(Bytes::len).sum::<usize>()
+            }
+            Data::Hash(hash) => {
+                // a control byte per bucket on top of the field and the value
+                hash.capacity() * (2 * mem::size_of::<Bytes>() + 1)
+                    + hash
+                        .iter()
+                        .map(|(field, value)| field.len() + value.len())
+                        .sum::<usize>()
+            }
+            Data::SortedSet(set) => set.memory_usage(),
+            Data::Stream(stream) => stream.memory_usage(),
+        };
+        entry + data
+    }
+
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+
+    pub fn blob(&self) -> Result<Bytes, ExecuteCommandError> {
+        match &self.data {
+            Data::Blob(data) => Ok(data.clone()),
+            _ => Err(ExecuteCommandError::WrongType(self.type_name().to_string())),
+        }
+    }
+
+    // modifying the blob gives the value a new version
+    pub fn blob_mut(&mut self) -> Result<&mut Bytes, ExecuteCommandError> {
+        let type_name = self.type_name();
+        match &mut self.data {
+            Data::Blob(data) => {
+                self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+                Ok(data)
+            }
+            _ => Err(ExecuteCommandError::WrongType(type_name.to_string())),
+        }
+    }
+
+    pub fn list(&self) -> Result<&VecDeque<Bytes>, ExecuteCommandError> {
+        match &self.data {
+            Data::List(list) => Ok(list),
+            _ => Err(ExecuteCommandError::WrongType(self.type_name().to_string())),
+        }
+    }
+
+    // modifying the list gives the value a new version
+    pub fn list_mut(&mut self) -> Result<&mut VecDeque<Bytes>, ExecuteCommandError> {
+        let type_name = self.type_name();
+        match &mut self.data {
+            Data::List(list) => {
+                self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+                Ok(list)
+            }
+            _ => Err(ExecuteCommandError::WrongType(type_name.to_string())),
+        }
+    }
+
+    pub fn hash(&self) -> Result<&HashMap<Bytes, Bytes>, ExecuteCommandError> {
+        match &self.data {
+            Data::Hash(hash) => Ok(hash),
+            _ => Err(ExecuteCommandError::WrongType(self.type_name().to_string())),
+        }
+    }
+
+    // modifying the hash gives the value a new version
+    pub fn hash_mut(&mut self) -> Result<&mut HashMap<Bytes, Bytes>, ExecuteCommandError> {
+        let type_name = self.type_name();
+        match &mut self.data {
+            Data::Hash(hash) => {
+                self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+                Ok(hash)
+            }
+            _ => Err(ExecuteCommandError::WrongType(type_name.to_string())),
+        }
+    }
+
+    pub fn sorted_set(&self) -> Result<&SortedSet, ExecuteCommandError> {
+        match &self.data {
+            Data::SortedSet(set) => Ok(set),
+            _ => Err(ExecuteCommandError::WrongType(self.type_name().to_string())),
+        }
+    }
+
+    // modifying the sorted set gives the value a new version
+    pub fn sorted_set_mut(&mut self) -> Result<&mut SortedSet, ExecuteCommandError> {
+        let type_name = self.type_name();
+        match &mut self.data {
+            Data::SortedSet(set) => {
+                self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+                Ok(set)
+            }
+            _ => Err(ExecuteCommandError::WrongType(type_name.to_string())),
+        }
+    }
+
+    pub fn stream(&self) -> Result<&Stream, ExecuteCommandError> {
+        match &self.data {
+            Data::Stream(stream) => Ok(stream),
+            _ => Err(ExecuteCommandError::WrongType(self.type_name().to_string())),
+        }
+    }
+
+    // adding to the stream gives the value a new version
+    pub fn stream_mut(&mut self) -> Result<&mut Stream, ExecuteCommandError> {
+        let type_name = self.type_name();
+        match &mut self.data {
+            Data::Stream(stream) => {
+                self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+                Ok(stream)
+            }
+            _ => Err(ExecuteCommandError::WrongType(type_name.to_string())),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.data.type_name()
+    }
+
+    pub fn to_frame(&self) -> Frame {
+        match &self.data {
+            Data::Blob(data) => Frame::String(data.clone()),
+            Data::List(list) => Frame::Array(list.iter().cloned().map(Frame::String).collect()),
+            Data::Hash(hash) => Frame::Map(
+                hash.iter()
+                    .flat_map(|(field, value)| {
+                        [Frame::String(field.clone()), Frame::String(value.clone())]
+                    })
+                    .collect(),
+            ),
+            Data::SortedSet(set) => Frame::Map(
+                set.iter()
+                    .flat_map(|(member, score)| {
+                        [Frame::String(member.clone()), Frame::Double(score)]
+                    })
+                    .collect(),
+            ),
+            Data::Stream(stream) => Frame::Array(
+                stream
+                    .range(StreamId::MIN, StreamId::MAX)
+                    .map(|(id, entry)| entry_to_frame(id, entry))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn expire_at(&self) -> Option<u64> {
+        self.expire_at
+    }
+
+    pub fn set_expire_at(&mut self, expire_at: Option<u64>) {
+        self.expire_at = expire_at;
+    }
+
+    pub fn last_accessed(&self) -> Instant {
+        self.last_accessed
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl Default for KeyspaceConfig {
+    fn default() -> Self {
+        KeyspaceConfig {
+            evictor: Evictor::default(),
+            ordered: false,
+            retention: None,
+            sample_size: DEFAULT_SAMPLE_SIZE,
+            upstream: None,
+            canary_of: None,
+            max_memory: 0,
+            max_keys: 0,
+            tombstone: None,
+            spill: false,
+            weight: DEFAULT_EVICTION_WEIGHT,
+        }
+    }
+}
+
+impl Evictor {
+    // the evictor named by `name` in any case, `None` if there is no such
+    // evictor
+    pub fn from_bytes(name: &[u8]) -> Option<Evictor> {
+        match name.to_ascii_lowercase().as_slice() {
+            b"nop" => Some(Evictor::Nop),
+            b"random" => Some(Evictor::Random),
+            b"lru" => Some(Evictor::Lru),
+            b"arc" => Some(Evictor::Arc),
+            b"slru" => Some(Evictor::Slru),
+            b"clock" => Some(Evictor::Clock),
+            b"exactlru" => Some(Evictor::ExactLru),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Evictor::Lru => b"LRU",
+            Evictor::Nop => b"NOP",
+            Evictor::Random => b"RANDOM",
+            Evictor::Arc => b"ARC",
+            Evictor::Slru => b"SLRU",
+            Evictor::Clock => b"CLOCK",
+            Evictor::ExactLru => b"EXACTLRU",
+        }
+    }
+
+    // the queues the store keeps for the evictor, `None` for the evictors
+    // which sample keys
+    fn queues(&self) -> Option<Queues> {
+        match self {
+            Evictor::Arc => Some(Queues::Adaptive(Adaptive::default())),
+            Evictor::Slru => Some(Queues::Segmented(Segmented::default())),
+            Evictor::Clock => Some(Queues::SecondChance(SecondChance::default())),
+            Evictor::ExactLru => Some(Queues::Recency(Recency::default())),
+            Evictor::Nop | Evictor::Random | Evictor::Lru => None,
+        }
+    }
+}
+
+// evicts a key picked by the evictor out of `sample_size` unpinned keys,
+// returns the key and its value or `None` if nothing was evicted. the arc,
+// slru, clock and exact lru evictors don't sample, they look at the keys at
+// the front of their queues.
+fn evict_one(
+    store: &mut Store<Value>,
+    pinned: &HashSet<Bytes>,
+    evictor: Evictor,
+    sample_size: u8,
+    stats: &EvictionStats,
+) -> Option<(Bytes, Value)> {
+    if matches!(
+        evictor,
+        Evictor::Arc | Evictor::Slru | Evictor::Clock | Evictor::ExactLru
+    ) {
+        let (key, value) = store.evict_queued(|key, value, queued_at| {
+            stats.sampled.fetch_add(1, Ordering::Relaxed);
+            let referenced = match evictor {
+                Evictor::Clock => mem::take(&mut value.referenced),
+                _ => value.last_accessed() > queued_at,
+            };
+            if pinned.contains(key) {
+                Candidate::Kept
+            } else if referenced && evictor == Evictor::ExactLru {
+                Candidate::Accessed(value.last_accessed())
+            } else if referenced {
+                Candidate::Referenced
+            } else {
+                Candidate::Cold
+            }
+        })?;
+        return Some(evicted(key, value, evictor, stats));
+    }
+    let key = {
+        let sample = store.sample(sample_size as usize, |key| !pinned.contains(key));
+        stats
+            .sampled
+            .fetch_add(sample.len() as u64, Ordering::Relaxed);
+        match evictor {
+            Evictor::Lru => sample
+                .iter()
+                .min_by_key(|(_, value)| value.last_accessed())
+                .map(|(key, _)| (*key).clone()),
+            Evictor::Random => sample.last().map(|(key, _)| (*key).clone()),
+            // the evictor is being stopped
+            Evictor::Nop | Evictor::Arc | Evictor::Slru | Evictor::Clock | Evictor::ExactLru => {
+                None
+            }
+        }
+    }?;
+    let value = store.remove(&key)?;
+    Some(evicted(key, value, evictor, stats))
+}
+
+// counts a key evicted by `evict_one`
+fn evicted(key: Bytes, value: Value, evictor: Evictor, stats: &EvictionStats) -> (Bytes, Value) {
+    debug!("key '{:?}' evicted using {:?} policy", key, evictor);
+    stats.evicted.fetch_add(1, Ordering::Relaxed);
+    stats
+        .reclaimed
+        .fetch_add((key.len() + value.weight()) as u64, Ordering::Relaxed);
+    (key, value)
+}
+
+// the keyspaces the commands of a transaction work on, `None` when one of
+// them works on every keyspace
+fn transaction_keyspaces(commands: &[Command]) -> Option<Vec<Bytes>> {
+    let mut keyspaces = Vec::new();
+    for command in commands {
+        keyspaces.extend(command.keyspaces()?);
+    }
+    Some(keyspaces)
+}
+
+// an evicted key which isn't spilled is gone, it is removed from the append
+// only file too
+fn discard_evicted(
+    spill: Option<&Spill>,
+    removals: Option<&Removals>,
+    stats: &EvictionStats,
+    key: Bytes,
+    value: Value,
+) {
+    if !spill_evicted(spill, stats, &key, &value) {
+        if let Some(removals) = removals {
+            removals.append(&key);
+        }
+    }
+}
+
+// keeps an evicted value on disk when the keyspace spills. only strings
+// without an expiry are spilled, an expiring value could outlive its expiry
+// on disk. the value is dropped when it can't be written. returns whether
+// the value was spilled.
+fn spill_evicted(spill: Option<&Spill>, stats: &EvictionStats, key: &Bytes, value: &Value) -> bool {
+    let spill = match spill {
+        Some(spill) => spill,
+        None => return false,
+    };
+    let data = match &value.data {
+        Data::Blob(data) if value.expire_at().is_none() => data,
+        _ => return false,
+    };
+    match spill.write(key, data) {
+        Ok(()) => {
+            stats.spilled.fetch_add(1, Ordering::Relaxed);
+            true
+        }
+        Err(e) => {
+            debug!("failed to spill key '{:?}', error = {}", key, e);
+            false
+        }
+    }
+}
+
+// the bytes a command holds while it builds its result, see
+// `max_command_memory` in the config
+#[derive(Debug)]
+pub struct CommandMemory {
+    // 0 for no limit
+    limit: usize,
+    used: usize,
+}
+
+impl CommandMemory {
+    pub fn new(limit: usize) -> Self {
+        CommandMemory { limit, used: 0 }
+    }
+
+    // adds `bytes` to what the command holds, fails once it is over the limit
+    pub fn charge(&mut self, bytes: usize) -> Result<(), ExecuteCommandError> {
+        self.used += bytes;
+        if self.limit > 0 && self.used > self.limit {
+            return Err(ExecuteCommandError::CommandMemoryExceeded(self.limit));
+        }
+        Ok(())
+    }
+}
+
+// picks `count` distinct indices out of `0..len` at random, in order. all of
+// them are picked when there are no more than `count`.
+fn random_indices(len: usize, count: usize) -> BTreeSet<usize> {
+    if len <= count {
+        return (0..len).collect();
+    }
+    let mut indices = BTreeSet::new();
+    while indices.len() < count {
+        indices.insert((store::random() % len as u64) as usize);
+    }
+    indices
+}
+
+// writes the keyspaces to a snapshot at `path` one after the other, returns
+// the number of keys written. canaries come after the keyspaces they copy so
+// their sources exist by the time they are loaded.
+fn save(
+    mut keyspaces: Vec<(Bytes, KeyspaceRef)>,
+    path: &Path,
+    task: &Task,
+) -> Result<u64, ExecuteCommandError> {
+    keyspaces.sort_by(|(a, ks_a), (b, ks_b)| {
+        (ks_a.canary_of().is_some(), a).cmp(&(ks_b.canary_of().is_some(), b))
+    });
+    task.set_total(keyspaces.len() as u64);
+    let mut writer = SnapshotWriter::create(path)?;
+    let mut keys = 0;
+    for (name, keyspace) in keyspaces {
+        if task.is_cancelled() {
+            writer.abort();
+            return Err(ExecuteCommandError::TaskCancelled(task.id()));
+        }
+        let encoder = keyspace.encode(&name);
+        keys += encoder.keys();
+        if let Err(e) = writer.write_keyspace(encoder) {
+            writer.abort();
+            return Err(e.into());
+        }
+        task.advance(1);
+    }
+    writer.finish()?;
+    Ok(keys)
+}
+
+// writes the keys of the encoded keyspace to `path`, returns the number of
+// keys written
+fn export(
+    encoder: KeyspaceEncoder,
+    path: &Path,
+    format: ExportFormat,
+    task: Task,
+) -> Result<u64, ExecuteCommandError> {
+    let mut reader = encoder.into_reader();
+    reader.next_keyspace()?;
+    let mut writer = ExportWriter::create(path, format, task.id())?;
+    loop {
+        if task.is_cancelled() {
+            writer.abort();
+            return Err(ExecuteCommandError::TaskCancelled(task.id()));
+        }
+        let (key, expire_at, data) = match reader.next_entry() {
+            Ok(Some(entry)) => entry,
+            Ok(None) => break,
+            Err(e) => {
+                writer.abort();
+                return Err(e.into());
+            }
+        };
+        if let Err(e) = writer.entry(&key, expire_at, &data) {
+            writer.abort();
+            return Err(e.into());
+        }
+        task.advance(1);
+    }
+    let keys = writer.finish()?;
+    task.done();
+    Ok(keys)
+}
+
+// a view can't take the name of a keyspace or of another view
+fn check_view_name(
+    keyspaces: &HashMap<Bytes, KeyspaceRef>,
+    views: &HashMap<Bytes, Arc<View>>,
+    name: &Bytes,
+) -> Result<(), ExecuteCommandError> {
+    if keyspaces.contains_key(name) {
+        return Err(ExecuteCommandError::KeyspaceExists(
+            str::from_utf8(&name[..])?.to_string(),
+        ));
+    }
+    if views.contains_key(name) {
+        return Err(ExecuteCommandError::ViewExists(
+            str::from_utf8(&name[..])?.to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn set(ks: &Keyspace, cmd: &Set) -> Result<Frame, ExecuteCommandError> {
+    let expire_at = match cmd.expire_after() {
+        Some(millis) => Some((ks.clock.now() + Duration::from_millis(millis)).as_secs()),
+        None => cmd.expire_at(),
+    };
+    if cmd.if_exists() {
+        ks.set_if_exists(cmd.key(), cmd.value(), expire_at)
+    } else if cmd.if_not_exists() {
+        ks.set_if_not_exists(cmd.key(), cmd.value(), expire_at)
+    } else {
+        ks.set(cmd.key(), cmd.value(), expire_at)
+    }
+}
+
+fn get(ks: &Keyspace, cmd: &Get) -> Result<Frame, ExecuteCommandError> {
+    if cmd.with_version() {
+        ks.get_with_version(cmd.key())
+    } else {
+        ks.get(cmd.key())
+    }
+}
+
+// turns the inclusive start and stop indexes of a range into positions of a
+// collection of the given length. negative indexes count from the end, out of
+// range indexes are clamped. returns `None` when the range is empty.
+fn clamp_range(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+// an entry of a stream is returned as its id followed by a map of its fields
+fn entry_to_frame(id: &StreamId, entry: &[(Bytes, Bytes)]) -> Frame {
+    Frame::Array(vec![
+        Frame::String(Bytes::from(id.to_string())),
+        Frame::Map(
+            entry
+                .iter()
+                .flat_map(|(field, value)| {
+                    [Frame::String(field.clone()), Frame::String(value.clone())]
+                })
+                .collect(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aof::{AofReader, AppendFsync};
+    use crate::clock::MockClock;
+    use crate::command;
+    use crate::scheduler;
+    use chrono::Utc;
+    use std::fs;
+
+    fn command(args: &[&'static str]) -> Command {
+        let frames = args
+            .iter()
+            .map(|arg| Frame::String(Bytes::from(*arg)))
+            .collect();
+        command::parse(Frame::Array(frames)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn get_given_clock_advanced_past_expiry_returns_null() {
+        let (_done_tx, done) = broadcast::channel(1);
+        let clock = Arc::new(MockClock::new());
+        let db = Db::with_clock(
+            &ServerConfig::default(),
+            done,
+            WaitGroup::new(),
+            clock.clone(),
+        );
+        db.execute(command(&["create", "foo"])).await.unwrap();
+        db.execute(command(&[
+            "set", "foo", "bar", "baz", "expire", "after", "10000",
+        ]))
+        .await
+        .unwrap();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(
+            db.execute(command(&["get", "foo", "bar"])).await.unwrap(),
+            Frame::String(Bytes::from("baz"))
+        );
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(
+            db.execute(command(&["get", "foo", "bar"])).await.unwrap(),
+            Frame::Null
+        );
+    }
+
+    #[tokio::test]
+    async fn set_given_keyspace_at_max_keys_evicts_or_refuses() {
+        let (_done_tx, done) = broadcast::channel(1);
+        let db = Db::new(&ServerConfig::default(), done, WaitGroup::new());
+        db.execute(command(&[
+            "create", "lru", "evictor", "lru", "maxkeys", "2",
+        ]))
+        .await
+        .unwrap();
+        db.execute(command(&["create", "nop", "maxkeys", "2"]))
+            .await
+            .unwrap();
+        for key in ["a", "b", "c"] {
+            db.execute(command(&["set", "lru", key, "1"]))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            db.execute(command(&["count", "lru"])).await.unwrap(),
+            Frame::Integer(2)
+        );
+        for key in ["a", "b"] {
+            db.execute(command(&["set", "nop", key, "1"]))
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            db.execute(command(&["set", "nop", "c", "1"])).await,
+            Err(ExecuteCommandError::KeyspaceFull(2))
+        ));
+        assert_eq!(
+            db.execute(command(&["set", "nop", "a", "2"]))
+                .await
+                .unwrap(),
+            Frame::Boolean(true)
+        );
+    }
+
+    async fn execute_appended(db: &Db, args: &[&'static str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::String(Bytes::from(*arg)))
+                .collect(),
+        );
+        db.execute_appended(command(args), Some(frame))
+            .await
+            .unwrap()
+    }
+
+    // the frames appended to the log of `db` once it is shut down
+    async fn appended(path: &Path, done_tx: broadcast::Sender<()>, wg: WaitGroup) -> Vec<Frame> {
+        drop(done_tx);
+        tokio::task::spawn_blocking(move || wg.wait())
+            .await
+            .unwrap();
+        let mut reader = AofReader::open(path).unwrap().unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = reader.next_command().unwrap() {
+            frames.push(frame);
+        }
+        fs::remove_file(path).unwrap();
+        frames
+    }
+
+    #[tokio::test]
+    async fn execute_appended_given_evicted_key_appends_del_before_write() {
+        let path = std::env::temp_dir().join(format!("segment-db-aof-{}", std::process::id()));
+        let (done_tx, done) = broadcast::channel(1);
+        let wg = WaitGroup::new();
+        let db = Db::new(
+            &ServerConfig::default(),
+            done.resubscribe(),
+            WaitGroup::new(),
+        );
+        let aof = Aof::open(&path, AppendFsync::No, done, wg.clone())
+            .await
+            .unwrap();
+        db.start_appending(aof);
+        execute_appended(&db, &["create", "lru", "evictor", "lru", "maxkeys", "2"]).await;
+        for key in ["a", "b", "c"] {
+            execute_appended(&db, &["set", "lru", key, "1"]).await;
+        }
+        execute_appended(&db, &["get", "lru", "a"]).await;
+        let names: Vec<_> = appended(&path, done_tx, wg)
+            .await
+            .into_iter()
+            .map(|frame| match frame {
+                Frame::Array(tokens) => tokens[..3].to_vec(),
+                frame => vec![frame],
+            })
+            .collect();
+        let tokens =
+            |args: [&'static str; 3]| args.map(|arg| Frame::String(Bytes::from(arg))).to_vec();
+        assert_eq!(
+            names,
+            vec![
+                tokens(["create", "lru", "evictor"]),
+                tokens(["set", "lru", "a"]),
+                tokens(["set", "lru", "b"]),
+                tokens(["del", "lru", "a"]),
+                tokens(["set", "lru", "c"]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn replay_given_relative_expiries_keeps_their_deadlines() {
+        let path = std::env::temp_dir().join(format!("segment-db-replay-{}", std::process::id()));
+        let (done_tx, done) = broadcast::channel(1);
+        let wg = WaitGroup::new();
+        let clock = Arc::new(MockClock::new());
+        let db = Db::with_clock(
+            &ServerConfig::default(),
+            done.resubscribe(),
+            WaitGroup::new(),
+            clock.clone(),
+        );
+        db.start_appending(
+            Aof::open(&path, AppendFsync::No, done, wg.clone())
+                .await
+                .unwrap(),
+        );
+        execute_appended(&db, &["create", "foo"]).await;
+        execute_appended(&db, &["set", "foo", "a", "1"]).await;
+        execute_appended(&db, &["set", "foo", "b", "1", "expire", "after", "10000"]).await;
+        let payload = snapshot::dump(Some(10000), &Data::Blob(Bytes::from("1")));
+        let restore = Frame::Array(vec![
+            Frame::String(Bytes::from("restore")),
+            Frame::String(Bytes::from("foo")),
+            Frame::String(Bytes::from("c")),
+            Frame::String(payload),
+        ]);
+        db.execute_appended(command::parse(restore.clone()).unwrap(), Some(restore))
+            .await
+            .unwrap();
+        let tokens = |args: &[&'static str]| {
+            args.iter()
+                .map(|arg| Frame::String(Bytes::from(*arg)))
+                .collect()
+        };
+        let mut batch: Vec<Frame> = tokens(&["batch", "t"]);
+        batch.push(Frame::Array(tokens(&["set", "foo", "d", "1"])));
+        batch.push(Frame::Array(tokens(&[
+            "set", "foo", "e", "1", "expire", "after", "10000",
+        ])));
+        let batch = Frame::Array(batch);
+        db.execute_appended(command::parse(batch.clone()).unwrap(), Some(batch))
+            .await
+            .unwrap();
+        drop(done_tx);
+        tokio::task::spawn_blocking(move || wg.wait())
+            .await
+            .unwrap();
+
+        // the server comes back after the keys expired
+        clock.advance(Duration::from_secs(12));
+        let (_done_tx, done) = broadcast::channel(1);
+        let replayed = Db::with_clock(&ServerConfig::default(), done, WaitGroup::new(), clock);
+        let mut reader = AofReader::open(&path).unwrap().unwrap();
+        assert_eq!(replayed.replay(&mut reader).await.unwrap(), 5);
+        fs::remove_file(&path).unwrap();
+        for key in ["a", "d"] {
+            assert_eq!(
+                replayed
+                    .execute(command(&["get", "foo", key]))
+                    .await
+                    .unwrap(),
+                Frame::String(Bytes::from("1"))
+            );
+        }
+        for key in ["b", "c", "e"] {
+            assert_eq!(
+                replayed
+                    .execute(command(&["get", "foo", key]))
+                    .await
+                    .unwrap(),
+                Frame::Null
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_given_delayed_commands_keeps_the_ones_which_did_not_run() {
+        let path = std::env::temp_dir().join(format!("segment-db-delay-{}", std::process::id()));
+        let (done_tx, done) = broadcast::channel(1);
+        let wg = WaitGroup::new();
+        let db = Db::new(
+            &ServerConfig::default(),
+            done.resubscribe(),
+            WaitGroup::new(),
+        );
+        db.start_appending(
+            Aof::open(&path, AppendFsync::No, done, wg.clone())
+                .await
+                .unwrap(),
+        );
+        execute_appended(&db, &["create", "foo"]).await;
+        execute_appended(&db, &["delay", "0", "set", "foo", "a", "1"]).await;
+        execute_appended(&db, &["delay", "60000", "set", "foo", "b", "1"]).await;
+        scheduler::run_due(&db, Utc::now()).await;
+        drop(done_tx);
+        tokio::task::spawn_blocking(move || wg.wait())
+            .await
+            .unwrap();
+
+        let (_done_tx, done) = broadcast::channel(1);
+        let replayed = Db::new(&ServerConfig::default(), done, WaitGroup::new());
+        let mut reader = AofReader::open(&path).unwrap().unwrap();
+        assert_eq!(replayed.replay(&mut reader).await.unwrap(), 5);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            replayed
+                .execute(command(&["get", "foo", "b"]))
+                .await
+                .unwrap(),
+            Frame::Null
+        );
+        replayed
+            .execute(command(&["set", "foo", "a", "2"]))
+            .await
+            .unwrap();
+        scheduler::run_due(&replayed, Utc::now() + chrono::Duration::minutes(2)).await;
+        for (key, value) in [("a", "2"), ("b", "1")] {
+            assert_eq!(
+                replayed
+                    .execute(command(&["get", "foo", key]))
+                    .await
+                    .unwrap(),
+                Frame::String(Bytes::from(value))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn execute_given_exclusive_command_running_runs_admin_commands() {
+        let (_done_tx, done) = broadcast::channel(1);
+        let db = Db::new(&ServerConfig::default(), done, WaitGroup::new());
+        db.execute(command(&["create", "foo"])).await.unwrap();
+        let exclusive = db.exec_lock.write().await;
+        let wait = Duration::from_millis(50);
+        assert!(time::timeout(wait, db.execute(command(&["info"])))
+            .await
+            .is_ok_and(|reply| reply.is_ok()));
+        assert!(
+            time::timeout(wait, db.execute(command(&["get", "foo", "a"])))
+                .await
+                .is_err()
+        );
+        drop(exclusive);
+    }
+
+    #[tokio::test]
+    async fn sum_given_keys_in_several_chunks_reads_every_key_once() {
+        let (_done_tx, done) = broadcast::channel(1);
+        let db = Db::new(&ServerConfig::default(), done, WaitGroup::new());
+        db.execute(command(&["create", "foo"])).await.unwrap();
+        for i in 0..2500 {
+            let set = ["set", "foo", &format!("k{}", i), "1"]
+                .map(|token| Frame::String(Bytes::from(token.to_string())));
+            db.execute(command::parse(Frame::Array(set.to_vec())).unwrap())
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            db.execute(command(&["sum", "foo"])).await.unwrap(),
+            Frame::Integer(2500)
+        );
+    }
+
+    #[tokio::test]
+    async fn view_create_given_keys_in_several_chunks_copies_all_of_them() {
+        let (_done_tx, done) = broadcast::channel(1);
+        let db = Db::new(&ServerConfig::default(), done, WaitGroup::new());
+        db.execute(command(&["create", "foo"])).await.unwrap();
+        db.execute(command(&["debug", "populate", "foo", "2500"]))
+            .await
+            .unwrap();
+        db.execute(command(&["view", "create", "bar", "foo"]))
+            .await
+            .unwrap();
+        assert_eq!(
+            db.execute(command(&["count", "bar"])).await.unwrap(),
+            Frame::Integer(2500)
+        );
+        assert!(matches!(
+            db.execute(command(&["view", "create", "bar", "foo"])).await,
+            Err(ExecuteCommandError::ViewExists(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn load_snapshot_given_saved_keyspaces_restores_them() {
+        let dir = std::env::temp_dir().join(format!("segment-db-snapshot-{}", std::process::id()));
+        let mut cfg = ServerConfig::default();
+        cfg.set_data_dir(dir.display().to_string());
+        let (_done_tx, done) = broadcast::channel(1);
+        let db = Db::new(&cfg, done, WaitGroup::new());
+        assert!(db.open_snapshot().unwrap().is_none());
+        db.execute(command(&["create", "foo", "evictor", "lru"]))
+            .await
+            .unwrap();
+        db.execute(command(&["create", "bar", "canary", "foo"]))
+            .await
+            .unwrap();
+        db.execute(command(&["set", "foo", "a", "1"]))
+            .await
+            .unwrap();
+        db.execute(command(&["rpush", "foo", "b", "x", "y"]))
+            .await
+            .unwrap();
+        assert_eq!(
+            db.execute(command(&["save"])).await.unwrap(),
+            Frame::Integer(3)
+        );
+
+        let (_done_tx, done) = broadcast::channel(1);
+        let restored = Db::new(&cfg, done, WaitGroup::new());
+        restored
+            .execute(command(&["create", "foo", "evictor", "lru"]))
+            .await
+            .unwrap();
+        restored
+            .execute(command(&["set", "foo", "a", "2"]))
+            .await
+            .unwrap();
+        let mut read = Vec::new();
+        let reader = restored.open_snapshot().unwrap().unwrap();
+        let loaded = restored.load_snapshot(reader, |percent| read.push(percent));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.unwrap(), 2);
+        assert_eq!(read, vec![100]);
+        let foo = restored.keyspace(&Bytes::from("foo")).unwrap();
+        assert_eq!(foo.evictor(), Evictor::Lru);
+        assert_eq!(foo.canaries().len(), 1);
+        assert_eq!(
+            restored
+                .execute(command(&["get", "foo", "a"]))
+                .await
+                .unwrap(),
+            Frame::String(Bytes::from("2"))
+        );
+        assert_eq!(
+            restored
+                .execute(command(&["lrange", "foo", "b", "0", "-1"]))
+                .await
+                .unwrap(),
+            Frame::Array(vec![
+                Frame::String(Bytes::from("x")),
+                Frame::String(Bytes::from("y"))
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn export_given_keyspace_writes_its_keys() {
+        let dir = std::env::temp_dir().join(format!("segment-db-export-{}", std::process::id()));
+        let mut cfg = ServerConfig::default();
+        cfg.set_data_dir(dir.display().to_string());
+        let (_done_tx, done) = broadcast::channel(1);
+        let db = Db::new(&cfg, done, WaitGroup::new());
+        db.execute(command(&["create", "foo"])).await.unwrap();
+        db.execute(command(&["set", "foo", "a", "1"]))
+            .await
+            .unwrap();
+        let exported = db
+            .execute(command(&["export", "foo", "foo.csv", "format", "csv"]))
+            .await;
+        let file = std::fs::read_to_string(dir.join(export::DIR_NAME).join("foo.csv"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(exported.unwrap(), Frame::Integer(1));
+        assert_eq!(file.unwrap(), "key,type,expire_at,value\r\na,string,,1\r\n");
+        assert!(matches!(
+            db.execute(command(&["export", "bar", "bar.json"])).await,
+            Err(ExecuteCommandError::KeyspaceDoesNotExist(_))
+        ));
+        assert!(matches!(
+            db.execute(command(&["export", "foo", "../segment.aof"]))
+                .await,
+            Err(ExecuteCommandError::ExportError(ExportError::InvalidPath(
+                _,
+                _
+            )))
+        ));
+    }
+
+    #[tokio::test]
+    async fn restore_given_dumped_key_recreates_it_with_its_ttl() {
+        let (_done_tx, done) = broadcast::channel(1);
+        let db = Db::new(&ServerConfig::default(), done, WaitGroup::new());
+        db.execute(command(&["create", "foo"])).await.unwrap();
+        db.execute(command(&["create", "bar"])).await.unwrap();
+        db.execute(command(&["hset", "foo", "a", "f", "v"]))
+            .await
+            .unwrap();
+        db.execute(command(&[
+            "set", "foo", "b", "1", "expire", "after", "60000",
+        ]))
+        .await
+        .unwrap();
+        assert_eq!(
+            db.execute(command(&["dump", "foo", "c"])).await.unwrap(),
+            Frame::Null
+        );
+        let restore = |key: &'static str, payload: Frame, replace: bool| {
+            let mut frames = vec![
+                Frame::String(Bytes::from("restore")),
+                Frame::String(Bytes::from("bar")),
+                Frame::String(Bytes::from(key)),
+                payload,
+            ];
+            if replace {
+                frames.push(Frame::String(Bytes::from("replace")));
+            }
+            command::parse(Frame::Array(frames)).unwrap()
+        };
+
+        let a = db.execute(command(&["dump", "foo", "a"])).await.unwrap();
+        let b = db.execute(command(&["dump", "foo", "b"])).await.unwrap();
+        db.execute(restore("a", a.clone(), false)).await.unwrap();
+        db.execute(restore("b", b, false)).await.unwrap();
+        assert_eq!(
+            db.execute(command(&["hget", "bar", "a", "f"]))
+                .await
+                .unwrap(),
+            Frame::String(Bytes::from("v"))
+        );
+        assert_eq!(
+            db.execute(command(&["ttl", "bar", "a"])).await.unwrap(),
+            Frame::Null
+        );
+        assert!(matches!(
+            db.execute(command(&["ttl", "bar", "b"])).await.unwrap(),
+            Frame::Integer(ttl) if ttl > 0
+        ));
+        assert!(matches!(
+            db.execute(restore("a", a.clone(), false)).await,
+            Err(ExecuteCommandError::KeyExists)
+        ));
+        db.execute(restore("a", a.clone(), true)).await.unwrap();
+        // replacing a key which expires with one which doesn't drops the
+        // expiry along with the old value
+        db.execute(restore("b", a, true)).await.unwrap();
+        let bar = db.keyspace(&Bytes::from("bar")).unwrap();
+        assert!(!bar.expiring.lock().contains_key(&Bytes::from("b")));
+        assert!(matches!(
+            db.execute(restore("c", Frame::String(Bytes::from("foo")), false))
+                .await,
+            Err(ExecuteCommandError::SnapshotError(
+                SnapshotError::InvalidPayload(_)
+            ))
+        ));
     }
 }
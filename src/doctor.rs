@@ -0,0 +1,197 @@
+use crate::config::ServerConfig;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use sysinfo::{System, SystemExt};
+use tokio::net::TcpListener;
+
+// memory limit of the cgroup the server runs in, v2 is checked before v1
+const CGROUP_MEMORY_LIMIT_PATHS: [&str; 2] = [
+    "/sys/fs/cgroup/memory.max",
+    "/sys/fs/cgroup/memory/memory.limit_in_bytes",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug)]
+pub struct Check {
+    status: Status,
+    message: String,
+}
+
+// outcome of the startup self test, the server is ready to start if none of
+// the checks failed. warnings point at settings that work but are likely not
+// what the operator wants.
+#[derive(Debug, Default)]
+pub struct Report {
+    checks: Vec<Check>,
+}
+
+impl Check {
+    fn new(status: Status, message: String) -> Self {
+        Check { status, message }
+    }
+}
+
+impl Report {
+    pub fn is_ready(&self) -> bool {
+        self.checks.iter().all(|check| check.status != Status::Fail)
+    }
+
+    fn push(&mut self, status: Status, message: String) {
+        self.checks.push(Check::new(status, message));
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in self.checks.iter() {
+            let status = match check.status {
+                Status::Ok => "ok",
+                Status::Warn => "warn",
+                Status::Fail => "fail",
+            };
+            writeln!(f, "[{:>4}] {}", status, check.message)?;
+        }
+        if self.is_ready() {
+            write!(f, "segment is ready to start")
+        } else {
+            write!(f, "segment is not ready to start")
+        }
+    }
+}
+
+// validates the config and the environment the server is about to run in
+// without starting it.
+pub async fn run(config_path: &str) -> Report {
+    let mut report = Report::default();
+    let cfg = match ServerConfig::load_from_disk(config_path) {
+        Ok(cfg) => {
+            report.push(Status::Ok, format!("config loaded from '{}'", config_path));
+            cfg
+        }
+        Err(e) => {
+            report.push(
+                Status::Fail,
+                format!("config '{}' could not be loaded, {}", config_path, e),
+            );
+            return report;
+        }
+    };
+
+    let mut system = System::new();
+    system.refresh_memory();
+    let check = check_memory(
+        cfg.max_memory(),
+        system.total_memory(),
+        cgroup_memory_limit(),
+    );
+    report.checks.push(check);
+
+    let address = format!("{}:{}", cfg.bind(), cfg.port());
+    match TcpListener::bind(&address).await {
+        Ok(_) => report.push(Status::Ok, format!("able to listen on {}", address)),
+        Err(e) => report.push(
+            Status::Fail,
+            format!("unable to listen on {}, {}", address, e),
+        ),
+    }
+
+    let loopback = cfg
+        .bind()
+        .parse::<IpAddr>()
+        .is_ok_and(|ip| ip.is_loopback());
+    if !loopback && !cfg.net_filter().is_restricted() {
+        report.push(
+            Status::Warn,
+            format!(
+                "listening on {} without an allow list, any peer that can reach the server can connect",
+                cfg.bind()
+            ),
+        );
+    }
+
+    report
+}
+
+fn cgroup_memory_limit() -> Option<u64> {
+    CGROUP_MEMORY_LIMIT_PATHS
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| parse_cgroup_memory_limit(&contents))
+}
+
+// cgroup v2 reports `max` when there is no limit, cgroup v1 reports a huge
+// number instead which is never lower than the system memory anyway.
+fn parse_cgroup_memory_limit(contents: &str) -> Option<u64> {
+    contents.trim().parse::<u64>().ok()
+}
+
+fn check_memory(max_memory: u64, total_memory: u64, cgroup_limit: Option<u64>) -> Check {
+    let (available, source) = match cgroup_limit {
+        Some(limit) if limit < total_memory => (limit, "cgroup memory limit"),
+        _ => (total_memory, "system memory"),
+    };
+
+    if max_memory == 0 {
+        return Check::new(
+            Status::Warn,
+            format!(
+                "max_memory is not set, keys are never evicted and the server can use up all of the {} ({} bytes)",
+                source, available
+            ),
+        );
+    }
+
+    if max_memory > available {
+        return Check::new(
+            Status::Fail,
+            format!(
+                "max_memory ({} bytes) is more than the {} ({} bytes)",
+                max_memory, source, available
+            ),
+        );
+    }
+
+    Check::new(
+        Status::Ok,
+        format!(
+            "max_memory ({} bytes) fits in the {} ({} bytes)",
+            max_memory, source, available
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cgroup_memory_limit_given_max_returns_none() {
+        assert_eq!(parse_cgroup_memory_limit("max\n"), None);
+        assert_eq!(parse_cgroup_memory_limit("1073741824\n"), Some(1073741824));
+    }
+
+    #[test]
+    fn check_memory_given_max_memory_over_cgroup_limit_fails() {
+        let check = check_memory(2048, 4096, Some(1024));
+        assert_eq!(check.status, Status::Fail);
+        assert!(check.message.contains("cgroup"));
+    }
+
+    #[test]
+    fn check_memory_given_max_memory_within_system_memory_is_ok() {
+        assert_eq!(check_memory(2048, 4096, None).status, Status::Ok);
+        assert_eq!(check_memory(2048, 4096, Some(8192)).status, Status::Ok);
+    }
+
+    #[test]
+    fn check_memory_given_no_max_memory_warns() {
+        assert_eq!(check_memory(0, 4096, None).status, Status::Warn);
+    }
+}
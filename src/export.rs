@@ -0,0 +1,358 @@
+use crate::aof;
+use crate::db::Data;
+use crate::snapshot;
+use bytes::Bytes;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+use tokio::task::JoinError;
+
+// the exports are written to this dir of the data dir, see `resolve`
+pub const DIR_NAME: &str = "exports";
+
+// the formats `EXPORT` writes, see `ExportWriter`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+// writes the keys of a keyspace to a file other tools can read, written by
+// `EXPORT`. a json export is an array with an object per key, a csv export
+// has a row per key under a header. values other than strings are written as
+// json in csv too, keys and values which aren't utf-8 have the invalid bytes
+// replaced. the export is written to a temporary file of its own next to the
+// file it replaces, which is only replaced once the export is complete.
+#[derive(Debug)]
+pub struct ExportWriter {
+    file: BufWriter<File>,
+    format: ExportFormat,
+    tmp: PathBuf,
+    path: PathBuf,
+    keys: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("failed to write export '{0}': {1}")]
+    Write(String, io::Error),
+
+    #[error("invalid export path '{0}', {1}")]
+    InvalidPath(String, &'static str),
+
+    #[error("export failed: {0}")]
+    Failed(#[from] JoinError),
+}
+
+// where an export to `path` is written. clients pick the path, so it has to
+// stay within the exports dir of `data_dir`: absolute paths and `..` are
+// refused, and so are the names of the files the server keeps itself and
+// the spill dir.
+pub fn resolve(data_dir: &str, spill_dir: &str, path: &str) -> Result<PathBuf, ExportError> {
+    let invalid = |reason| Err(ExportError::InvalidPath(path.to_string(), reason));
+    let relative = Path::new(path);
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir => return invalid("it can't contain '..'"),
+            Component::RootDir | Component::Prefix(_) => return invalid("it must be relative"),
+        }
+    }
+    let name = match relative.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return invalid("it must name a file"),
+    };
+    if name == aof::FILE_NAME || name == snapshot::FILE_NAME || name.ends_with(".tmp") {
+        return invalid("the name is reserved by the server");
+    }
+    let resolved = Path::new(data_dir).join(DIR_NAME).join(relative);
+    if !spill_dir.is_empty() && resolved.starts_with(spill_dir) {
+        return invalid("it is in the spill dir");
+    }
+    Ok(resolved)
+}
+
+impl ExportFormat {
+    // the format named by `name` in any case, `None` if there is no such
+    // format
+    pub fn from_bytes(name: &[u8]) -> Option<ExportFormat> {
+        match name.to_ascii_lowercase().as_slice() {
+            b"json" => Some(ExportFormat::Json),
+            b"csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+impl ExportWriter {
+    // `id` is the id of the task running the export, it keeps exports that
+    // run at the same time from sharing a temporary file
+    pub fn create(path: &Path, format: ExportFormat, id: u64) -> Result<Self, ExportError> {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}.{}.export.tmp", std::process::id(), id));
+        let tmp = path.with_file_name(name);
+        let error = |e| ExportError::Write(tmp.display().to_string(), e);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(error)?;
+        }
+        let mut file = BufWriter::new(File::create(&tmp).map_err(error)?);
+        let header: &[u8] = match format {
+            ExportFormat::Json => b"[",
+            ExportFormat::Csv => b"key,type,expire_at,value\r\n",
+        };
+        file.write_all(header).map_err(error)?;
+        Ok(ExportWriter {
+            file,
+            format,
+            tmp,
+            path: path.to_path_buf(),
+            keys: 0,
+        })
+    }
+
+    // `expire_at` is in seconds since the unix epoch, it is exported in
+    // milliseconds like the timestamps the commands take
+    pub fn entry(
+        &mut self,
+        key: &[u8],
+        expire_at: Option<u64>,
+        data: &Data,
+    ) -> Result<(), ExportError> {
+        let mut line = String::new();
+        let expire_at = expire_at.map(|expiry| expiry * 1000);
+        match self.format {
+            ExportFormat::Json => {
+                if self.keys > 0 {
+                    line.push(',');
+                }
+                line.push_str("\n{\"key\":");
+                put_json_string(&mut line, key);
+                line.push_str(",\"type\":\"");
+                line.push_str(data.type_name());
+                line.push_str("\",\"expire_at\":");
+                match expire_at {
+                    Some(expiry) => line.push_str(&expiry.to_string()),
+                    None => line.push_str("null"),
+                }
+                line.push_str(",\"value\":");
+                put_json_data(&mut line, data);
+                line.push('}');
+            }
+            ExportFormat::Csv => {
+                put_csv_field(&mut line, &String::from_utf8_lossy(key));
+                line.push(',');
+                line.push_str(data.type_name());
+                line.push(',');
+                if let Some(expiry) = expire_at {
+                    line.push_str(&expiry.to_string());
+                }
+                line.push(',');
+                match data {
+                    Data::Blob(blob) => put_csv_field(&mut line, &String::from_utf8_lossy(blob)),
+                    data => {
+                        let mut json = String::new();
+                        put_json_data(&mut json, data);
+                        put_csv_field(&mut line, &json);
+                    }
+                }
+                line.push_str("\r\n");
+            }
+        }
+        self.keys += 1;
+        self.file
+            .write_all(line.as_bytes())
+            .map_err(|e| self.error(e))
+    }
+
+    // completes the export and puts it in place of the file at the path, which
+    // is left in place if that fails. returns the number of keys exported.
+    pub fn finish(mut self) -> Result<u64, ExportError> {
+        let footer: &[u8] = match (self.format, self.keys) {
+            (ExportFormat::Json, 0) => b"]\n",
+            (ExportFormat::Json, _) => b"\n]\n",
+            (ExportFormat::Csv, _) => b"",
+        };
+        let result = self
+            .file
+            .write_all(footer)
+            .and_then(|_| self.file.flush())
+            .and_then(|_| self.file.get_ref().sync_all())
+            .and_then(|_| fs::rename(&self.tmp, &self.path));
+        match result {
+            Ok(()) => Ok(self.keys),
+            Err(e) => {
+                let _ = fs::remove_file(&self.tmp);
+                Err(self.error(e))
+            }
+        }
+    }
+
+    // leaves the file at the path in place
+    pub fn abort(self) {
+        let _ = fs::remove_file(&self.tmp);
+    }
+
+    fn error(&self, e: io::Error) -> ExportError {
+        ExportError::Write(self.tmp.display().to_string(), e)
+    }
+}
+
+fn put_json_data(buf: &mut String, data: &Data) {
+    match data {
+        Data::Blob(blob) => put_json_string(buf, blob),
+        Data::List(list) => {
+            buf.push('[');
+            for (i, item) in list.iter().enumerate() {
+                if i > 0 {
+                    buf.push(',');
+                }
+                put_json_string(buf, item);
+            }
+            buf.push(']');
+        }
+        Data::Hash(hash) => put_json_object(buf, hash.iter()),
+        Data::SortedSet(set) => {
+            buf.push('[');
+            for (i, (member, score)) in set.iter().enumerate() {
+                if i > 0 {
+                    buf.push(',');
+                }
+                buf.push_str("{\"member\":");
+                put_json_string(buf, member);
+                buf.push_str(",\"score\":");
+                // json has no infinity, it is written as a string instead
+                if score.is_finite() {
+                    buf.push_str(&score.to_string());
+                } else {
+                    buf.push_str(&format!("\"{}\"", score));
+                }
+                buf.push('}');
+            }
+            buf.push(']');
+        }
+        Data::Stream(stream) => {
+            buf.push('[');
+            for (i, (id, entry)) in stream.iter().enumerate() {
+                if i > 0 {
+                    buf.push(',');
+                }
+                buf.push_str(&format!("{{\"id\":\"{}\",\"fields\":", id));
+                put_json_object(buf, entry.iter().map(|(field, value)| (field, value)));
+                buf.push('}');
+            }
+            buf.push(']');
+        }
+    }
+}
+
+fn put_json_object<'a>(buf: &mut String, pairs: impl Iterator<Item = (&'a Bytes, &'a Bytes)>) {
+    buf.push('{');
+    for (i, (field, value)) in pairs.enumerate() {
+        if i > 0 {
+            buf.push(',');
+        }
+        put_json_string(buf, field);
+        buf.push(':');
+        put_json_string(buf, value);
+    }
+    buf.push('}');
+}
+
+fn put_json_string(buf: &mut String, bytes: &[u8]) {
+    buf.push('"');
+    for c in String::from_utf8_lossy(bytes).chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+// fields with a comma, a quote or a line break are quoted, quotes are
+// doubled
+fn put_csv_field(buf: &mut String, field: &str) {
+    if !field.contains([',', '"', '\r', '\n']) {
+        buf.push_str(field);
+        return;
+    }
+    buf.push('"');
+    buf.push_str(&field.replace('"', "\"\""));
+    buf.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, VecDeque};
+    use std::env;
+
+    fn export(format: ExportFormat, name: &str) -> String {
+        let path = env::temp_dir().join(format!("segment-export-{}-{}", name, std::process::id()));
+        let mut writer = ExportWriter::create(&path, format, 1).unwrap();
+        writer
+            .entry(b"a", Some(42), &Data::Blob(Bytes::from("say \"hi\", bye")))
+            .unwrap();
+        let list = VecDeque::from([Bytes::from("x"), Bytes::from("y\n")]);
+        writer.entry(b"b", None, &Data::List(list)).unwrap();
+        let hash = HashMap::from([(Bytes::from("f"), Bytes::from("v"))]);
+        writer.entry(b"c", None, &Data::Hash(hash)).unwrap();
+        assert_eq!(writer.finish().unwrap(), 3);
+        let file = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        file
+    }
+
+    #[test]
+    fn resolve_given_path_outside_exports_dir_returns_invalid_path() {
+        assert_eq!(
+            resolve("data", "", "a/b.json").unwrap(),
+            Path::new("data").join(DIR_NAME).join("a/b.json")
+        );
+        for path in [
+            "/etc/passwd",
+            "../segment.aof",
+            "a/../../b.csv",
+            "segment.aof",
+            "segment.snapshot",
+            "b.tmp",
+            "",
+        ] {
+            assert!(matches!(
+                resolve("data", "", path),
+                Err(ExportError::InvalidPath(_, _))
+            ));
+        }
+        assert!(resolve("data", "data/exports/spill", "spill/a.json").is_err());
+    }
+
+    #[test]
+    fn finish_given_json_writes_object_per_key() {
+        assert_eq!(
+            export(ExportFormat::Json, "json"),
+            "[\n\
+             {\"key\":\"a\",\"type\":\"string\",\"expire_at\":42000,\"value\":\"say \\\"hi\\\", bye\"},\n\
+             {\"key\":\"b\",\"type\":\"list\",\"expire_at\":null,\"value\":[\"x\",\"y\\n\"]},\n\
+             {\"key\":\"c\",\"type\":\"hash\",\"expire_at\":null,\"value\":{\"f\":\"v\"}}\n\
+             ]\n"
+        );
+    }
+
+    #[test]
+    fn finish_given_csv_writes_row_per_key() {
+        assert_eq!(
+            export(ExportFormat::Csv, "csv"),
+            "key,type,expire_at,value\r\n\
+             a,string,42000,\"say \"\"hi\"\", bye\"\r\n\
+             b,list,,\"[\"\"x\"\",\"\"y\\n\"\"]\"\r\n\
+             c,hash,,\"{\"\"f\"\":\"\"v\"\"}\"\r\n"
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use atoi::atoi;
 use bytes::Buf;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use std::io::Cursor;
+use std::mem;
 use std::str;
 use thiserror::Error;
 
@@ -13,8 +14,12 @@ pub const NULL_IDENT: u8 = b'-';
 pub const MAP_IDENT: u8 = b'#';
 pub const DOUBLE_IDENT: u8 = b'.';
 pub const ERROR_IDENT: u8 = b'!';
+// a chunk of a chunked string, see `parse_chunked`
+pub const CHUNK_IDENT: u8 = b';';
+// takes the place of the length of a chunked string
+pub const CHUNKED_LEN: &[u8] = b"?";
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Frame {
     String(Bytes),
     Integer(i64),
@@ -26,6 +31,32 @@ pub enum Frame {
     Error(Bytes),
 }
 
+// builds the frame of a command. the name and the arguments are sent as string
+// frames which are length prefixed, so arguments can hold any bytes and not
+// just valid utf-8.
+#[derive(Debug)]
+pub struct CommandBuilder {
+    tokens: Vec<Frame>,
+}
+
+// the chunked strings of a frame that is still being received, see
+// `parse_buf`. strings are kept in the order they appear in the frame.
+#[derive(Debug, Default)]
+pub struct Partial {
+    strings: Vec<PartialString>,
+}
+
+#[derive(Debug)]
+struct PartialString {
+    // position of the first chunk in the buffer
+    chunks: usize,
+    // end of the chunks which were already read into `data`
+    end: usize,
+    data: BytesMut,
+    // the whole string once the last chunk was read
+    done: Option<Bytes>,
+}
+
 #[derive(Debug, Error, PartialEq)]
 pub enum ParseFrameError {
     #[error("more data is required to parse the frame")]
@@ -35,7 +66,64 @@ pub enum ParseFrameError {
     InvalidFormat,
 }
 
+impl CommandBuilder {
+    pub fn new(name: impl AsRef<[u8]>) -> Self {
+        CommandBuilder {
+            tokens: vec![Frame::String(Bytes::copy_from_slice(name.as_ref()))],
+        }
+    }
+
+    pub fn arg(mut self, arg: impl AsRef<[u8]>) -> Self {
+        self.tokens
+            .push(Frame::String(Bytes::copy_from_slice(arg.as_ref())));
+        self
+    }
+
+    pub fn args<I>(mut self, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        for arg in args {
+            self = self.arg(arg);
+        }
+        self
+    }
+
+    pub fn build(self) -> Frame {
+        Frame::Array(self.tokens)
+    }
+}
+
 pub fn parse(buf: &mut Cursor<&[u8]>) -> Result<Frame, ParseFrameError> {
+    parse_frame(buf, &mut Partial::default())
+}
+
+// parses the next frame out of the buffer and removes it from the buffer. when
+// the frame isn't complete yet, the chunks of its chunked strings read so far
+// are moved out of the buffer into `partial`, so a large value is never held
+// in the buffer and in the frame at once. `partial` has to be passed back in
+// along with the same buffer until the frame is complete.
+pub fn parse_buf(buf: &mut BytesMut, partial: &mut Partial) -> Result<Frame, ParseFrameError> {
+    let mut cursor = Cursor::new(&buf[..]);
+    match parse_frame(&mut cursor, partial) {
+        Ok(frame) => {
+            buf.advance(cursor.position() as usize);
+            *partial = Partial::default();
+            Ok(frame)
+        }
+        Err(ParseFrameError::Incomplete) => {
+            partial.drain(buf);
+            Err(ParseFrameError::Incomplete)
+        }
+        Err(e) => {
+            *partial = Partial::default();
+            Err(e)
+        }
+    }
+}
+
+fn parse_frame(buf: &mut Cursor<&[u8]>, partial: &mut Partial) -> Result<Frame, ParseFrameError> {
     // since our frames are CRLF delimited, we read our frames line by line.
     // A line here represents a CRLF delimited section of frame. This is binary
     // safe because when reading bytes which might contain binary data, we
@@ -51,12 +139,13 @@ pub fn parse(buf: &mut Cursor<&[u8]>) -> Result<Frame, ParseFrameError> {
     // or the whole data in case of other data types
     let line = &line[1..];
     match frame_type {
+        STRING_IDENT if line == CHUNKED_LEN => parse_chunked(buf, partial),
         STRING_IDENT => parse_string(buf, line),
         INTEGER_IDENT => parse_integer(line),
-        ARRAY_IDENT => parse_array(buf, line),
+        ARRAY_IDENT => parse_array(buf, line, partial),
         BOOLEAN_IDENT => parse_boolean(line),
         NULL_IDENT => parse_null(line),
-        MAP_IDENT => parse_map(buf, line),
+        MAP_IDENT => parse_map(buf, line, partial),
         DOUBLE_IDENT => parse_double(line),
         ERROR_IDENT => parse_error(buf, line),
         _ => Err(ParseFrameError::InvalidFormat),
@@ -107,16 +196,64 @@ fn parse_string(buf: &mut Cursor<&[u8]>, line: &[u8]) -> Result<Frame, ParseFram
     Ok(Frame::String(data))
 }
 
+// a chunked string is sent in pieces when its length isn't known upfront or
+// it is too large to be sent at once: `$?` followed by chunks of the form
+// `;<len>\r\n<data>\r\n`, a chunk of length 0 ends the string. chunks read
+// before the whole frame is received are remembered in `partial`.
+fn parse_chunked(buf: &mut Cursor<&[u8]>, partial: &mut Partial) -> Result<Frame, ParseFrameError> {
+    let chunks = buf.position() as usize;
+    let index = match partial.strings.iter().position(|s| s.chunks == chunks) {
+        Some(index) => index,
+        None => {
+            partial.strings.push(PartialString {
+                chunks,
+                end: chunks,
+                data: BytesMut::new(),
+                done: None,
+            });
+            partial.strings.len() - 1
+        }
+    };
+    let string = &mut partial.strings[index];
+    if let Some(data) = &string.done {
+        return Ok(Frame::String(data.clone()));
+    }
+
+    loop {
+        let line = get_line(buf)?;
+        if line.first() != Some(&CHUNK_IDENT) {
+            return Err(ParseFrameError::InvalidFormat);
+        }
+        let len = atoi::<usize>(&line[1..]).ok_or(ParseFrameError::InvalidFormat)?;
+        if len == 0 {
+            let data = mem::take(&mut string.data).freeze();
+            string.done = Some(data.clone());
+            string.end = buf.position() as usize;
+            return Ok(Frame::String(data));
+        }
+        if buf.remaining() < len + 2 {
+            return Err(ParseFrameError::Incomplete);
+        }
+        string.data.extend_from_slice(&buf.chunk()[..len]);
+        skip(buf, len + 2)?;
+        string.end = buf.position() as usize;
+    }
+}
+
 fn parse_integer(line: &[u8]) -> Result<Frame, ParseFrameError> {
     let int = atoi::<i64>(line).ok_or(ParseFrameError::InvalidFormat)?;
     Ok(Frame::Integer(int))
 }
 
-fn parse_array(buf: &mut Cursor<&[u8]>, line: &[u8]) -> Result<Frame, ParseFrameError> {
+fn parse_array(
+    buf: &mut Cursor<&[u8]>,
+    line: &[u8],
+    partial: &mut Partial,
+) -> Result<Frame, ParseFrameError> {
     let len = atoi::<usize>(line).ok_or(ParseFrameError::InvalidFormat)?;
     let mut vec = Vec::with_capacity(len);
     for _ in 0..len {
-        vec.push(parse(buf)?);
+        vec.push(parse_frame(buf, partial)?);
     }
 
     Ok(Frame::Array(vec))
@@ -143,12 +280,16 @@ fn parse_null(line: &[u8]) -> Result<Frame, ParseFrameError> {
     Ok(Frame::Null)
 }
 
-fn parse_map(buf: &mut Cursor<&[u8]>, line: &[u8]) -> Result<Frame, ParseFrameError> {
+fn parse_map(
+    buf: &mut Cursor<&[u8]>,
+    line: &[u8],
+    partial: &mut Partial,
+) -> Result<Frame, ParseFrameError> {
     let len = atoi::<usize>(line).ok_or(ParseFrameError::InvalidFormat)?;
     let mut map = Vec::with_capacity(2 * len);
     for _ in 0..len {
-        let key = parse(buf)?;
-        let value = parse(buf)?;
+        let key = parse_frame(buf, partial)?;
+        let value = parse_frame(buf, partial)?;
         map.push(key);
         map.push(value);
     }
@@ -179,6 +320,23 @@ fn parse_error(buf: &mut Cursor<&[u8]>, line: &[u8]) -> Result<Frame, ParseFrame
     Ok(Frame::Error(data))
 }
 
+impl Partial {
+    // removes the chunks that were read into the partial strings from the
+    // buffer, the strings move up by the chunks removed ahead of them.
+    fn drain(&mut self, buf: &mut BytesMut) {
+        let mut removed = 0;
+        for string in self.strings.iter_mut() {
+            string.chunks -= removed;
+            string.end -= removed;
+            let n = string.end - string.chunks;
+            buf.copy_within(string.end.., string.chunks);
+            buf.truncate(buf.len() - n);
+            string.end = string.chunks;
+            removed += n;
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -530,9 +688,70 @@ mod tests {
         assert_eq!(parse(&mut buf), Err(ParseFrameError::InvalidFormat))
     }
 
+    #[test]
+    fn command_builder_given_binary_arg_returns_string_frame() {
+        let command = CommandBuilder::new("set")
+            .args(["foo", "bar"])
+            .arg([0xff, 0x00, 0xfe])
+            .build();
+        assert_eq!(
+            command,
+            Frame::Array(vec![
+                Frame::String(Bytes::from("set")),
+                Frame::String(Bytes::from("foo")),
+                Frame::String(Bytes::from("bar")),
+                Frame::String(Bytes::from_static(&[0xff, 0x00, 0xfe])),
+            ])
+        )
+    }
+
     #[test]
     fn parse_given_incomplete_map_return_incomplete_error() {
         let mut buf = get_cursor_from_bytes(b"#2\r\n$3\r\nfoo\r\n");
         assert_eq!(parse(&mut buf), Err(ParseFrameError::Incomplete))
     }
+
+    #[test]
+    fn parse_given_chunked_string_returns_string() {
+        let mut buf = get_cursor_from_bytes(b"$?\r\n;5\r\nhello\r\n;6\r\n world\r\n;0\r\n");
+        assert_eq!(
+            parse(&mut buf),
+            Ok(Frame::String(Bytes::from("hello world")))
+        )
+    }
+
+    #[test]
+    fn parse_given_chunked_string_without_chunk_ident_returns_invalid_format_error() {
+        let mut buf = get_cursor_from_bytes(b"$?\r\n$5\r\nhello\r\n;0\r\n");
+        assert_eq!(parse(&mut buf), Err(ParseFrameError::InvalidFormat))
+    }
+
+    #[test]
+    fn parse_buf_given_frame_in_pieces_moves_chunks_out_of_buffer() {
+        let frame = b"*3\r\n$?\r\n;3\r\nfoo\r\n;3\r\nbar\r\n;0\r\n$?\r\n;3\r\nbaz\r\n;0\r\n%1\r\n";
+        let mut buf = BytesMut::new();
+        let mut partial = Partial::default();
+        let mut received = 0;
+        for at in [12, 20, 29, 40] {
+            buf.put_slice(&frame[received..at]);
+            received = at;
+            assert_eq!(
+                parse_buf(&mut buf, &mut partial),
+                Err(ParseFrameError::Incomplete)
+            );
+        }
+        // only the headers of the chunked strings are left of the first 40
+        // bytes along with the chunk that isn't complete yet
+        assert_eq!(&buf[..], b"*3\r\n$?\r\n$?\r\n;3\r\nba");
+        buf.put_slice(&frame[40..]);
+        assert_eq!(
+            parse_buf(&mut buf, &mut partial),
+            Ok(Frame::Array(vec![
+                Frame::String(Bytes::from("foobar")),
+                Frame::String(Bytes::from("baz")),
+                Frame::Integer(1),
+            ]))
+        );
+        assert!(buf.is_empty());
+    }
 }
@@ -0,0 +1,256 @@
+use crate::frame::Frame;
+use bytes::Bytes;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::{self, Utf8Error};
+use thiserror::Error;
+use tokio::task::{self, JoinError};
+use wasmtime::{Config, Engine, Instance, Module, Store, Trap, Val, ValType};
+
+pub struct Functions {
+    engine: Engine,
+    libraries: RwLock<HashMap<Bytes, Module>>,
+    fuel: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum FunctionError {
+    #[error("function library '{0}' does not exist")]
+    LibraryDoesNotExist(String),
+
+    #[error("function '{0}' does not exist")]
+    FunctionDoesNotExist(String),
+
+    #[error("function '{0}' must only take and return i64 values")]
+    UnsupportedSignature(String),
+
+    #[error("wrong number of arguments for function '{0}'")]
+    WrongArgCount(String),
+
+    #[error("function ran out of fuel")]
+    OutOfFuel,
+
+    #[error("unable to read plugin '{0}', {1}")]
+    PluginRead(String, String),
+
+    #[error("{0}")]
+    Wasm(String),
+
+    #[error(transparent)]
+    Utf8Error(#[from] Utf8Error),
+
+    #[error(transparent)]
+    JoinError(#[from] JoinError),
+}
+
+impl Functions {
+    pub fn new(fuel: u64) -> Self {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        // the engine configuration is static, it can only be rejected on hosts
+        // that cranelift doesn't support in which case we can't do anything useful.
+        let engine = Engine::new(&config).expect("invalid wasm engine configuration");
+        Functions {
+            engine,
+            libraries: RwLock::new(HashMap::new()),
+            fuel,
+        }
+    }
+
+    pub async fn load(&self, library: Bytes, module: Bytes) -> Result<Frame, FunctionError> {
+        // compiling a module can take a while, so we do it on the blocking pool
+        // to keep the connection tasks responsive.
+        let engine = self.engine.clone();
+        let module = task::spawn_blocking(move || Module::new(&engine, &module[..]))
+            .await?
+            .map_err(FunctionError::from_wasm)?;
+        let mut handle = self.libraries.write();
+        let replaced = handle.insert(library, module).is_some();
+        Ok(Frame::Boolean(!replaced))
+    }
+
+    pub fn delete(&self, library: Bytes) -> Result<Frame, FunctionError> {
+        let mut handle = self.libraries.write();
+        Ok(Frame::Boolean(handle.remove(&library).is_some()))
+    }
+
+    pub fn list(&self) -> Result<Frame, FunctionError> {
+        let handle = self.libraries.read();
+        let mut libraries = Vec::with_capacity(handle.len());
+        for (name, module) in handle.iter() {
+            let functions = exported_functions(module)
+                .into_iter()
+                .map(|function| Frame::String(Bytes::from(function)))
+                .collect();
+            libraries.push(Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"name")),
+                Frame::String(name.clone()),
+                Frame::String(Bytes::from_static(b"functions")),
+                Frame::Array(functions),
+            ]));
+        }
+        Ok(Frame::Array(libraries))
+    }
+
+    // returns the names of the functions exported by the library
+    pub fn exports(&self, library: &Bytes) -> Option<Vec<String>> {
+        self.libraries.read().get(library).map(exported_functions)
+    }
+
+    pub async fn call(
+        &self,
+        library: Bytes,
+        function: Bytes,
+        args: Vec<i64>,
+    ) -> Result<Frame, FunctionError> {
+        let module = match self.libraries.read().get(&library) {
+            Some(module) => module.clone(),
+            None => {
+                return Err(FunctionError::LibraryDoesNotExist(
+                    str::from_utf8(&library[..])?.to_string(),
+                ))
+            }
+        };
+        let function = str::from_utf8(&function[..])?.to_string();
+        let engine = self.engine.clone();
+        let fuel = self.fuel;
+        task::spawn_blocking(move || Self::invoke(&engine, &module, &function, &args, fuel)).await?
+    }
+
+    fn invoke(
+        engine: &Engine,
+        module: &Module,
+        function: &str,
+        args: &[i64],
+        fuel: u64,
+    ) -> Result<Frame, FunctionError> {
+        // every call gets a fresh store and instance, this way functions are
+        // sandboxed from each other and can't hold on to any state between calls.
+        let mut store = Store::new(engine, ());
+        store.set_fuel(fuel).map_err(FunctionError::from_wasm)?;
+        let instance = Instance::new(&mut store, module, &[]).map_err(FunctionError::from_wasm)?;
+        let func = instance
+            .get_func(&mut store, function)
+            .ok_or_else(|| FunctionError::FunctionDoesNotExist(function.to_string()))?;
+
+        let ty = func.ty(&store);
+        if ty.params().any(|param| !matches!(param, ValType::I64))
+            || ty.results().any(|result| !matches!(result, ValType::I64))
+            || ty.results().len() > 1
+        {
+            return Err(FunctionError::UnsupportedSignature(function.to_string()));
+        }
+
+        if ty.params().len() != args.len() {
+            return Err(FunctionError::WrongArgCount(function.to_string()));
+        }
+
+        let params: Vec<Val> = args.iter().map(|arg| Val::I64(*arg)).collect();
+        let mut results = vec![Val::I64(0); ty.results().len()];
+        func.call(&mut store, &params, &mut results)
+            .map_err(FunctionError::from_wasm)?;
+
+        match results.first() {
+            Some(Val::I64(result)) => Ok(Frame::Integer(*result)),
+            _ => Ok(Frame::Null),
+        }
+    }
+}
+
+fn exported_functions(module: &Module) -> Vec<String> {
+    module
+        .exports()
+        .filter(|export| export.ty().func().is_some())
+        .map(|export| export.name().to_string())
+        .collect()
+}
+
+impl FunctionError {
+    fn from_wasm(err: wasmtime::Error) -> Self {
+        if let Some(Trap::OutOfFuel) = err.downcast_ref::<Trap>() {
+            return FunctionError::OutOfFuel;
+        }
+        FunctionError::Wasm(err.to_string())
+    }
+}
+
+impl fmt::Debug for Functions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Functions")
+            .field("libraries", &self.libraries.read().keys())
+            .field("fuel", &self.fuel)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: &str = r#"
+        (module
+            (func (export "add") (param i64 i64) (result i64)
+                local.get 0
+                local.get 1
+                i64.add)
+            (func (export "spin")
+                (loop br 0)))
+    "#;
+
+    async fn load_module(functions: &Functions) {
+        functions
+            .load(Bytes::from("foo"), Bytes::from(MODULE))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn call_given_function_returns_result() {
+        let functions = Functions::new(1_000);
+        load_module(&functions).await;
+        let result = functions
+            .call(Bytes::from("foo"), Bytes::from("add"), vec![40, 2])
+            .await
+            .unwrap();
+        assert_eq!(result, Frame::Integer(42));
+    }
+
+    #[tokio::test]
+    async fn call_given_infinite_loop_returns_out_of_fuel_error() {
+        let functions = Functions::new(1_000);
+        load_module(&functions).await;
+        match functions
+            .call(Bytes::from("foo"), Bytes::from("spin"), vec![])
+            .await
+        {
+            Err(FunctionError::OutOfFuel) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn call_given_wrong_arg_count_returns_wrong_arg_count_error() {
+        let functions = Functions::new(1_000);
+        load_module(&functions).await;
+        match functions
+            .call(Bytes::from("foo"), Bytes::from("add"), vec![1])
+            .await
+        {
+            Err(FunctionError::WrongArgCount(_)) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn call_given_unknown_library_returns_library_does_not_exist_error() {
+        let functions = Functions::new(1_000);
+        match functions
+            .call(Bytes::from("foo"), Bytes::from("add"), vec![])
+            .await
+        {
+            Err(FunctionError::LibraryDoesNotExist(_)) => {}
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::command::{self, ParseCommandError};
+use crate::db::{Db, ExecuteCommandError};
+use crate::frame::Frame;
+use crate::netfilter::NetFilter;
+use bytes::Bytes;
+use crossbeam::sync::WaitGroup;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::TcpListenerStream, StreamExt};
+use tonic::{transport::Server, Request, Response, Status};
+use tracing::{debug, error};
+
+mod proto {
+    tonic::include_proto!("segment.v1");
+}
+
+use proto::segment_server::{Segment, SegmentServer};
+use proto::{DelRequest, DelResponse, GetRequest, GetResponse, SetRequest, SetResponse};
+
+// a gateway which runs the calls of the service in `proto/segment.proto` as
+// commands, so segment can be used from languages without a client of the
+// frame protocol, see `grpc_port` in the config
+pub async fn serve(
+    ln: TcpListener,
+    db: Arc<Db>,
+    net_filter: NetFilter,
+    mut done: broadcast::Receiver<()>,
+    wg: WaitGroup,
+) {
+    let incoming = TcpListenerStream::new(ln).filter(move |stream| match stream {
+        Ok(stream) => match stream.peer_addr() {
+            Ok(address) if net_filter.is_allowed(address.ip()) => true,
+            Ok(address) => {
+                debug!(
+                    "rejecting grpc connection from {}, peer is not allowed",
+                    address
+                );
+                false
+            }
+            Err(_) => false,
+        },
+        Err(_) => true,
+    });
+    let shutdown = async move {
+        let _ = done.recv().await;
+        debug!("stopping grpc gateway, shutdown signal received");
+    };
+    if let Err(e) = Server::builder()
+        .add_service(SegmentServer::new(Gateway { db }))
+        .serve_with_incoming_shutdown(incoming, shutdown)
+        .await
+    {
+        error!("grpc gateway failed, error = {}", e);
+    }
+    drop(wg);
+}
+
+struct Gateway {
+    db: Arc<Db>,
+}
+
+impl Gateway {
+    // parses and runs the command made of the tokens
+    async fn execute(&self, tokens: Vec<Bytes>) -> Result<Frame, Status> {
+        let frame = Frame::Array(tokens.into_iter().map(Frame::String).collect());
+        let appended = self.db.is_appending().then(|| frame.clone());
+        let cmd = command::parse(frame).map_err(parse_status)?;
+        self.db
+            .execute_appended(cmd, appended)
+            .await
+            .map_err(execute_status)
+    }
+}
+
+#[tonic::async_trait]
+impl Segment for Gateway {
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        // reads are refused while warming up like on the frame protocol
+        if self.db.is_warming() {
+            return Err(Status::unavailable("server is warming up"));
+        }
+        let request = request.into_inner();
+        let tokens = vec![
+            Bytes::from_static(b"get"),
+            Bytes::from(request.keyspace),
+            Bytes::from(request.key),
+        ];
+        let value = match self.execute(tokens).await? {
+            Frame::String(value) => Some(value.to_vec()),
+            _ => None,
+        };
+        Ok(Response::new(GetResponse { value }))
+    }
+
+    async fn set(&self, request: Request<SetRequest>) -> Result<Response<SetResponse>, Status> {
+        let request = request.into_inner();
+        let mut tokens = vec![
+            Bytes::from_static(b"set"),
+            Bytes::from(request.keyspace),
+            Bytes::from(request.key),
+            Bytes::from(request.value),
+        ];
+        if let Some(ttl) = request.ttl {
+            tokens.push(Bytes::from_static(b"expire"));
+            tokens.push(Bytes::from_static(b"after"));
+            tokens.push(Bytes::from(ttl.to_string()));
+        }
+        self.execute(tokens).await?;
+        Ok(Response::new(SetResponse {}))
+    }
+
+    async fn del(&self, request: Request<DelRequest>) -> Result<Response<DelResponse>, Status> {
+        let request = request.into_inner();
+        let tokens = vec![
+            Bytes::from_static(b"del"),
+            Bytes::from(request.keyspace),
+            Bytes::from(request.key),
+        ];
+        let deleted = matches!(self.execute(tokens).await?, Frame::Boolean(true));
+        Ok(Response::new(DelResponse { deleted }))
+    }
+}
+
+fn parse_status(e: ParseCommandError) -> Status {
+    Status::invalid_argument(e.to_string())
+}
+
+fn execute_status(e: ExecuteCommandError) -> Status {
+    match e {
+        ExecuteCommandError::KeyspaceDoesNotExist(_) => Status::not_found(e.to_string()),
+        ExecuteCommandError::WrongType(_) => Status::failed_precondition(e.to_string()),
+        ExecuteCommandError::CommandMemoryExceeded(_) => Status::resource_exhausted(e.to_string()),
+        e => Status::internal(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    #[test]
+    fn execute_status_given_missing_keyspace_returns_not_found() {
+        let status = execute_status(ExecuteCommandError::KeyspaceDoesNotExist("foo".to_string()));
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "keyspace 'foo' does not exist");
+        let status = execute_status(ExecuteCommandError::WrongType("list".to_string()));
+        assert_eq!(status.code(), Code::FailedPrecondition);
+    }
+}
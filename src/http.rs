@@ -0,0 +1,398 @@
+use crate::command::{self, registry::MAX_REQUEST_SIZE};
+use crate::db::{Db, ExecuteCommandError};
+use crate::frame::Frame;
+use crate::netfilter::NetFilter;
+use bytes::Bytes;
+use crossbeam::sync::WaitGroup;
+use std::io;
+use std::str;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tracing::{debug, error};
+
+// the most bytes the request line and the headers of a request can take up
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+// a gateway which maps plain http requests onto commands, so segment can be
+// used without a client of the frame protocol, see `http_port` in the config.
+// every key is a resource of its own:
+//
+//   GET    /keyspaces/<keyspace>/keys/<key>           runs GET
+//   PUT    /keyspaces/<keyspace>/keys/<key>[?ttl=<ms>] runs SET with the body
+//   DELETE /keyspaces/<keyspace>/keys/<key>           runs DEL
+pub async fn serve(
+    ln: TcpListener,
+    db: Arc<Db>,
+    net_filter: NetFilter,
+    mut done: broadcast::Receiver<()>,
+    wg: WaitGroup,
+) {
+    loop {
+        tokio::select! {
+            _ = done.recv() => {
+                debug!("stopping http gateway, shutdown signal received");
+                break;
+            }
+            accepted = ln.accept() => {
+                let (stream, address) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("failed to accept http connection, error = {:?}", e);
+                        continue;
+                    }
+                };
+                if !net_filter.is_allowed(address.ip()) {
+                    debug!("rejecting http connection from {}, peer is not allowed", address);
+                    continue;
+                }
+                let db = db.clone();
+                let done = done.resubscribe();
+                let wg = wg.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(stream, db, done).await {
+                        debug!("http connection from {} failed, error = {}", address, e);
+                    }
+                    drop(wg);
+                });
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+enum HttpError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("malformed request")]
+    BadRequest,
+
+    #[error("request headers are too large")]
+    HeadTooLarge,
+
+    #[error("request body is too large")]
+    BodyTooLarge,
+
+    #[error("only requests with a content-length are supported")]
+    LengthRequired,
+}
+
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    body: Bytes,
+    // whether the client wants the connection closed after the response
+    close: bool,
+}
+
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Bytes,
+}
+
+// serves the requests of a connection one after the other until the client
+// closes it, asks for it to be closed or sends a request which can't be read
+async fn handle(
+    stream: TcpStream,
+    db: Arc<Db>,
+    mut done: broadcast::Receiver<()>,
+) -> Result<(), HttpError> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    loop {
+        let request = tokio::select! {
+            _ = done.recv() => return Ok(()),
+            request = read_request(&mut reader) => request,
+        };
+        let (response, close) = match request {
+            Ok(Some(request)) => (route(&request, &db).await, request.close),
+            Ok(None) => return Ok(()),
+            Err(HttpError::Io(e)) => return Err(HttpError::Io(e)),
+            Err(e) => {
+                let status = match e {
+                    HttpError::HeadTooLarge => 431,
+                    HttpError::BodyTooLarge => 413,
+                    HttpError::LengthRequired => 411,
+                    _ => 400,
+                };
+                (Response::text(status, e.to_string()), true)
+            }
+        };
+        writer.write_all(&response.encode(close)).await?;
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+// reads the next request, `None` when the connection was closed in between
+// requests
+async fn read_request(
+    reader: &mut (impl AsyncBufRead + Unpin),
+) -> Result<Option<Request>, HttpError> {
+    let mut head = Vec::new();
+    let mut lines = Vec::new();
+    loop {
+        let start = head.len();
+        let mut limited = reader.take((MAX_HEAD_SIZE - start) as u64 + 1);
+        limited.read_until(b'\n', &mut head).await?;
+        if head.len() > MAX_HEAD_SIZE {
+            return Err(HttpError::HeadTooLarge);
+        }
+        if head.len() == start {
+            if lines.is_empty() && start == 0 {
+                return Ok(None);
+            }
+            return Err(HttpError::BadRequest);
+        }
+        let line = str::from_utf8(&head[start..])
+            .map_err(|_| HttpError::BadRequest)?
+            .trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line.to_string());
+    }
+
+    let mut request_line = lines[0].split(' ');
+    let (method, target, version) = match (
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+    ) {
+        (Some(method), Some(target), Some(version), None) => (method, target, version),
+        _ => return Err(HttpError::BadRequest),
+    };
+    let mut close = match version {
+        "HTTP/1.1" => false,
+        "HTTP/1.0" => true,
+        _ => return Err(HttpError::BadRequest),
+    };
+    let mut length = 0;
+    for header in &lines[1..] {
+        let (name, value) = header.split_once(':').ok_or(HttpError::BadRequest)?;
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => length = value.parse().map_err(|_| HttpError::BadRequest)?,
+            "transfer-encoding" => return Err(HttpError::LengthRequired),
+            "connection" if value.eq_ignore_ascii_case("close") => close = true,
+            "connection" if value.eq_ignore_ascii_case("keep-alive") => close = false,
+            _ => {}
+        }
+    }
+    if length > MAX_REQUEST_SIZE {
+        return Err(HttpError::BodyTooLarge);
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (target, None),
+    };
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        body: Bytes::from(body),
+        close,
+    }))
+}
+
+// runs the command the request maps to and turns its result into a response
+async fn route(request: &Request, db: &Db) -> Response {
+    let (keyspace, key) = match parse_path(&request.path) {
+        Some(resource) => resource,
+        None => return Response::text(404, "not found".to_string()),
+    };
+    let mut tokens = match request.method.as_str() {
+        "GET" => vec![Bytes::from_static(b"get"), keyspace, key],
+        "PUT" => vec![
+            Bytes::from_static(b"set"),
+            keyspace,
+            key,
+            request.body.clone(),
+        ],
+        "DELETE" => vec![Bytes::from_static(b"del"), keyspace, key],
+        _ => return Response::text(405, "method not allowed".to_string()),
+    };
+    if request.method == "PUT" {
+        if let Some(ttl) = query_param(request.query.as_deref(), "ttl") {
+            tokens.push(Bytes::from_static(b"expire"));
+            tokens.push(Bytes::from_static(b"after"));
+            tokens.push(ttl);
+        }
+    }
+
+    let frame = Frame::Array(tokens.into_iter().map(Frame::String).collect());
+    let appended = db.is_appending().then(|| frame.clone());
+    let cmd = match command::parse(frame) {
+        Ok(cmd) => cmd,
+        Err(e) => return Response::text(400, e.to_string()),
+    };
+    // reads are refused while warming up like on the frame protocol
+    if db.is_warming() && request.method == "GET" {
+        return Response::text(503, "server is warming up".to_string());
+    }
+    match (
+        request.method.as_str(),
+        db.execute_appended(cmd, appended).await,
+    ) {
+        ("GET", Ok(Frame::String(value))) => {
+            let content_type = match str::from_utf8(&value) {
+                Ok(_) => "text/plain; charset=utf-8",
+                Err(_) => "application/octet-stream",
+            };
+            Response {
+                status: 200,
+                content_type,
+                body: value,
+            }
+        }
+        ("GET", Ok(_)) | ("DELETE", Ok(Frame::Boolean(false))) => {
+            Response::text(404, "key not found".to_string())
+        }
+        (_, Ok(_)) => Response::text(204, String::new()),
+        (_, Err(e)) => {
+            let status = match e {
+                ExecuteCommandError::KeyspaceDoesNotExist(_) => 404,
+                ExecuteCommandError::WrongType(_) => 409,
+                _ => 500,
+            };
+            Response::text(status, e.to_string())
+        }
+    }
+}
+
+// splits `/keyspaces/<keyspace>/keys/<key>` into the keyspace and the key, a
+// key holding a `/` has it percent encoded
+fn parse_path(path: &str) -> Option<(Bytes, Bytes)> {
+    let mut segments = path.split('/');
+    match (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) {
+        (Some(""), Some("keyspaces"), Some(keyspace), Some("keys"), Some(key), None)
+            if !keyspace.is_empty() && !key.is_empty() =>
+        {
+            Some((percent_decode(keyspace)?, percent_decode(key)?))
+        }
+        _ => None,
+    }
+}
+
+fn query_param(query: Option<&str>, name: &str) -> Option<Bytes> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .and_then(|(_, value)| percent_decode(value))
+}
+
+// decodes `%XX` escapes, `None` when an escape is malformed
+fn percent_decode(data: &str) -> Option<Bytes> {
+    let data = data.as_bytes();
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'%' {
+            let hex = str::from_utf8(data.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(data[i]);
+            i += 1;
+        }
+    }
+    Some(Bytes::from(decoded))
+}
+
+impl Response {
+    fn text(status: u16, body: String) -> Self {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: Bytes::from(body),
+        }
+    }
+
+    fn encode(&self, close: bool) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            411 => "Length Required",
+            413 => "Content Too Large",
+            431 => "Request Header Fields Too Large",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        };
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason);
+        if self.status == 405 {
+            head.push_str("Allow: GET, PUT, DELETE\r\n");
+        }
+        if self.status != 204 {
+            head.push_str(&format!(
+                "Content-Type: {}\r\nContent-Length: {}\r\n",
+                self.content_type,
+                self.body.len()
+            ));
+        }
+        if close {
+            head.push_str("Connection: close\r\n");
+        }
+        head.push_str("\r\n");
+        let mut encoded = head.into_bytes();
+        encoded.extend_from_slice(&self.body);
+        encoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_path_given_key_resource_returns_keyspace_and_key() {
+        assert_eq!(
+            parse_path("/keyspaces/users/keys/a%2Fb%20c"),
+            Some((Bytes::from("users"), Bytes::from("a/b c")))
+        );
+        assert_eq!(parse_path("/keyspaces/users/keys/"), None);
+        assert_eq!(parse_path("/keyspaces/users/keys/a/b"), None);
+        assert_eq!(parse_path("/keyspaces/users/keys/a%2"), None);
+    }
+
+    #[tokio::test]
+    async fn read_request_given_requests_reads_them_in_turn() {
+        let mut data: &[u8] = b"PUT /keyspaces/a/keys/b?ttl=10 HTTP/1.1\r\nContent-Length: 3\r\n\r\nfooGET / HTTP/1.0\r\n\r\n";
+        let request = read_request(&mut data).await.unwrap().unwrap();
+        assert_eq!(request.method, "PUT");
+        assert_eq!(request.path, "/keyspaces/a/keys/b");
+        assert_eq!(
+            query_param(request.query.as_deref(), "ttl"),
+            Some(Bytes::from("10"))
+        );
+        assert_eq!(request.body, Bytes::from("foo"));
+        assert!(!request.close);
+        let request = read_request(&mut data).await.unwrap().unwrap();
+        assert_eq!(request.method, "GET");
+        assert!(request.close);
+        assert!(read_request(&mut data).await.unwrap().is_none());
+    }
+}
@@ -1,6 +1,55 @@
-mod command;
+mod adaptive;
+mod allocator;
+mod aof;
+mod batch;
+pub mod client;
+pub mod clock;
+pub mod command;
 pub mod config;
 mod connection;
-mod db;
-mod frame;
+#[cfg(feature = "server")]
+mod coordinator;
+#[cfg(feature = "server")]
+pub mod crash;
+pub mod db;
+#[cfg(feature = "server")]
+pub mod doctor;
+mod export;
+pub mod frame;
+#[cfg(feature = "wasm")]
+mod function;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "http")]
+mod http;
+pub mod lifecycle;
+#[cfg(feature = "memcached")]
+mod memcached;
+#[cfg(feature = "server")]
+mod mirror;
+mod netfilter;
+mod pattern;
+#[cfg(feature = "quic")]
+mod quic;
+#[cfg(feature = "server")]
+mod ratelimit;
+mod recency;
+mod resultcache;
+pub mod scheduler;
+#[cfg(feature = "lua")]
+mod script;
+mod secondchance;
+mod segmented;
+#[cfg(feature = "server")]
 pub mod server;
+mod snapshot;
+mod sortedset;
+mod spill;
+mod stats;
+mod store;
+mod stream;
+mod tasks;
+mod trace;
+pub mod upstream;
+#[cfg(feature = "websocket")]
+mod websocket;
@@ -0,0 +1,132 @@
+use crate::frame::Frame;
+use bytes::Bytes;
+use parking_lot::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::info;
+
+// the server goes through `started`, `loading`, `warming` when it has a warm-up
+// threshold, `ready` and `shutting_down`, keyspace events are published as
+// keyspaces come and go.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifecycleEvent {
+    Started,
+    Loading,
+    Warming,
+    Ready,
+    ShuttingDown,
+    KeyspaceCreated(Bytes),
+    KeyspaceDropped(Bytes),
+}
+
+// publishes the lifecycle events to the connections watching them and to the
+// logs, and keeps track of the state of the server for `INFO`.
+#[derive(Debug)]
+pub struct Lifecycle {
+    events: broadcast::Sender<(LifecycleEvent, u128)>,
+    state: Mutex<&'static str>,
+}
+
+impl LifecycleEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LifecycleEvent::Started => "started",
+            LifecycleEvent::Loading => "loading",
+            LifecycleEvent::Warming => "warming",
+            LifecycleEvent::Ready => "ready",
+            LifecycleEvent::ShuttingDown => "shutting_down",
+            LifecycleEvent::KeyspaceCreated(_) => "keyspace_created",
+            LifecycleEvent::KeyspaceDropped(_) => "keyspace_dropped",
+        }
+    }
+
+    fn keyspace(&self) -> Option<&Bytes> {
+        match self {
+            LifecycleEvent::KeyspaceCreated(keyspace)
+            | LifecycleEvent::KeyspaceDropped(keyspace) => Some(keyspace),
+            _ => None,
+        }
+    }
+
+    // `timestamp` is a unix timestamp in milliseconds
+    pub fn to_frame(&self, timestamp: u128) -> Frame {
+        let mut map = vec![
+            Frame::String(Bytes::from_static(b"event")),
+            Frame::String(Bytes::from_static(self.name().as_bytes())),
+        ];
+        if let Some(keyspace) = self.keyspace() {
+            map.push(Frame::String(Bytes::from_static(b"keyspace")));
+            map.push(Frame::String(keyspace.clone()));
+        }
+        map.push(Frame::String(Bytes::from_static(b"timestamp")));
+        map.push(Frame::Integer(timestamp as i64));
+        Frame::Map(map)
+    }
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Lifecycle {
+            events: broadcast::channel(64).0,
+            state: Mutex::new(LifecycleEvent::Started.name()),
+        }
+    }
+}
+
+impl Lifecycle {
+    pub fn publish(&self, event: LifecycleEvent) {
+        match event.keyspace() {
+            Some(keyspace) => info!(
+                "lifecycle event {}, keyspace = {}",
+                event.name(),
+                String::from_utf8_lossy(keyspace)
+            ),
+            None => info!("lifecycle event {}", event.name()),
+        }
+        if event.keyspace().is_none() {
+            *self.state.lock() = event.name();
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis())
+            .unwrap_or_default();
+        // an error only means that no one is watching
+        let _ = self.events.send((event, timestamp));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<(LifecycleEvent, u128)> {
+        self.events.subscribe()
+    }
+
+    // the name of the last server event, `started` until the server moves on
+    pub fn state(&self) -> &'static str {
+        *self.state.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publish_given_events_updates_state_and_notifies_watchers() {
+        let lifecycle = Lifecycle::default();
+        let mut events = lifecycle.subscribe();
+        lifecycle.publish(LifecycleEvent::Ready);
+        lifecycle.publish(LifecycleEvent::KeyspaceCreated(Bytes::from("foo")));
+        assert_eq!(lifecycle.state(), "ready");
+        assert_eq!(events.try_recv().unwrap().0, LifecycleEvent::Ready);
+        let (event, timestamp) = events.try_recv().unwrap();
+        assert_eq!(
+            event.to_frame(timestamp),
+            Frame::Map(vec![
+                Frame::String(Bytes::from("event")),
+                Frame::String(Bytes::from("keyspace_created")),
+                Frame::String(Bytes::from("keyspace")),
+                Frame::String(Bytes::from("foo")),
+                Frame::String(Bytes::from("timestamp")),
+                Frame::Integer(timestamp as i64),
+            ])
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use crate::command::{self, registry::MAX_REQUEST_SIZE};
+use crate::db::Db;
+use crate::frame::Frame;
+use crate::netfilter::NetFilter;
+use bytes::Bytes;
+use crossbeam::sync::WaitGroup;
+use std::io;
+use std::str;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tracing::{debug, error};
+
+// the most bytes a command line can take up, `get` with many keys included
+const MAX_LINE_SIZE: usize = 16 * 1024;
+// keys longer than this are refused like memcached does
+const MAX_KEY_SIZE: usize = 250;
+// exptimes up to 30 days are relative, longer ones are unix timestamps
+const MAX_RELATIVE_EXPTIME: i64 = 60 * 60 * 24 * 30;
+
+// a listener which speaks the text protocol of memcached, so applications
+// using a memcached client can be pointed at segment as they are, see
+// `memcached_port` in the config. all keys live in a single keyspace:
+//
+//   get <key>*                                 runs GET for every key
+//   set <key> <flags> <exptime> <bytes> [noreply] runs SET
+//   delete <key> [noreply]                     runs DEL
+//
+// flags are not stored, values always come back with flags 0
+pub async fn serve(
+    ln: TcpListener,
+    db: Arc<Db>,
+    keyspace: Bytes,
+    net_filter: NetFilter,
+    mut done: broadcast::Receiver<()>,
+    wg: WaitGroup,
+) {
+    loop {
+        tokio::select! {
+            _ = done.recv() => {
+                debug!("stopping memcached listener, shutdown signal received");
+                break;
+            }
+            accepted = ln.accept() => {
+                let (stream, address) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("failed to accept memcached connection, error = {:?}", e);
+                        continue;
+                    }
+                };
+                if !net_filter.is_allowed(address.ip()) {
+                    debug!("rejecting memcached connection from {}, peer is not allowed", address);
+                    continue;
+                }
+                let db = db.clone();
+                let keyspace = keyspace.clone();
+                let done = done.resubscribe();
+                let wg = wg.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(stream, db, keyspace, done).await {
+                        debug!("memcached connection from {} failed, error = {}", address, e);
+                    }
+                    drop(wg);
+                });
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+enum MemcachedError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    // the command isn't supported, memcached replies with a bare `ERROR`
+    #[error("unknown command")]
+    UnknownCommand,
+
+    #[error("{0}")]
+    Client(&'static str),
+
+    // the rest of the request can't be told apart from the next one, the
+    // connection is closed after the reply
+    #[error("{0}")]
+    Fatal(&'static str),
+}
+
+#[derive(Debug, PartialEq)]
+enum Request {
+    Get(Vec<Bytes>),
+    Set {
+        key: Bytes,
+        exptime: i64,
+        data: Bytes,
+        noreply: bool,
+    },
+    Delete {
+        key: Bytes,
+        noreply: bool,
+    },
+    Version,
+    Quit,
+}
+
+// serves the requests of a connection one after the other until the client
+// closes it, quits or sends a request which can't be read
+async fn handle(
+    stream: TcpStream,
+    db: Arc<Db>,
+    keyspace: Bytes,
+    mut done: broadcast::Receiver<()>,
+) -> Result<(), MemcachedError> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    loop {
+        let request = tokio::select! {
+            _ = done.recv() => return Ok(()),
+            request = read_request(&mut reader) => request,
+        };
+        let reply = match request {
+            Ok(Some(Request::Quit)) | Ok(None) => return Ok(()),
+            Ok(Some(request)) => execute(request, &db, &keyspace).await,
+            Err(MemcachedError::Io(e)) => return Err(MemcachedError::Io(e)),
+            Err(MemcachedError::UnknownCommand) => b"ERROR\r\n".to_vec(),
+            Err(MemcachedError::Client(e)) => format!("CLIENT_ERROR {}\r\n", e).into_bytes(),
+            Err(MemcachedError::Fatal(e)) => {
+                writer
+                    .write_all(format!("CLIENT_ERROR {}\r\n", e).as_bytes())
+                    .await?;
+                return Ok(());
+            }
+        };
+        writer.write_all(&reply).await?;
+    }
+}
+
+// reads the next request, `None` when the connection was closed in between
+// requests
+async fn read_request(
+    reader: &mut (impl AsyncBufRead + Unpin),
+) -> Result<Option<Request>, MemcachedError> {
+    let mut line = Vec::new();
+    let mut limited = reader.take(MAX_LINE_SIZE as u64 + 1);
+    limited.read_until(b'\n', &mut line).await?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.len() > MAX_LINE_SIZE {
+        return Err(MemcachedError::Fatal("line too long"));
+    }
+    let line = str::from_utf8(&line)
+        .map_err(|_| MemcachedError::Client("bad command line format"))?
+        .trim_end_matches(['\r', '\n']);
+    let tokens: Vec<&str> = line.split_ascii_whitespace().collect();
+    let request = match tokens.as_slice() {
+        ["get", keys @ ..] if !keys.is_empty() => Request::Get(
+            keys.iter()
+                .map(|key| parse_key(key))
+                .collect::<Result<_, _>>()?,
+        ),
+        ["set", key, flags, exptime, bytes, rest @ ..] => {
+            // the data is read before anything else is checked, so a bad
+            // request doesn't leave it behind to be read as the next one
+            let bytes = bytes
+                .parse::<usize>()
+                .map_err(|_| MemcachedError::Fatal("bad command line format"))?;
+            if bytes > MAX_REQUEST_SIZE {
+                return Err(MemcachedError::Fatal("object too large for cache"));
+            }
+            let mut data = vec![0; bytes + 2];
+            reader.read_exact(&mut data).await?;
+            if !data.ends_with(b"\r\n") {
+                return Err(MemcachedError::Fatal("bad data chunk"));
+            }
+            data.truncate(bytes);
+            let noreply = parse_noreply(rest)?;
+            flags
+                .parse::<u32>()
+                .map_err(|_| MemcachedError::Client("bad command line format"))?;
+            let exptime = exptime
+                .parse::<i64>()
+                .map_err(|_| MemcachedError::Client("bad command line format"))?;
+            Request::Set {
+                key: parse_key(key)?,
+                exptime,
+                data: Bytes::from(data),
+                noreply,
+            }
+        }
+        ["delete", key, rest @ ..] if rest.len() <= 1 => Request::Delete {
+            key: parse_key(key)?,
+            noreply: parse_noreply(rest)?,
+        },
+        ["version"] => Request::Version,
+        ["quit"] => Request::Quit,
+        _ => return Err(MemcachedError::UnknownCommand),
+    };
+    Ok(Some(request))
+}
+
+fn parse_key(key: &str) -> Result<Bytes, MemcachedError> {
+    if key.len() > MAX_KEY_SIZE {
+        return Err(MemcachedError::Client("key too long"));
+    }
+    Ok(Bytes::copy_from_slice(key.as_bytes()))
+}
+
+fn parse_noreply(rest: &[&str]) -> Result<bool, MemcachedError> {
+    match rest {
+        [] => Ok(false),
+        ["noreply"] => Ok(true),
+        _ => Err(MemcachedError::Client("bad command line format")),
+    }
+}
+
+// runs the commands the request maps to and returns the reply, empty when the
+// client asked for none
+async fn execute(request: Request, db: &Db, keyspace: &Bytes) -> Vec<u8> {
+    let mut reply = Vec::new();
+    match request {
+        Request::Get(keys) => {
+            // reads are refused while warming up like on the frame protocol
+            if db.is_warming() {
+                return b"SERVER_ERROR server is warming up\r\n".to_vec();
+            }
+            for key in keys {
+                let tokens = vec![Bytes::from_static(b"get"), keyspace.clone(), key.clone()];
+                match run(db, tokens).await {
+                    Ok(Frame::String(value)) => {
+                        reply.extend_from_slice(b"VALUE ");
+                        reply.extend_from_slice(&key);
+                        reply.extend_from_slice(format!(" 0 {}\r\n", value.len()).as_bytes());
+                        reply.extend_from_slice(&value);
+                        reply.extend_from_slice(b"\r\n");
+                    }
+                    Ok(_) => {}
+                    Err(e) => return server_error(&e),
+                }
+            }
+            reply.extend_from_slice(b"END\r\n");
+        }
+        Request::Set {
+            key,
+            exptime,
+            data,
+            noreply,
+        } => {
+            let result = if exptime < 0 {
+                // a negative exptime expires the item right away
+                run(db, vec![Bytes::from_static(b"del"), keyspace.clone(), key]).await
+            } else {
+                let mut tokens = vec![Bytes::from_static(b"set"), keyspace.clone(), key, data];
+                if exptime > MAX_RELATIVE_EXPTIME {
+                    tokens.push(Bytes::from_static(b"expire"));
+                    tokens.push(Bytes::from_static(b"at"));
+                    tokens.push(Bytes::from(exptime.to_string()));
+                } else if exptime > 0 {
+                    tokens.push(Bytes::from_static(b"expire"));
+                    tokens.push(Bytes::from_static(b"after"));
+                    tokens.push(Bytes::from((exptime * 1000).to_string()));
+                }
+                run(db, tokens).await
+            };
+            match result {
+                Ok(_) if noreply => {}
+                Ok(_) => reply.extend_from_slice(b"STORED\r\n"),
+                Err(e) => return server_error(&e),
+            }
+        }
+        Request::Delete { key, noreply } => {
+            let tokens = vec![Bytes::from_static(b"del"), keyspace.clone(), key];
+            match run(db, tokens).await {
+                Ok(_) if noreply => {}
+                Ok(Frame::Boolean(true)) => reply.extend_from_slice(b"DELETED\r\n"),
+                Ok(_) => reply.extend_from_slice(b"NOT_FOUND\r\n"),
+                Err(e) => return server_error(&e),
+            }
+        }
+        Request::Version => reply.extend_from_slice(
+            format!("VERSION segment-{}\r\n", env!("CARGO_PKG_VERSION")).as_bytes(),
+        ),
+        Request::Quit => {}
+    }
+    reply
+}
+
+async fn run(db: &Db, tokens: Vec<Bytes>) -> Result<Frame, String> {
+    let frame = Frame::Array(tokens.into_iter().map(Frame::String).collect());
+    let appended = db.is_appending().then(|| frame.clone());
+    let cmd = command::parse(frame).map_err(|e| e.to_string())?;
+    db.execute_appended(cmd, appended)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+fn server_error(e: &str) -> Vec<u8> {
+    format!("SERVER_ERROR {}\r\n", e).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn read_request_given_requests_reads_them_in_turn() {
+        let mut data: &[u8] =
+            b"set foo 5 60 3 noreply\r\nbar\r\nget foo baz\r\ndelete foo\r\nincr foo 1\r\n";
+        assert_eq!(
+            read_request(&mut data).await.unwrap(),
+            Some(Request::Set {
+                key: Bytes::from("foo"),
+                exptime: 60,
+                data: Bytes::from("bar"),
+                noreply: true,
+            })
+        );
+        assert_eq!(
+            read_request(&mut data).await.unwrap(),
+            Some(Request::Get(vec![Bytes::from("foo"), Bytes::from("baz")]))
+        );
+        assert_eq!(
+            read_request(&mut data).await.unwrap(),
+            Some(Request::Delete {
+                key: Bytes::from("foo"),
+                noreply: false,
+            })
+        );
+        assert!(matches!(
+            read_request(&mut data).await,
+            Err(MemcachedError::UnknownCommand)
+        ));
+        assert_eq!(read_request(&mut data).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn read_request_given_bad_set_skips_its_data() {
+        let mut data: &[u8] = b"set foo x 0 3\r\nbar\r\nversion\r\nset foo 0 0 3\r\nbarbaz\r\n";
+        assert!(matches!(
+            read_request(&mut data).await,
+            Err(MemcachedError::Client(_))
+        ));
+        assert_eq!(
+            read_request(&mut data).await.unwrap(),
+            Some(Request::Version)
+        );
+        assert!(matches!(
+            read_request(&mut data).await,
+            Err(MemcachedError::Fatal("bad data chunk"))
+        ));
+    }
+}
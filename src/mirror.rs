@@ -0,0 +1,121 @@
+use crate::client::Client;
+use crate::frame::Frame;
+use crate::stats::Stats;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::time::{self, Instant};
+use tracing::debug;
+
+// how many commands can wait to be mirrored, commands offered while the queue
+// is full are dropped so a slow mirror never holds up the connections
+const MIRROR_QUEUE_SIZE: usize = 1024;
+// how long the mirror gets to reply to a command
+const MIRROR_TIMEOUT: Duration = Duration::from_secs(1);
+// how long commands are dropped for after the mirror failed, instead of trying
+// to reach it again on every command
+const MIRROR_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// sends a copy of the commands a server runs to another segment server, see
+// `mirror` in the config. the commands are sent in the background by a single
+// task and their replies are thrown away, so mirroring never changes what the
+// clients see.
+#[derive(Debug)]
+pub struct Mirror {
+    sample_percent: u64,
+    offered: AtomicU64,
+    stats: Arc<Stats>,
+    tx: mpsc::Sender<Frame>,
+}
+
+impl Mirror {
+    pub fn start(address: String, sample_percent: u8, stats: Arc<Stats>) -> Self {
+        let (tx, rx) = mpsc::channel(MIRROR_QUEUE_SIZE);
+        tokio::spawn(run(address, rx, stats.clone()));
+        Mirror {
+            sample_percent: sample_percent as u64,
+            offered: AtomicU64::new(0),
+            stats,
+            tx,
+        }
+    }
+
+    // queues the command to be mirrored if it is part of the sample
+    pub fn offer(&self, command: Frame) {
+        if !self.sampled() {
+            return;
+        }
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(command) {
+            self.stats.record_mirror_dropped();
+        }
+    }
+
+    // picks `sample_percent` out of every 100 commands offered, spread evenly
+    fn sampled(&self) -> bool {
+        let offered = self.offered.fetch_add(1, Ordering::Relaxed);
+        offered * self.sample_percent / 100 != (offered + 1) * self.sample_percent / 100
+    }
+}
+
+async fn run(address: String, mut rx: mpsc::Receiver<Frame>, stats: Arc<Stats>) {
+    let mut client: Option<Client> = None;
+    let mut retry_at = Instant::now();
+    while let Some(command) = rx.recv().await {
+        if Instant::now() < retry_at {
+            stats.record_mirror_dropped();
+            continue;
+        }
+        match time::timeout(MIRROR_TIMEOUT, send(&address, &mut client, &command)).await {
+            Ok(true) => stats.record_mirrored(),
+            result => {
+                if result.is_err() {
+                    debug!("mirror {} did not reply in time", address);
+                }
+                client = None;
+                retry_at = Instant::now() + MIRROR_RETRY_DELAY;
+                stats.record_mirror_dropped();
+            }
+        }
+    }
+}
+
+// returns false when the command could not be sent, errors returned by the
+// mirror are expected, it might not have the same keyspaces
+async fn send(address: &str, client: &mut Option<Client>, command: &Frame) -> bool {
+    let client = match client {
+        Some(client) => client,
+        None => match Client::connect(address).await {
+            Ok(connected) => client.insert(connected),
+            Err(e) => {
+                debug!("failed to connect to mirror {}: {}", address, e);
+                return false;
+            }
+        },
+    };
+    match client.send(command).await {
+        Ok(_) => true,
+        Err(e) => {
+            debug!("failed to mirror command to {}: {}", address, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sampled_given_sample_percent_picks_that_share_of_commands() {
+        for (sample_percent, expected) in [(0, 0), (25, 25), (50, 50), (100, 100)] {
+            let mirror = Mirror::start(
+                "127.0.0.1:0".to_string(),
+                sample_percent,
+                Arc::new(Stats::new(0)),
+            );
+            let sampled = (0..100).filter(|_| mirror.sampled()).count();
+            assert_eq!(sampled, expected);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use ipnet::{AddrParseError, IpNet};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+// decides which peers are allowed to connect to the server, a peer is rejected
+// if it matches any of the denied networks or if there are allowed networks
+// and it doesn't match any of them.
+#[derive(Debug, Default, Clone)]
+pub struct NetFilter {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl NetFilter {
+    pub fn set_allow(&mut self, networks: &str) -> Result<(), AddrParseError> {
+        self.allow = parse_networks(networks)?;
+        Ok(())
+    }
+
+    pub fn set_deny(&mut self, networks: &str) -> Result<(), AddrParseError> {
+        self.deny = parse_networks(networks)?;
+        Ok(())
+    }
+
+    // whether only the peers in the allow list can connect
+    pub fn is_restricted(&self) -> bool {
+        !self.allow.is_empty()
+    }
+
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        // ipv4 peers connecting to a dual stack listener show up as ipv4
+        // mapped ipv6 addresses, they should match the ipv4 networks.
+        let ip = ip.to_canonical();
+        if self.deny.iter().any(|network| network.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|network| network.contains(&ip))
+    }
+}
+
+// parses a comma separated list of networks in CIDR notation, a plain address
+// is treated as a network with just that address.
+fn parse_networks(networks: &str) -> Result<Vec<IpNet>, AddrParseError> {
+    networks
+        .split(',')
+        .map(|network| network.trim())
+        .filter(|network| !network.is_empty())
+        .map(|network| match IpAddr::from_str(network) {
+            Ok(ip) => Ok(IpNet::from(ip)),
+            Err(_) => IpNet::from_str(network),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        IpAddr::from_str(ip).unwrap()
+    }
+
+    #[test]
+    fn is_allowed_given_empty_filter_returns_true() {
+        let filter = NetFilter::default();
+        assert!(filter.is_allowed(ip("10.1.2.3")));
+        assert!(filter.is_allowed(ip("::1")));
+    }
+
+    #[test]
+    fn is_allowed_given_allow_list_returns_true_only_for_allowed_networks() {
+        let mut filter = NetFilter::default();
+        filter.set_allow("10.0.0.0/8, 127.0.0.1").unwrap();
+        assert!(filter.is_allowed(ip("10.1.2.3")));
+        assert!(filter.is_allowed(ip("127.0.0.1")));
+        assert!(!filter.is_allowed(ip("127.0.0.2")));
+        assert!(!filter.is_allowed(ip("192.168.1.1")));
+    }
+
+    #[test]
+    fn is_allowed_given_denied_peer_in_allowed_network_returns_false() {
+        let mut filter = NetFilter::default();
+        filter.set_allow("10.0.0.0/8").unwrap();
+        filter.set_deny("10.0.0.0/24").unwrap();
+        assert!(!filter.is_allowed(ip("10.0.0.7")));
+        assert!(filter.is_allowed(ip("10.0.1.7")));
+    }
+
+    #[test]
+    fn is_allowed_given_ipv4_mapped_peer_matches_ipv4_network() {
+        let mut filter = NetFilter::default();
+        filter.set_deny("192.168.0.0/16").unwrap();
+        assert!(!filter.is_allowed(ip("::ffff:192.168.1.1")));
+    }
+
+    #[test]
+    fn set_allow_given_invalid_network_returns_error() {
+        let mut filter = NetFilter::default();
+        assert!(filter.set_allow("10.0.0.0/33").is_err());
+        assert!(filter.set_allow("localhost").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+// glob style matching where `*` matches any sequence of bytes (including an
+// empty one) and `?` matches exactly one byte, every other byte matches itself.
+pub fn matches(pattern: &[u8], input: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // position of the last `*` seen in the pattern and the input position it
+    // was matched at, used to backtrack when the rest of the pattern fails.
+    let mut star: Option<(usize, usize)> = None;
+
+    while i < input.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, i));
+                p += 1;
+            }
+            Some(b'?') => {
+                p += 1;
+                i += 1;
+            }
+            Some(byte) if *byte == input[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => match star {
+                Some((star_p, star_i)) => {
+                    p = star_p + 1;
+                    i = star_i + 1;
+                    star = Some((star_p, star_i + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|byte| *byte == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_given_literal_pattern_returns_true_only_for_equal_input() {
+        assert!(matches(b"foo", b"foo"));
+        assert!(!matches(b"foo", b"foobar"));
+        assert!(!matches(b"foo", b"fo"));
+    }
+
+    #[test]
+    fn matches_given_star_pattern_returns_true() {
+        assert!(matches(b"*", b""));
+        assert!(matches(b"foo*", b"foo"));
+        assert!(matches(b"foo*", b"foobar"));
+        assert!(matches(b"*bar", b"foobar"));
+        assert!(matches(b"f*o*r", b"foobar"));
+        assert!(!matches(b"f*z", b"foobar"));
+    }
+
+    #[test]
+    fn matches_given_question_mark_pattern_matches_single_byte() {
+        assert!(matches(b"f?o", b"foo"));
+        assert!(!matches(b"f?o", b"fo"));
+        assert!(matches(b"*?", b"a"));
+        assert!(!matches(b"*?", b""));
+    }
+}
@@ -0,0 +1,99 @@
+use crate::netfilter::NetFilter;
+use anyhow::{Context, Result};
+use crossbeam::sync::WaitGroup;
+use quinn::{Connection, Endpoint, Incoming, ServerConfig};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::net::SocketAddr;
+use tokio::io::{self, DuplexStream};
+use tokio::sync::{broadcast, mpsc};
+use tracing::debug;
+
+// opens the udp endpoint of the quic listener with the certificate chain and
+// the private key from the given pem files
+pub fn bind(addr: SocketAddr, cert_file: &str, key_file: &str) -> Result<Endpoint> {
+    let certs = CertificateDer::pem_file_iter(cert_file)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("unable to read quic certificates from '{}'", cert_file))?;
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .with_context(|| format!("unable to read quic private key from '{}'", key_file))?;
+    let config = ServerConfig::with_single_cert(certs, key)?;
+    Ok(Endpoint::server(config, addr)?)
+}
+
+// an experimental listener which carries the frame protocol over quic, see
+// `quic_port` in the config. every bidirectional stream a client opens is a
+// connection of its own, bridged onto an in memory stream and handed to the
+// server through `connections` like a websocket. since streams are delivered
+// independently, a lost packet only holds up the stream it belongs to instead
+// of every request sent over the same tcp connection.
+pub async fn serve(
+    endpoint: Endpoint,
+    net_filter: NetFilter,
+    buf_size: usize,
+    connections: mpsc::Sender<(DuplexStream, SocketAddr)>,
+    mut done: broadcast::Receiver<()>,
+    wg: WaitGroup,
+) {
+    loop {
+        tokio::select! {
+            _ = done.recv() => {
+                debug!("stopping quic listener, shutdown signal received");
+                endpoint.close(0u32.into(), b"server is shutting down");
+                break;
+            }
+            incoming = endpoint.accept() => {
+                let incoming = match incoming {
+                    Some(incoming) => incoming,
+                    None => break,
+                };
+                let address = incoming.remote_address();
+                if !net_filter.is_allowed(address.ip()) {
+                    debug!("rejecting quic connection from {}, peer is not allowed", address);
+                    incoming.refuse();
+                    continue;
+                }
+                let connections = connections.clone();
+                let wg = wg.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = accept(incoming, buf_size, connections, wg.clone()).await {
+                        debug!("quic connection from {} failed, error = {}", address, e);
+                    }
+                    drop(wg);
+                });
+            }
+        }
+    }
+}
+
+// completes the handshake and bridges the streams of the connection until it
+// is closed
+async fn accept(
+    incoming: Incoming,
+    buf_size: usize,
+    connections: mpsc::Sender<(DuplexStream, SocketAddr)>,
+    wg: WaitGroup,
+) -> Result<(), quinn::ConnectionError> {
+    let connection: Connection = incoming.await?;
+    let address = connection.remote_address();
+    loop {
+        let (send, recv) = match connection.accept_bi().await {
+            Ok(stream) => stream,
+            Err(quinn::ConnectionError::ApplicationClosed(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let (server, mut bridge) = io::duplex(buf_size);
+        if connections.send((server, address)).await.is_err() {
+            // the server is shutting down
+            return Ok(());
+        }
+        let wg = wg.clone();
+        tokio::spawn(async move {
+            let mut stream = io::join(recv, send);
+            if let Err(e) = io::copy_bidirectional(&mut stream, &mut bridge).await {
+                debug!("quic stream from {} failed, error = {}", address, e);
+            }
+            drop(wg);
+        });
+    }
+}
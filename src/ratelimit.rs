@@ -0,0 +1,72 @@
+use std::time::Instant;
+
+// token bucket which allows `rate` commands per second on average and bursts
+// of up to `burst` commands, tokens are refilled lazily when one is taken.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64, burst: u64) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            burst: burst as f64,
+            tokens: burst as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn try_acquire_given_empty_bucket_returns_false() {
+        let mut bucket = TokenBucket::new(10, 2);
+        let now = bucket.last_refill;
+        assert!(bucket.try_acquire_at(now));
+        assert!(bucket.try_acquire_at(now));
+        assert!(!bucket.try_acquire_at(now));
+    }
+
+    #[test]
+    fn try_acquire_given_elapsed_time_refills_bucket() {
+        let mut bucket = TokenBucket::new(10, 2);
+        let now = bucket.last_refill;
+        assert!(bucket.try_acquire_at(now));
+        assert!(bucket.try_acquire_at(now));
+        assert!(bucket.try_acquire_at(now + Duration::from_millis(100)));
+        assert!(!bucket.try_acquire_at(now + Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn try_acquire_given_long_idle_time_caps_tokens_at_burst() {
+        let mut bucket = TokenBucket::new(10, 2);
+        let now = bucket.last_refill + Duration::from_secs(60);
+        assert!(bucket.try_acquire_at(now));
+        assert!(bucket.try_acquire_at(now));
+        assert!(!bucket.try_acquire_at(now));
+    }
+}
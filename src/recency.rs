@@ -0,0 +1,103 @@
+use crate::adaptive::Candidate;
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+// the bookkeeping of the exact lru evictor, keys ordered by when they were
+// last accessed. moving a key on every read would need the queue on every
+// read, so reads only stamp the value like they do for the sampling lru and
+// the order is fixed up on eviction instead: a key is queued as of an access
+// which is never later than its last one, so when the key at the front wasn't
+// accessed since, no other key was accessed before it and it is the least
+// recently used key. a key that was accessed since is queued again as of its
+// last access.
+#[derive(Debug, Default)]
+pub struct Recency {
+    order: BTreeMap<(Instant, u64), Bytes>,
+    keys: HashMap<Bytes, (Instant, u64)>,
+    next: u64,
+}
+
+impl Recency {
+    // a new key was written, keys written again are left where they are
+    pub fn insert(&mut self, key: &Bytes, now: Instant) {
+        if !self.keys.contains_key(key) {
+            self.push(key.clone(), now);
+        }
+    }
+
+    // a key was removed other than by eviction, like a deleted key
+    pub fn remove(&mut self, key: &Bytes) {
+        if let Some(at) = self.keys.remove(key) {
+            self.order.remove(&at);
+        }
+    }
+
+    // picks the least recently used key, `check` tells whether the key at the
+    // front was accessed since it was queued. returns `None` when every key
+    // was kept.
+    pub fn evict(
+        &mut self,
+        mut check: impl FnMut(&Bytes, Instant) -> Candidate,
+        now: Instant,
+    ) -> Option<Bytes> {
+        // every key moves at most once, after that it is queued as of its
+        // last access
+        for _ in 0..=2 * self.keys.len() {
+            let ((queued_at, _), key) = self.order.pop_first()?;
+            self.keys.remove(&key);
+            match check(&key, queued_at) {
+                Candidate::Missing => {}
+                Candidate::Accessed(at) => self.push(key, at),
+                Candidate::Kept | Candidate::Referenced => self.push(key, now),
+                Candidate::Cold => return Some(key),
+            }
+        }
+        None
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn push(&mut self, key: Bytes, at: Instant) {
+        let tick = self.next;
+        self.next += 1;
+        self.order.insert((at, tick), key.clone());
+        self.keys.insert(key, (at, tick));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn evict_given_keys_accessed_out_of_order_evicts_least_recently_used() {
+        let mut recency = Recency::default();
+        let now = Instant::now();
+        for key in ["a", "b", "c"] {
+            recency.insert(&Bytes::from(key), now);
+        }
+        // b was read before a, c wasn't read at all after it was written
+        let accessed = |key: &Bytes| match &key[..] {
+            b"a" => Some(now + Duration::from_secs(2)),
+            b"b" => Some(now + Duration::from_secs(1)),
+            _ => None,
+        };
+        let mut evict = || {
+            recency.evict(
+                |key, queued_at| match accessed(key) {
+                    Some(at) if at > queued_at => Candidate::Accessed(at),
+                    _ => Candidate::Cold,
+                },
+                now,
+            )
+        };
+        assert_eq!(evict(), Some(Bytes::from("c")));
+        assert_eq!(evict(), Some(Bytes::from("b")));
+        assert_eq!(evict(), Some(Bytes::from("a")));
+        assert_eq!(evict(), None);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::frame::Frame;
+use bytes::Bytes;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// the most results kept at once, the oldest one makes room for a new one
+const MAX_ENTRIES: usize = 1024;
+
+// memoized results of expensive reads like `SUM` and `EVAL`, see
+// `result_cache_ttl` in the config. a result is keyed by the tokens of the
+// command and kept along with the epochs of the keyspaces it read, it is
+// served again only while every one of them is still at that epoch, so any
+// write to those keyspaces invalidates it. keys expire without a write until
+// they are removed, the ttl bounds how long such a result can be served.
+#[derive(Debug)]
+pub struct ResultCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<Vec<Bytes>, Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    frame: Frame,
+    epochs: Vec<(Bytes, u64)>,
+    cached_at: Instant,
+}
+
+impl ResultCache {
+    // a ttl of 0 turns the cache off
+    pub fn new(ttl: Duration) -> Self {
+        ResultCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    // `epoch` returns the current epoch of a keyspace
+    pub fn get(&self, key: &[Bytes], epoch: impl Fn(&Bytes) -> u64) -> Option<Frame> {
+        let mut entries = self.entries.lock();
+        let entry = entries.get(key)?;
+        let valid = entry.cached_at.elapsed() < self.ttl
+            && entry
+                .epochs
+                .iter()
+                .all(|(keyspace, cached)| epoch(keyspace) == *cached);
+        if !valid {
+            entries.remove(key);
+            return None;
+        }
+        Some(entry.frame.clone())
+    }
+
+    // `epochs` are the epochs of the keyspaces read, taken before they were
+    // read so a write in the meantime leaves the result invalid
+    pub fn insert(&self, key: Vec<Bytes>, epochs: Vec<(Bytes, u64)>, frame: Frame) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut entries = self.entries.lock();
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.cached_at.elapsed() < self.ttl);
+        }
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.cached_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            Entry {
+                frame,
+                epochs,
+                cached_at: Instant::now(),
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_given_keyspace_written_since_misses() {
+        let cache = ResultCache::new(Duration::from_secs(60));
+        let key = vec![Bytes::from("sum"), Bytes::from("foo")];
+        cache.insert(
+            key.clone(),
+            vec![(Bytes::from("foo"), 1)],
+            Frame::Integer(3),
+        );
+        assert_eq!(cache.get(&key, |_| 1), Some(Frame::Integer(3)));
+        assert_eq!(cache.get(&key, |_| 2), None);
+        assert_eq!(cache.get(&key, |_| 1), None);
+    }
+
+    #[test]
+    fn insert_given_ttl_of_zero_keeps_nothing() {
+        let cache = ResultCache::new(Duration::ZERO);
+        let key = vec![Bytes::from("sum"), Bytes::from("foo")];
+        cache.insert(
+            key.clone(),
+            vec![(Bytes::from("foo"), 1)],
+            Frame::Integer(3),
+        );
+        assert_eq!(cache.get(&key, |_| 1), None);
+        assert_eq!(cache.len(), 0);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::command;
+use crate::db::Db;
+use crate::frame::Frame;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use crossbeam::sync::WaitGroup;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::str::{self, Utf8Error};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tokio::time;
+use tracing::{debug, error};
+
+#[derive(Debug)]
+struct Job {
+    schedule: Schedule,
+    command: Vec<Bytes>,
+    next_run: Option<DateTime<Utc>>,
+}
+
+// `run_at` is in milliseconds since the unix epoch
+#[derive(Debug)]
+struct Delayed {
+    run_at: u64,
+    command: Vec<Bytes>,
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    jobs: Mutex<HashMap<Bytes, Job>>,
+    delayed: Mutex<Vec<Delayed>>,
+}
+
+#[derive(Debug, Error)]
+pub enum SchedulerError {
+    #[error("job '{0}' already exists")]
+    JobExists(String),
+
+    #[error(transparent)]
+    Utf8Error(#[from] Utf8Error),
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    pub fn add(
+        &self,
+        name: Bytes,
+        schedule: Schedule,
+        command: Vec<Bytes>,
+    ) -> Result<Frame, SchedulerError> {
+        let mut handle = self.jobs.lock();
+        if handle.contains_key(&name) {
+            return Err(SchedulerError::JobExists(
+                str::from_utf8(&name[..])?.to_string(),
+            ));
+        }
+        let next_run = schedule.upcoming(Utc).next();
+        handle.insert(
+            name,
+            Job {
+                schedule,
+                command,
+                next_run,
+            },
+        );
+        Ok(Frame::Boolean(true))
+    }
+
+    // `run_at` is in milliseconds since the unix epoch, a command whose time
+    // already passed runs right away
+    pub fn delay(&self, run_at: u64, command: Vec<Bytes>) -> Result<Frame, SchedulerError> {
+        let mut handle = self.delayed.lock();
+        handle.push(Delayed { run_at, command });
+        Ok(Frame::Boolean(true))
+    }
+
+    // forgets the delayed command that was due at `run_at`, once it ran.
+    // commands delayed to the same time are the same, any of them goes.
+    pub fn done(&self, run_at: u64, command: &[Bytes]) -> Result<Frame, SchedulerError> {
+        let mut handle = self.delayed.lock();
+        let idx = handle
+            .iter()
+            .position(|delayed| delayed.run_at == run_at && delayed.command == command);
+        if let Some(idx) = idx {
+            handle.swap_remove(idx);
+        }
+        Ok(Frame::Boolean(idx.is_some()))
+    }
+
+    pub fn remove(&self, name: Bytes) -> Result<Frame, SchedulerError> {
+        let mut handle = self.jobs.lock();
+        Ok(Frame::Boolean(handle.remove(&name).is_some()))
+    }
+
+    pub fn list(&self) -> Result<Frame, SchedulerError> {
+        let handle = self.jobs.lock();
+        let mut jobs = Vec::with_capacity(handle.len());
+        for (name, job) in handle.iter() {
+            let next_run = match job.next_run {
+                Some(next_run) => Frame::Integer(next_run.timestamp()),
+                None => Frame::Null,
+            };
+            jobs.push(Frame::Map(vec![
+                Frame::String(Bytes::from_static(b"name")),
+                Frame::String(name.clone()),
+                Frame::String(Bytes::from_static(b"expression")),
+                Frame::String(Bytes::copy_from_slice(job.schedule.source().as_bytes())),
+                Frame::String(Bytes::from_static(b"command")),
+                Frame::Array(job.command.iter().cloned().map(Frame::String).collect()),
+                Frame::String(Bytes::from_static(b"next_run")),
+                next_run,
+            ]));
+        }
+        Ok(Frame::Array(jobs))
+    }
+
+    // returns the commands of all the jobs that are due at the given time and
+    // moves those jobs to their next run
+    fn due(&self, now: DateTime<Utc>) -> Vec<(Bytes, Vec<Bytes>)> {
+        let mut handle = self.jobs.lock();
+        let mut due = Vec::new();
+        for (name, job) in handle.iter_mut() {
+            match job.next_run {
+                Some(next_run) if next_run <= now => {
+                    due.push((name.clone(), job.command.clone()));
+                    job.next_run = job.schedule.after(&now).next();
+                }
+                _ => continue,
+            }
+        }
+        due
+    }
+
+    // returns the delayed commands that are due at the given time along with
+    // the time they were due at, delayed commands only ever run once
+    fn due_delayed(&self, now: DateTime<Utc>) -> Vec<(u64, Vec<Bytes>)> {
+        let now = now.timestamp_millis() as u64;
+        let mut handle = self.delayed.lock();
+        let mut due = Vec::new();
+        let mut idx = 0;
+        while idx < handle.len() {
+            if handle[idx].run_at <= now {
+                let delayed = handle.swap_remove(idx);
+                due.push((delayed.run_at, delayed.command));
+            } else {
+                idx += 1;
+            }
+        }
+        due
+    }
+}
+
+pub async fn run(db: Arc<Db>, mut done: broadcast::Receiver<()>, wg: WaitGroup) {
+    debug!("scheduler started");
+    let mut retention = time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            _ = done.recv() => {
+                debug!("shutting down scheduler, shutdown signal received");
+                break;
+            }
+            _ = retention.tick() => {
+                let now = Utc::now().timestamp_millis() as u64;
+                db.enforce_retention(now);
+                db.purge_tombstones(now);
+            }
+            _ = time::sleep(Duration::from_millis(100)) => run_due(&db, Utc::now()).await,
+        }
+    }
+    drop(db);
+    drop(wg);
+}
+
+// runs the jobs and the delayed commands that are due at `now`. a delayed
+// command is appended once it ran followed by a `DELAY DONE`, so a replay
+// runs it where it ran and doesn't keep it delayed.
+pub async fn run_due(db: &Db, now: DateTime<Utc>) {
+    for (name, tokens) in db.scheduler().due(now) {
+        debug!("running scheduled job '{:?}'", name);
+        run_command(db, &name, tokens).await;
+    }
+    for (run_at, tokens) in db.scheduler().due_delayed(now) {
+        let name = Bytes::from_static(b"delayed");
+        debug!("running delayed command due at {}", run_at);
+        run_command(db, &name, tokens.clone()).await;
+        if db.is_appending() {
+            let mut done = vec![
+                Bytes::from_static(b"delay"),
+                Bytes::from_static(b"done"),
+                Bytes::from(run_at.to_string()),
+            ];
+            done.extend(tokens);
+            run_command(db, &name, done).await;
+        }
+    }
+}
+
+async fn run_command(db: &Db, name: &Bytes, tokens: Vec<Bytes>) {
+    let frame = Frame::Array(tokens.into_iter().map(Frame::String).collect());
+    // the command is appended as it ran, a replay doesn't schedule it again
+    let appended = db.is_appending().then(|| frame.clone());
+    let cmd = match command::parse(frame) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            error!(
+                "failed to parse command for scheduled job '{:?}', error = {}",
+                name, e
+            );
+            return;
+        }
+    };
+    if let Err(e) = db.execute_appended(cmd, appended).await {
+        error!("scheduled job '{:?}' failed, error = {}", name, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::str::FromStr;
+
+    fn add_job(scheduler: &Scheduler) {
+        scheduler
+            .add(
+                Bytes::from("foo"),
+                Schedule::from_str("* * * * * *").unwrap(),
+                vec![Bytes::from("ping")],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn add_given_existing_job_returns_job_exists_error() {
+        let scheduler = Scheduler::new();
+        add_job(&scheduler);
+        match scheduler.add(
+            Bytes::from("foo"),
+            Schedule::from_str("* * * * * *").unwrap(),
+            vec![Bytes::from("ping")],
+        ) {
+            Err(SchedulerError::JobExists(_)) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn due_given_job_before_next_run_returns_nothing() {
+        let scheduler = Scheduler::new();
+        add_job(&scheduler);
+        assert!(scheduler.due(Utc::now() - Duration::seconds(10)).is_empty());
+    }
+
+    #[test]
+    fn due_given_job_after_next_run_returns_job_command() {
+        let scheduler = Scheduler::new();
+        add_job(&scheduler);
+        assert_eq!(
+            scheduler.due(Utc::now() + Duration::seconds(10)),
+            vec![(Bytes::from("foo"), vec![Bytes::from("ping")])]
+        );
+    }
+
+    #[test]
+    fn due_delayed_given_delayed_command_returns_command_once() {
+        let scheduler = Scheduler::new();
+        scheduler.delay(1000, vec![Bytes::from("ping")]).unwrap();
+        let now = Utc::now();
+        assert_eq!(
+            scheduler.due_delayed(now),
+            vec![(1000, vec![Bytes::from("ping")])]
+        );
+        assert!(scheduler.due_delayed(now).is_empty());
+    }
+
+    #[test]
+    fn due_delayed_given_done_command_returns_nothing() {
+        let scheduler = Scheduler::new();
+        scheduler.delay(1000, vec![Bytes::from("ping")]).unwrap();
+        assert_eq!(
+            scheduler.done(1000, &[Bytes::from("ping")]).unwrap(),
+            Frame::Boolean(true)
+        );
+        assert!(scheduler.due_delayed(Utc::now()).is_empty());
+    }
+
+    #[test]
+    fn due_given_removed_job_returns_nothing() {
+        let scheduler = Scheduler::new();
+        add_job(&scheduler);
+        scheduler.remove(Bytes::from("foo")).unwrap();
+        assert!(scheduler.due(Utc::now() + Duration::seconds(10)).is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::frame::Frame;
+use bytes::Bytes;
+use mlua::{HookTriggers, Lua, Table, Value, Variadic};
+use std::cell::Cell;
+use std::rc::Rc;
+use thiserror::Error;
+
+// scripts are interrupted every this many instructions to check whether they
+// ran out of their instruction budget
+const INSTRUCTION_CHECK_INTERVAL: u32 = 1000;
+
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("script ran out of instructions")]
+    OutOfInstructions,
+
+    #[error("{0}")]
+    Lua(String),
+}
+
+// runs a lua script and converts its result to a frame. the script gets its
+// arguments in `ARGV` and can run commands through `segment.call`, which hands
+// the command tokens over to `call`. every script runs in a fresh lua state so
+// scripts can't hold on to any state between calls.
+pub fn eval(
+    script: &[u8],
+    args: &[Bytes],
+    max_instructions: u64,
+    call: impl Fn(Vec<Bytes>) -> Result<Frame, String>,
+) -> Result<Frame, ScriptError> {
+    let lua = Lua::new();
+
+    let executed = Rc::new(Cell::new(0u64));
+    let counter = executed.clone();
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(INSTRUCTION_CHECK_INTERVAL),
+        move |_, _| {
+            counter.set(counter.get() + INSTRUCTION_CHECK_INTERVAL as u64);
+            if counter.get() > max_instructions {
+                return Err(mlua::Error::RuntimeError(
+                    "script ran out of instructions".to_string(),
+                ));
+            }
+            Ok(())
+        },
+    );
+
+    let result = lua.scope(|scope| {
+        let segment = lua.create_table()?;
+        segment.set(
+            "call",
+            scope.create_function(|lua, tokens: Variadic<mlua::String>| {
+                let tokens = tokens
+                    .iter()
+                    .map(|token| Bytes::copy_from_slice(token.as_bytes()))
+                    .collect();
+                let frame = call(tokens).map_err(mlua::Error::RuntimeError)?;
+                frame_to_lua(lua, frame)
+            })?,
+        )?;
+        lua.globals().set("segment", segment)?;
+
+        let argv = lua.create_table()?;
+        for arg in args {
+            argv.push(lua.create_string(&arg[..])?)?;
+        }
+        lua.globals().set("ARGV", argv)?;
+
+        let value: Value = lua.load(script).set_name("script").eval()?;
+        lua_to_frame(value)
+    });
+
+    result.map_err(|e| {
+        if executed.get() > max_instructions {
+            ScriptError::OutOfInstructions
+        } else {
+            // the stack traceback that lua appends to errors doesn't fit in a
+            // single line error reply
+            let message = e.to_string();
+            ScriptError::Lua(message.lines().next().unwrap_or_default().to_string())
+        }
+    })
+}
+
+// errors are raised in the script, this way a failing command stops the script
+// unless it is called through `pcall`.
+fn frame_to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    Ok(match frame {
+        Frame::String(data) => Value::String(lua.create_string(&data[..])?),
+        Frame::Integer(value) => Value::Integer(value),
+        Frame::Double(value) => Value::Number(value),
+        Frame::Boolean(value) => Value::Boolean(value),
+        Frame::Null => Value::Nil,
+        Frame::Array(frames) => {
+            let table = lua.create_table()?;
+            for frame in frames {
+                table.push(frame_to_lua(lua, frame)?)?;
+            }
+            Value::Table(table)
+        }
+        Frame::Map(frames) => {
+            let table = lua.create_table()?;
+            let mut frames = frames.into_iter();
+            while let (Some(key), Some(value)) = (frames.next(), frames.next()) {
+                table.set(frame_to_lua(lua, key)?, frame_to_lua(lua, value)?)?;
+            }
+            Value::Table(table)
+        }
+        Frame::Error(message) => {
+            return Err(mlua::Error::RuntimeError(
+                String::from_utf8_lossy(&message).to_string(),
+            ))
+        }
+    })
+}
+
+// tables are returned as arrays of their sequence part, like the arguments
+fn lua_to_frame(value: Value) -> mlua::Result<Frame> {
+    Ok(match value {
+        Value::Nil => Frame::Null,
+        Value::Boolean(value) => Frame::Boolean(value),
+        Value::Integer(value) => Frame::Integer(value),
+        Value::Number(value) => Frame::Double(value),
+        Value::String(value) => Frame::String(Bytes::copy_from_slice(value.as_bytes())),
+        Value::Table(table) => table_to_frame(table)?,
+        value => {
+            return Err(mlua::Error::RuntimeError(format!(
+                "scripts can't return a {}",
+                value.type_name()
+            )))
+        }
+    })
+}
+
+fn table_to_frame(table: Table) -> mlua::Result<Frame> {
+    let mut frames = Vec::new();
+    for value in table.sequence_values::<Value>() {
+        frames.push(lua_to_frame(value?)?);
+    }
+    Ok(Frame::Array(frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_calls(_: Vec<Bytes>) -> Result<Frame, String> {
+        Err("no calls".to_string())
+    }
+
+    #[test]
+    fn eval_given_script_returns_converted_result() {
+        let args = [Bytes::from("a"), Bytes::from("b")];
+        let result = eval(
+            b"return {ARGV[2], 1, true, 1.5}",
+            &args,
+            1_000_000,
+            no_calls,
+        );
+        assert_eq!(
+            result.unwrap(),
+            Frame::Array(vec![
+                Frame::String(Bytes::from("b")),
+                Frame::Integer(1),
+                Frame::Boolean(true),
+                Frame::Double(1.5),
+            ])
+        );
+    }
+
+    #[test]
+    fn eval_given_call_passes_tokens_and_returns_result() {
+        let result = eval(
+            b"return segment.call('get', 'foo', 'bar')",
+            &[],
+            1_000_000,
+            |tokens| {
+                assert_eq!(
+                    tokens,
+                    vec![Bytes::from("get"), Bytes::from("foo"), Bytes::from("bar")]
+                );
+                Ok(Frame::String(Bytes::from("baz")))
+            },
+        );
+        assert_eq!(result.unwrap(), Frame::String(Bytes::from("baz")));
+    }
+
+    #[test]
+    fn eval_given_failing_call_returns_error() {
+        let result = eval(b"return segment.call('get')", &[], 1_000_000, no_calls);
+        assert!(matches!(result, Err(ScriptError::Lua(_))));
+    }
+
+    #[test]
+    fn eval_given_endless_loop_runs_out_of_instructions() {
+        let result = eval(b"while true do end", &[], 100_000, no_calls);
+        assert!(matches!(result, Err(ScriptError::OutOfInstructions)));
+    }
+}
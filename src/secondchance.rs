@@ -0,0 +1,79 @@
+use crate::adaptive::{Candidate, Queue};
+use bytes::Bytes;
+use std::time::Instant;
+
+// the bookkeeping of the clock evictor. keys sit on a single ring in the order
+// they were written, reads only set the referenced bit of the value. the hand
+// of the clock goes round the ring, a key whose bit is set gets a second
+// chance, the bit is cleared and the key goes to the back of the ring, the
+// first key without it is evicted.
+#[derive(Debug, Default)]
+pub struct SecondChance {
+    ring: Queue,
+}
+
+impl SecondChance {
+    // a new key was written, keys written again are left where they are
+    pub fn insert(&mut self, key: &Bytes, now: Instant) {
+        if !self.ring.contains(key) {
+            self.ring.push(key.clone(), now);
+        }
+    }
+
+    // a key was removed other than by eviction, like a deleted key
+    pub fn remove(&mut self, key: &Bytes) {
+        self.ring.remove(key);
+    }
+
+    // picks the key to evict, `check` tells what to do with the key under the
+    // hand and clears its referenced bit. returns `None` when every key was
+    // kept.
+    pub fn evict(
+        &mut self,
+        mut check: impl FnMut(&Bytes, Instant) -> Candidate,
+        now: Instant,
+    ) -> Option<Bytes> {
+        // one round clears every bit, the next finds a key without it
+        for _ in 0..=2 * self.ring.len() {
+            let (key, queued_at) = self.ring.pop()?;
+            match check(&key, queued_at) {
+                Candidate::Missing => {}
+                Candidate::Kept | Candidate::Referenced | Candidate::Accessed(_) => {
+                    self.ring.push(key, now)
+                }
+                Candidate::Cold => return Some(key),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evict_given_referenced_key_gives_it_a_second_chance() {
+        let mut clock = SecondChance::default();
+        for key in ["a", "b"] {
+            clock.insert(&Bytes::from(key), Instant::now());
+        }
+        let mut referenced = vec![Bytes::from("a")];
+        let evicted = clock.evict(
+            |key, _| match referenced.iter().position(|r| r == key) {
+                Some(i) => {
+                    referenced.remove(i);
+                    Candidate::Referenced
+                }
+                None => Candidate::Cold,
+            },
+            Instant::now(),
+        );
+        assert_eq!(evicted, Some(Bytes::from("b")));
+        assert_eq!(
+            clock.evict(|_, _| Candidate::Cold, Instant::now()),
+            Some(Bytes::from("a"))
+        );
+        assert_eq!(clock.evict(|_, _| Candidate::Cold, Instant::now()), None);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::adaptive::{Candidate, Queue};
+use bytes::Bytes;
+use std::time::Instant;
+
+// the share of the keys the protected segment holds at most, past it the
+// oldest protected keys go back on probation
+const PROTECTED_PERCENT: usize = 80;
+
+// the bookkeeping of the slru evictor, a segmented lru. written keys start out
+// on probation and are only protected once they are accessed again, so a scan
+// which reads every key once can only push out other keys on probation. like
+// the arc evictor, accesses are checked once a key reaches the front of its
+// segment instead of moving the key on every access.
+#[derive(Debug, Default)]
+pub struct Segmented {
+    probation: Queue,
+    protected: Queue,
+}
+
+impl Segmented {
+    // a new key was written, keys written again are left where they are
+    pub fn insert(&mut self, key: &Bytes, now: Instant) {
+        if self.probation.contains(key) || self.protected.contains(key) {
+            return;
+        }
+        self.probation.push(key.clone(), now);
+    }
+
+    // a key was removed other than by eviction, like a deleted key
+    pub fn remove(&mut self, key: &Bytes) {
+        if !self.probation.remove(key) {
+            self.protected.remove(key);
+        }
+    }
+
+    // picks the key to evict, `check` tells what to do with the keys at the
+    // front of the segments. keys on probation are evicted first, protected
+    // keys only once there are none left. returns `None` when every key was
+    // kept.
+    pub fn evict(
+        &mut self,
+        mut check: impl FnMut(&Bytes, Instant) -> Candidate,
+        now: Instant,
+    ) -> Option<Bytes> {
+        // every key gets looked at at most twice, once to find out it was
+        // referenced and once more after it moved
+        for _ in 0..=2 * self.len() {
+            let on_probation = !self.probation.is_empty();
+            let segment = if on_probation {
+                &mut self.probation
+            } else {
+                &mut self.protected
+            };
+            let (key, queued_at) = segment.pop()?;
+            match check(&key, queued_at) {
+                Candidate::Missing => {}
+                Candidate::Kept => segment.push(key, now),
+                Candidate::Referenced | Candidate::Accessed(_) => {
+                    self.protected.push(key, now);
+                    if self.protected.len() > self.len() * PROTECTED_PERCENT / 100 {
+                        if let Some((demoted, _)) = self.protected.pop() {
+                            self.probation.push(demoted, now);
+                        }
+                    }
+                }
+                Candidate::Cold => return Some(key),
+            }
+        }
+        None
+    }
+
+    // the number of keys held
+    pub fn len(&self) -> usize {
+        self.probation.len() + self.protected.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evict_given_scanned_keys_keeps_protected_keys() {
+        let mut segmented = Segmented::default();
+        for key in ["a", "b", "c", "d", "e"] {
+            segmented.insert(&Bytes::from(key), Instant::now());
+        }
+        // `a` was read again, the rest were only written
+        let evicted = segmented.evict(
+            |key, _| match &key[..] {
+                b"a" => Candidate::Referenced,
+                _ => Candidate::Cold,
+            },
+            Instant::now(),
+        );
+        assert_eq!(evicted, Some(Bytes::from("b")));
+        assert!(segmented.protected.contains(&Bytes::from("a")));
+
+        let mut evicted = Vec::new();
+        while let Some(key) = segmented.evict(|_, _| Candidate::Cold, Instant::now()) {
+            evicted.push(key);
+        }
+        assert_eq!(
+            evicted,
+            vec![
+                Bytes::from("c"),
+                Bytes::from("d"),
+                Bytes::from("e"),
+                Bytes::from("a")
+            ]
+        );
+    }
+}
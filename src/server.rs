@@ -1,16 +1,51 @@
-use crate::command;
+use crate::allocator;
+use crate::aof::{self, Aof, AofReader};
+use crate::command::{self, Command, Use};
 use crate::config::ServerConfig;
 use crate::connection::Connection;
-use crate::db::Db;
+use crate::coordinator;
+use crate::db::{Db, ExecuteCommandError, WatchedVersion};
+use crate::frame::Frame;
+#[cfg(feature = "grpc")]
+use crate::grpc;
+#[cfg(feature = "http")]
+use crate::http;
+use crate::lifecycle::LifecycleEvent;
+#[cfg(feature = "memcached")]
+use crate::memcached;
+use crate::mirror::Mirror;
+#[cfg(feature = "quic")]
+use crate::quic;
+use crate::ratelimit::TokenBucket;
+use crate::scheduler;
+use crate::snapshot::SnapshotReader;
+use crate::trace::KeyEvent;
+#[cfg(feature = "websocket")]
+use crate::websocket;
 use anyhow::Result;
+use bytes::Bytes;
 use crossbeam::sync::WaitGroup;
+use std::future;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str;
 use std::sync::Arc;
 use std::time::Duration;
 use sysinfo::{Pid, ProcessExt, System, SystemExt};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream};
+use tokio::net::TcpListener;
 use tokio::signal;
-use tokio::sync::broadcast;
-use tracing::{debug, error, info};
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+use tokio::sync::mpsc;
+use tokio::time;
+use tracing::{debug, error, info, warn};
+
+// how long a connection gets to write out its replies once the server shuts
+// down
+const SHUTDOWN_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
 
 struct Server {
     ln: TcpListener,
@@ -18,37 +53,106 @@ struct Server {
     wg: WaitGroup,
     db: Arc<Db>,
     done_tx: broadcast::Sender<()>,
-    evict_tx: broadcast::Sender<()>,
+    evict_tx: broadcast::Sender<usize>,
+    mirror: Option<Arc<Mirror>>,
+    // the snapshot loaded while the server is warming up, see `start_warmup`
+    snapshot: Option<SnapshotReader>,
 }
 
-struct ConnectionHandler {
-    connection: Connection<TcpStream>,
+// handles the commands of a connection, tcp connections are handled right
+// away and other transports, like websockets, are bridged onto a stream
+struct ConnectionHandler<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    id: u64,
+    address: SocketAddr,
+    connection: Connection<T>,
     done: broadcast::Receiver<()>,
     db: Arc<Db>,
+    watching: Option<Watching>,
+    limiter: Option<TokenBucket>,
+    mirror: Option<Arc<Mirror>>,
+    watched: Vec<WatchedVersion>,
+    // commands sent after `MULTI`, they are executed together on `EXEC`
+    queued: Option<Vec<Command>>,
+    // number of commands received, see `CLIENT INFO`
+    commands: u64,
+    last_command: Option<&'static str>,
+    // the keyspace `GET`, `SET` and `DEL` work on, see `USE`
+    keyspace: Option<Bytes>,
+    // the frames of the queued commands, `None` for the ones which aren't
+    // appended, see `Db::execute_transaction_appended`
+    queued_appended: Vec<Option<Frame>>,
+}
+
+// what the connection streams events of, see `WATCHKEY` and `WATCHLIFECYCLE`
+enum Watching {
+    Key {
+        keyspace: Bytes,
+        key: Bytes,
+        events: broadcast::Receiver<KeyEvent>,
+    },
+    Lifecycle(broadcast::Receiver<(LifecycleEvent, u128)>),
 }
 
 pub async fn start(ln: TcpListener, cfg: ServerConfig) -> Result<()> {
-    let srv = Server::new(ln, cfg);
+    let srv = Server::new(ln, cfg)?;
     srv.start().await
 }
 
 impl Server {
-    pub fn new(ln: TcpListener, cfg: ServerConfig) -> Self {
+    pub fn new(ln: TcpListener, cfg: ServerConfig) -> Result<Self> {
         let wg = WaitGroup::new();
         let (done_tx, _) = broadcast::channel(1);
         let (evict_tx, _) = broadcast::channel(1);
-        let db = Db::new(done_tx.subscribe(), wg.clone(), evict_tx.subscribe());
-        Server {
+        let db = Db::new(&cfg, done_tx.subscribe(), wg.clone());
+        // the keyspaces are created along with their evictors before the
+        // server accepts any connection
+        let mut snapshot = None;
+        if cfg.load_snapshot() && cfg.appendonly() {
+            warn!("the append only file is replayed instead of loading the snapshot");
+        } else if cfg.load_snapshot() {
+            match db.open_snapshot()? {
+                Some(reader) if cfg.warmup_percent() > 0 => snapshot = Some(reader),
+                Some(reader) => info!(
+                    "loaded {} keys from the snapshot in '{}'",
+                    db.load_snapshot(reader, |_| {})?,
+                    cfg.data_dir()
+                ),
+                None => info!("no snapshot to load in '{}'", cfg.data_dir()),
+            }
+        }
+        let mirror = if cfg.mirror().is_empty() {
+            None
+        } else {
+            Some(Arc::new(Mirror::start(
+                cfg.mirror().to_string(),
+                cfg.mirror_sample_percent(),
+                db.stats(),
+            )))
+        };
+        Ok(Server {
             ln,
             cfg,
             wg,
             done_tx,
             db: Arc::new(db),
             evict_tx,
-        }
+            mirror,
+            snapshot,
+        })
     }
 
-    pub async fn start(self) -> Result<()> {
+    pub async fn start(mut self) -> Result<()> {
+        self.db.publish_lifecycle_event(LifecycleEvent::Started);
+        self.db.publish_lifecycle_event(LifecycleEvent::Loading);
+        self.load_plugins().await?;
+        self.start_appending().await?;
+        match self.snapshot.take() {
+            Some(reader) => self.start_warmup(reader),
+            None => self.db.publish_lifecycle_event(LifecycleEvent::Ready),
+        }
         info!(
             "server started on port {}:{}",
             self.cfg.bind(),
@@ -56,13 +160,28 @@ impl Server {
         );
         let monitor_wg = self.wg.clone();
         let mut monitor_done_rx = self.done_tx.subscribe();
+        // subscribed before the monitor starts so no evict event is missed
+        tokio::spawn(coordinator::run(
+            self.db.clone(),
+            self.evict_tx.subscribe(),
+            self.done_tx.subscribe(),
+            self.wg.clone(),
+        ));
         let monitor_evict_tx = self.evict_tx.clone();
         let server_max_memory = self.cfg.max_memory();
+        let eviction_batch_size = self.cfg.eviction_batch_size();
+        let high_watermark = server_max_memory * self.cfg.eviction_high_watermark() as u64 / 100;
+        let low_watermark = server_max_memory * self.cfg.eviction_low_watermark() as u64 / 100;
+        let monitor_stats = self.db.stats();
+        let monitor_db = self.db.clone();
         // FIXME: move this to a separate fn
         tokio::spawn(async move {
             let pid = std::process::id() as i32;
             let mut monitor = System::new();
             monitor.refresh_process(Pid::from(pid));
+            // whether memory went over the high watermark and didn't get
+            // under the low watermark yet
+            let mut evicting = false;
             loop {
                 tokio::select! {
                     _ = monitor_done_rx.recv() => {
@@ -70,39 +189,72 @@ impl Server {
                         break;
                     }
                     _ = tokio::time::sleep(Duration::from_millis(1000)) => {
-                        monitor.refresh_process(Pid::from(pid));
-                        if let Some(process) = monitor.process(Pid::from(pid)) {
-                            let memory = process.memory();
-                            if memory >= server_max_memory && server_max_memory > 0 {
-                                debug!("broadcasting evict event, server max memory (bytes) = {}, current memory usage (bytes) = {}", server_max_memory, memory);
-                                if let Err(err) = monitor_evict_tx.send(()) {
-                                    error!("no listeners available for max memory eviction event, error = {:?}", err);
-                                }
+                        // the statistics of the allocator are preferred over
+                        // the resident memory the system reports
+                        if let Some(allocated) = allocator::allocated() {
+                            monitor_stats.set_memory(allocated);
+                        } else {
+                            monitor.refresh_process(Pid::from(pid));
+                            match monitor.process(Pid::from(pid)) {
+                                Some(process) => monitor_stats.set_memory(process.memory()),
+                                None => error!("no process found with pid {}, used memory will not be reported", pid),
+                            }
+                        }
+                        // evictions are decided on the bytes held by the
+                        // keyspaces rather than the memory of the process,
+                        // which also counts allocator overhead and buffers
+                        let data_memory = monitor_db.memory();
+                        monitor_stats.set_data_memory(data_memory);
+                        if server_max_memory > 0 && !evicting && data_memory >= high_watermark {
+                            debug!("starting eviction, high watermark (bytes) = {}, current data memory (bytes) = {}", high_watermark, data_memory);
+                            evicting = true;
+                        } else if evicting && data_memory < low_watermark {
+                            debug!("stopping eviction, low watermark (bytes) = {}, current data memory (bytes) = {}", low_watermark, data_memory);
+                            evicting = false;
+                        }
+                        if evicting {
+                            debug!("broadcasting evict event, server max memory (bytes) = {}, current data memory (bytes) = {}", server_max_memory, data_memory);
+                            if let Err(err) = monitor_evict_tx.send(eviction_batch_size) {
+                                error!("no listeners available for max memory eviction event, error = {:?}", err);
                             }
-                        }else {
-                            error!("no process found with pid {}, max memory evictors will not work", pid);
-                            break;
                         }
                     }
                 }
             }
             drop(monitor_wg)
         });
+        tokio::spawn(scheduler::run(
+            self.db.clone(),
+            self.done_tx.subscribe(),
+            self.wg.clone(),
+        ));
+        self.start_grpc().await?;
+        self.start_http().await?;
+        self.start_memcached().await?;
+        // connections of other transports, bridged onto in memory streams
+        let (bridged_tx, mut bridged_rx) = mpsc::channel(16);
+        self.start_websocket(bridged_tx.clone()).await?;
+        self.start_quic(bridged_tx).await?;
+        let mut next_connection_id = 0;
         loop {
             tokio::select! {
                 maybe_connection = self.ln.accept() => {
-                    let (stream, _) = maybe_connection?;
-                    let mut handler = ConnectionHandler::new(self.done_tx.subscribe(), stream, self.cfg.connection_buffer_size(), self.db.clone());
-                    let wg = self.wg.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = handler.handle().await {
-                            error!("{}", e)
-                        }
-                        drop(wg);
-                    });
+                    let (stream, address) = maybe_connection?;
+                    if !self.cfg.net_filter().is_allowed(address.ip()) {
+                        debug!("rejecting connection from {}, peer is not allowed", address);
+                        drop(stream);
+                        continue;
+                    }
+                    next_connection_id += 1;
+                    self.spawn_connection(next_connection_id, address, stream);
+                }
+                Some((stream, address)) = bridged_rx.recv() => {
+                    next_connection_id += 1;
+                    self.spawn_connection(next_connection_id, address, stream);
                 }
                  _ = signal::ctrl_c() => {
                     info!("shutdown signal received");
+                    self.db.publish_lifecycle_event(LifecycleEvent::ShuttingDown);
                     drop(self.ln);
                     drop(self.done_tx);
                     break;
@@ -110,35 +262,334 @@ impl Server {
             }
         }
         drop(self.db);
-        self.wg.wait();
+        // waiting blocks the thread, other servers may be running on the same
+        // runtime
+        let wg = self.wg;
+        tokio::task::spawn_blocking(move || wg.wait()).await?;
         info!("shutdown complete, bye bye :)");
         Ok(())
     }
+
+    // loads the snapshot while the server is warming up, so it takes
+    // connections right away but only serves reads once `warmup_percent` of
+    // the snapshot is loaded. a snapshot that fails to load part way keeps
+    // the server warming.
+    fn start_warmup(&self, reader: SnapshotReader) {
+        self.db.publish_lifecycle_event(LifecycleEvent::Warming);
+        let db = self.db.clone();
+        let warmup_percent = self.cfg.warmup_percent();
+        let data_dir = self.cfg.data_dir().to_string();
+        tokio::task::spawn_blocking(move || {
+            let ready = |db: &Db| {
+                if db.is_warming() {
+                    db.publish_lifecycle_event(LifecycleEvent::Ready);
+                }
+            };
+            let loaded = db.load_snapshot(reader, |read| {
+                if read >= warmup_percent {
+                    ready(&db);
+                }
+            });
+            match loaded {
+                Ok(keys) => info!("loaded {} keys from the snapshot in '{}'", keys, data_dir),
+                Err(e) => error!(
+                    "failed to load the snapshot in '{}', the server stays warming: {}",
+                    data_dir, e
+                ),
+            }
+        });
+    }
+
+    #[cfg(feature = "grpc")]
+    async fn start_grpc(&self) -> Result<()> {
+        if self.cfg.grpc_port() == 0 {
+            return Ok(());
+        }
+        let addr = format!("{}:{}", self.cfg.bind(), self.cfg.grpc_port());
+        let ln = TcpListener::bind(&addr).await?;
+        info!("grpc gateway started on {}", addr);
+        tokio::spawn(grpc::serve(
+            ln,
+            self.db.clone(),
+            self.cfg.net_filter().clone(),
+            self.done_tx.subscribe(),
+            self.wg.clone(),
+        ));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "grpc"))]
+    async fn start_grpc(&self) -> Result<()> {
+        if self.cfg.grpc_port() > 0 {
+            tracing::warn!("grpc_port is set but segment is built without the grpc feature, the gateway will not be started");
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "http")]
+    async fn start_http(&self) -> Result<()> {
+        if self.cfg.http_port() == 0 {
+            return Ok(());
+        }
+        let addr = format!("{}:{}", self.cfg.bind(), self.cfg.http_port());
+        let ln = TcpListener::bind(&addr).await?;
+        info!("http gateway started on {}", addr);
+        tokio::spawn(http::serve(
+            ln,
+            self.db.clone(),
+            self.cfg.net_filter().clone(),
+            self.done_tx.subscribe(),
+            self.wg.clone(),
+        ));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "http"))]
+    async fn start_http(&self) -> Result<()> {
+        if self.cfg.http_port() > 0 {
+            tracing::warn!("http_port is set but segment is built without the http feature, the gateway will not be started");
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "quic")]
+    async fn start_quic(&self, bridged: mpsc::Sender<(DuplexStream, SocketAddr)>) -> Result<()> {
+        if self.cfg.quic_port() == 0 {
+            return Ok(());
+        }
+        use anyhow::Context;
+        let addr = format!("{}:{}", self.cfg.bind(), self.cfg.quic_port());
+        let addr = tokio::net::lookup_host(&addr)
+            .await?
+            .next()
+            .with_context(|| format!("unable to resolve quic address {}", addr))?;
+        let endpoint = quic::bind(addr, self.cfg.quic_cert_file(), self.cfg.quic_key_file())?;
+        info!("experimental quic listener started on {}", addr);
+        tokio::spawn(quic::serve(
+            endpoint,
+            self.cfg.net_filter().clone(),
+            self.cfg.connection_buffer_size(),
+            bridged,
+            self.done_tx.subscribe(),
+            self.wg.clone(),
+        ));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "quic"))]
+    async fn start_quic(&self, _bridged: mpsc::Sender<(DuplexStream, SocketAddr)>) -> Result<()> {
+        if self.cfg.quic_port() > 0 {
+            tracing::warn!("quic_port is set but segment is built without the quic feature, the listener will not be started");
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "memcached")]
+    async fn start_memcached(&self) -> Result<()> {
+        if self.cfg.memcached_port() == 0 {
+            return Ok(());
+        }
+        let addr = format!("{}:{}", self.cfg.bind(), self.cfg.memcached_port());
+        let ln = TcpListener::bind(&addr).await?;
+        info!(
+            "memcached listener started on {}, keyspace = {}",
+            addr,
+            self.cfg.memcached_keyspace()
+        );
+        tokio::spawn(memcached::serve(
+            ln,
+            self.db.clone(),
+            Bytes::from(self.cfg.memcached_keyspace().to_string()),
+            self.cfg.net_filter().clone(),
+            self.done_tx.subscribe(),
+            self.wg.clone(),
+        ));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "memcached"))]
+    async fn start_memcached(&self) -> Result<()> {
+        if self.cfg.memcached_port() > 0 {
+            tracing::warn!("memcached_port is set but segment is built without the memcached feature, the listener will not be started");
+        }
+        Ok(())
+    }
+
+    // starts handling the commands of a new connection
+    fn spawn_connection<T>(&self, id: u64, address: SocketAddr, stream: T)
+    where
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let limiter = if self.cfg.rate_limit() > 0 {
+            Some(TokenBucket::new(
+                self.cfg.rate_limit(),
+                self.cfg.rate_limit_burst(),
+            ))
+        } else {
+            None
+        };
+        let connection = Connection::new(stream, self.cfg.connection_buffer_size())
+            .with_output_limits(
+                self.cfg.output_buffer_soft_limit(),
+                self.cfg.output_buffer_hard_limit(),
+            )
+            .with_chunk_size(self.cfg.chunk_size());
+        let mut handler = ConnectionHandler::new(
+            id,
+            address,
+            self.done_tx.subscribe(),
+            connection,
+            self.db.clone(),
+            limiter,
+            self.mirror.clone(),
+        );
+        let wg = self.wg.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handler.handle().await {
+                error!("{}", e)
+            }
+            drop(wg);
+        });
+    }
+
+    #[cfg(feature = "websocket")]
+    async fn start_websocket(
+        &self,
+        bridged: mpsc::Sender<(DuplexStream, SocketAddr)>,
+    ) -> Result<()> {
+        if self.cfg.websocket_port() == 0 {
+            return Ok(());
+        }
+        let addr = format!("{}:{}", self.cfg.bind(), self.cfg.websocket_port());
+        let ln = TcpListener::bind(&addr).await?;
+        info!("websocket listener started on {}", addr);
+        tokio::spawn(websocket::serve(
+            ln,
+            self.cfg.net_filter().clone(),
+            self.cfg.connection_buffer_size(),
+            bridged,
+            self.done_tx.subscribe(),
+            self.wg.clone(),
+        ));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "websocket"))]
+    async fn start_websocket(
+        &self,
+        _bridged: mpsc::Sender<(DuplexStream, SocketAddr)>,
+    ) -> Result<()> {
+        if self.cfg.websocket_port() > 0 {
+            tracing::warn!("websocket_port is set but segment is built without the websocket feature, the listener will not be started");
+        }
+        Ok(())
+    }
+
+    // replays the append only file, the writes run from then on are appended
+    // to it
+    async fn start_appending(&self) -> Result<()> {
+        if !self.cfg.appendonly() {
+            return Ok(());
+        }
+        let path = Path::new(self.cfg.data_dir()).join(aof::FILE_NAME);
+        if let Some(mut reader) = AofReader::open(&path)? {
+            let replayed = self.db.replay(&mut reader).await?;
+            let cut = reader.finish()?;
+            if cut > 0 {
+                warn!(
+                    "dropped {} bytes of a command cut short at the end of '{}'",
+                    cut,
+                    path.display()
+                );
+            }
+            info!("replayed {} commands from '{}'", replayed, path.display());
+        }
+        let aof = Aof::open(
+            &path,
+            self.cfg.appendfsync(),
+            self.done_tx.subscribe(),
+            self.wg.clone(),
+        )
+        .await?;
+        self.db.start_appending(aof);
+        Ok(())
+    }
+
+    #[cfg(feature = "wasm")]
+    async fn load_plugins(&self) -> Result<()> {
+        let commands = self.db.load_plugins(self.cfg.plugins()).await?;
+        if !commands.is_empty() {
+            info!("registered plugin commands {:?}", commands);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    async fn load_plugins(&self) -> Result<()> {
+        if !self.cfg.plugins().is_empty() {
+            tracing::warn!("plugins are configured but segment is built without the wasm feature, they will not be loaded");
+        }
+        Ok(())
+    }
 }
 
-impl ConnectionHandler {
+impl<T> ConnectionHandler<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
     pub fn new(
+        id: u64,
+        address: SocketAddr,
         done: broadcast::Receiver<()>,
-        stream: TcpStream,
-        connection_buf_size: usize,
+        connection: Connection<T>,
         db: Arc<Db>,
+        limiter: Option<TokenBucket>,
+        mirror: Option<Arc<Mirror>>,
     ) -> Self {
-        let connection = Connection::new(stream, connection_buf_size);
         ConnectionHandler {
+            id,
+            address,
             connection,
             done,
             db,
+            watching: None,
+            limiter,
+            mirror,
+            watched: Vec::new(),
+            queued: None,
+            commands: 0,
+            last_command: None,
+            keyspace: None,
+            queued_appended: Vec::new(),
         }
     }
 
     pub async fn handle(&mut self) -> Result<()> {
+        let stats = self.db.stats();
+        stats.connection_opened();
+        let result = self.run().await;
+        self.db.drop_owned_keyspaces(self.id);
+        stats.connection_closed();
+        result
+    }
+
+    async fn run(&mut self) -> Result<()> {
         debug!("new connection started");
         loop {
+            // replies are queued and written out while waiting for the next
+            // command, key events wait while the client is behind on reading.
+            // the branches are safe to cancel, so the shutdown signal can't
+            // cut a reply short.
+            let paused = self.connection.is_output_paused();
             let maybe_frame = tokio::select! {
                 _ = self.done.recv() => {
                     break;
                 }
                 res = self.connection.read_frame() => res?,
+                event = next_event(&mut self.watching), if !paused => {
+                    self.connection.queue_frame(&event)?;
+                    continue;
+                }
             };
 
             let frame = match maybe_frame {
@@ -146,10 +597,31 @@ impl ConnectionHandler {
                 None => return Ok(()),
             };
 
+            // sending any command ends the watch mode
+            self.watching = None;
+
+            if let Some(limiter) = self.limiter.as_mut() {
+                if !limiter.try_acquire() {
+                    self.db.stats().record_throttled();
+                    self.connection
+                        .queue_frame(&Frame::Error(Bytes::from_static(
+                            b"ERRTHROTTLED too many commands, slow down",
+                        )))?;
+                    continue;
+                }
+            }
+
+            let frame = match &self.keyspace {
+                Some(keyspace) => command::in_keyspace(frame, keyspace),
+                None => frame,
+            };
+            let mirrored = self.mirror.as_ref().map(|_| frame.clone());
+            let appended = self.db.is_appending().then(|| frame.clone());
             let maybe_cmd = match command::parse(frame) {
                 Ok(cmd) => Some(cmd),
                 Err(e) => {
-                    self.connection.write_error(e).await?;
+                    self.connection
+                        .queue_frame(&Frame::Error(Bytes::from(format!("ERRPARSE {}", e))))?;
                     None
                 }
             };
@@ -158,21 +630,378 @@ impl ConnectionHandler {
                 Some(cmd) => cmd,
                 None => continue,
             };
+            self.commands += 1;
+            let last_command = self.last_command.replace(cmd.name());
+
+            // answered right away so the reply describes the command sent
+            // before this one
+            if let (Command::ClientInfo, None) = (&cmd, &self.queued) {
+                let info = self.client_info(last_command);
+                self.connection.queue_frame(&info)?;
+                continue;
+            }
+
+            if let Some(queued) = self.queued.as_mut() {
+                match cmd {
+                    Command::Exec | Command::Discard => {}
+                    Command::Multi
+                    | Command::Watch(_)
+                    | Command::WatchKey(_)
+                    | Command::WatchLifecycle
+                    | Command::Use(_)
+                    | Command::ClientInfo
+                    | Command::DebugProtocol(_) => {
+                        self.connection.queue_error(
+                            ExecuteCommandError::NotAllowedInTransaction(cmd.name().to_string()),
+                        )?;
+                        continue;
+                    }
+                    cmd => {
+                        self.queued_appended
+                            .push(appended.filter(|_| cmd.is_appended()));
+                        queued.push(cmd);
+                        self.connection
+                            .queue_frame(&Frame::String(Bytes::from_static(b"QUEUED")))?;
+                        continue;
+                    }
+                }
+            }
 
-            let maybe_result = match self.db.execute(cmd).await {
+            // while the server is warming up reads are refused, so clients
+            // don't read from a cold cache while the snapshot loads. pings
+            // fail too, which keeps the server out of rotation.
+            if self.db.is_warming() && is_refused_while_warming(&cmd) {
+                self.connection
+                    .queue_frame(&Frame::Error(Bytes::from_static(
+                        b"ERRWARMING server is warming up",
+                    )))?;
+                continue;
+            }
+
+            // pings are used for health checks, so they are answered right
+            // away without going through the db.
+            if let Command::Ping(ping) = &cmd {
+                self.connection.queue_frame(&Frame::String(ping.reply()))?;
+                continue;
+            }
+
+            if let (Some(mirror), Some(frame)) = (&self.mirror, mirrored) {
+                if is_mirrored(&cmd) {
+                    mirror.offer(frame);
+                }
+            }
+
+            let maybe_result = match self.execute(cmd, appended).await {
                 Ok(frame) => Some(frame),
                 Err(e) => {
-                    self.connection.write_error(e).await?;
+                    self.connection.queue_error(e)?;
                     None
                 }
             };
 
             match maybe_result {
-                Some(frame) => self.connection.write_frame(&frame).await?,
+                Some(frame) => self.connection.queue_frame(&frame)?,
 
                 None => continue,
             }
         }
+
+        // the shutdown is announced before the connections are told to stop,
+        // so lifecycle watchers get to see it
+        if let Some(Watching::Lifecycle(events)) = self.watching.as_mut() {
+            loop {
+                match events.try_recv() {
+                    Ok((event, timestamp)) => {
+                        self.connection.queue_frame(&event.to_frame(timestamp))?
+                    }
+                    Err(TryRecvError::Lagged(_)) => continue,
+                    Err(_) => break,
+                }
+            }
+        }
+
+        // replies queued before the shutdown are still sent, unless the client
+        // doesn't take them in time
+        let close = time::timeout(SHUTDOWN_WRITE_TIMEOUT, self.connection.close()).await;
+        match close {
+            Ok(res) => res?,
+            Err(_) => debug!(
+                "dropping {} bytes of output, the client didn't read them before the shutdown",
+                self.connection.pending_output()
+            ),
+        }
         Ok(())
     }
+
+    // `appended` is the frame the command was parsed from while the append
+    // only file is on
+    async fn execute(
+        &mut self,
+        cmd: Command,
+        appended: Option<Frame>,
+    ) -> Result<Frame, ExecuteCommandError> {
+        let keys: Vec<(&'static str, Bytes, Bytes)> = if !self.db.is_tracing_keys() {
+            Vec::new()
+        } else if let (Command::Exec, Some(queued)) = (&cmd, &self.queued) {
+            queued
+                .iter()
+                .flat_map(|cmd| {
+                    let name = cmd.name();
+                    cmd.keys()
+                        .into_iter()
+                        .map(move |(keyspace, key)| (name, keyspace, key))
+                })
+                .collect()
+        } else {
+            let name = cmd.name();
+            cmd.keys()
+                .into_iter()
+                .map(|(keyspace, key)| (name, keyspace, key))
+                .collect()
+        };
+
+        let is_exec = matches!(cmd, Command::Exec);
+
+        let result = match cmd {
+            Command::Create(cmd) if cmd.temp() => self.db.create_temp_keyspace(&cmd, self.id).await,
+            Command::WatchKey(cmd) => {
+                self.watching = Some(Watching::Key {
+                    keyspace: cmd.keyspace(),
+                    key: cmd.key(),
+                    events: self.db.subscribe_key_events(),
+                });
+                Ok(Frame::Boolean(true))
+            }
+            // the reply is the current state, so watchers don't miss the
+            // events published before they started watching
+            Command::WatchLifecycle => {
+                self.watching = Some(Watching::Lifecycle(self.db.subscribe_lifecycle_events()));
+                Ok(Frame::String(Bytes::from_static(
+                    self.db.state().as_bytes(),
+                )))
+            }
+            Command::Use(cmd) => self.use_keyspace(&cmd),
+            Command::DebugProtocol(cmd) => {
+                self.connection.set_trace(cmd.enabled().then_some(self.id));
+                Ok(Frame::Boolean(true))
+            }
+            Command::Watch(cmd) => self.db.watch(&cmd).map(|watched| {
+                self.watched.push(watched);
+                Frame::Boolean(true)
+            }),
+            Command::Unwatch => {
+                self.watched.clear();
+                Ok(Frame::Boolean(true))
+            }
+            Command::Multi => {
+                self.queued = Some(Vec::new());
+                self.queued_appended.clear();
+                Ok(Frame::Boolean(true))
+            }
+            Command::Exec => match self.queued.take() {
+                Some(queued) => {
+                    let watched = std::mem::take(&mut self.watched);
+                    let appended = std::mem::take(&mut self.queued_appended);
+                    self.db
+                        .execute_transaction_appended(&watched, queued, appended)
+                        .await
+                }
+                None => Err(ExecuteCommandError::TransactionRequired("exec".to_string())),
+            },
+            Command::Discard => match self.queued.take() {
+                Some(_) => {
+                    self.watched.clear();
+                    self.queued_appended.clear();
+                    Ok(Frame::Boolean(true))
+                }
+                None => Err(ExecuteCommandError::TransactionRequired(
+                    "discard".to_string(),
+                )),
+            },
+            cmd => self.db.execute_appended(cmd, appended).await,
+        };
+
+        // an aborted transaction didn't touch any of its keys
+        let keys = if is_exec && matches!(result, Ok(Frame::Null)) {
+            Vec::new()
+        } else {
+            keys
+        };
+        for (name, keyspace, key) in keys {
+            self.db.publish_key_event(KeyEvent::new(
+                self.id,
+                self.address,
+                name,
+                keyspace,
+                key,
+                result.is_ok(),
+            ));
+        }
+
+        result
+    }
+
+    // `queue_depth` is the number of commands the client sent after this one
+    // which are waiting to be read, it stays at 0 unless commands are
+    // pipelined. the bytes written don't include the reply to this command,
+    // `output_buffer` is the number of bytes of replies the client hasn't read
+    // yet.
+    fn client_info(&self, last_command: Option<&'static str>) -> Frame {
+        Frame::Map(vec![
+            Frame::String(Bytes::from_static(b"id")),
+            Frame::Integer(self.id as i64),
+            Frame::String(Bytes::from_static(b"address")),
+            Frame::String(Bytes::from(self.address.to_string())),
+            Frame::String(Bytes::from_static(b"commands")),
+            Frame::Integer(self.commands as i64),
+            Frame::String(Bytes::from_static(b"last_command")),
+            match last_command {
+                Some(name) => Frame::String(Bytes::from_static(name.as_bytes())),
+                None => Frame::Null,
+            },
+            Frame::String(Bytes::from_static(b"bytes_in")),
+            Frame::Integer(self.connection.bytes_read() as i64),
+            Frame::String(Bytes::from_static(b"bytes_out")),
+            Frame::Integer(self.connection.bytes_written() as i64),
+            Frame::String(Bytes::from_static(b"queue_depth")),
+            Frame::Integer(self.connection.buffered_frames() as i64),
+            Frame::String(Bytes::from_static(b"output_buffer")),
+            Frame::Integer(self.connection.pending_output() as i64),
+            Frame::String(Bytes::from_static(b"keyspace")),
+            match &self.keyspace {
+                Some(keyspace) => Frame::String(keyspace.clone()),
+                None => Frame::Null,
+            },
+        ])
+    }
+
+    // the keyspace has to exist when it is picked, it can still be dropped
+    // later on, the commands sent after that fail like they would otherwise.
+    fn use_keyspace(&mut self, cmd: &Use) -> Result<Frame, ExecuteCommandError> {
+        if let Some(keyspace) = cmd.keyspace() {
+            if self.db.keyspace(&keyspace).is_none() {
+                return Err(ExecuteCommandError::KeyspaceDoesNotExist(
+                    str::from_utf8(&keyspace[..])?.to_string(),
+                ));
+            }
+        }
+        self.keyspace = cmd.keyspace();
+        Ok(Frame::Boolean(true))
+    }
+}
+
+// waits for the next event of the watched key or of the server lifecycle, if
+// nothing is being watched this never resolves.
+async fn next_event(watching: &mut Option<Watching>) -> Frame {
+    loop {
+        let res = match watching {
+            Some(Watching::Key {
+                keyspace,
+                key,
+                events,
+            }) => match events.recv().await {
+                Ok(event) if event.matches(keyspace, key) => Ok(event.to_frame()),
+                Ok(_) => continue,
+                Err(e) => Err(e),
+            },
+            Some(Watching::Lifecycle(events)) => events
+                .recv()
+                .await
+                .map(|(event, timestamp)| event.to_frame(timestamp)),
+            None => return future::pending().await,
+        };
+        match res {
+            Ok(frame) => return frame,
+            Err(RecvError::Lagged(n)) => {
+                return Frame::Error(Bytes::from(format!("{} events were dropped", n)))
+            }
+            Err(RecvError::Closed) => return future::pending().await,
+        }
+    }
+}
+
+// admin commands and the commands tied to the connection, like transactions,
+// are not sent to the mirror, the mirror runs every command on one connection.
+fn is_mirrored(cmd: &Command) -> bool {
+    match cmd {
+        Command::Create(cmd) => !cmd.temp(),
+        Command::Multi
+        | Command::Exec
+        | Command::Discard
+        | Command::Watch(_)
+        | Command::Unwatch
+        | Command::WatchKey(_)
+        | Command::WatchLifecycle => false,
+        cmd => !cmd.is_admin(),
+    }
+}
+
+// pings and the commands that read keyspaces are refused while the server is
+// warming up. writes, admin commands and commands that take no keyspace, like
+// `MULTI`, still run.
+fn is_refused_while_warming(cmd: &Command) -> bool {
+    match cmd {
+        Command::Ping(_) => true,
+        cmd => {
+            !cmd.is_admin()
+                && !cmd.is_write()
+                && cmd
+                    .keyspaces()
+                    .is_some_and(|keyspaces| !keyspaces.is_empty())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    async fn reply(client: &mut Connection<DuplexStream>, args: &[&'static str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::String(Bytes::from(*arg)))
+                .collect(),
+        );
+        client.write_frame(&frame).await.unwrap();
+        client.read_frame().await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn handle_given_warming_runs_commands_without_keyspace() {
+        let (done_tx, done) = broadcast::channel(1);
+        let db = Arc::new(Db::new(
+            &ServerConfig::default(),
+            done_tx.subscribe(),
+            WaitGroup::new(),
+        ));
+        db.publish_lifecycle_event(LifecycleEvent::Warming);
+        let (client, server) = duplex(4096);
+        let mut handler = ConnectionHandler::new(
+            1,
+            "127.0.0.1:0".parse().unwrap(),
+            done,
+            Connection::new(server, 4096),
+            db,
+            None,
+            None,
+        );
+        tokio::spawn(async move { handler.handle().await });
+        let mut client = Connection::new(client, 4096);
+
+        assert_eq!(
+            reply(&mut client, &["echo", "hi"]).await,
+            Frame::String(Bytes::from("hi"))
+        );
+        assert_eq!(
+            reply(&mut client, &["create", "foo"]).await,
+            Frame::Boolean(true)
+        );
+        assert_eq!(
+            reply(&mut client, &["get", "foo", "a"]).await,
+            Frame::Error(Bytes::from("ERRWARMING server is warming up"))
+        );
+        assert_eq!(reply(&mut client, &["multi"]).await, Frame::Boolean(true));
+        drop(done_tx);
+    }
 }
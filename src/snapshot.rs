@@ -0,0 +1,744 @@
+use crate::db::{Data, Evictor, KeyspaceConfig};
+use crate::sortedset::SortedSet;
+use crate::stream::{Stream, StreamId};
+use crate::upstream::UpstreamConfig;
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+// every snapshot starts with these bytes followed by the version of its
+// format, the version is bumped whenever the format changes so snapshots
+// written by older servers can still be told apart
+const MAGIC: &[u8] = b"SEGSNAP\0";
+pub const VERSION: u32 = 1;
+pub const FILE_NAME: &str = "segment.snapshot";
+
+// every `DUMP` payload starts with these bytes followed by the version, the
+// payloads share the encoding of the values with the snapshots
+const DUMP_MAGIC: &[u8] = b"SEGDUMP\0";
+
+// what follows in the file
+const KEYSPACE: u8 = 1;
+const END: u8 = 0xff;
+
+// the types of values
+const BLOB: u8 = 0;
+const LIST: u8 = 1;
+const HASH: u8 = 2;
+const SORTED_SET: u8 = 3;
+const STREAM: u8 = 4;
+
+// a snapshot of the keyspaces, written by `SAVE` and `BGSAVE`. the file holds
+// the magic bytes and the version, then every keyspace with its settings and
+// its keys, then an end marker, so a file cut short is told apart from a
+// complete one. integers are little endian, strings are prefixed with their
+// length. the snapshot is written next to the file it replaces, which is only
+// replaced once the snapshot is complete.
+#[derive(Debug)]
+pub struct SnapshotWriter {
+    file: BufWriter<File>,
+    tmp: PathBuf,
+    path: PathBuf,
+}
+
+// a keyspace encoded in memory, so its keys can be read at once without
+// holding the keyspace while the file is written
+#[derive(Debug)]
+pub struct KeyspaceEncoder {
+    buf: Vec<u8>,
+    // where the number of keys goes, it is only known at the end
+    count_at: usize,
+    keys: u64,
+}
+
+// reads a snapshot back one keyspace and one key at a time, so a snapshot is
+// never held in memory as a whole while it is loaded
+#[derive(Debug)]
+pub struct SnapshotReader<R = BufReader<File>> {
+    file: R,
+    path: String,
+    // the keys of the current keyspace that weren't read yet
+    keys_left: u64,
+}
+
+// whether a save is running and how the last one went, see `INFO`
+#[derive(Debug, Default)]
+pub struct SaveState {
+    saving: AtomicBool,
+    // unix time in seconds of the last save that completed, 0 for none
+    last_save: AtomicU64,
+    last_save_keys: AtomicU64,
+    last_save_failed: AtomicBool,
+}
+
+// a running save, the next save can start once it is dropped
+#[derive(Debug)]
+pub struct Saving {
+    state: Arc<SaveState>,
+}
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("failed to write snapshot '{0}': {1}")]
+    Write(String, io::Error),
+
+    #[error("failed to read snapshot '{0}': {1}")]
+    Read(String, io::Error),
+
+    #[error(
+        "snapshot '{0}' is of version {1}, only version {} can be read",
+        VERSION
+    )]
+    UnsupportedVersion(String, u32),
+
+    #[error("snapshot '{0}' is corrupt: {1}")]
+    Corrupt(String, &'static str),
+
+    #[error("a save is already running")]
+    InProgress,
+
+    #[error("invalid DUMP payload: {0}")]
+    InvalidPayload(&'static str),
+
+    #[error(
+        "DUMP payload is of version {0}, only version {} can be restored",
+        VERSION
+    )]
+    UnsupportedPayloadVersion(u32),
+}
+
+impl SnapshotWriter {
+    pub fn create(path: &Path) -> Result<Self, SnapshotError> {
+        let tmp = path.with_extension("tmp");
+        let error = |e| SnapshotError::Write(tmp.display().to_string(), e);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(error)?;
+        }
+        let mut file = BufWriter::new(File::create(&tmp).map_err(error)?);
+        file.write_all(MAGIC).map_err(error)?;
+        file.write_all(&VERSION.to_le_bytes()).map_err(error)?;
+        Ok(SnapshotWriter {
+            file,
+            tmp,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn write_keyspace(&mut self, keyspace: KeyspaceEncoder) -> Result<(), SnapshotError> {
+        let buf = keyspace.finish();
+        self.file.write_all(&buf).map_err(|e| self.error(e))
+    }
+
+    // completes the snapshot and puts it in place of the previous one, which
+    // is left in place if that fails
+    pub fn finish(mut self) -> Result<(), SnapshotError> {
+        let result = self
+            .file
+            .write_all(&[END])
+            .and_then(|_| self.file.flush())
+            .and_then(|_| self.file.get_ref().sync_all())
+            .and_then(|_| fs::rename(&self.tmp, &self.path));
+        result.map_err(|e| {
+            let _ = fs::remove_file(&self.tmp);
+            self.error(e)
+        })
+    }
+
+    // leaves the previous snapshot in place
+    pub fn abort(self) {
+        let _ = fs::remove_file(&self.tmp);
+    }
+
+    fn error(&self, e: io::Error) -> SnapshotError {
+        SnapshotError::Write(self.tmp.display().to_string(), e)
+    }
+}
+
+impl SnapshotReader {
+    // `None` when there is no snapshot at `path`
+    pub fn open(path: &Path) -> Result<Option<Self>, SnapshotError> {
+        let name = path.display().to_string();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(SnapshotError::Read(name, e)),
+        };
+        let mut reader = SnapshotReader {
+            file: BufReader::new(file),
+            path: name,
+            keys_left: 0,
+        };
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(reader.corrupt("not a snapshot"));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(reader.path, version));
+        }
+        Ok(Some(reader))
+    }
+
+    // how much of the snapshot was read so far, in percent
+    pub fn percent_read(&mut self) -> Result<u8, SnapshotError> {
+        let size = self
+            .file
+            .get_ref()
+            .metadata()
+            .map_err(|e| self.read_error(e))?
+            .len();
+        let read = self
+            .file
+            .stream_position()
+            .map_err(|e| self.read_error(e))?;
+        Ok((read * 100 / size.max(1)).min(100) as u8)
+    }
+}
+
+impl<R: Read> SnapshotReader<R> {
+    // the next keyspace along with its settings, `None` once every keyspace
+    // was read. the keys of a keyspace have to be read before the next one.
+    pub fn next_keyspace(&mut self) -> Result<Option<(Bytes, KeyspaceConfig)>, SnapshotError> {
+        if self.keys_left > 0 {
+            return Err(self.corrupt("keys of the previous keyspace left unread"));
+        }
+        match self.u8()? {
+            KEYSPACE => {}
+            END => return Ok(None),
+            _ => return Err(self.corrupt("unknown record")),
+        }
+        let name = self.bytes()?;
+        let evictor =
+            Evictor::from_bytes(&self.bytes()?).ok_or_else(|| self.corrupt("unknown evictor"))?;
+        let ordered = self.bool()?;
+        let retention = self.option()?;
+        let sample_size = self.u8()?;
+        let upstream = match self.bool()? {
+            true => Some(UpstreamConfig {
+                address: String::from_utf8(self.bytes()?.to_vec())
+                    .map_err(|_| self.corrupt("upstream address is not utf-8"))?,
+                cache_ttl: self.u64()?,
+            }),
+            false => None,
+        };
+        let canary_of = match self.bool()? {
+            true => Some(self.bytes()?),
+            false => None,
+        };
+        let config = KeyspaceConfig {
+            evictor,
+            ordered,
+            retention,
+            sample_size,
+            upstream,
+            canary_of,
+            max_memory: self.u64()?,
+            max_keys: self.u64()?,
+            tombstone: self.option()?,
+            spill: self.bool()?,
+            weight: self.u32()?,
+        };
+        self.keys_left = self.u64()?;
+        Ok(Some((name, config)))
+    }
+
+    // the next key of the current keyspace with its expiry in seconds since
+    // the unix epoch, `None` once every key of the keyspace was read
+    pub fn next_entry(&mut self) -> Result<Option<(Bytes, Option<u64>, Data)>, SnapshotError> {
+        if self.keys_left == 0 {
+            return Ok(None);
+        }
+        self.keys_left -= 1;
+        let key = self.bytes()?;
+        let expire_at = self.option()?;
+        let data = self.data()?;
+        Ok(Some((key, expire_at, data)))
+    }
+
+    fn data(&mut self) -> Result<Data, SnapshotError> {
+        match self.u8()? {
+            BLOB => Ok(Data::Blob(self.bytes()?)),
+            LIST => {
+                let mut list = VecDeque::new();
+                for _ in 0..self.u64()? {
+                    list.push_back(self.bytes()?);
+                }
+                Ok(Data::List(list))
+            }
+            HASH => {
+                let mut hash = HashMap::new();
+                for _ in 0..self.u64()? {
+                    hash.insert(self.bytes()?, self.bytes()?);
+                }
+                Ok(Data::Hash(hash))
+            }
+            SORTED_SET => {
+                let mut set = SortedSet::default();
+                for _ in 0..self.u64()? {
+                    let member = self.bytes()?;
+                    set.insert(member, f64::from_bits(self.u64()?));
+                }
+                Ok(Data::SortedSet(set))
+            }
+            STREAM => {
+                let last_id = StreamId::new(self.u64()?, self.u64()?);
+                let mut stream = Stream::default();
+                for _ in 0..self.u64()? {
+                    let id = StreamId::new(self.u64()?, self.u64()?);
+                    let mut entry = Vec::new();
+                    for _ in 0..self.u64()? {
+                        entry.push((self.bytes()?, self.bytes()?));
+                    }
+                    stream
+                        .add(Some(id), 0, entry)
+                        .ok_or_else(|| self.corrupt("stream ids out of order"))?;
+                }
+                stream.advance_last_id(last_id);
+                Ok(Data::Stream(stream))
+            }
+            _ => Err(self.corrupt("unknown value type")),
+        }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), SnapshotError> {
+        self.file.read_exact(buf).map_err(|e| self.read_error(e))
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        let mut buf = [0; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.corrupt("invalid boolean")),
+        }
+    }
+
+    fn option(&mut self) -> Result<Option<u64>, SnapshotError> {
+        match self.bool()? {
+            true => Ok(Some(self.u64()?)),
+            false => Ok(None),
+        }
+    }
+
+    fn bytes(&mut self) -> Result<Bytes, SnapshotError> {
+        let len = self.u64()?;
+        // the buffer grows as the bytes are read, so a corrupt length can't
+        // allocate more than the file holds
+        let mut buf = Vec::new();
+        (&mut self.file)
+            .take(len)
+            .read_to_end(&mut buf)
+            .map_err(|e| self.read_error(e))?;
+        if buf.len() as u64 != len {
+            return Err(self.corrupt("the file is cut short"));
+        }
+        Ok(Bytes::from(buf))
+    }
+
+    fn read_error(&self, e: io::Error) -> SnapshotError {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            return self.corrupt("the file is cut short");
+        }
+        SnapshotError::Read(self.path.clone(), e)
+    }
+
+    fn corrupt(&self, reason: &'static str) -> SnapshotError {
+        SnapshotError::Corrupt(self.path.clone(), reason)
+    }
+}
+
+// encodes a key for `DUMP`, with its time to live in milliseconds so it
+// expires as planned on an instance whose clock is set differently
+pub fn dump(ttl: Option<u64>, data: &Data) -> Bytes {
+    let mut buf = DUMP_MAGIC.to_vec();
+    buf.extend_from_slice(&VERSION.to_le_bytes());
+    put_option(&mut buf, ttl);
+    encode_data(&mut buf, data);
+    Bytes::from(buf)
+}
+
+// decodes a payload of `DUMP` into the time to live in milliseconds and the
+// value of the key
+pub fn undump(payload: &[u8]) -> Result<(Option<u64>, Data), SnapshotError> {
+    let decode = |reader: &mut SnapshotReader<&[u8]>| {
+        let ttl = reader.option()?;
+        let data = reader.data()?;
+        if !reader.file.is_empty() {
+            return Err(reader.corrupt("unexpected bytes after the value"));
+        }
+        Ok((ttl, data))
+    };
+    decode(&mut dump_reader(payload)?).map_err(payload_error)
+}
+
+// the time to live of a payload of `DUMP` in milliseconds, without decoding
+// the value
+pub fn undump_ttl(payload: &[u8]) -> Result<Option<u64>, SnapshotError> {
+    dump_reader(payload)?.option().map_err(payload_error)
+}
+
+// the unix timestamp in seconds a time to live of a payload ends at, `now` is
+// the time since the unix epoch. expiry is kept in seconds, rounding up keeps
+// a short time to live from expiring the key right away.
+pub fn ttl_deadline(now: Duration, ttl: u64) -> u64 {
+    (now.as_millis() as u64 + ttl).div_ceil(1000)
+}
+
+// reads a payload of `DUMP` from past its magic and version
+fn dump_reader(payload: &[u8]) -> Result<SnapshotReader<&[u8]>, SnapshotError> {
+    let mut reader = SnapshotReader {
+        file: payload,
+        path: String::new(),
+        keys_left: 0,
+    };
+    let mut magic = [0; DUMP_MAGIC.len()];
+    reader.read_exact(&mut magic).map_err(payload_error)?;
+    if magic != DUMP_MAGIC {
+        return Err(SnapshotError::InvalidPayload("unknown format"));
+    }
+    let version = reader.u32().map_err(payload_error)?;
+    if version != VERSION {
+        return Err(SnapshotError::UnsupportedPayloadVersion(version));
+    }
+    Ok(reader)
+}
+
+// the reader speaks of files, a payload cut short is reported as such
+fn payload_error(e: SnapshotError) -> SnapshotError {
+    match e {
+        SnapshotError::Corrupt(_, "the file is cut short") => {
+            SnapshotError::InvalidPayload("the payload is cut short")
+        }
+        SnapshotError::Corrupt(_, reason) => SnapshotError::InvalidPayload(reason),
+        e => e,
+    }
+}
+
+impl KeyspaceEncoder {
+    pub fn new(name: &Bytes, config: &KeyspaceConfig) -> Self {
+        let mut buf = vec![KEYSPACE];
+        put_bytes(&mut buf, name);
+        put_bytes(&mut buf, config.evictor.as_bytes());
+        put_bool(&mut buf, config.ordered);
+        put_option(&mut buf, config.retention);
+        buf.push(config.sample_size);
+        match &config.upstream {
+            Some(upstream) => {
+                put_bool(&mut buf, true);
+                put_bytes(&mut buf, upstream.address.as_bytes());
+                put_u64(&mut buf, upstream.cache_ttl);
+            }
+            None => put_bool(&mut buf, false),
+        }
+        match &config.canary_of {
+            Some(source) => {
+                put_bool(&mut buf, true);
+                put_bytes(&mut buf, source);
+            }
+            None => put_bool(&mut buf, false),
+        }
+        put_u64(&mut buf, config.max_memory);
+        put_u64(&mut buf, config.max_keys);
+        put_option(&mut buf, config.tombstone);
+        put_bool(&mut buf, config.spill);
+        buf.extend_from_slice(&config.weight.to_le_bytes());
+        let count_at = buf.len();
+        put_u64(&mut buf, 0);
+        KeyspaceEncoder {
+            buf,
+            count_at,
+            keys: 0,
+        }
+    }
+
+    // `expire_at` is in seconds since the unix epoch
+    pub fn entry(&mut self, key: &Bytes, expire_at: Option<u64>, data: &Data) {
+        put_bytes(&mut self.buf, key);
+        put_option(&mut self.buf, expire_at);
+        encode_data(&mut self.buf, data);
+        self.keys += 1;
+    }
+
+    pub fn keys(&self) -> u64 {
+        self.keys
+    }
+
+    // reads the keys back from memory, see `EXPORT`
+    pub fn into_reader(self) -> SnapshotReader<io::Cursor<Vec<u8>>> {
+        SnapshotReader {
+            file: io::Cursor::new(self.finish()),
+            path: String::new(),
+            keys_left: 0,
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.buf[self.count_at..self.count_at + 8].copy_from_slice(&self.keys.to_le_bytes());
+        self.buf
+    }
+}
+
+impl SaveState {
+    // `None` while another save is running
+    pub fn start(self: &Arc<Self>) -> Option<Saving> {
+        if self.saving.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        Some(Saving {
+            state: self.clone(),
+        })
+    }
+
+    pub fn is_saving(&self) -> bool {
+        self.saving.load(Ordering::Relaxed)
+    }
+
+    pub fn last_save(&self) -> u64 {
+        self.last_save.load(Ordering::Relaxed)
+    }
+
+    pub fn last_save_keys(&self) -> u64 {
+        self.last_save_keys.load(Ordering::Relaxed)
+    }
+
+    pub fn last_save_failed(&self) -> bool {
+        self.last_save_failed.load(Ordering::Relaxed)
+    }
+}
+
+impl Saving {
+    // `at` is the unix time in seconds the save completed at
+    pub fn done(self, at: u64, keys: u64) {
+        self.state.last_save.store(at, Ordering::Relaxed);
+        self.state.last_save_keys.store(keys, Ordering::Relaxed);
+        self.state.last_save_failed.store(false, Ordering::Relaxed);
+    }
+
+    pub fn failed(self) {
+        self.state.last_save_failed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Saving {
+    fn drop(&mut self) {
+        self.state.saving.store(false, Ordering::Release);
+    }
+}
+
+fn encode_data(buf: &mut Vec<u8>, data: &Data) {
+    match data {
+        Data::Blob(blob) => {
+            buf.push(BLOB);
+            put_bytes(buf, blob);
+        }
+        Data::List(list) => {
+            buf.push(LIST);
+            put_u64(buf, list.len() as u64);
+            for item in list {
+                put_bytes(buf, item);
+            }
+        }
+        Data::Hash(hash) => {
+            buf.push(HASH);
+            put_u64(buf, hash.len() as u64);
+            for (field, value) in hash {
+                put_bytes(buf, field);
+                put_bytes(buf, value);
+            }
+        }
+        Data::SortedSet(set) => {
+            buf.push(SORTED_SET);
+            put_u64(buf, set.len() as u64);
+            for (member, score) in set.iter() {
+                put_bytes(buf, member);
+                buf.extend_from_slice(&score.to_le_bytes());
+            }
+        }
+        Data::Stream(stream) => {
+            buf.push(STREAM);
+            let last_id = stream.last_id();
+            put_u64(buf, last_id.ms());
+            put_u64(buf, last_id.seq());
+            put_u64(buf, stream.len() as u64);
+            for (id, entry) in stream.iter() {
+                put_u64(buf, id.ms());
+                put_u64(buf, id.seq());
+                put_u64(buf, entry.len() as u64);
+                for (field, value) in entry {
+                    put_bytes(buf, field);
+                    put_bytes(buf, value);
+                }
+            }
+        }
+    }
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_bool(buf: &mut Vec<u8>, value: bool) {
+    buf.push(value as u8);
+}
+
+fn put_option(buf: &mut Vec<u8>, value: Option<u64>) {
+    put_bool(buf, value.is_some());
+    if let Some(value) = value {
+        put_u64(buf, value);
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_u64(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn finish_given_keyspace_writes_header_keyspace_and_end() {
+        let path = env::temp_dir().join(format!("segment-snapshot-test-{}", std::process::id()));
+        let mut writer = SnapshotWriter::create(&path).unwrap();
+        let mut keyspace = KeyspaceEncoder::new(&Bytes::from("foo"), &KeyspaceConfig::default());
+        keyspace.entry(&Bytes::from("bar"), None, &Data::Blob(Bytes::from("baz")));
+        assert_eq!(keyspace.keys(), 1);
+        writer.write_keyspace(keyspace).unwrap();
+        writer.finish().unwrap();
+
+        let file = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(file.starts_with(MAGIC));
+        assert_eq!(file[MAGIC.len()..MAGIC.len() + 4], VERSION.to_le_bytes());
+        assert_eq!(file[MAGIC.len() + 4], KEYSPACE);
+        assert_eq!(file.last(), Some(&END));
+        assert!(file.windows(3).any(|window| window == b"baz"));
+    }
+
+    #[test]
+    fn next_entry_given_written_snapshot_reads_it_back() {
+        let path = env::temp_dir().join(format!("segment-snapshot-load-{}", std::process::id()));
+        let config = KeyspaceConfig {
+            evictor: Evictor::Slru,
+            retention: Some(60_000),
+            ordered: true,
+            upstream: Some(UpstreamConfig {
+                address: "127.0.0.1:1698".to_string(),
+                cache_ttl: 500,
+            }),
+            max_keys: 10,
+            weight: 3,
+            ..KeyspaceConfig::default()
+        };
+        let mut stream = Stream::default();
+        stream.add(
+            Some(StreamId::new(1, 0)),
+            0,
+            vec![(Bytes::from("f"), Bytes::from("v"))],
+        );
+        stream.advance_last_id(StreamId::new(7, 2));
+        let mut set = SortedSet::default();
+        set.insert(Bytes::from("m"), 1.5);
+        let mut keyspace = KeyspaceEncoder::new(&Bytes::from("foo"), &config);
+        keyspace.entry(&Bytes::from("a"), Some(42), &Data::Blob(Bytes::from("1")));
+        keyspace.entry(&Bytes::from("b"), None, &Data::SortedSet(set));
+        keyspace.entry(&Bytes::from("c"), None, &Data::Stream(stream));
+        let mut writer = SnapshotWriter::create(&path).unwrap();
+        writer.write_keyspace(keyspace).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = SnapshotReader::open(&path).unwrap().unwrap();
+        let (name, read) = reader.next_keyspace().unwrap().unwrap();
+        assert_eq!(name, Bytes::from("foo"));
+        assert_eq!(read.evictor, Evictor::Slru);
+        assert_eq!((read.retention, read.ordered), (Some(60_000), true));
+        assert_eq!(read.upstream.unwrap().address, "127.0.0.1:1698");
+        assert_eq!((read.max_keys, read.weight), (10, 3));
+        let (key, expire_at, data) = reader.next_entry().unwrap().unwrap();
+        assert_eq!((key, expire_at), (Bytes::from("a"), Some(42)));
+        assert!(matches!(data, Data::Blob(blob) if blob == "1"));
+        let (_, _, data) = reader.next_entry().unwrap().unwrap();
+        assert!(matches!(data, Data::SortedSet(set) if set.score(&Bytes::from("m")) == Some(1.5)));
+        let (_, _, data) = reader.next_entry().unwrap().unwrap();
+        assert!(
+            matches!(data, Data::Stream(stream) if stream.len() == 1 && stream.last_id() == StreamId::new(7, 2))
+        );
+        assert!(reader.next_entry().unwrap().is_none());
+        assert!(reader.next_keyspace().unwrap().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn next_entry_given_snapshot_cut_short_returns_corrupt() {
+        let path = env::temp_dir().join(format!("segment-snapshot-short-{}", std::process::id()));
+        let mut keyspace = KeyspaceEncoder::new(&Bytes::from("foo"), &KeyspaceConfig::default());
+        keyspace.entry(&Bytes::from("bar"), None, &Data::Blob(Bytes::from("baz")));
+        let mut writer = SnapshotWriter::create(&path).unwrap();
+        writer.write_keyspace(keyspace).unwrap();
+        writer.finish().unwrap();
+        let file = fs::read(&path).unwrap();
+        fs::write(&path, &file[..file.len() - 3]).unwrap();
+
+        let mut reader = SnapshotReader::open(&path).unwrap().unwrap();
+        reader.next_keyspace().unwrap().unwrap();
+        let result = reader.next_entry();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(SnapshotError::Corrupt(_, _))));
+        assert!(SnapshotReader::open(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn undump_given_dumped_key_reads_it_back() {
+        let payload = dump(Some(1500), &Data::Blob(Bytes::from("baz")));
+        let (ttl, data) = undump(&payload).unwrap();
+        assert_eq!(ttl, Some(1500));
+        assert!(matches!(data, Data::Blob(blob) if blob == "baz"));
+
+        assert!(matches!(
+            undump(&payload[..payload.len() - 1]),
+            Err(SnapshotError::InvalidPayload("the payload is cut short"))
+        ));
+        let mut newer = payload.to_vec();
+        newer[DUMP_MAGIC.len()..DUMP_MAGIC.len() + 4].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            undump(&newer),
+            Err(SnapshotError::UnsupportedPayloadVersion(_))
+        ));
+    }
+
+    #[test]
+    fn start_given_save_running_returns_none() {
+        let state = Arc::new(SaveState::default());
+        let saving = state.start().unwrap();
+        assert!(state.start().is_none());
+        saving.done(10, 3);
+        assert!(state.start().is_some());
+        assert_eq!((state.last_save(), state.last_save_keys()), (10, 3));
+    }
+}
@@ -0,0 +1,129 @@
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::mem;
+
+// scores are compared using `total_cmp` so they can be ordered, NaN scores are
+// rejected when the commands are parsed.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// members are kept ordered by score and then by member, members with the same
+// score are ordered lexicographically. the scores are kept in a separate map so
+// members can be looked up without a scan.
+#[derive(Debug, Default)]
+pub struct SortedSet {
+    ordered: BTreeSet<(Score, Bytes)>,
+    scores: HashMap<Bytes, f64>,
+}
+
+impl SortedSet {
+    // returns true if the member was added, false if only its score changed
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        let previous = self.scores.insert(member.clone(), score);
+        if let Some(previous) = previous {
+            self.ordered.remove(&(Score(previous), member.clone()));
+        }
+        self.ordered.insert((Score(score), member));
+        previous.is_none()
+    }
+
+    pub fn remove(&mut self, member: &Bytes) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.ordered.remove(&(Score(score), member.clone())),
+            None => false,
+        }
+    }
+
+    pub fn score(&self, member: &Bytes) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    // an estimate of the memory taken by the set, the buffers of the members
+    // are shared by both orders so they are counted once
+    pub fn memory_usage(&self) -> usize {
+        let ordered = self.ordered.len() * mem::size_of::<(Score, Bytes)>();
+        let scores = self.scores.capacity() * (mem::size_of::<(Bytes, f64)>() + 1);
+        let members: usize = self.scores.keys().map(Bytes::len).sum();
+        ordered + scores + members
+    }
+
+    // iterates over the members from the lowest to the highest score
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> {
+        self.ordered.iter().map(|(score, member)| (member, score.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iter_given_members_returns_them_ordered_by_score_then_member() {
+        let mut set = SortedSet::default();
+        assert!(set.insert(Bytes::from("c"), 2.0));
+        assert!(set.insert(Bytes::from("b"), 1.0));
+        assert!(set.insert(Bytes::from("a"), 2.0));
+        assert!(set.insert(Bytes::from("d"), -1.5));
+        let members: Vec<_> = set.iter().map(|(member, _)| member.clone()).collect();
+        assert_eq!(
+            members,
+            vec![
+                Bytes::from("d"),
+                Bytes::from("b"),
+                Bytes::from("a"),
+                Bytes::from("c")
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_given_existing_member_moves_it() {
+        let mut set = SortedSet::default();
+        set.insert(Bytes::from("a"), 1.0);
+        set.insert(Bytes::from("b"), 2.0);
+        assert!(!set.insert(Bytes::from("a"), 3.0));
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.score(&Bytes::from("a")), Some(3.0));
+        let members: Vec<_> = set.iter().map(|(member, _)| member.clone()).collect();
+        assert_eq!(members, vec![Bytes::from("b"), Bytes::from("a")]);
+    }
+
+    #[test]
+    fn remove_given_member_removes_it_from_both_orders() {
+        let mut set = SortedSet::default();
+        set.insert(Bytes::from("a"), 1.0);
+        assert!(set.remove(&Bytes::from("a")));
+        assert!(!set.remove(&Bytes::from("a")));
+        assert!(set.is_empty());
+        assert_eq!(set.iter().count(), 0);
+    }
+}
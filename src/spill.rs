@@ -0,0 +1,159 @@
+use bytes::Bytes;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+// keyspaces get a directory of their own even when they share a name with a
+// dropped keyspace that is still being cleaned up
+static NEXT_SPILL_ID: AtomicU64 = AtomicU64::new(1);
+const SPILL_EXTENSION: &str = "spill";
+
+// the evicted values of a keyspace kept on disk, see `CREATE ... SPILL`. every
+// value is a file named after the hex of its key, so keys too long to make a
+// file name are dropped like in keyspaces that don't spill. the directory only
+// lives as long as the keyspace, it is removed once the keyspace is gone.
+#[derive(Debug)]
+pub struct Spill {
+    dir: PathBuf,
+    // the keys with a value on disk, so misses of keys that were never spilled
+    // don't touch the disk
+    keys: Mutex<HashSet<Bytes>>,
+}
+
+#[derive(Debug, Error)]
+pub enum SpillError {
+    #[error("spill_dir is not set")]
+    NotConfigured,
+
+    #[error("failed to open spill directory '{0}': {1}")]
+    Open(String, io::Error),
+}
+
+impl Spill {
+    // creates the directory of the keyspace in `root`
+    pub fn open(root: &Path, keyspace: &Bytes) -> Result<Self, SpillError> {
+        let id = NEXT_SPILL_ID.fetch_add(1, Ordering::Relaxed);
+        let dir = root.join(format!("{}-{}.{}", hex(keyspace), id, SPILL_EXTENSION));
+        fs::create_dir_all(&dir).map_err(|e| SpillError::Open(dir.display().to_string(), e))?;
+        Ok(Spill {
+            dir,
+            keys: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn write(&self, key: &Bytes, value: &Bytes) -> io::Result<()> {
+        fs::write(self.path(key), value)?;
+        self.keys.lock().insert(key.clone());
+        Ok(())
+    }
+
+    // reads the value of the key back and removes it from disk, `None` if the
+    // key has no value on disk
+    pub fn take(&self, key: &Bytes) -> io::Result<Option<Bytes>> {
+        if !self.keys.lock().remove(key) {
+            return Ok(None);
+        }
+        let path = self.path(key);
+        let value = fs::read(&path)?;
+        fs::remove_file(&path)?;
+        Ok(Some(Bytes::from(value)))
+    }
+
+    // reads the value of the key without taking it off disk
+    pub fn read(&self, key: &Bytes) -> io::Result<Option<Bytes>> {
+        if !self.contains(key) {
+            return Ok(None);
+        }
+        Ok(Some(Bytes::from(fs::read(self.path(key))?)))
+    }
+
+    pub fn keys(&self) -> Vec<Bytes> {
+        self.keys.lock().iter().cloned().collect()
+    }
+
+    pub fn contains(&self, key: &Bytes) -> bool {
+        self.keys.lock().contains(key)
+    }
+
+    // forgets the value of the key, for keys written or deleted while their
+    // value is on disk. returns whether there was a value.
+    pub fn discard(&self, key: &Bytes) -> bool {
+        let discarded = self.keys.lock().remove(key);
+        if discarded {
+            let _ = fs::remove_file(self.path(key));
+        }
+        discarded
+    }
+
+    // forgets the values of the keys matching the filter
+    pub fn discard_matching(&self, filter: impl Fn(&Bytes) -> bool) {
+        let mut keys = self.keys.lock();
+        keys.retain(|key| {
+            if !filter(key) {
+                return true;
+            }
+            let _ = fs::remove_file(self.path(key));
+            false
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.lock().len()
+    }
+
+    pub fn clear(&self) {
+        self.discard_matching(|_| true);
+    }
+
+    fn path(&self, key: &Bytes) -> PathBuf {
+        self.dir.join(hex(key))
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// removes the directories keyspaces left in `root` when the server stopped
+// without cleaning up, anything else in `root` is left alone
+pub fn clean(root: &Path) {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == SPILL_EXTENSION) {
+            let _ = fs::remove_dir_all(path);
+        }
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_given_written_value_returns_it_once() {
+        let root = std::env::temp_dir();
+        let spill = Spill::open(&root, &Bytes::from("spill-test")).unwrap();
+        let dir = spill.dir.clone();
+        let key = Bytes::from("foo");
+        spill.write(&key, &Bytes::from("bar")).unwrap();
+        assert!(spill.contains(&key));
+        assert_eq!(spill.take(&key).unwrap(), Some(Bytes::from("bar")));
+        assert_eq!(spill.take(&key).unwrap(), None);
+        drop(spill);
+        assert!(!dir.exists());
+    }
+}
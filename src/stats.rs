@@ -0,0 +1,138 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// server wide counters reported by `INFO`, the counters are updated from the
+// connections and the background tasks so they are kept as atomics.
+#[derive(Debug)]
+pub struct Stats {
+    started_at: Instant,
+    max_memory: u64,
+    memory: AtomicU64,
+    data_memory: AtomicU64,
+    connections: AtomicU64,
+    total_connections: AtomicU64,
+    throttled_commands: AtomicU64,
+    mirrored_commands: AtomicU64,
+    mirror_dropped_commands: AtomicU64,
+    expired_keys: AtomicU64,
+    evicted_keys: AtomicU64,
+    result_cache_hits: AtomicU64,
+    result_cache_misses: AtomicU64,
+}
+
+impl Stats {
+    pub fn new(max_memory: u64) -> Self {
+        Stats {
+            started_at: Instant::now(),
+            max_memory,
+            memory: AtomicU64::new(0),
+            data_memory: AtomicU64::new(0),
+            connections: AtomicU64::new(0),
+            total_connections: AtomicU64::new(0),
+            throttled_commands: AtomicU64::new(0),
+            mirrored_commands: AtomicU64::new(0),
+            mirror_dropped_commands: AtomicU64::new(0),
+            expired_keys: AtomicU64::new(0),
+            evicted_keys: AtomicU64::new(0),
+            result_cache_hits: AtomicU64::new(0),
+            result_cache_misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn connection_opened(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        self.total_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn set_memory(&self, memory: u64) {
+        self.memory.store(memory, Ordering::Relaxed);
+    }
+
+    pub fn set_data_memory(&self, memory: u64) {
+        self.data_memory.store(memory, Ordering::Relaxed);
+    }
+
+    pub fn record_throttled(&self) {
+        self.throttled_commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_mirrored(&self) {
+        self.mirrored_commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_mirror_dropped(&self) {
+        self.mirror_dropped_commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_expired(&self, count: u64) {
+        self.expired_keys.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_evicted(&self) {
+        self.evicted_keys.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_result_cache_hit(&self) {
+        self.result_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_result_cache_miss(&self) {
+        self.result_cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn max_memory(&self) -> u64 {
+        self.max_memory
+    }
+
+    pub fn memory(&self) -> u64 {
+        self.memory.load(Ordering::Relaxed)
+    }
+
+    pub fn data_memory(&self) -> u64 {
+        self.data_memory.load(Ordering::Relaxed)
+    }
+
+    pub fn connections(&self) -> u64 {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    pub fn total_connections(&self) -> u64 {
+        self.total_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn throttled_commands(&self) -> u64 {
+        self.throttled_commands.load(Ordering::Relaxed)
+    }
+
+    pub fn mirrored_commands(&self) -> u64 {
+        self.mirrored_commands.load(Ordering::Relaxed)
+    }
+
+    pub fn mirror_dropped_commands(&self) -> u64 {
+        self.mirror_dropped_commands.load(Ordering::Relaxed)
+    }
+
+    pub fn expired_keys(&self) -> u64 {
+        self.expired_keys.load(Ordering::Relaxed)
+    }
+
+    pub fn evicted_keys(&self) -> u64 {
+        self.evicted_keys.load(Ordering::Relaxed)
+    }
+
+    pub fn result_cache_hits(&self) -> u64 {
+        self.result_cache_hits.load(Ordering::Relaxed)
+    }
+
+    pub fn result_cache_misses(&self) -> u64 {
+        self.result_cache_misses.load(Ordering::Relaxed)
+    }
+}
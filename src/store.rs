@@ -0,0 +1,649 @@
+use crate::adaptive::{Adaptive, Candidate};
+use crate::clock::{Clock, SystemClock};
+use crate::recency::Recency;
+use crate::secondchance::SecondChance;
+use crate::segmented::Segmented;
+use bytes::Bytes;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{BuildHasher, Hasher};
+use std::mem;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+// epochs are drawn from a single counter so a store never gets an epoch that
+// another store, or itself, had before, see `Store::epoch`
+static NEXT_EPOCH: AtomicU64 = AtomicU64::new(1);
+
+pub fn next_epoch() -> u64 {
+    NEXT_EPOCH.fetch_add(1, Ordering::Relaxed)
+}
+
+// values kept in a store, the store keeps a running total of their weights
+pub trait Weigh {
+    // estimated bytes taken by the value, the key isn't included
+    fn weight(&self) -> usize;
+}
+
+// storage of a keyspace, ordered keyspaces keep their keys sorted which makes
+// range reads possible at the cost of slower point lookups. the store keeps
+// track of the bytes taken by its keys and values, see `memory`.
+#[derive(Debug)]
+pub struct Store<V> {
+    map: Map<V>,
+    memory: usize,
+    // keys whose values were handed out through `get_mut` since the memory was
+    // last counted, they are weighed again the next time it is counted so
+    // changing a large value in place doesn't weigh it on every change
+    dirty: HashSet<Bytes>,
+    // every key in no particular order, so keys can be picked at random, see
+    // `sample`. entries know their slot, a removed key's slot is taken over by
+    // the last key.
+    slots: Vec<Bytes>,
+    // the order of the keys for the evictors which queue them instead of
+    // sampling, kept only while such an evictor is the evictor of the keyspace
+    queues: Option<Queues>,
+    epoch: u64,
+    // when keys are queued, see `Keyspace::clock`
+    clock: Arc<dyn Clock>,
+}
+
+// the queues of an evictor, see `Store::set_queues`
+#[derive(Debug)]
+pub enum Queues {
+    Adaptive(Adaptive),
+    Segmented(Segmented),
+    SecondChance(SecondChance),
+    Recency(Recency),
+}
+
+#[derive(Debug)]
+enum Map<V> {
+    Hash(HashMap<Bytes, Entry<V>>),
+    Ordered(BTreeMap<Bytes, Entry<V>>),
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    // the weight of the key and the value when they were last weighed
+    weight: usize,
+    slot: usize,
+}
+
+impl<V: Weigh> Store<V> {
+    pub fn new(ordered: bool) -> Self {
+        Store::with_clock(ordered, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(ordered: bool, clock: Arc<dyn Clock>) -> Self {
+        let map = if ordered {
+            Map::Ordered(BTreeMap::new())
+        } else {
+            Map::Hash(HashMap::new())
+        };
+        Store {
+            map,
+            memory: 0,
+            dirty: HashSet::new(),
+            slots: Vec::new(),
+            queues: None,
+            epoch: next_epoch(),
+            clock,
+        }
+    }
+
+    pub fn is_ordered(&self) -> bool {
+        matches!(self.map, Map::Ordered(_))
+    }
+
+    pub fn get(&self, key: &Bytes) -> Option<&V> {
+        self.entry(key).map(|entry| &entry.value)
+    }
+
+    pub fn get_mut(&mut self, key: &Bytes) -> Option<&mut V> {
+        let entry = self.map.get_mut(key)?;
+        if !self.dirty.contains(key) {
+            self.dirty.insert(key.clone());
+        }
+        self.epoch = next_epoch();
+        Some(&mut entry.value)
+    }
+
+    // like `get_mut`, for changes which leave the weight of the value as it
+    // is, like marking the value as accessed
+    pub fn get_mut_same_weight(&mut self, key: &Bytes) -> Option<&mut V> {
+        let entry = self.map.get_mut(key)?;
+        Some(&mut entry.value)
+    }
+
+    pub fn contains_key(&self, key: &Bytes) -> bool {
+        self.entry(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: Bytes, value: V) -> Option<V> {
+        let weight = key.len() + value.weight();
+        self.memory += weight;
+        self.epoch = next_epoch();
+        if let Some(queues) = &mut self.queues {
+            queues.insert(&key, self.clock.instant());
+        }
+        let slot = match self.map.get(&key) {
+            Some(entry) => entry.slot,
+            None => {
+                self.slots.push(key.clone());
+                self.slots.len() - 1
+            }
+        };
+        let entry = Entry {
+            value,
+            weight,
+            slot,
+        };
+        let replaced = match &mut self.map {
+            Map::Hash(map) => map.insert(key, entry),
+            Map::Ordered(map) => map.insert(key, entry),
+        }?;
+        self.memory -= replaced.weight;
+        Some(replaced.value)
+    }
+
+    pub fn remove(&mut self, key: &Bytes) -> Option<V> {
+        let removed = match &mut self.map {
+            Map::Hash(map) => map.remove(key),
+            Map::Ordered(map) => map.remove(key),
+        }?;
+        self.memory -= removed.weight;
+        self.slots.swap_remove(removed.slot);
+        if let Some(moved) = self.slots.get(removed.slot) {
+            if let Some(entry) = self.map.get_mut(moved) {
+                entry.slot = removed.slot;
+            }
+        }
+        self.dirty.remove(key);
+        self.epoch = next_epoch();
+        if let Some(queues) = &mut self.queues {
+            queues.remove(key);
+        }
+        Some(removed.value)
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.map {
+            Map::Hash(map) => map.len(),
+            Map::Ordered(map) => map.len(),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, &V)> + '_> {
+        match &self.map {
+            Map::Hash(map) => Box::new(map.iter().map(|(key, entry)| (key, &entry.value))),
+            Map::Ordered(map) => Box::new(map.iter().map(|(key, entry)| (key, &entry.value))),
+        }
+    }
+
+    pub fn keys(&self) -> Box<dyn Iterator<Item = &Bytes> + '_> {
+        match &self.map {
+            Map::Hash(map) => Box::new(map.keys()),
+            Map::Ordered(map) => Box::new(map.keys()),
+        }
+    }
+
+    // up to `count` entries picked at random among those whose key `keep`
+    // accepts. the slots are walked from a random one with a random stride
+    // coprime to their number, so every key is as likely to be looked at first
+    // and every key is looked at before any is looked at twice.
+    pub fn sample(&self, count: usize, mut keep: impl FnMut(&Bytes) -> bool) -> Vec<(&Bytes, &V)> {
+        let len = self.slots.len();
+        let mut sample = Vec::with_capacity(count.min(len));
+        if len == 0 || count == 0 {
+            return sample;
+        }
+        let mut slot = random() as usize % len;
+        let mut stride = random() as usize % len;
+        while gcd(stride, len) != 1 {
+            stride = (stride + 1) % len;
+        }
+        for _ in 0..len {
+            let key = &self.slots[slot];
+            if keep(key) {
+                if let Some(entry) = self.map.get(key) {
+                    sample.push((key, &entry.value));
+                }
+                if sample.len() == count {
+                    break;
+                }
+            }
+            slot = (slot + stride) % len;
+        }
+        sample
+    }
+
+    // up to `count` of the entries in the slots before `cursor`, and the
+    // cursor of the entries before them, 0 once every slot was walked. the
+    // first cursor is `usize::MAX`. the lock of the store can be let go in
+    // between, walking the slots down still reaches every key that stays in
+    // the store: keys inserted meanwhile take slots past the cursor, and the
+    // slot of a removed key is taken by the key in the last slot.
+    pub fn chunk(&self, cursor: usize, count: usize) -> (Vec<(&Bytes, &V)>, usize) {
+        let end = cursor.min(self.slots.len());
+        let start = end.saturating_sub(count);
+        let entries = self.slots[start..end]
+            .iter()
+            .filter_map(|key| Some((key, &self.map.get(key)?.value)))
+            .collect();
+        (entries, start)
+    }
+
+    // returns the entries with keys in `start..end`, or `None` if the store
+    // isn't ordered.
+    pub fn range(
+        &self,
+        start: Bytes,
+        end: Bytes,
+    ) -> Option<Box<dyn Iterator<Item = (&Bytes, &V)> + '_>> {
+        match &self.map {
+            Map::Hash(_) => None,
+            // `BTreeMap::range` panics when start is greater than end, such a
+            // range is empty anyway.
+            Map::Ordered(_) if start > end => Some(Box::new(std::iter::empty())),
+            Map::Ordered(map) => Some(Box::new(
+                map.range((Bound::Included(start), Bound::Excluded(end)))
+                    .map(|(key, entry)| (key, &entry.value)),
+            )),
+        }
+    }
+
+    // removes the entries with keys in `start..end` and returns how many were
+    // removed, or `None` if the store isn't ordered.
+    pub fn remove_range(&mut self, start: Bytes, end: Bytes) -> Option<usize> {
+        let keys: Vec<Bytes> = self
+            .range(start, end)?
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys.iter() {
+            self.remove(key);
+        }
+        Some(keys.len())
+    }
+
+    // takes all the entries out of the store and leaves an empty store of the
+    // same kind behind.
+    pub fn take(&mut self) -> Self {
+        let mut store = Store::with_clock(self.is_ordered(), self.clock.clone());
+        store.queues = self.queues.as_ref().map(Queues::empty);
+        mem::replace(self, store)
+    }
+
+    // starts keeping the given queues, or stops keeping any with `None`. the
+    // keys already in the store are queued as written, queues of the same kind
+    // as the ones kept are left as they are.
+    pub fn set_queues(&mut self, queues: Option<Queues>) {
+        let same = match (&self.queues, &queues) {
+            (Some(current), Some(queues)) => {
+                mem::discriminant(current) == mem::discriminant(queues)
+            }
+            (current, queues) => current.is_none() && queues.is_none(),
+        };
+        if same {
+            return;
+        }
+        let now = self.clock.instant();
+        self.queues = queues.map(|mut queues| {
+            for key in self.keys() {
+                queues.insert(key, now);
+            }
+            queues
+        });
+    }
+
+    // removes the key picked by the evictor whose queues are kept, `check`
+    // tells what to do with the keys it looks at given when they were queued,
+    // it may change the value as long as its weight stays the same. returns
+    // `None` when nothing was evicted or no queues are kept.
+    pub fn evict_queued(
+        &mut self,
+        mut check: impl FnMut(&Bytes, &mut V, Instant) -> Candidate,
+    ) -> Option<(Bytes, V)> {
+        let map = &mut self.map;
+        let now = self.clock.instant();
+        let key = self.queues.as_mut()?.evict(
+            |key, queued_at| match map.get_mut(key) {
+                Some(entry) => check(key, &mut entry.value, queued_at),
+                None => Candidate::Missing,
+            },
+            now,
+        )?;
+        let value = self.remove(&key)?;
+        Some((key, value))
+    }
+
+    // changes whenever a key is written, changed in place or removed, so a
+    // result computed from the keys of the store is still valid as long as
+    // the epoch it was computed at is
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    // the estimated bytes taken by the keys and values of the store, values
+    // changed in place are weighed again first
+    pub fn memory(&mut self) -> usize {
+        for key in self.dirty.drain() {
+            let entry = self.map.get_mut(&key);
+            if let Some(entry) = entry {
+                let weight = key.len() + entry.value.weight();
+                self.memory = self.memory - entry.weight + weight;
+                entry.weight = weight;
+            }
+        }
+        self.memory
+    }
+
+    // checks that the weights kept by the store match the keys and values, the
+    // values changed in place since the memory was last counted aside. returns
+    // a description of every discrepancy found.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let entries: Box<dyn Iterator<Item = (&Bytes, &Entry<V>)>> = match &self.map {
+            Map::Hash(map) => Box::new(map.iter()),
+            Map::Ordered(map) => Box::new(map.iter()),
+        };
+        let mut memory = 0;
+        for (key, entry) in entries {
+            memory += entry.weight;
+            let weight = key.len() + entry.value.weight();
+            if entry.weight != weight && !self.dirty.contains(key) {
+                problems.push(format!(
+                    "key '{}' weighs {} bytes but {} are counted",
+                    String::from_utf8_lossy(key),
+                    weight,
+                    entry.weight
+                ));
+            }
+        }
+        if memory != self.memory {
+            problems.push(format!(
+                "memory is {} bytes but the keys add up to {}",
+                self.memory, memory
+            ));
+        }
+        if self.slots.len() != self.len() {
+            problems.push(format!(
+                "{} keys have a slot but the store holds {}",
+                self.slots.len(),
+                self.len()
+            ));
+        }
+        for (slot, key) in self.slots.iter().enumerate() {
+            if self.entry(key).is_none_or(|entry| entry.slot != slot) {
+                problems.push(format!(
+                    "key '{}' in slot {} doesn't know its slot",
+                    String::from_utf8_lossy(key),
+                    slot
+                ));
+            }
+        }
+        for key in self.dirty.iter() {
+            if !self.contains_key(key) {
+                problems.push(format!(
+                    "changed key '{}' is not in the store",
+                    String::from_utf8_lossy(key)
+                ));
+            }
+        }
+        problems
+    }
+
+    fn entry(&self, key: &Bytes) -> Option<&Entry<V>> {
+        self.map.get(key)
+    }
+}
+
+impl Queues {
+    // queues of the same kind without any keys
+    fn empty(&self) -> Self {
+        match self {
+            Queues::Adaptive(_) => Queues::Adaptive(Adaptive::default()),
+            Queues::Segmented(_) => Queues::Segmented(Segmented::default()),
+            Queues::SecondChance(_) => Queues::SecondChance(SecondChance::default()),
+            Queues::Recency(_) => Queues::Recency(Recency::default()),
+        }
+    }
+
+    fn insert(&mut self, key: &Bytes, now: Instant) {
+        match self {
+            Queues::Adaptive(adaptive) => adaptive.insert(key, now),
+            Queues::Segmented(segmented) => segmented.insert(key, now),
+            Queues::SecondChance(clock) => clock.insert(key, now),
+            Queues::Recency(recency) => recency.insert(key, now),
+        }
+    }
+
+    fn remove(&mut self, key: &Bytes) {
+        match self {
+            Queues::Adaptive(adaptive) => adaptive.remove(key),
+            Queues::Segmented(segmented) => segmented.remove(key),
+            Queues::SecondChance(clock) => clock.remove(key),
+            Queues::Recency(recency) => recency.remove(key),
+        }
+    }
+
+    fn evict(
+        &mut self,
+        check: impl FnMut(&Bytes, Instant) -> Candidate,
+        now: Instant,
+    ) -> Option<Bytes> {
+        match self {
+            Queues::Adaptive(adaptive) => adaptive.evict(check, now),
+            Queues::Segmented(segmented) => segmented.evict(check, now),
+            Queues::SecondChance(clock) => clock.evict(check, now),
+            Queues::Recency(recency) => recency.evict(check, now),
+        }
+    }
+}
+
+// every `RandomState` is seeded differently, which is all the randomness
+// sampling needs
+pub fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl<V> Map<V> {
+    fn get(&self, key: &Bytes) -> Option<&Entry<V>> {
+        match self {
+            Map::Hash(map) => map.get(key),
+            Map::Ordered(map) => map.get(key),
+        }
+    }
+
+    fn get_mut(&mut self, key: &Bytes) -> Option<&mut Entry<V>> {
+        match self {
+            Map::Hash(map) => map.get_mut(key),
+            Map::Ordered(map) => map.get_mut(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Weigh for u8 {
+        fn weight(&self) -> usize {
+            *self as usize
+        }
+    }
+
+    fn ordered_store() -> Store<u8> {
+        let mut store = Store::new(true);
+        for key in ["a", "b", "c", "d"] {
+            store.insert(Bytes::from(key), 0);
+        }
+        store
+    }
+
+    #[test]
+    fn range_given_ordered_store_returns_keys_in_range() {
+        let store = ordered_store();
+        let keys: Vec<_> = store
+            .range(Bytes::from("b"), Bytes::from("d"))
+            .unwrap()
+            .map(|(key, _)| key.clone())
+            .collect();
+        assert_eq!(keys, vec![Bytes::from("b"), Bytes::from("c")]);
+    }
+
+    #[test]
+    fn chunk_given_keys_removed_in_between_reaches_remaining_keys() {
+        let mut store = ordered_store();
+        let (chunk, cursor) = store.chunk(usize::MAX, 2);
+        let mut walked: Vec<_> = chunk.into_iter().map(|(key, _)| key.clone()).collect();
+        // the last key takes the slot of the removed one, both were walked
+        store.remove(&Bytes::from("c"));
+        store.insert(Bytes::from("e"), 0);
+        let (chunk, cursor) = store.chunk(cursor, 2);
+        walked.extend(chunk.into_iter().map(|(key, _)| key.clone()));
+        assert_eq!(cursor, 0);
+        walked.sort();
+        assert_eq!(
+            walked,
+            vec![
+                Bytes::from("a"),
+                Bytes::from("b"),
+                Bytes::from("c"),
+                Bytes::from("d")
+            ]
+        );
+    }
+
+    #[test]
+    fn range_given_start_after_end_returns_nothing() {
+        let store = ordered_store();
+        assert_eq!(
+            store
+                .range(Bytes::from("d"), Bytes::from("a"))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn range_given_hash_store_returns_none() {
+        let store: Store<u8> = Store::new(false);
+        assert!(store.range(Bytes::from("a"), Bytes::from("b")).is_none());
+    }
+
+    #[test]
+    fn remove_range_given_ordered_store_removes_keys_in_range() {
+        let mut store = ordered_store();
+        assert_eq!(
+            store.remove_range(Bytes::from("b"), Bytes::from("d")),
+            Some(2)
+        );
+        let keys: Vec<_> = store.keys().cloned().collect();
+        assert_eq!(keys, vec![Bytes::from("a"), Bytes::from("d")]);
+    }
+
+    #[test]
+    fn take_given_ordered_store_leaves_empty_ordered_store() {
+        let mut store = ordered_store();
+        assert_eq!(store.take().len(), 4);
+        assert_eq!(store.len(), 0);
+        assert!(store.is_ordered());
+    }
+
+    #[test]
+    fn epoch_given_changes_moves_and_given_reads_stays() {
+        let mut store = ordered_store();
+        let epoch = store.epoch();
+        store.get(&Bytes::from("a"));
+        store.get_mut_same_weight(&Bytes::from("a"));
+        store.remove(&Bytes::from("z"));
+        assert_eq!(store.epoch(), epoch);
+        store.get_mut(&Bytes::from("a"));
+        assert!(store.epoch() > epoch);
+        let epoch = store.epoch();
+        store.take();
+        assert!(store.epoch() > epoch);
+    }
+
+    #[test]
+    fn memory_given_writes_and_changes_in_place_counts_keys_and_values() {
+        let mut store = ordered_store();
+        assert_eq!(store.memory(), 4);
+        store.insert(Bytes::from("a"), 10);
+        assert_eq!(store.memory(), 14);
+        *store.get_mut(&Bytes::from("b")).unwrap() = 5;
+        assert_eq!(store.memory(), 19);
+        store.remove(&Bytes::from("a"));
+        store.remove_range(Bytes::from("c"), Bytes::from("e"));
+        assert_eq!(store.memory(), 6);
+        store.take();
+        assert_eq!(store.memory(), 0);
+    }
+
+    #[test]
+    fn evict_queued_given_queues_evicts_oldest_key() {
+        let mut store = ordered_store();
+        assert!(store.evict_queued(|_, _, _| Candidate::Cold).is_none());
+        store.set_queues(Some(Queues::Adaptive(Adaptive::default())));
+        store.insert(Bytes::from("e"), 1);
+        let (key, _) = store.evict_queued(|_, _, _| Candidate::Cold).unwrap();
+        assert_ne!(key, Bytes::from("e"));
+        assert!(!store.contains_key(&key));
+        assert_eq!(store.len(), 4);
+    }
+
+    #[test]
+    fn check_given_value_changed_without_being_weighed_returns_problem() {
+        let mut store = ordered_store();
+        *store.get_mut(&Bytes::from("a")).unwrap() = 5;
+        assert!(store.check().is_empty());
+        *store.get_mut_same_weight(&Bytes::from("b")).unwrap() = 5;
+        assert_eq!(
+            store.check(),
+            vec!["key 'b' weighs 6 bytes but 1 are counted".to_string()]
+        );
+    }
+
+    #[test]
+    fn sample_given_removed_keys_picks_every_kept_key() {
+        let mut store = Store::new(false);
+        for n in 0..100 {
+            store.insert(Bytes::from(format!("key:{}", n)), 0u8);
+        }
+        for n in (0..100).step_by(3) {
+            store.remove(&Bytes::from(format!("key:{}", n)));
+        }
+        assert!(store.check().is_empty());
+
+        let sample = store.sample(10, |key| !key.ends_with(b"1"));
+        let keys: HashSet<_> = sample.iter().map(|(key, _)| (*key).clone()).collect();
+        assert_eq!(keys.len(), 10);
+        assert!(keys
+            .iter()
+            .all(|key| !key.ends_with(b"1") && store.contains_key(key)));
+
+        let mut seen = HashSet::new();
+        for _ in 0..2000 {
+            seen.extend(
+                store
+                    .sample(1, |_| true)
+                    .into_iter()
+                    .map(|(key, _)| key.clone()),
+            );
+        }
+        assert_eq!(seen.len(), store.len());
+    }
+}
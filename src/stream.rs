@@ -0,0 +1,176 @@
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::mem;
+use std::num::ParseIntError;
+
+// entry ids are made of the time the entry was added in milliseconds and a
+// sequence number which orders the entries added within the same millisecond
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct StreamId {
+    ms: u64,
+    seq: u64,
+}
+
+// an entry is a list of field value pairs, kept in the order they were given
+pub type Entry = Vec<(Bytes, Bytes)>;
+
+// entries ordered by id, ids only ever grow so new entries are always added
+// at the end of the stream
+#[derive(Debug, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Entry>,
+    last_id: StreamId,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    pub fn ms(&self) -> u64 {
+        self.ms
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    // parses `<ms>-<seq>` or `<ms>`, the sequence number of the latter is the
+    // given default
+    pub fn parse(value: &str, default_seq: u64) -> Result<Self, ParseIntError> {
+        match value.split_once('-') {
+            Some((ms, seq)) => Ok(StreamId::new(ms.parse()?, seq.parse()?)),
+            None => Ok(StreamId::new(value.parse()?, default_seq)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl Stream {
+    // adds the entry under the given id, or under an id generated from the
+    // current time when no id is given. a clock that goes backwards only bumps
+    // the sequence number, so generated ids keep growing. returns `None` when
+    // the given id isn't greater than the last id of the stream.
+    pub fn add(&mut self, id: Option<StreamId>, now_ms: u64, entry: Entry) -> Option<StreamId> {
+        let id = match id {
+            Some(id) if id > self.last_id => id,
+            Some(_) => return None,
+            None if now_ms > self.last_id.ms => StreamId::new(now_ms, 0),
+            None => StreamId::new(self.last_id.ms, self.last_id.seq.checked_add(1)?),
+        };
+        self.entries.insert(id, entry);
+        self.last_id = id;
+        Some(id)
+    }
+
+    // iterates over the entries between the start and the end id, both
+    // inclusive
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl Iterator<Item = (&StreamId, &Entry)> {
+        // `BTreeMap::range` panics when the start is past the end
+        (start <= end)
+            .then(|| self.entries.range(start..=end))
+            .into_iter()
+            .flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&StreamId, &Entry)> {
+        self.entries.iter()
+    }
+
+    // the id of the last entry added, which stays even once the entry is gone
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    // moves the last id up to `id`, so entries added after a restore keep
+    // growing from where the stream was at even if its last entries are gone
+    pub fn advance_last_id(&mut self, id: StreamId) {
+        self.last_id = self.last_id.max(id);
+    }
+
+    // an estimate of the memory taken by the entries
+    pub fn memory_usage(&self) -> usize {
+        self.entries
+            .values()
+            .map(|entry| {
+                mem::size_of::<(StreamId, Entry)>()
+                    + entry.capacity() * mem::size_of::<(Bytes, Bytes)>()
+                    + entry
+                        .iter()
+                        .map(|(field, value)| field.len() + value.len())
+                        .sum::<usize>()
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        vec![(Bytes::from("temp"), Bytes::from("21"))]
+    }
+
+    #[test]
+    fn add_given_no_id_generates_growing_ids() {
+        let mut stream = Stream::default();
+        assert_eq!(stream.add(None, 10, entry()), Some(StreamId::new(10, 0)));
+        assert_eq!(stream.add(None, 10, entry()), Some(StreamId::new(10, 1)));
+        // the clock went backwards
+        assert_eq!(stream.add(None, 5, entry()), Some(StreamId::new(10, 2)));
+        assert_eq!(stream.add(None, 11, entry()), Some(StreamId::new(11, 0)));
+        assert_eq!(stream.len(), 4);
+    }
+
+    #[test]
+    fn add_given_id_not_greater_than_last_id_returns_none() {
+        let mut stream = Stream::default();
+        assert_eq!(stream.add(Some(StreamId::MIN), 10, entry()), None);
+        assert!(stream.add(Some(StreamId::new(5, 1)), 10, entry()).is_some());
+        assert_eq!(stream.add(Some(StreamId::new(5, 1)), 10, entry()), None);
+        assert_eq!(stream.add(Some(StreamId::new(4, 9)), 10, entry()), None);
+    }
+
+    #[test]
+    fn range_given_bounds_returns_entries_between_them() {
+        let mut stream = Stream::default();
+        for ms in 1..=5 {
+            stream.add(Some(StreamId::new(ms, 0)), 0, entry());
+        }
+        let ids: Vec<_> = stream
+            .range(
+                StreamId::parse("2", 0).unwrap(),
+                StreamId::parse("4", u64::MAX).unwrap(),
+            )
+            .map(|(id, _)| id.to_string())
+            .collect();
+        assert_eq!(ids, vec!["2-0", "3-0", "4-0"]);
+        assert_eq!(
+            stream
+                .range(StreamId::new(4, 0), StreamId::new(2, 0))
+                .count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use crate::frame::Frame;
+use bytes::Bytes;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+// finished tasks are kept around so their outcome can still be looked up,
+// the oldest ones are forgotten past this many
+const FINISHED_TASKS: usize = 32;
+
+// long running commands register themselves as tasks while they run, so they
+// can be followed and cancelled with `TASKS`. a task only stops once it
+// checks whether it was cancelled, which it does in between chunks of work.
+#[derive(Debug, Default)]
+pub struct Tasks {
+    next_id: AtomicU64,
+    tasks: Mutex<BTreeMap<u64, Arc<TaskState>>>,
+}
+
+// a running task, it failed unless it is marked as done before it is dropped
+#[derive(Debug)]
+pub struct Task {
+    state: Arc<TaskState>,
+}
+
+#[derive(Debug)]
+struct TaskState {
+    id: u64,
+    command: &'static str,
+    keyspace: Bytes,
+    started_at: Instant,
+    // the units of work done so far out of `total`, keys for most commands.
+    // the total is 0 until it is known.
+    done: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+    finished: Mutex<Option<(Status, Instant)>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl Tasks {
+    pub fn start(&self, command: &'static str, keyspace: Bytes) -> Task {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let state = Arc::new(TaskState {
+            id,
+            command,
+            keyspace,
+            started_at: Instant::now(),
+            done: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            cancelled: AtomicBool::new(false),
+            finished: Mutex::new(None),
+        });
+        let mut tasks = self.tasks.lock();
+        let finished: Vec<u64> = tasks
+            .values()
+            .filter(|task| task.status() != Status::Running)
+            .map(|task| task.id)
+            .collect();
+        for id in finished
+            .iter()
+            .take(finished.len().saturating_sub(FINISHED_TASKS - 1))
+        {
+            tasks.remove(id);
+        }
+        tasks.insert(id, state.clone());
+        Task { state }
+    }
+
+    // the running tasks followed by the recently finished ones, oldest first
+    pub fn list(&self) -> Frame {
+        let tasks = self.tasks.lock();
+        Frame::Array(tasks.values().map(|task| task.to_frame()).collect())
+    }
+
+    // `None` if there is no such task or it was finished long ago
+    pub fn status(&self, id: u64) -> Option<Frame> {
+        self.tasks.lock().get(&id).map(|task| task.to_frame())
+    }
+
+    // asks the task to stop, returns whether it was still running. `None` if
+    // there is no such task.
+    pub fn cancel(&self, id: u64) -> Option<bool> {
+        let tasks = self.tasks.lock();
+        let task = tasks.get(&id)?;
+        if task.status() != Status::Running {
+            return Some(false);
+        }
+        Some(!task.cancelled.swap(true, Ordering::Relaxed))
+    }
+}
+
+impl Task {
+    pub fn id(&self) -> u64 {
+        self.state.id
+    }
+
+    pub fn set_total(&self, total: u64) {
+        self.state.total.store(total, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, done: u64) {
+        self.state.done.fetch_add(done, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn done(self) {
+        self.finish(Status::Done);
+    }
+
+    fn finish(&self, status: Status) {
+        let mut finished = self.state.finished.lock();
+        if finished.is_none() {
+            *finished = Some((status, Instant::now()));
+        }
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        if self.is_cancelled() {
+            self.finish(Status::Cancelled);
+        } else {
+            self.finish(Status::Failed);
+        }
+    }
+}
+
+impl TaskState {
+    fn status(&self) -> Status {
+        match *self.finished.lock() {
+            Some((status, _)) => status,
+            None => Status::Running,
+        }
+    }
+
+    fn to_frame(&self) -> Frame {
+        let (status, elapsed) = match *self.finished.lock() {
+            Some((status, finished_at)) => (status, finished_at - self.started_at),
+            None => (Status::Running, self.started_at.elapsed()),
+        };
+        let total = match self.total.load(Ordering::Relaxed) {
+            0 => Frame::Null,
+            total => Frame::Integer(total as i64),
+        };
+        Frame::Map(vec![
+            Frame::String(Bytes::from_static(b"id")),
+            Frame::Integer(self.id as i64),
+            Frame::String(Bytes::from_static(b"command")),
+            Frame::String(Bytes::from_static(self.command.as_bytes())),
+            Frame::String(Bytes::from_static(b"keyspace")),
+            Frame::String(self.keyspace.clone()),
+            Frame::String(Bytes::from_static(b"status")),
+            Frame::String(Bytes::from_static(status.as_bytes())),
+            Frame::String(Bytes::from_static(b"done")),
+            Frame::Integer(self.done.load(Ordering::Relaxed) as i64),
+            Frame::String(Bytes::from_static(b"total")),
+            total,
+            Frame::String(Bytes::from_static(b"elapsed")),
+            Frame::Integer(elapsed.as_millis() as i64),
+        ])
+    }
+}
+
+impl Status {
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            Status::Running => b"running",
+            Status::Done => b"done",
+            Status::Failed => b"failed",
+            Status::Cancelled => b"cancelled",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_given_running_task_cancels_it_once() {
+        let tasks = Tasks::default();
+        let task = tasks.start("sum", Bytes::from("foo"));
+        assert_eq!(tasks.cancel(task.id()), Some(true));
+        assert_eq!(tasks.cancel(task.id()), Some(false));
+        assert!(task.is_cancelled());
+        let id = task.id();
+        drop(task);
+        assert_eq!(tasks.cancel(id), Some(false));
+        assert_eq!(tasks.cancel(id + 1), None);
+        assert_eq!(tasks.tasks.lock()[&id].status(), Status::Cancelled);
+    }
+
+    #[test]
+    fn start_given_many_finished_tasks_forgets_the_oldest() {
+        let tasks = Tasks::default();
+        let running = tasks.start("sum", Bytes::from("foo"));
+        for _ in 0..FINISHED_TASKS + 5 {
+            tasks.start("avg", Bytes::from("foo")).done();
+        }
+        let ids: Vec<u64> = tasks.tasks.lock().keys().copied().collect();
+        assert_eq!(ids.len(), FINISHED_TASKS + 1);
+        assert_eq!(ids[0], running.id());
+        assert_eq!(ids[1], 7);
+    }
+}
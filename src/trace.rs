@@ -0,0 +1,65 @@
+use crate::frame::Frame;
+use bytes::Bytes;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A key event is published every time a command touches a key while at least
+// one connection is watching keys using WATCHKEY.
+#[derive(Debug, Clone)]
+pub struct KeyEvent {
+    connection: u64,
+    address: SocketAddr,
+    command: &'static str,
+    keyspace: Bytes,
+    key: Bytes,
+    timestamp: u128,
+    ok: bool,
+}
+
+impl KeyEvent {
+    pub fn new(
+        connection: u64,
+        address: SocketAddr,
+        command: &'static str,
+        keyspace: Bytes,
+        key: Bytes,
+        ok: bool,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis())
+            .unwrap_or_default();
+        KeyEvent {
+            connection,
+            address,
+            command,
+            keyspace,
+            key,
+            timestamp,
+            ok,
+        }
+    }
+
+    pub fn matches(&self, keyspace: &Bytes, key: &Bytes) -> bool {
+        self.keyspace == keyspace && self.key == key
+    }
+
+    pub fn to_frame(&self) -> Frame {
+        Frame::Map(vec![
+            Frame::String(Bytes::from_static(b"connection")),
+            Frame::Integer(self.connection as i64),
+            Frame::String(Bytes::from_static(b"address")),
+            Frame::String(Bytes::from(self.address.to_string())),
+            Frame::String(Bytes::from_static(b"command")),
+            Frame::String(Bytes::from_static(self.command.as_bytes())),
+            Frame::String(Bytes::from_static(b"keyspace")),
+            Frame::String(self.keyspace.clone()),
+            Frame::String(Bytes::from_static(b"key")),
+            Frame::String(self.key.clone()),
+            Frame::String(Bytes::from_static(b"timestamp")),
+            Frame::Integer(self.timestamp as i64),
+            Frame::String(Bytes::from_static(b"ok")),
+            Frame::Boolean(self.ok),
+        ])
+    }
+}
@@ -0,0 +1,132 @@
+use crate::client::{Client, ConnectionError};
+use crate::frame::Frame;
+use bytes::Bytes;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tokio::time;
+
+// how long a miss waits on the upstream before it fails
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(1);
+
+// where a keyspace reads its misses from, see `CREATE ... UPSTREAM`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpstreamConfig {
+    pub address: String,
+    // how long fetched values are cached in milliseconds, 0 keeps them until
+    // they are evicted
+    pub cache_ttl: u64,
+}
+
+// a connection to the segment server a keyspace reads its misses from. fetches
+// take turns on the one connection, which is dropped on errors and opened
+// again by the next fetch.
+#[derive(Debug)]
+pub struct Upstream {
+    config: UpstreamConfig,
+    client: Mutex<Option<Client>>,
+}
+
+#[derive(Debug, Error)]
+pub enum UpstreamError {
+    #[error("upstream '{0}' did not reply in time")]
+    Timeout(String),
+
+    #[error("upstream '{0}': {1}")]
+    Connection(String, ConnectionError),
+
+    #[error("upstream '{0}' returned an error: {1}")]
+    Reply(String, String),
+
+    #[error("upstream '{0}' returned an unexpected reply")]
+    UnexpectedReply(String),
+}
+
+impl Upstream {
+    pub fn new(config: UpstreamConfig) -> Self {
+        Upstream {
+            config,
+            client: Mutex::new(None),
+        }
+    }
+
+    pub fn config(&self) -> &UpstreamConfig {
+        &self.config
+    }
+
+    // returns the value of the key in the keyspace of the same name on the
+    // upstream, `None` if the upstream doesn't have it.
+    pub async fn get(&self, keyspace: &Bytes, key: &Bytes) -> Result<Option<Bytes>, UpstreamError> {
+        let command = Frame::Array(vec![
+            Frame::String(Bytes::from_static(b"GET")),
+            Frame::String(keyspace.clone()),
+            Frame::String(key.clone()),
+        ]);
+        let mut client = self.client.lock().await;
+        let reply = match time::timeout(UPSTREAM_TIMEOUT, self.send(&mut client, &command)).await {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(e)) => {
+                *client = None;
+                return Err(UpstreamError::Connection(self.config.address.clone(), e));
+            }
+            Err(_) => {
+                *client = None;
+                return Err(UpstreamError::Timeout(self.config.address.clone()));
+            }
+        };
+        match reply {
+            Frame::String(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            Frame::Error(e) => Err(UpstreamError::Reply(
+                self.config.address.clone(),
+                String::from_utf8_lossy(&e).to_string(),
+            )),
+            _ => Err(UpstreamError::UnexpectedReply(self.config.address.clone())),
+        }
+    }
+
+    async fn send(
+        &self,
+        client: &mut Option<Client>,
+        command: &Frame,
+    ) -> Result<Frame, ConnectionError> {
+        let client = match client {
+            Some(client) => client,
+            None => client.insert(Client::connect(self.config.address.as_str()).await?),
+        };
+        client.send(command).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn get_given_upstream_replies_returns_value_and_reuses_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = Upstream::new(UpstreamConfig {
+            address: listener.local_addr().unwrap().to_string(),
+            cache_ttl: 0,
+        });
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 64];
+            for reply in [&b"$3\r\nbar\r\n"[..], &b"-\r\n"[..]] {
+                let _ = socket.read(&mut buf).await.unwrap();
+                socket.write_all(reply).await.unwrap();
+            }
+        });
+        let keyspace = Bytes::from("foo");
+        assert_eq!(
+            upstream.get(&keyspace, &Bytes::from("a")).await.unwrap(),
+            Some(Bytes::from("bar"))
+        );
+        assert_eq!(
+            upstream.get(&keyspace, &Bytes::from("b")).await.unwrap(),
+            None
+        );
+    }
+}
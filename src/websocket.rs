@@ -0,0 +1,154 @@
+use crate::frame::{self, ParseFrameError};
+use crate::netfilter::NetFilter;
+use bytes::{Bytes, BytesMut};
+use crossbeam::sync::WaitGroup;
+use futures_util::{SinkExt, StreamExt};
+use std::io::Cursor;
+use std::net::SocketAddr;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, error};
+
+// a listener which carries the frame protocol over websockets, so browsers
+// can talk to segment directly, see `websocket_port` in the config. every
+// websocket is bridged onto one end of an in memory stream, the other end is
+// handed to the server through `connections` and handled like a tcp
+// connection. clients send frames as binary messages, a frame can be split
+// across messages or several frames sent in one. every reply comes back as a
+// binary message of its own.
+pub async fn serve(
+    ln: TcpListener,
+    net_filter: NetFilter,
+    buf_size: usize,
+    connections: mpsc::Sender<(DuplexStream, SocketAddr)>,
+    mut done: broadcast::Receiver<()>,
+    wg: WaitGroup,
+) {
+    loop {
+        tokio::select! {
+            _ = done.recv() => {
+                debug!("stopping websocket listener, shutdown signal received");
+                break;
+            }
+            accepted = ln.accept() => {
+                let (stream, address) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("failed to accept websocket connection, error = {:?}", e);
+                        continue;
+                    }
+                };
+                if !net_filter.is_allowed(address.ip()) {
+                    debug!("rejecting websocket connection from {}, peer is not allowed", address);
+                    continue;
+                }
+                let connections = connections.clone();
+                let wg = wg.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = accept(stream, address, buf_size, connections).await {
+                        debug!("websocket connection from {} failed, error = {}", address, e);
+                    }
+                    drop(wg);
+                });
+            }
+        }
+    }
+}
+
+// completes the handshake and bridges the websocket until either side closes
+async fn accept(
+    stream: TcpStream,
+    address: SocketAddr,
+    buf_size: usize,
+    connections: mpsc::Sender<(DuplexStream, SocketAddr)>,
+) -> Result<(), WsError> {
+    let ws = tokio_tungstenite::accept_async(stream).await?;
+    let (server, bridge) = io::duplex(buf_size);
+    if connections.send((server, address)).await.is_err() {
+        // the server is shutting down
+        return Ok(());
+    }
+    relay(ws, bridge, buf_size).await
+}
+
+// copies binary messages into the bridge and the frames written to the bridge
+// back out as messages. messages are only read once the previous one was
+// taken by the server, the websocket gets the same backpressure as a tcp
+// connection.
+async fn relay<S>(
+    ws: WebSocketStream<S>,
+    bridge: DuplexStream,
+    buf_size: usize,
+) -> Result<(), WsError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut sink, mut messages) = ws.split();
+    let (mut reader, mut writer) = io::split(bridge);
+    let mut requests = Bytes::new();
+    let mut replies = BytesMut::with_capacity(buf_size);
+    loop {
+        tokio::select! {
+            message = messages.next(), if requests.is_empty() => match message {
+                Some(Ok(Message::Binary(data))) => requests = data,
+                Some(Ok(Message::Text(_))) => {
+                    let reason = CloseFrame {
+                        code: CloseCode::Unsupported,
+                        reason: "frames must be sent as binary messages".into(),
+                    };
+                    return sink.send(Message::Close(Some(reason))).await;
+                }
+                // pings are answered by the websocket itself
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => {}
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Err(e)) => return Err(e),
+            },
+            written = writer.write_buf(&mut requests), if !requests.is_empty() => {
+                if written.is_err() {
+                    break;
+                }
+            }
+            read = reader.read_buf(&mut replies) => {
+                if read? == 0 {
+                    // the server closed the connection
+                    break;
+                }
+                while let Some(len) = next_frame_len(&replies) {
+                    let reply = replies.split_to(len).freeze();
+                    sink.send(Message::Binary(reply)).await?;
+                }
+            }
+        }
+    }
+    sink.close().await
+}
+
+// the length of the first frame in the buffer, `None` until all of it was
+// written
+fn next_frame_len(buf: &[u8]) -> Option<usize> {
+    let mut cursor = Cursor::new(buf);
+    match frame::parse(&mut cursor) {
+        Ok(_) => Some(cursor.position() as usize),
+        Err(ParseFrameError::Incomplete) => None,
+        // the server only writes valid frames, whatever is left is sent as is
+        Err(ParseFrameError::InvalidFormat) => Some(buf.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_frame_len_given_partial_frame_waits_for_the_rest() {
+        let buf = b"$5\r\nhello\r\n%1\r\n$3\r\nfo";
+        assert_eq!(next_frame_len(buf), Some(11));
+        assert_eq!(next_frame_len(&buf[11..]), Some(4));
+        assert_eq!(next_frame_len(&buf[15..]), None);
+    }
+}